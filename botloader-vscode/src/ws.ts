@@ -168,6 +168,7 @@ export interface LogItem {
     message: string,
     script_context?: ScriptContext,
     level: LogLevel,
+    stack?: StackFrame[],
 }
 
 export type LogLevel = "Critical" |
@@ -180,3 +181,9 @@ export interface ScriptContext {
     filename: String,
     line_col?: [number, number],
 }
+
+export interface StackFrame {
+    filename: string,
+    line_col?: [number, number],
+    function_name?: string,
+}
@@ -70,7 +70,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     plugin_auto_update: None,
                     plugin_id: None,
                     plugin_version_number: None,
+                    is_library: false,
+                    library_modules: Vec::new(),
                 },
+                None,
             )
             .await?;
 
@@ -95,7 +98,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         plugin_version_number: None,
                         settings_definitions: None,
                         settings_values: Some(deserialized),
+                        is_library: None,
+                        library_modules: None,
                     },
+                    None,
                 )
                 .await?;
 
@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use common::dispatch_event::EvalOutput;
 use futures::{Stream, StreamExt};
 use guild_logger::LogEntry;
 use twilight_model::id::{marker::GuildMarker, Id};
@@ -34,6 +37,22 @@ impl Client {
         Ok(())
     }
 
+    pub async fn reload_guild_script(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+    ) -> Result<(), tonic::Status> {
+        let mut conn = self.get_conn();
+
+        conn.reload_script(proto::GuildScriptId {
+            guild_id: guild_id.get(),
+            script_id,
+        })
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn guild_log_stream(
         &self,
         guild_id: Id<GuildMarker>,
@@ -50,14 +69,62 @@ impl Client {
         Ok(stream.map(|item| item.map(Into::into)))
     }
 
+    pub async fn eval_stream(
+        &self,
+        guild_id: Id<GuildMarker>,
+        source: String,
+    ) -> Result<impl Stream<Item = Result<EvalOutput, tonic::Status>>, tonic::Status> {
+        let mut conn = self.get_conn();
+
+        let stream = conn
+            .eval_script(proto::EvalRequest {
+                guild_id: guild_id.get(),
+                source,
+            })
+            .await?
+            .into_inner();
+
+        Ok(stream.map(|item| item.map(Into::into)))
+    }
+
+    /// Passes a request to one of the guild's inbound webhooks on to its scripts, returning the
+    /// json encoded body they responded with
+    pub async fn dispatch_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook_id: u64,
+        name: String,
+        body: String,
+    ) -> Result<Option<String>, tonic::Status> {
+        let mut conn = self.get_conn();
+
+        let result = conn
+            .dispatch_webhook(proto::WebhookRequest {
+                guild_id: guild_id.get(),
+                webhook_id,
+                name,
+                body,
+            })
+            .await?;
+
+        Ok(result.into_inner().body)
+    }
+
     pub async fn get_vm_worker_statuses(
         &self,
     ) -> Result<Vec<proto::VmWorkerStatus>, tonic::Status> {
+        Ok(self.get_vm_worker_pool_status().await?.workers)
+    }
+
+    /// Returns the workers along with the requests queued for them and the recent waits of guilds
+    pub async fn get_vm_worker_pool_status(
+        &self,
+    ) -> Result<proto::VmWorkerStatusResponse, tonic::Status> {
         let mut conn = self.get_conn();
 
         let result = conn.vm_worker_status(proto::Empty {}).await?;
 
-        Ok(result.into_inner().workers)
+        Ok(result.into_inner())
     }
 
     pub async fn get_guild_status(
@@ -74,4 +141,38 @@ impl Client {
 
         Ok(result.into_inner())
     }
+
+    pub async fn impose_guild_suspension(
+        &self,
+        guild_id: Id<GuildMarker>,
+        duration: Option<Duration>,
+        message: Option<String>,
+    ) -> Result<(), tonic::Status> {
+        let mut conn = self.get_conn();
+
+        conn.impose_guild_suspension(proto::ImposeGuildSuspensionRequest {
+            guild_id: guild_id.get(),
+            duration_secs: duration.map(|v| v.as_secs()),
+            message,
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Lifts the active suspensions of the guild, returning whether it was suspended
+    pub async fn lift_guild_suspension(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<bool, tonic::Status> {
+        let mut conn = self.get_conn();
+
+        let result = conn
+            .lift_guild_suspension(proto::GuildSpecifier {
+                guild_id: guild_id.get(),
+            })
+            .await?;
+
+        Ok(result.into_inner().was_suspended)
+    }
 }
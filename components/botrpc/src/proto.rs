@@ -1,3 +1,4 @@
+use common::dispatch_event::{self, EvalOutput};
 use twilight_model::id::Id;
 
 tonic::include_proto!("botrpc");
@@ -9,6 +10,7 @@ impl From<guild_logger::LogEntry> for GuildLogItem {
             level: LogLevel::from(entry.level) as i32,
             message: entry.message,
             script_context: entry.script_context.map(Into::into),
+            stack: entry.stack.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -27,6 +29,7 @@ impl From<GuildLogItem> for guild_logger::LogEntry {
             },
             message: entry.message,
             script_context: entry.script_context.map(Into::into),
+            stack: entry.stack.into_iter().map(Into::into).collect(),
         }
     }
 }
@@ -49,6 +52,26 @@ impl From<ScriptContext> for guild_logger::ScriptContext {
     }
 }
 
+impl From<guild_logger::StackFrame> for StackFrame {
+    fn from(frame: guild_logger::StackFrame) -> Self {
+        Self {
+            filename: frame.filename,
+            line_col: frame.line_col.map(Into::into),
+            function_name: frame.function_name,
+        }
+    }
+}
+
+impl From<StackFrame> for guild_logger::StackFrame {
+    fn from(frame: StackFrame) -> Self {
+        Self {
+            filename: frame.filename,
+            line_col: frame.line_col.map(Into::into),
+            function_name: frame.function_name,
+        }
+    }
+}
+
 impl From<LineCol> for (u32, u32) {
     fn from(l: LineCol) -> Self {
         (l.line, l.column)
@@ -87,3 +110,42 @@ impl From<LogLevel> for guild_logger::LogLevel {
         }
     }
 }
+
+impl From<EvalOutput> for EvalOutputItem {
+    fn from(output: EvalOutput) -> Self {
+        Self {
+            eval_id: output.eval_id,
+            kind: EvalOutputKind::from(output.kind) as i32,
+            message: output.message,
+        }
+    }
+}
+
+impl From<EvalOutputItem> for EvalOutput {
+    fn from(item: EvalOutputItem) -> Self {
+        Self {
+            eval_id: item.eval_id,
+            kind: match item.kind {
+                0 => dispatch_event::EvalOutputKind::ConsoleLog,
+                1 => dispatch_event::EvalOutputKind::ConsoleWarn,
+                2 => dispatch_event::EvalOutputKind::ConsoleError,
+                3 => dispatch_event::EvalOutputKind::Result,
+                // newer kinds we don't know about end the eval rather than leaving it hanging
+                _ => dispatch_event::EvalOutputKind::Exception,
+            },
+            message: item.message,
+        }
+    }
+}
+
+impl From<dispatch_event::EvalOutputKind> for EvalOutputKind {
+    fn from(kind: dispatch_event::EvalOutputKind) -> Self {
+        match kind {
+            dispatch_event::EvalOutputKind::ConsoleLog => Self::ConsoleLog,
+            dispatch_event::EvalOutputKind::ConsoleWarn => Self::ConsoleWarn,
+            dispatch_event::EvalOutputKind::ConsoleError => Self::ConsoleError,
+            dispatch_event::EvalOutputKind::Result => Self::Result,
+            dispatch_event::EvalOutputKind::Exception => Self::Exception,
+        }
+    }
+}
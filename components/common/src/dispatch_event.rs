@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 pub enum EventSource {
     Discord,
     Timer,
+    Eval,
+    Webhook,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,3 +16,48 @@ pub struct VmDispatchEvent {
     pub source: EventSource,
     pub source_timestamp: chrono::DateTime<chrono::Utc>,
 }
+
+/// Dispatch events with this name are not passed on to scripts, instead the value is a [`EvalRequest`]
+/// that is evaluated inside the vm
+pub const EVAL_EVENT_NAME: &str = "BOTLOADER_EVAL";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvalRequest {
+    pub eval_id: u64,
+    pub source: String,
+}
+
+/// Dispatch events with this name are requests to one of the guild's inbound webhooks, the script
+/// handling it answers with a [`WebhookResponse`]
+pub const WEBHOOK_EVENT_NAME: &str = "BOTLOADER_WEBHOOK_REQUEST";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookResponse {
+    pub request_id: u64,
+    /// Sent back to the caller as json, no body is sent if not set
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EvalOutput {
+    pub eval_id: u64,
+    pub kind: EvalOutputKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EvalOutputKind {
+    ConsoleLog,
+    ConsoleWarn,
+    ConsoleError,
+
+    // the below kinds are the final output of a eval
+    Result,
+    Exception,
+}
+
+impl EvalOutputKind {
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Result | Self::Exception)
+    }
+}
@@ -25,6 +25,12 @@ pub struct Plugin {
     pub installed_guilds_updated_at: Option<DateTime<Utc>>,
     pub discord_thread_id: Option<Id<GuildMarker>>,
 
+    pub category: Option<PluginCategory>,
+    /// Assigned by the author, lowercase
+    pub tags: Vec<String>,
+    pub rating_average: f32,
+    pub rating_count: u32,
+
     pub images: Vec<PluginImage>,
 
     pub data: PluginData,
@@ -52,8 +58,363 @@ pub enum PluginType {
 pub struct ScriptPluginData {
     pub published_version: Option<String>,
     pub published_version_updated_at: Option<DateTime<Utc>>,
+    pub published_library_modules: Vec<LibraryModule>,
     pub dev_version: Option<String>,
     pub dev_version_updated_at: Option<DateTime<Utc>>,
+    pub dev_library_modules: Vec<LibraryModule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginCategory {
+    Moderation,
+    Utility,
+    Fun,
+    Games,
+    Economy,
+    Leveling,
+    Logging,
+    Roles,
+    Other,
+}
+
+impl PluginCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Moderation => "moderation",
+            Self::Utility => "utility",
+            Self::Fun => "fun",
+            Self::Games => "games",
+            Self::Economy => "economy",
+            Self::Leveling => "leveling",
+            Self::Logging => "logging",
+            Self::Roles => "roles",
+            Self::Other => "other",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "moderation" => Some(Self::Moderation),
+            "utility" => Some(Self::Utility),
+            "fun" => Some(Self::Fun),
+            "games" => Some(Self::Games),
+            "economy" => Some(Self::Economy),
+            "leveling" => Some(Self::Leveling),
+            "logging" => Some(Self::Logging),
+            "roles" => Some(Self::Roles),
+            "other" => Some(Self::Other),
+            _ => None,
+        }
+    }
+}
+
+/// A module shipped alongside a script that other modules can import, but that is never run on its own
+///
+/// Imported relative to the script, e.g. `import { something } from "./name"`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LibraryModule {
+    pub name: String,
+    pub source: String,
+}
+
+/// A published version of a script plugin
+#[derive(Serialize, Clone)]
+pub struct PluginVersion {
+    #[serde(flatten)]
+    pub meta: PluginVersionMeta,
+    pub source: String,
+    pub library_modules: Vec<LibraryModule>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct PluginVersionMeta {
+    pub plugin_id: u64,
+    pub version_number: u32,
+    pub created_at: DateTime<Utc>,
+    pub changelog: String,
+    pub channel: ReleaseChannel,
+    pub rollout: PluginVersionRollout,
+    pub capabilities: PluginCapabilities,
+    pub dependencies: Vec<PluginDependency>,
+    pub settings_migrations: Vec<SettingsMigration>,
+}
+
+/// Guilds auto updating a plugin only get the versions published to the channel they're on
+///
+/// Guilds on the beta channel also get the stable versions
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseChannel {
+    #[default]
+    Stable,
+    Beta,
+}
+
+impl ReleaseChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Beta => "beta",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stable" => Some(Self::Stable),
+            "beta" => Some(Self::Beta),
+            _ => None,
+        }
+    }
+}
+
+/// How far along a version is in being rolled out to the guilds auto updating the plugin
+#[derive(Serialize, Clone)]
+pub struct PluginVersionRollout {
+    /// Percentage of the auto updating guilds that have been updated to this version
+    pub percentage: u8,
+    pub updated_at: DateTime<Utc>,
+    pub paused_at: Option<DateTime<Utc>>,
+    pub pause_reason: Option<String>,
+}
+
+impl PluginVersionRollout {
+    pub fn is_in_progress(&self) -> bool {
+        self.percentage < 100 && self.paused_at.is_none()
+    }
+}
+
+/// What a plugin version needs access to, guild admins consent to these when installing the plugin
+/// and again when a new version asks for more
+///
+/// Everything not covered by these, e.g. sending messages in the guild or reacting to events, is
+/// always available to plugins
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PluginCapabilities {
+    /// Hosts the plugin can send http requests to, `*.example.com` also matches any subdomain and
+    /// `*` matches every host
+    #[serde(default)]
+    pub http_hosts: Vec<String>,
+    /// Banning, kicking and timing out members as well as managing roles
+    #[serde(default)]
+    pub moderation: bool,
+    /// Storing data in the guild's storage buckets
+    #[serde(default)]
+    pub storage: bool,
+    /// Sending direct messages to members
+    ///
+    /// Scripts can't send direct messages yet, this is declared up front so that plugins relying
+    /// on it don't have to ask for consent again once they can
+    #[serde(default)]
+    pub direct_messages: bool,
+}
+
+impl PluginCapabilities {
+    /// Everything a plugin could do before manifests existed, granted to the versions and installs
+    /// that predate them
+    pub fn unrestricted() -> Self {
+        Self {
+            http_hosts: vec!["*".to_string()],
+            moderation: true,
+            storage: true,
+            direct_messages: true,
+        }
+    }
+
+    pub fn allows_http_host(&self, host: &str) -> bool {
+        self.http_hosts
+            .iter()
+            .any(|pattern| http_host_pattern_matches(pattern, host))
+    }
+
+    /// Everything either of the two asks for
+    pub fn union(&self, other: &PluginCapabilities) -> Self {
+        let mut http_hosts = self.http_hosts.clone();
+        for host in &other.http_hosts {
+            if !http_hosts.contains(host) {
+                http_hosts.push(host.clone());
+            }
+        }
+
+        Self {
+            http_hosts,
+            moderation: self.moderation || other.moderation,
+            storage: self.storage || other.storage,
+            direct_messages: self.direct_messages || other.direct_messages,
+        }
+    }
+
+    /// Whether these capabilities cover everything `requested` asks for, i.e. no new consent is
+    /// needed to go from these to `requested`
+    pub fn covers(&self, requested: &PluginCapabilities) -> bool {
+        (!requested.moderation || self.moderation)
+            && (!requested.storage || self.storage)
+            && (!requested.direct_messages || self.direct_messages)
+            && requested
+                .http_hosts
+                .iter()
+                .all(|host| self.allows_http_host(host))
+    }
+}
+
+fn http_host_pattern_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" || pattern.eq_ignore_ascii_case(host) {
+        return true;
+    }
+
+    let Some(domain) = pattern.strip_prefix("*.") else {
+        return false;
+    };
+
+    host.to_ascii_lowercase()
+        .strip_suffix(&domain.to_ascii_lowercase())
+        .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.'))
+}
+
+/// Another plugin a plugin version needs installed on the guild, installing the plugin also
+/// installs its dependencies
+///
+/// Plugins can import the scripts of the plugins they depend on with
+/// `import { something } from "plugin:<plugin id>"` and their library modules with
+/// `"plugin:<plugin id>/<module name>"`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PluginDependency {
+    pub plugin_id: u64,
+    /// The oldest version that works with the dependent plugin
+    pub min_version: u32,
+    /// The newest version that works with the dependent plugin, newer versions are held back from
+    /// the guilds that have the dependent plugin installed
+    #[serde(default)]
+    pub max_version: Option<u32>,
+}
+
+impl PluginDependency {
+    pub fn is_satisfied_by(&self, version_number: u32) -> bool {
+        version_number >= self.min_version
+            && self.max_version.is_none_or(|max| version_number <= max)
+    }
+}
+
+/// A version of a plugin installed on a guild, along with what it depends on
+#[derive(Clone, Debug)]
+pub struct InstalledPluginVersion {
+    pub plugin_id: u64,
+    pub version_number: u32,
+    pub dependencies: Vec<PluginDependency>,
+}
+
+/// Returns the dependency that would no longer be satisfied if `updated` was installed on a guild
+/// that has the `installed` plugins, either one of its own or one of the other plugins depending
+/// on it
+pub fn find_unsatisfied_dependency(
+    installed: &[InstalledPluginVersion],
+    updated: &InstalledPluginVersion,
+) -> Option<PluginDependency> {
+    let others = installed
+        .iter()
+        .filter(|v| v.plugin_id != updated.plugin_id)
+        .collect::<Vec<_>>();
+
+    let own = updated.dependencies.iter().find(|dep| {
+        !others
+            .iter()
+            .any(|v| v.plugin_id == dep.plugin_id && dep.is_satisfied_by(v.version_number))
+    });
+
+    let dependents = others.iter().flat_map(|v| &v.dependencies).find(|dep| {
+        dep.plugin_id == updated.plugin_id && !dep.is_satisfied_by(updated.version_number)
+    });
+
+    own.or(dependents).copied()
+}
+
+/// A change to the settings of a plugin, applied to the settings values of a guild's install when
+/// it's updated to the version declaring it, or past it
+///
+/// Migrations can end up applied more than once, e.g. when a stable version repeats the ones from
+/// the beta before it, so they're all no-ops when there's nothing left to migrate
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SettingsMigration {
+    /// Moves the value of an option to a new name, unless the guild already has a value there
+    Rename { from: String, to: String },
+    /// Sets the value of an option the guild has no value for
+    FillDefault {
+        name: String,
+        value: serde_json::Value,
+    },
+    /// Converts the value of an option to another type, values that can't be converted are
+    /// dropped so that the default is used
+    Convert {
+        name: String,
+        to: SettingsValueConversion,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingsValueConversion {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// Wraps a single value in a list, e.g. going from a channel option to a channels option
+    List,
+    /// Takes the first value of a list
+    Single,
+}
+
+impl SettingsValueConversion {
+    /// Converts the value, values that already have the target type are returned as is
+    pub fn convert(&self, value: &serde_json::Value) -> Result<serde_json::Value, String> {
+        use serde_json::Value;
+
+        let converted = match (self, value) {
+            (Self::String, Value::String(_)) => Some(value.clone()),
+            (Self::String, Value::Number(v)) => Some(Value::String(v.to_string())),
+            (Self::String, Value::Bool(v)) => Some(Value::String(v.to_string())),
+            (Self::Integer, Value::Number(v)) => v.as_i64().map(Value::from).or_else(|| {
+                v.as_f64()
+                    .filter(|v| v.is_finite())
+                    .map(|v| Value::from(v.trunc() as i64))
+            }),
+            (Self::Integer, Value::String(v)) => v.trim().parse::<i64>().ok().map(Value::from),
+            (Self::Integer, Value::Bool(v)) => Some(Value::from(*v as i64)),
+            (Self::Float, Value::Number(v)) => v.as_f64().map(Value::from),
+            (Self::Float, Value::String(v)) => v
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .map(Value::from),
+            (Self::Boolean, Value::Bool(_)) => Some(value.clone()),
+            (Self::Boolean, Value::Number(v)) => v.as_f64().map(|v| Value::Bool(v != 0.0)),
+            (Self::Boolean, Value::String(v)) => match v.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            (Self::List, Value::Array(_)) => Some(value.clone()),
+            (Self::List, Value::Null) => Some(Value::Array(Vec::new())),
+            (Self::List, _) => Some(Value::Array(vec![value.clone()])),
+            (Self::Single, Value::Array(v)) => v.first().cloned(),
+            (Self::Single, _) => Some(value.clone()),
+            _ => None,
+        };
+
+        converted.ok_or_else(|| format!("can't convert {value} to {}", self.as_str()))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Float => "float",
+            Self::Boolean => "boolean",
+            Self::List => "list",
+            Self::Single => "single value",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy)]
@@ -143,3 +504,135 @@ pub struct Image {
 //     LatestDevel,
 //     Pinned(VersionNumber),
 // }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        find_unsatisfied_dependency, InstalledPluginVersion, PluginCapabilities, PluginDependency,
+        SettingsValueConversion,
+    };
+
+    fn http_hosts(hosts: &[&str]) -> PluginCapabilities {
+        PluginCapabilities {
+            http_hosts: hosts.iter().map(|v| v.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_allows_http_host() {
+        let caps = http_hosts(&["api.example.com", "*.botloader.io"]);
+
+        assert!(caps.allows_http_host("api.example.com"));
+        assert!(caps.allows_http_host("API.example.com"));
+        assert!(!caps.allows_http_host("example.com"));
+        assert!(!caps.allows_http_host("evilapi.example.com"));
+
+        assert!(caps.allows_http_host("a.botloader.io"));
+        assert!(caps.allows_http_host("a.b.botloader.io"));
+        assert!(!caps.allows_http_host("botloader.io"));
+        assert!(!caps.allows_http_host("evilbotloader.io"));
+
+        assert!(http_hosts(&["*"]).allows_http_host("anything.com"));
+        assert!(!PluginCapabilities::default().allows_http_host("example.com"));
+    }
+
+    #[test]
+    fn test_covers() {
+        let granted = PluginCapabilities {
+            storage: true,
+            ..http_hosts(&["*.example.com"])
+        };
+
+        assert!(granted.covers(&PluginCapabilities::default()));
+        assert!(granted.covers(&http_hosts(&["api.example.com"])));
+        assert!(granted.covers(&PluginCapabilities {
+            storage: true,
+            ..Default::default()
+        }));
+
+        assert!(!granted.covers(&http_hosts(&["example.org"])));
+        assert!(!granted.covers(&PluginCapabilities {
+            moderation: true,
+            ..Default::default()
+        }));
+        assert!(PluginCapabilities::unrestricted().covers(&granted));
+    }
+
+    fn installed(
+        plugin_id: u64,
+        version_number: u32,
+        dependencies: &[PluginDependency],
+    ) -> InstalledPluginVersion {
+        InstalledPluginVersion {
+            plugin_id,
+            version_number,
+            dependencies: dependencies.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_find_unsatisfied_dependency() {
+        let needs_lib = PluginDependency {
+            plugin_id: 1,
+            min_version: 2,
+            max_version: Some(3),
+        };
+        let guild = [installed(1, 2, &[]), installed(2, 5, &[needs_lib])];
+
+        // updating the dependency within the range the dependent accepts
+        assert_eq!(
+            find_unsatisfied_dependency(&guild, &installed(1, 3, &[])),
+            None
+        );
+        // past it
+        assert_eq!(
+            find_unsatisfied_dependency(&guild, &installed(1, 4, &[])),
+            Some(needs_lib)
+        );
+
+        // the dependent itself moving to a version needing a newer dependency
+        let needs_newer_lib = PluginDependency {
+            min_version: 3,
+            ..needs_lib
+        };
+        assert_eq!(
+            find_unsatisfied_dependency(&guild, &installed(2, 6, &[needs_newer_lib])),
+            Some(needs_newer_lib)
+        );
+
+        // dependencies that aren't installed at all
+        let needs_missing = PluginDependency {
+            plugin_id: 3,
+            min_version: 1,
+            max_version: None,
+        };
+        assert_eq!(
+            find_unsatisfied_dependency(&guild, &installed(2, 6, &[needs_missing])),
+            Some(needs_missing)
+        );
+    }
+
+    #[test]
+    fn test_settings_value_conversion() {
+        use SettingsValueConversion::*;
+
+        assert_eq!(String.convert(&json!(5)), Ok(json!("5")));
+        assert_eq!(String.convert(&json!("a")), Ok(json!("a")));
+        assert_eq!(Integer.convert(&json!(" 12 ")), Ok(json!(12)));
+        assert_eq!(Integer.convert(&json!(2.7)), Ok(json!(2)));
+        assert_eq!(Float.convert(&json!("1.5")), Ok(json!(1.5)));
+        assert_eq!(Boolean.convert(&json!("false")), Ok(json!(false)));
+        assert_eq!(Boolean.convert(&json!(1)), Ok(json!(true)));
+        assert_eq!(List.convert(&json!("123")), Ok(json!(["123"])));
+        assert_eq!(List.convert(&json!(["123"])), Ok(json!(["123"])));
+        assert_eq!(Single.convert(&json!(["1", "2"])), Ok(json!("1")));
+
+        assert!(Integer.convert(&json!("abc")).is_err());
+        assert!(Boolean.convert(&json!("yes")).is_err());
+        assert!(Single.convert(&json!([])).is_err());
+        assert!(Float.convert(&json!(["1"])).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use std::{future::Future, task::Poll};
+use tokio::sync::watch;
 use tracing::info;
 #[cfg(target_os = "linux")]
 pub async fn wait_shutdown_signal() {
@@ -23,6 +24,43 @@ pub async fn wait_shutdown_signal() {
     Empty.await
 }
 
+/// Creates a pair used to put a service into drain mode, see [`DrainSignal`]
+pub fn drain_pair() -> (DrainTrigger, DrainSignal) {
+    let (tx, rx) = watch::channel(false);
+    (DrainTrigger { tx }, DrainSignal { rx })
+}
+
+/// Puts the service into drain mode, usually after receiving a shutdown signal
+pub struct DrainTrigger {
+    tx: watch::Sender<bool>,
+}
+
+impl DrainTrigger {
+    pub fn start_draining(&self) {
+        info!("draining before shutting down...");
+        self.tx.send_replace(true);
+    }
+}
+
+/// Lets the parts of a service taking in new work know when to stop doing so
+/// so that the work already in flight can be finished before shutting down
+#[derive(Clone)]
+pub struct DrainSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl DrainSignal {
+    pub fn is_draining(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once drain mode has been entered
+    pub async fn wait_draining(&mut self) {
+        // an error means the trigger was dropped, which only happens as the service is exiting
+        let _ = self.rx.wait_for(|draining| *draining).await;
+    }
+}
+
 /// A future which is never resolved.
 struct Empty;
 
@@ -12,15 +12,68 @@ use twilight_model::{
     voice::VoiceState,
 };
 
+/// Events are numbered per connection starting at 1,
+/// which lets the scheduler acknowledge several of them at once
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SequencedBrokerEvent {
+    pub seq: u64,
+    pub event: BrokerEvent,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum BrokerEvent {
     Hello(HelloData),
     DiscordEvent(DiscordEvent),
+    // sent when a scheduler joins or leaves, the guilds this scheduler owns changed
+    PartitionChanged(HelloData),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HelloData {
+    // the connected guilds owned by this scheduler
     pub connected_guilds: Vec<Id<GuildMarker>>,
+    // all the schedulers currently connected to the broker, including the receiver
+    pub schedulers: Vec<SchedulerIdentity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulerIdentity {
+    pub id: String,
+    // the address other schedulers can reach its botrpc server at
+    pub bot_rpc_addr: String,
+}
+
+/// Picks the scheduler that owns a guild using rendezvous hashing
+///
+/// Every guild is scored against every scheduler id and the highest score wins,
+/// that way only the guilds of a scheduler joining or leaving change owners.
+pub fn guild_owner<'a>(
+    guild_id: Id<GuildMarker>,
+    scheduler_ids: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    scheduler_ids
+        .into_iter()
+        .max_by_key(|id| (rendezvous_score(guild_id, id), *id))
+}
+
+// the std hasher is not guaranteed to be stable across rust versions
+// and the broker and schedulers need to agree on the owners, so use fnv-1a instead
+fn rendezvous_score(guild_id: Id<GuildMarker>, scheduler_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in guild_id
+        .get()
+        .to_le_bytes()
+        .iter()
+        .chain(scheduler_id.as_bytes())
+    {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    // fnv has poor avalanche on the last bytes, finish it off with the splitmix64 finalizer
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -81,5 +134,8 @@ pub enum DiscordEventData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum SchedulerEvent {
-    Ack,
+    /// The first message sent by a scheduler after connecting
+    Identify(SchedulerIdentity),
+    /// Acknowledges every event up to and including this sequence number
+    Ack(u64),
 }
@@ -1,19 +1,26 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{atomic::AtomicBool, Arc},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
-use dbrokerapi::broker_scheduler_rpc::{self, BrokerEvent, DiscordEventData, HelloData};
+use dbrokerapi::broker_scheduler_rpc::{
+    self, BrokerEvent, DiscordEventData, HelloData, SchedulerIdentity,
+};
+
+use crate::scheduler_conn::SchedulerConn;
 
 use stores::Db;
 use tokio::{
     net::TcpStream,
     sync::mpsc::{self, UnboundedSender},
+    task::JoinHandle,
 };
 use tracing::{error, info, instrument, warn};
 use twilight_cache_inmemory::InMemoryCache;
-use twilight_gateway::{Event, EventTypeFlags, Intents, Shard, ShardId, StreamExt};
+use twilight_gateway::{
+    queue::InMemoryQueue, ConfigBuilder, Event, EventTypeFlags, Intents, Shard, ShardId, StreamExt,
+};
 use twilight_model::{
     gateway::{event::DispatchEvent, payload::outgoing::RequestGuildMembers},
     guild::Member,
@@ -26,10 +33,12 @@ use twilight_model::{
 
 pub async fn run_broker(
     token: String,
+    shards: ShardRange,
+    event_queue_retention: Duration,
     discord_state: Arc<InMemoryCache>,
     db: Db,
     ready: Arc<AtomicBool>,
-) -> Result<BrokerHandle, Box<dyn std::error::Error>> {
+) -> Result<(BrokerHandle, JoinHandle<()>), Box<dyn std::error::Error>> {
     let intents = Intents::GUILD_MESSAGES
         | Intents::MESSAGE_CONTENT
         | Intents::GUILDS
@@ -39,50 +48,165 @@ pub async fn run_broker(
         | Intents::GUILD_VOICE_STATES
         | Intents::GUILD_MESSAGES
         | Intents::GUILD_MESSAGE_REACTIONS;
-    // let config = Config::new(token.clone(), intents);
 
-    // let (cluster, events) = Cluster::new(token, intents).await?;
+    let client = twilight_http::Client::new(token.clone());
+    let info = client.gateway().authed().await?.model().await?;
+
+    let total_shards = match shards.total {
+        Some(total) => total,
+        None => {
+            info!(shards = info.shards, "using recommended shard count");
+            info.shards
+        }
+    };
+
+    let shard_ids = shards.start..shards.end.unwrap_or(total_shards).min(total_shards);
+    if shard_ids.is_empty() {
+        return Err(format!(
+            "empty shard range {}..{:?} for {total_shards} total shards",
+            shards.start, shards.end
+        )
+        .into());
+    }
 
-    // let client = Client::new(token.clone());
-    // let shards = stream::create_recommended(&client, config, |_, builder| builder.build())
-    //     .await?
-    //     .collect::<Vec<_>>();
-    let shard = Shard::new(ShardId::new(0, 1), token.clone(), intents);
+    info!(?shard_ids, total_shards, "starting shards");
+
+    // brokers running different shards have their own queues,
+    // the name stays the same across restarts so events queued before one are not lost
+    let event_queue_name = format!(
+        "shards_{}_{}_{}",
+        shard_ids.start, shard_ids.end, total_shards
+    );
+
+    // all our shards share a queue so they stay within the max concurrency when identifying
+    let limit = info.session_start_limit;
+    let queue = InMemoryQueue::new(
+        limit.max_concurrency,
+        limit.remaining,
+        Duration::from_millis(limit.reset_after),
+        limit.total,
+    );
+    let config = ConfigBuilder::new(token, intents).queue(queue).build();
 
     let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+    let (shard_events_tx, shard_events_rx) = mpsc::unbounded_channel();
+
+    let mut shard_handles = HashMap::new();
+    for id in shard_ids {
+        let shard_id = ShardId::new(id, total_shards);
+        let shard = Shard::with_config(shard_id, config.clone());
+        let (shard_cmd_tx, shard_cmd_rx) = mpsc::unbounded_channel();
+
+        metrics::gauge!("bl.broker.shard_ready", "shard" => id.to_string()).set(0.0);
+        tokio::spawn(run_shard(shard, shard_events_tx.clone(), shard_cmd_rx));
+        shard_handles.insert(id, shard_cmd_tx);
+    }
 
     let mut discord_manager = Broker {
         discord_state,
         cmd_rx,
+        shard_events_rx,
         db,
         ready,
-        connected_scheduler: None,
-        queued_events: Vec::new(),
-        scheduler_disconnected_at: Instant::now(),
-        // gateway_message_senders: shards.iter().map(|v| v.sender()).collect(),
+        schedulers: BTreeMap::new(),
+        replayed_up_to: 0,
+        in_flight_queue_ids: HashSet::new(),
+        event_queue_name,
+        event_queue_retention,
+        event_queue_depth: 0,
         nonce_counter: 0,
         pending_guild_member_requests: Default::default(),
-        shard,
+        total_shards,
+        shard_handles,
+        ready_shards: HashSet::new(),
+        closed_shards: 0,
     };
 
-    tokio::spawn(async move { discord_manager.run().await });
+    let task = tokio::spawn(async move { discord_manager.run().await });
+
+    Ok((cmd_tx, task))
+}
 
-    Ok(cmd_tx)
+/// The shards a broker process runs, `start..end` out of `total`
+///
+/// If total is not set the count recommended by discord is used,
+/// and if end is not set the broker runs all shards from start.
+#[derive(Clone, Copy, Debug)]
+pub struct ShardRange {
+    pub total: Option<u32>,
+    pub start: u32,
+    pub end: Option<u32>,
 }
 
 pub type BrokerHandle = mpsc::UnboundedSender<BrokerCommand>;
 
+/// Drives a single shard, forwarding its events to the broker and sending commands to the gateway
+async fn run_shard(
+    mut shard: Shard,
+    events_tx: UnboundedSender<ShardMessage>,
+    mut cmd_rx: mpsc::UnboundedReceiver<RequestGuildMembers>,
+) {
+    let shard_id = shard.id();
+
+    loop {
+        tokio::select! {
+            evt = shard.next_event(EventTypeFlags::all()) => match evt {
+                Some(Ok(evt)) => {
+                    if events_tx.send(ShardMessage::Event(shard_id, evt)).is_err() {
+                        // broker is gone
+                        return;
+                    }
+                }
+                Some(Err(err)) => {
+                    error!(?err, shard = shard_id.number(), "failed receiving gateway message");
+                }
+                None => {
+                    // twilight reconnects on its own, this only happens on fatal close codes
+                    // like an invalid token or disallowed intents so restarting won't help
+                    error!(shard = shard_id.number(), "shard closed for good");
+                    let _ = events_tx.send(ShardMessage::Closed(shard_id));
+                    return;
+                }
+            },
+            cmd = cmd_rx.recv() => match cmd {
+                Some(cmd) => shard.command(&cmd),
+                None => return,
+            },
+        }
+    }
+}
+
+enum ShardMessage {
+    Event(ShardId, Event),
+    Closed(ShardId),
+}
+
 struct Broker {
     discord_state: Arc<InMemoryCache>,
     cmd_rx: mpsc::UnboundedReceiver<BrokerCommand>,
-
-    connected_scheduler: Option<TcpStream>,
-    queued_events: Vec<(Id<GuildMarker>, BrokerEvent)>,
-    scheduler_disconnected_at: Instant,
+    shard_events_rx: mpsc::UnboundedReceiver<ShardMessage>,
+
+    // connected schedulers by their id, guilds are partitioned between them
+    schedulers: BTreeMap<String, SchedulerConn>,
+    // the last queue entry sent to a scheduler
+    replayed_up_to: u64,
+    // queue entries sent to a scheduler but not acknowledged yet
+    in_flight_queue_ids: HashSet<u64>,
     db: Db,
     ready: Arc<AtomicBool>,
-    // gateway_message_senders: Vec<MessageSender>,
-    shard: Shard,
+
+    total_shards: u32,
+    // command senders for the shards run by this broker, keyed by shard number
+    shard_handles: HashMap<u32, UnboundedSender<RequestGuildMembers>>,
+    ready_shards: HashSet<u32>,
+    // shards that closed for good, these won't come back until the broker is restarted
+    closed_shards: usize,
+
+    // events that could not be delivered to the scheduler are stored in postgres
+    // and replayed in order when it connects again
+    event_queue_name: String,
+    event_queue_retention: Duration,
+    event_queue_depth: u64,
 
     nonce_counter: u64,
 
@@ -91,41 +215,73 @@ struct Broker {
 }
 
 impl Broker {
-    // pub async fn run(&mut self, mut shards: Vec<Shard>) {
     pub async fn run(&mut self) {
-        // let mut stream = ShardEventStream::new(shards.iter_mut());
+        let mut prune_queue_interval = tokio::time::interval(Duration::from_secs(30));
 
         loop {
             tokio::select! {
-                evt = self.shard.next_event(EventTypeFlags::all()) => match evt {
-                    Some(evt) => match evt{
-                        Ok(evt) => {
-                            self.handle_event(evt).await;
-                        },
-                        Err(err) => {
-                            error!(?err, "failed receiving gateway message");
-                            // if err.is_fatal(){
-                            //     error!(?err, "fatal error occurred");
-                            //     break;
-                            // }
-                        }
-                    },
-                    None => todo!(),
+                _ = prune_queue_interval.tick() => self.prune_event_queue().await,
+                (scheduler_id, ack) = recv_scheduler_ack(&mut self.schedulers) => match ack {
+                    Some(seq) => self.handle_scheduler_ack(&scheduler_id, seq).await,
+                    None => {
+                        error!(%scheduler_id, "scheduler disconnected");
+                        self.remove_scheduler(&scheduler_id).await;
+                        self.rebalance().await;
+                    }
+                },
+                evt = self.shard_events_rx.recv() => match evt {
+                    Some(ShardMessage::Event(shard_id, evt)) => {
+                        self.handle_event(shard_id, evt).await
+                    }
+                    Some(ShardMessage::Closed(shard_id)) => self.handle_shard_closed(shard_id),
+                    None => {
+                        error!("all shards closed, shutting down broker");
+                        break;
+                    }
                 },
                 cmd = self.cmd_rx.recv() => match cmd{
                     Some(cmd) => self.handle_cmd(cmd).await,
-                    None => todo!(),
+                    None => {
+                        info!("broker handle dropped, shutting down broker");
+                        break;
+                    }
                 },
             }
         }
+
+        self.shutdown().await;
+    }
+
+    /// Queues the events the schedulers haven't acknowledged so they're not lost
+    async fn shutdown(&mut self) {
+        self.ready.store(false, std::sync::atomic::Ordering::SeqCst);
+
+        let scheduler_ids = self.schedulers.keys().cloned().collect::<Vec<_>>();
+        for scheduler_id in scheduler_ids {
+            self.remove_scheduler(&scheduler_id).await;
+        }
+    }
+
+    fn handle_shard_closed(&mut self, shard_id: ShardId) {
+        metrics::gauge!("bl.broker.shard_ready", "shard" => shard_id.number().to_string()).set(0.0);
+
+        // the broker stays unready, the guilds on this shard won't receive any events
+        self.ready_shards.remove(&shard_id.number());
+        self.ready.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.shard_handles.remove(&shard_id.number());
+        self.closed_shards += 1;
     }
 
     async fn handle_cmd(&mut self, cmd: BrokerCommand) {
         match cmd {
-            BrokerCommand::SchedulerConnected(stream) => {
-                info!("scheduler connected");
-                self.connected_scheduler = Some(stream);
-                self.handle_new_scheduler_connected().await;
+            BrokerCommand::SchedulerConnected(identity, stream) => {
+                info!(scheduler_id = %identity.id, "scheduler connected");
+
+                // a scheduler reconnecting replaces its old connection
+                self.remove_scheduler(&identity.id).await;
+                self.schedulers
+                    .insert(identity.id.clone(), SchedulerConn::new(identity, stream));
+                self.rebalance().await;
             }
             BrokerCommand::RequestGuildMembers(req) => {
                 self.handle_request_guild_members(req).await;
@@ -133,19 +289,43 @@ impl Broker {
         }
     }
 
-    #[instrument(skip(self, evt))]
-    async fn handle_event(&mut self, evt: Event) {
+    fn mark_shard_ready(&mut self, shard_id: ShardId) {
+        metrics::gauge!("bl.broker.shard_ready", "shard" => shard_id.number().to_string()).set(1.0);
+        self.ready_shards.insert(shard_id.number());
+
+        // the broker as a whole is ready once all the shards it runs are
+        if self.closed_shards == 0 && self.ready_shards.len() >= self.shard_handles.len() {
+            self.ready.store(true, std::sync::atomic::Ordering::SeqCst);
+            info!(ready_shards = self.ready_shards.len(), "all shards ready!");
+        }
+    }
+
+    #[instrument(skip(self, evt), fields(shard = shard_id.number()))]
+    async fn handle_event(&mut self, shard_id: ShardId, evt: Event) {
         metrics::counter!("bl.broker.handled_events_total").increment(1);
 
+        let shard_label = shard_id.number().to_string();
+
         match &evt {
             Event::Ready(_) => {
-                self.ready.store(true, std::sync::atomic::Ordering::SeqCst);
-
-                metrics::gauge!("bl.broker.connected_guilds_total").set(0.0);
-                info!("received ready!");
+                metrics::gauge!("bl.broker.connected_guilds_total", "shard" => shard_label.clone())
+                    .set(0.0);
+                info!("received ready for shard");
+                self.mark_shard_ready(shard_id);
+            }
+            Event::Resumed => {
+                info!("shard resumed");
+                self.mark_shard_ready(shard_id);
+            }
+            Event::GatewayClose(frame) => {
+                metrics::gauge!("bl.broker.shard_ready", "shard" => shard_label.clone()).set(0.0);
+                self.ready_shards.remove(&shard_id.number());
+                self.ready.store(false, std::sync::atomic::Ordering::SeqCst);
+                warn!(?frame, "shard disconnected");
             }
             Event::GuildDelete(g) => {
-                metrics::gauge!("bl.broker.connected_guilds_total").decrement(1.0);
+                metrics::gauge!("bl.broker.connected_guilds_total", "shard" => shard_label.clone())
+                    .decrement(1.0);
 
                 if !g.unavailable {
                     let _ = self.db.set_guild_left_status(g.id, true).await;
@@ -172,7 +352,8 @@ impl Broker {
                     twilight_model::gateway::payload::incoming::GuildCreate::Unavailable(_) => {}
                 }
 
-                metrics::gauge!("bl.broker.connected_guilds_total").increment(1.0);
+                metrics::gauge!("bl.broker.connected_guilds_total", "shard" => shard_label.clone())
+                    .increment(1.0);
             }
             Event::MemberChunk(chunk) => {
                 let nonce = chunk.nonce.clone().unwrap_or_default();
@@ -193,11 +374,7 @@ impl Broker {
 
         if let Ok(dispatch) = DispatchEvent::try_from(evt.clone()) {
             if let Some(broker_event) = self.prepare_dispatch_event(dispatch) {
-                self.dispatch_or_queue_event(
-                    broker_event.guild_id,
-                    BrokerEvent::DiscordEvent(broker_event),
-                )
-                .await;
+                self.dispatch_or_queue_event(broker_event).await;
 
                 metrics::counter!("bl.broker.dispatched_events").increment(1);
             }
@@ -207,61 +384,315 @@ impl Broker {
         self.discord_state.update(&evt);
     }
 
-    async fn handle_new_scheduler_connected(&mut self) {
-        // send connected guilds
-        let guilds = self
-            .discord_state
-            .iter()
-            .guilds()
-            .map(|v| v.id())
+    /// Called when the set of connected schedulers changed,
+    /// lets every scheduler know which guilds it owns now and sends them the queued events
+    async fn rebalance(&mut self) {
+        metrics::gauge!("bl.broker.connected_schedulers").set(self.schedulers.len() as f64);
+
+        loop {
+            let mut failed = self.send_partitions().await;
+            if failed.is_empty() {
+                failed = self.replay_queued_events().await;
+            }
+
+            if failed.is_empty() {
+                return;
+            }
+
+            for scheduler_id in failed {
+                error!(%scheduler_id, "scheduler disconnected while rebalancing");
+                self.remove_scheduler(&scheduler_id).await;
+            }
+        }
+    }
+
+    /// Sends the queued events to their owners without touching the partition,
+    /// only rebalancing if a scheduler disconnects along the way
+    async fn flush_event_queue(&mut self) {
+        let failed = self.replay_queued_events().await;
+        if failed.is_empty() {
+            return;
+        }
+
+        for scheduler_id in failed {
+            error!(%scheduler_id, "scheduler disconnected while replaying queued events");
+            self.remove_scheduler(&scheduler_id).await;
+        }
+
+        self.rebalance().await;
+    }
+
+    /// Sends a hello to new schedulers and the updated partition to the existing ones,
+    /// returns the schedulers we failed to send to.
+    async fn send_partitions(&mut self) -> Vec<String> {
+        let members = self.schedulers.keys().cloned().collect::<Vec<_>>();
+        let identities = self
+            .schedulers
+            .values()
+            .map(|v| v.identity.clone())
             .collect::<Vec<_>>();
-        if self
-            .send_event(&broker_scheduler_rpc::BrokerEvent::Hello(HelloData {
-                connected_guilds: guilds,
-            }))
-            .await
-            .is_err()
-        {
-            self.connected_scheduler = None;
+
+        let mut owned_guilds: HashMap<&str, Vec<Id<GuildMarker>>> = HashMap::new();
+        for guild in self.discord_state.iter().guilds() {
+            if let Some(owner) =
+                broker_scheduler_rpc::guild_owner(guild.id(), members.iter().map(|v| v.as_str()))
+            {
+                owned_guilds.entry(owner).or_default().push(guild.id());
+            }
         }
 
-        // send pending events
+        let mut failed = Vec::new();
+        for (scheduler_id, conn) in &mut self.schedulers {
+            if conn.partition_members.as_ref() == Some(&members) {
+                continue;
+            }
 
-        let old_queued = std::mem::take(&mut self.queued_events);
-        for (guild_id, evt) in old_queued.into_iter() {
-            if self.connected_scheduler.is_some() {
-                // let prepared = self.prepare_dispatch_event(guild_id, &evt);
-                // let v = serde_json::to_value(&evt).unwrap();
+            let data = HelloData {
+                connected_guilds: owned_guilds
+                    .remove(scheduler_id.as_str())
+                    .unwrap_or_default(),
+                schedulers: identities.clone(),
+            };
+
+            info!(
+                %scheduler_id,
+                guilds = data.connected_guilds.len(),
+                "sending partition"
+            );
+
+            let evt = if conn.partition_members.is_none() {
+                BrokerEvent::Hello(data)
+            } else {
+                BrokerEvent::PartitionChanged(data)
+            };
 
-                if self.send_event(&evt).await.is_err() {
-                    // connection dead, re-queue
-                    self.queued_events.push((guild_id, evt));
-                    self.connected_scheduler = None;
-                    self.scheduler_disconnected_at = Instant::now();
-                }
+            if conn.send(evt, None).await.is_err() {
+                failed.push(scheduler_id.clone());
             } else {
-                self.queued_events.push((guild_id, evt))
+                conn.partition_members = Some(members.clone());
+            }
+        }
+
+        failed
+    }
+
+    async fn handle_scheduler_ack(&mut self, scheduler_id: &str, seq: u64) {
+        if let Some(conn) = self.schedulers.get_mut(scheduler_id) {
+            conn.handle_ack(seq);
+        }
+
+        self.remove_delivered_queued_events().await;
+
+        // events queued while the window was full can go out now
+        if self.event_queue_depth > self.in_flight_queue_ids.len() as u64 {
+            self.flush_event_queue().await;
+        }
+    }
+
+    /// Queues the events that were sent but never acknowledged so they're delivered again
+    ///
+    /// The caller is responsible for rebalancing afterwards.
+    async fn remove_scheduler(&mut self, scheduler_id: &str) {
+        let Some(conn) = self.schedulers.remove(scheduler_id) else {
+            return;
+        };
+
+        let unacked = conn.into_unacked();
+        if !unacked.live.is_empty() {
+            warn!(
+                %scheduler_id,
+                count = unacked.live.len(),
+                "queueing unacknowledged events"
+            );
+        }
+
+        // queue entries sent to this scheduler have to be sent again
+        for queue_id in unacked.queue_ids {
+            self.in_flight_queue_ids.remove(&queue_id);
+            self.replayed_up_to = self.replayed_up_to.min(queue_id.saturating_sub(1));
+        }
+
+        for evt in unacked.live {
+            self.queue_event(evt).await;
+        }
+    }
+
+    /// Sends the queued events to the owning schedulers in the order they were queued,
+    /// they're removed from the queue once acknowledged.
+    ///
+    /// Returns the schedulers we failed to send to.
+    async fn replay_queued_events(&mut self) -> Vec<String> {
+        if self.schedulers.is_empty() {
+            return Vec::new();
+        }
+
+        loop {
+            let batch = match self
+                .db
+                .get_queued_broker_events(&self.event_queue_name, self.replayed_up_to, 100)
+                .await
+            {
+                Ok(batch) => batch,
+                Err(err) => {
+                    error!(%err, "failed fetching queued events");
+                    return Vec::new();
+                }
+            };
+
+            if batch.is_empty() {
+                return Vec::new();
+            }
+
+            let mut dropped = Vec::new();
+            for queued in batch {
+                self.replayed_up_to = queued.id;
+                if self.in_flight_queue_ids.contains(&queued.id) {
+                    continue;
+                }
+
+                let evt: broker_scheduler_rpc::DiscordEvent = match serde_json::from_value(
+                    queued.event,
+                ) {
+                    Ok(evt) => evt,
+                    Err(err) => {
+                        error!(%err, id = queued.id, "failed decoding queued event, dropping it");
+                        metrics::counter!("bl.broker.event_queue_dropped_total", "reason" => "invalid")
+                                .increment(1);
+                        dropped.push(queued.id);
+                        continue;
+                    }
+                };
+
+                if is_expired_interaction(&evt) {
+                    metrics::counter!(
+                        "bl.broker.event_queue_dropped_total",
+                        "reason" => "interaction_expired"
+                    )
+                    .increment(1);
+                    dropped.push(queued.id);
+                    continue;
+                }
+
+                let Some(owner) = self.guild_owner(evt.guild_id) else {
+                    return Vec::new();
+                };
+
+                // picked up again once the scheduler acknowledges some of its events
+                if !self.has_room(&owner) {
+                    self.replayed_up_to = queued.id.saturating_sub(1);
+                    self.remove_queued_events(&dropped).await;
+                    return Vec::new();
+                }
+
+                self.in_flight_queue_ids.insert(queued.id);
+                if self
+                    .send_event(&owner, BrokerEvent::DiscordEvent(evt), Some(queued.id))
+                    .await
+                    .is_err()
+                {
+                    self.remove_queued_events(&dropped).await;
+                    return vec![owner];
+                }
+
+                metrics::counter!("bl.broker.event_queue_replayed_total").increment(1);
+            }
+
+            self.remove_queued_events(&dropped).await;
+        }
+    }
+
+    async fn remove_delivered_queued_events(&mut self) {
+        let mut acked = Vec::new();
+        for conn in self.schedulers.values_mut() {
+            acked.extend(conn.take_acked_queue_ids());
+        }
+
+        for id in &acked {
+            self.in_flight_queue_ids.remove(id);
+        }
+
+        self.remove_queued_events(&acked).await;
+    }
+
+    async fn remove_queued_events(&mut self, ids: &[u64]) {
+        if ids.is_empty() {
+            return;
+        }
+
+        match self
+            .db
+            .del_queued_broker_events(&self.event_queue_name, ids)
+            .await
+        {
+            Ok(deleted) => {
+                self.set_event_queue_depth(self.event_queue_depth.saturating_sub(deleted))
+            }
+            // not fatal, but the events will be delivered again on the next connection
+            Err(err) => error!(%err, "failed removing events from the queue"),
+        }
+    }
+
+    async fn prune_event_queue(&mut self) {
+        let older_than = chrono::Utc::now()
+            - chrono::Duration::from_std(self.event_queue_retention).unwrap_or_default();
+
+        match self
+            .db
+            .del_expired_queued_broker_events(&self.event_queue_name, older_than)
+            .await
+        {
+            Ok(0) => {}
+            Ok(deleted) => {
+                warn!(deleted, "dropped expired events from the queue");
+                metrics::counter!("bl.broker.event_queue_dropped_total", "reason" => "expired")
+                    .increment(deleted);
             }
+            Err(err) => error!(%err, "failed pruning event queue"),
+        }
+
+        match self
+            .db
+            .get_queued_broker_event_count(&self.event_queue_name)
+            .await
+        {
+            Ok(count) => self.set_event_queue_depth(count),
+            Err(err) => error!(%err, "failed fetching event queue depth"),
         }
     }
 
+    fn set_event_queue_depth(&mut self, depth: u64) {
+        self.event_queue_depth = depth;
+        metrics::gauge!("bl.broker.event_queue_depth").set(depth as f64);
+    }
+
     async fn handle_request_guild_members(&mut self, req: GuildMembersRequest) {
-        // let destination_shard =
-        //     (req.guild_id.get() >> 22) % self.gateway_message_senders.len() as u64;
+        let destination_shard = shard_for_guild(req.guild_id, self.total_shards);
+        let Some(shard) = self.shard_handles.get(&destination_shard) else {
+            // dropping the response sender lets the requester know the request failed
+            warn!(
+                guild_id = req.guild_id.get(),
+                destination_shard, "guild members requested for a shard not run by this broker"
+            );
+            return;
+        };
 
         let nonce = self.next_nonce();
 
-        // let sender = self
-        //     .gateway_message_senders
-        //     .get(destination_shard as usize)
-        //     .unwrap();
-
-        self.shard.command(
-            &RequestGuildMembers::builder(req.guild_id)
-                .nonce(nonce.to_string())
-                .user_ids(req.user_ids)
-                .unwrap(),
-        );
+        if shard
+            .send(
+                RequestGuildMembers::builder(req.guild_id)
+                    .nonce(nonce.to_string())
+                    .user_ids(req.user_ids)
+                    .unwrap(),
+            )
+            .is_err()
+        {
+            error!(
+                destination_shard,
+                "shard task is gone, dropping guild members request"
+            );
+            return;
+        }
 
         self.pending_guild_member_requests.insert(
             nonce.to_string(),
@@ -272,28 +703,68 @@ impl Broker {
         );
     }
 
-    async fn dispatch_or_queue_event(
-        &mut self,
-        guild_id: Id<GuildMarker>,
-        evt: broker_scheduler_rpc::BrokerEvent,
-    ) {
-        if self.connected_scheduler.is_some() {
-            info!("dispatching event");
+    async fn dispatch_or_queue_event(&mut self, evt: broker_scheduler_rpc::DiscordEvent) {
+        // its unacknowledged events have to be queued before this one to keep the order
+        if let Some(owner) = self.guild_owner(evt.guild_id) {
+            if self.is_stalled(&owner) {
+                error!(
+                    scheduler_id = %owner,
+                    "Scheduler stopped acknowledging events, queueing its events"
+                );
+                self.remove_scheduler(&owner).await;
+                self.rebalance().await;
+            }
+        }
 
-            // let prepared = self.prepare_dispatch_event(evt);
-            if self.send_event(&evt).await.is_err() {
-                self.connected_scheduler = None;
-                self.scheduler_disconnected_at = Instant::now();
-                self.queue_event(guild_id, evt);
+        let owner = self.guild_owner(evt.guild_id);
+
+        match owner {
+            Some(owner) if self.event_queue_depth == 0 && self.has_room(&owner) => {
+                info!("dispatching event");
+
+                if self
+                    .send_event(&owner, BrokerEvent::DiscordEvent(evt), None)
+                    .await
+                    .is_err()
+                {
+                    // the event is queued along with the other unacknowledged ones
+                    error!(
+                        scheduler_id = %owner,
+                        "Scheduler disconnected, queueing its events"
+                    );
+                    self.remove_scheduler(&owner).await;
+                    self.rebalance().await;
+                }
+            }
+            _ => {
+                info!("queued event");
+                self.queue_event(evt).await;
 
-                error!("Scheduler disconnected, started queueing events");
+                // events left over from a failed replay need to go out first to keep the order
+                if owner.is_some() {
+                    self.flush_event_queue().await;
+                }
             }
-        } else {
-            info!("queued event");
-            self.queue_event(guild_id, evt);
         }
     }
 
+    fn guild_owner(&self, guild_id: Id<GuildMarker>) -> Option<String> {
+        broker_scheduler_rpc::guild_owner(guild_id, self.schedulers.keys().map(|v| v.as_str()))
+            .map(ToString::to_string)
+    }
+
+    fn has_room(&self, scheduler_id: &str) -> bool {
+        self.schedulers
+            .get(scheduler_id)
+            .is_some_and(|conn| conn.has_room())
+    }
+
+    fn is_stalled(&self, scheduler_id: &str) -> bool {
+        self.schedulers
+            .get(scheduler_id)
+            .is_some_and(|conn| conn.is_stalled())
+    }
+
     fn prepare_dispatch_event(
         &mut self,
         evt: DispatchEvent,
@@ -396,33 +867,43 @@ impl Broker {
         })
     }
 
-    fn queue_event(&mut self, guild_id: Id<GuildMarker>, evt: BrokerEvent) {
-        if Instant::elapsed(&self.scheduler_disconnected_at) > Duration::from_secs(60) {
-            warn!("event queue too old, expired, clearing");
-            self.queued_events = Vec::new();
-            return;
-        }
-
-        self.queued_events.push((guild_id, evt));
-    }
+    async fn queue_event(&mut self, evt: broker_scheduler_rpc::DiscordEvent) {
+        let guild_id = evt.guild_id;
+        let serialized = match serde_json::to_value(&evt) {
+            Ok(v) => v,
+            Err(err) => {
+                error!(%err, "failed serializing event for the queue");
+                metrics::counter!("bl.broker.event_queue_dropped_total", "reason" => "invalid")
+                    .increment(1);
+                return;
+            }
+        };
 
-    async fn send_event(&mut self, evt: &broker_scheduler_rpc::BrokerEvent) -> std::io::Result<()> {
-        if let Some(connected) = &mut self.connected_scheduler {
-            simpleproto::write_message(&evt, connected).await?;
-            self.wait_for_ack().await?;
+        match self
+            .db
+            .push_queued_broker_event(&self.event_queue_name, guild_id, serialized)
+            .await
+        {
+            Ok(_) => self.set_event_queue_depth(self.event_queue_depth + 1),
+            Err(err) => {
+                error!(%err, "failed queueing event");
+                metrics::counter!("bl.broker.event_queue_dropped_total", "reason" => "db_error")
+                    .increment(1);
+            }
         }
-
-        Ok(())
     }
 
-    #[instrument(skip(self))]
-    async fn wait_for_ack(&mut self) -> std::io::Result<()> {
-        if let Some(connected) = &mut self.connected_scheduler {
-            let _msg: broker_scheduler_rpc::SchedulerEvent =
-                simpleproto::read_message(connected).await?;
-        }
+    async fn send_event(
+        &mut self,
+        scheduler_id: &str,
+        evt: broker_scheduler_rpc::BrokerEvent,
+        queue_id: Option<u64>,
+    ) -> std::io::Result<()> {
+        let Some(conn) = self.schedulers.get_mut(scheduler_id) else {
+            return Err(std::io::ErrorKind::NotConnected.into());
+        };
 
-        Ok(())
+        conn.send(evt, queue_id).await
     }
 
     fn next_nonce(&mut self) -> u64 {
@@ -431,8 +912,37 @@ impl Broker {
     }
 }
 
+/// Waits for the next ack from any of the schedulers, ack is none if the connection was closed
+async fn recv_scheduler_ack(
+    schedulers: &mut BTreeMap<String, SchedulerConn>,
+) -> (String, Option<u64>) {
+    if schedulers.is_empty() {
+        return std::future::pending().await;
+    }
+
+    let futures = schedulers.iter_mut().map(|(id, conn)| {
+        Box::pin(async move {
+            let ack = conn.recv_ack().await;
+            (id.clone(), ack)
+        })
+    });
+
+    futures_util::future::select_all(futures).await.0
+}
+
+/// Interactions have to be responded to within 3 seconds, there's no point in delivering them after that
+fn is_expired_interaction(evt: &broker_scheduler_rpc::DiscordEvent) -> bool {
+    matches!(evt.event, DiscordEventData::InteractionCreate(_))
+        && chrono::Utc::now() - evt.timestamp > chrono::Duration::seconds(3)
+}
+
+/// The shard a guild is on, as per discord's sharding formula
+fn shard_for_guild(guild_id: Id<GuildMarker>, total_shards: u32) -> u32 {
+    ((guild_id.get() >> 22) % total_shards as u64) as u32
+}
+
 pub enum BrokerCommand {
-    SchedulerConnected(TcpStream),
+    SchedulerConnected(SchedulerIdentity, TcpStream),
     RequestGuildMembers(GuildMembersRequest),
 }
 
@@ -1,4 +1,8 @@
-use tokio::net::TcpListener;
+use std::time::Duration;
+
+use dbrokerapi::broker_scheduler_rpc::{SchedulerEvent, SchedulerIdentity};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
 
 use crate::broker::{BrokerCommand, BrokerHandle};
 
@@ -8,11 +12,40 @@ pub async fn start_server(addr: String, broker: BrokerHandle) {
     loop {
         let (socket, _) = listener.accept().await.unwrap();
         socket.set_nodelay(true).unwrap();
-        if broker
-            .send(BrokerCommand::SchedulerConnected(socket))
-            .is_err()
-        {
-            return;
+
+        let broker = broker.clone();
+        tokio::spawn(async move {
+            let mut socket = socket;
+            if let Some(identity) = read_identify(&mut socket).await {
+                let _ = broker.send(BrokerCommand::SchedulerConnected(identity, socket));
+            }
+        });
+    }
+}
+
+async fn read_identify(socket: &mut TcpStream) -> Option<SchedulerIdentity> {
+    let msg = tokio::time::timeout(
+        Duration::from_secs(10),
+        simpleproto::read_message::<SchedulerEvent>(socket),
+    )
+    .await;
+
+    match msg {
+        Ok(Ok(SchedulerEvent::Identify(identity))) => Some(identity),
+        Ok(Ok(other)) => {
+            warn!(
+                ?other,
+                "scheduler sent unexpected message before identifying"
+            );
+            None
+        }
+        Ok(Err(err)) => {
+            warn!(%err, "failed reading scheduler identity");
+            None
+        }
+        Err(_) => {
+            warn!("timed out waiting for scheduler to identify");
+            None
         }
     }
 }
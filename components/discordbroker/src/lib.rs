@@ -1,14 +1,21 @@
-use std::sync::{atomic::AtomicBool, Arc};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 
 use stores::Db;
-use tracing::info;
+use tracing::{error, info};
 use twilight_cache_inmemory::InMemoryCacheBuilder;
 
-use crate::{broker::run_broker, http_api::run_http_server};
+use crate::{
+    broker::{run_broker, ShardRange},
+    http_api::run_http_server,
+};
 
 mod broker;
 mod dispatch_server;
 mod http_api;
+mod scheduler_conn;
 
 pub async fn run(
     common_conf: common::config::RunConfig,
@@ -30,8 +37,14 @@ pub async fn run(
     let postgres_store = Db::new_with_url(&common_conf.database_url).await.unwrap();
 
     let ready = Arc::new(AtomicBool::new(false));
-    let handle = run_broker(
+    let (handle, broker_task) = run_broker(
         common_conf.discord_token.clone(),
+        ShardRange {
+            total: config.total_shards,
+            start: config.shard_range_start,
+            end: config.shard_range_end,
+        },
+        Duration::from_secs(config.event_queue_retention_secs),
         discord_state.clone(),
         postgres_store,
         ready.clone(),
@@ -44,7 +57,12 @@ pub async fn run(
         handle.clone(),
     ));
 
-    run_http_server(config, discord_state, ready.clone(), handle).await;
+    tokio::select! {
+        _ = run_http_server(config, discord_state, ready.clone(), handle) => {},
+        _ = broker_task => {
+            error!("broker stopped, shutting down");
+        },
+    }
 
     // Ok(())
 }
@@ -105,4 +123,24 @@ pub struct BrokerConfig {
         default_value = "127.0.0.1:7449"
     )]
     pub(crate) http_api_listen_addr: String,
+
+    /// Total number of shards across all brokers, uses the count recommended by discord if not set
+    #[clap(long, env = "BL_BROKER_TOTAL_SHARDS")]
+    pub(crate) total_shards: Option<u32>,
+
+    /// First shard run by this broker (inclusive)
+    #[clap(long, env = "BL_BROKER_SHARD_RANGE_START", default_value = "0")]
+    pub(crate) shard_range_start: u32,
+
+    /// Last shard run by this broker (exclusive), runs up to the total if not set
+    #[clap(long, env = "BL_BROKER_SHARD_RANGE_END")]
+    pub(crate) shard_range_end: Option<u32>,
+
+    /// How long events are kept around for the scheduler while it's disconnected
+    #[clap(
+        long,
+        env = "BL_BROKER_EVENT_QUEUE_RETENTION_SECS",
+        default_value = "300"
+    )]
+    pub(crate) event_queue_retention_secs: u64,
 }
@@ -0,0 +1,198 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use dbrokerapi::broker_scheduler_rpc::{
+    BrokerEvent, DiscordEvent, SchedulerEvent, SchedulerIdentity, SequencedBrokerEvent,
+};
+use tokio::{
+    net::{tcp::OwnedWriteHalf, TcpStream},
+    sync::mpsc,
+};
+use tracing::{info, warn};
+
+// max number of events sent to the scheduler that it has not acknowledged yet
+const MAX_IN_FLIGHT_EVENTS: usize = 512;
+
+// how long the window can stay full without any acks before we give up on the connection
+const ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection to a scheduler
+///
+/// Events are streamed without waiting for each one to be acknowledged,
+/// the scheduler acknowledges them cumulatively by their sequence number.
+/// Once the in-flight window is full the broker queues events instead of sending them.
+/// Events that were not acknowledged by the time the connection is lost
+/// are handed back so they can be delivered again.
+pub(crate) struct SchedulerConn {
+    pub identity: SchedulerIdentity,
+    // the scheduler ids this scheduler was last told about, none if it has not received a hello yet
+    pub partition_members: Option<Vec<String>>,
+
+    writer: OwnedWriteHalf,
+    acks_rx: mpsc::UnboundedReceiver<u64>,
+
+    last_seq: u64,
+    in_flight: VecDeque<InFlightEvent>,
+    // when the window last filled up, reset whenever an ack frees up room
+    window_full_since: Option<Instant>,
+
+    // queue entries that have been acknowledged and can be removed from the queue
+    acked_queue_ids: Vec<u64>,
+}
+
+struct InFlightEvent {
+    seq: u64,
+    source: InFlightSource,
+}
+
+enum InFlightSource {
+    Partition,
+    // sent directly, needs to be queued if it's not acknowledged
+    Live(DiscordEvent),
+    // sent from the queue, stays in there until acknowledged
+    Queued(u64),
+}
+
+impl SchedulerConn {
+    pub fn new(identity: SchedulerIdentity, stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        let (acks_tx, acks_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut reader = reader;
+            loop {
+                match simpleproto::read_message(&mut reader).await {
+                    Ok(SchedulerEvent::Ack(seq)) => {
+                        if acks_tx.send(seq).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(SchedulerEvent::Identify(_)) => {
+                        warn!("scheduler sent a second identify, ignoring it");
+                    }
+                    Err(err) => {
+                        info!(%err, "scheduler connection reader closed");
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self {
+            identity,
+            partition_members: None,
+            writer,
+            acks_rx,
+            last_seq: 0,
+            in_flight: VecDeque::new(),
+            window_full_since: None,
+            acked_queue_ids: Vec::new(),
+        }
+    }
+
+    pub fn has_room(&self) -> bool {
+        self.in_flight.len() < MAX_IN_FLIGHT_EVENTS
+    }
+
+    /// Whether the window has been full for longer than [`ACK_TIMEOUT`] without any acks
+    pub fn is_stalled(&self) -> bool {
+        self.window_full_since
+            .is_some_and(|since| since.elapsed() > ACK_TIMEOUT)
+    }
+
+    /// Sends the event without waiting for acks, even if the in-flight window is full
+    ///
+    /// Callers should check [`Self::has_room`] first for events that can be queued.
+    /// The event is tracked as in-flight even if the write fails,
+    /// as we don't know how much of it made it through.
+    pub async fn send(&mut self, event: BrokerEvent, queue_id: Option<u64>) -> std::io::Result<()> {
+        self.last_seq += 1;
+        let msg = SequencedBrokerEvent {
+            seq: self.last_seq,
+            event,
+        };
+
+        let res = simpleproto::write_message(&msg, &mut self.writer).await;
+
+        let source = match (queue_id, msg.event) {
+            (Some(queue_id), _) => InFlightSource::Queued(queue_id),
+            (None, BrokerEvent::DiscordEvent(evt)) => InFlightSource::Live(evt),
+            (None, BrokerEvent::Hello(_) | BrokerEvent::PartitionChanged(_)) => {
+                InFlightSource::Partition
+            }
+        };
+        self.in_flight.push_back(InFlightEvent {
+            seq: msg.seq,
+            source,
+        });
+        if !self.has_room() && self.window_full_since.is_none() {
+            self.window_full_since = Some(Instant::now());
+        }
+        metrics::gauge!("bl.broker.scheduler_in_flight_events", "scheduler" => self.identity.id.clone())
+            .set(self.in_flight.len() as f64);
+
+        res
+    }
+
+    /// Waits for the next ack, returns None if the connection was closed
+    pub async fn recv_ack(&mut self) -> Option<u64> {
+        self.acks_rx.recv().await
+    }
+
+    pub fn handle_ack(&mut self, seq: u64) {
+        while let Some(front) = self.in_flight.front() {
+            if front.seq > seq {
+                break;
+            }
+
+            if let Some(InFlightEvent {
+                source: InFlightSource::Queued(queue_id),
+                ..
+            }) = self.in_flight.pop_front()
+            {
+                self.acked_queue_ids.push(queue_id);
+            }
+        }
+
+        if self.has_room() {
+            self.window_full_since = None;
+        }
+
+        metrics::gauge!("bl.broker.scheduler_in_flight_events", "scheduler" => self.identity.id.clone())
+            .set(self.in_flight.len() as f64);
+    }
+
+    pub fn take_acked_queue_ids(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.acked_queue_ids)
+    }
+
+    /// Returns the events that were never acknowledged, in the order they were sent
+    pub fn into_unacked(self) -> UnackedEvents {
+        metrics::gauge!("bl.broker.scheduler_in_flight_events", "scheduler" => self.identity.id.clone())
+            .set(0.0);
+
+        let mut unacked = UnackedEvents {
+            live: Vec::new(),
+            queue_ids: Vec::new(),
+        };
+
+        for evt in self.in_flight {
+            match evt.source {
+                InFlightSource::Live(evt) => unacked.live.push(evt),
+                InFlightSource::Queued(queue_id) => unacked.queue_ids.push(queue_id),
+                InFlightSource::Partition => {}
+            }
+        }
+
+        unacked
+    }
+}
+
+pub(crate) struct UnackedEvents {
+    // events sent directly, these need to be queued
+    pub live: Vec<DiscordEvent>,
+    // events sent from the queue, these are still in the queue
+    pub queue_ids: Vec<u64>,
+}
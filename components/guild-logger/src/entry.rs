@@ -9,6 +9,8 @@ pub struct LogEntry {
     pub message: String,
     pub script_context: Option<ScriptContext>,
     pub level: LogLevel,
+    #[serde(default)]
+    pub stack: Vec<StackFrame>,
 }
 
 impl LogEntry {
@@ -17,6 +19,7 @@ impl LogEntry {
             guild_id,
             message: msg,
             level: LogLevel::Critical,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -26,6 +29,7 @@ impl LogEntry {
             guild_id,
             message: msg,
             level: LogLevel::Error,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -35,6 +39,7 @@ impl LogEntry {
             guild_id,
             message: msg,
             level: LogLevel::Info,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -50,6 +55,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Error,
+            stack: Vec::new(),
         }
     }
     pub fn script_warning(
@@ -63,6 +69,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Warn,
+            stack: Vec::new(),
         }
     }
     pub fn script_console(
@@ -76,6 +83,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::ConsoleLog,
+            stack: Vec::new(),
         }
     }
     pub fn script_info(
@@ -89,6 +97,7 @@ impl LogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Info,
+            stack: Vec::new(),
         }
     }
 }
@@ -97,13 +106,20 @@ pub struct CreateLogEntry {
     pub message: String,
     pub script_context: Option<ScriptContext>,
     pub level: LogLevel,
+    pub stack: Vec<StackFrame>,
 }
 
 impl CreateLogEntry {
+    pub fn with_stack(mut self, stack: Vec<StackFrame>) -> Self {
+        self.stack = stack;
+        self
+    }
+
     pub fn critical(msg: String) -> Self {
         Self {
             message: msg,
             level: LogLevel::Critical,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -112,6 +128,7 @@ impl CreateLogEntry {
         Self {
             message: msg,
             level: LogLevel::Error,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -120,6 +137,7 @@ impl CreateLogEntry {
         Self {
             message: msg,
             level: LogLevel::Info,
+            stack: Vec::new(),
             script_context: None,
         }
     }
@@ -129,6 +147,7 @@ impl CreateLogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Error,
+            stack: Vec::new(),
         }
     }
     pub fn script_warning(msg: String, filename: String, line_col: Option<LineCol>) -> Self {
@@ -136,6 +155,7 @@ impl CreateLogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Warn,
+            stack: Vec::new(),
         }
     }
     pub fn script_console(msg: String, filename: String, line_col: Option<LineCol>) -> Self {
@@ -143,6 +163,7 @@ impl CreateLogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::ConsoleLog,
+            stack: Vec::new(),
         }
     }
     pub fn script_info(msg: String, filename: String, line_col: Option<LineCol>) -> Self {
@@ -150,6 +171,7 @@ impl CreateLogEntry {
             script_context: Some(ScriptContext { filename, line_col }),
             message: msg,
             level: LogLevel::Info,
+            stack: Vec::new(),
         }
     }
 }
@@ -173,6 +195,35 @@ impl Display for ScriptContext {
     }
 }
 
+/// A single frame in the stack trace of a script error
+///
+/// Positions are mapped back to the original typescript source where possible
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StackFrame {
+    pub filename: String,
+    pub line_col: Option<LineCol>,
+    pub function_name: Option<String>,
+}
+
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(function_name) = &self.function_name {
+            write!(f, "{function_name} (")?;
+        }
+
+        write!(f, "{}", self.filename)?;
+        if let Some((line, col)) = self.line_col {
+            write!(f, ":{line}:{col}")?;
+        }
+
+        if self.function_name.is_some() {
+            write!(f, ")")?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub enum LogLevel {
     Critical,
@@ -8,8 +8,10 @@ use tokio::sync::{
 pub mod discord_backend;
 pub mod entry;
 pub mod guild_subscriber_backend;
+pub mod rollout_backend;
+pub mod webhook_backend;
 
-pub use entry::{LogEntry, LogLevel, ScriptContext};
+pub use entry::{LogEntry, LogLevel, ScriptContext, StackFrame};
 use twilight_model::id::{marker::GuildMarker, Id};
 
 #[async_trait::async_trait]
@@ -135,6 +137,7 @@ impl GuildLogSender {
             level: entry.level,
             message: entry.message,
             script_context: entry.script_context,
+            stack: entry.stack,
         }));
     }
 
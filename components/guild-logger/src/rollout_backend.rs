@@ -0,0 +1,132 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use crate::{LogEntry, LogLevel};
+use stores::Db;
+use tokio::sync::mpsc;
+use tracing::error;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+const QUEUE_SIZE: usize = 1_000;
+
+const ROLLOUTS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+// errors from the same plugin in the same guild are only recorded once per window,
+// the rollout job only cares about how many guilds are affected anyway
+const RECORD_WINDOW: Duration = Duration::from_secs(60);
+
+/// Records the errors logged by plugin versions that are being rolled out, so that the rollout can
+/// be paused if the new version is broken
+pub struct PluginRolloutLogger {
+    tx: mpsc::Sender<(Id<GuildMarker>, u64)>,
+}
+
+impl PluginRolloutLogger {
+    pub fn new(db: Db) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+
+        let worker = RolloutWorker {
+            db,
+            rx,
+            rollouts: HashSet::new(),
+            rollouts_fetched_at: None,
+            recorded: HashMap::new(),
+        };
+
+        tokio::spawn(worker.run());
+
+        Self { tx }
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::GuildLoggerBackend for PluginRolloutLogger {
+    async fn handle_entry(&self, entry: LogEntry) {
+        if !matches!(entry.level, LogLevel::Error | LogLevel::Critical) {
+            return;
+        }
+
+        let Some(plugin_id) = entry
+            .script_context
+            .as_ref()
+            .and_then(|ctx| plugin_id_from_filename(&ctx.filename))
+        else {
+            return;
+        };
+
+        if self.tx.try_send((entry.guild_id, plugin_id)).is_err() {
+            metrics::counter!("bl.guild_logger.rollout_dropped_entries_total").increment(1);
+        }
+    }
+}
+
+/// Plugin scripts are loaded as `file:///plugins/{plugin_id}/{name}.ts`
+fn plugin_id_from_filename(filename: &str) -> Option<u64> {
+    let rest = filename.strip_prefix("file:///plugins/")?;
+    let (id, _) = rest.split_once('/')?;
+    id.parse().ok()
+}
+
+struct RolloutWorker {
+    db: Db,
+    rx: mpsc::Receiver<(Id<GuildMarker>, u64)>,
+    rollouts: HashSet<u64>,
+    rollouts_fetched_at: Option<Instant>,
+    recorded: HashMap<(Id<GuildMarker>, u64), Instant>,
+}
+
+impl RolloutWorker {
+    async fn run(mut self) {
+        while let Some((guild_id, plugin_id)) = self.rx.recv().await {
+            self.handle_error(guild_id, plugin_id).await;
+        }
+    }
+
+    async fn handle_error(&mut self, guild_id: Id<GuildMarker>, plugin_id: u64) {
+        self.refresh_rollouts().await;
+        if !self.rollouts.contains(&plugin_id) {
+            return;
+        }
+
+        if let Some(recorded_at) = self.recorded.get(&(guild_id, plugin_id)) {
+            if recorded_at.elapsed() < RECORD_WINDOW {
+                return;
+            }
+        }
+
+        self.recorded.insert((guild_id, plugin_id), Instant::now());
+        if let Err(err) = self
+            .db
+            .record_plugin_version_error(guild_id, plugin_id)
+            .await
+        {
+            error!(%err, "failed recording plugin version error");
+        }
+    }
+
+    async fn refresh_rollouts(&mut self) {
+        if let Some(fetched_at) = self.rollouts_fetched_at {
+            if fetched_at.elapsed() < ROLLOUTS_CACHE_TTL {
+                return;
+            }
+        }
+
+        // don't retry on every entry if the db is having issues
+        self.rollouts_fetched_at = Some(Instant::now());
+
+        match self.db.get_plugin_rollouts_in_progress().await {
+            Ok(rollouts) => {
+                self.rollouts = rollouts.into_iter().map(|v| v.plugin_id).collect();
+            }
+            Err(err) => {
+                error!(%err, "failed fetching plugin rollouts in progress");
+            }
+        }
+
+        // clear out stale entries so this does not grow forever
+        self.recorded
+            .retain(|_, recorded_at| recorded_at.elapsed() < RECORD_WINDOW);
+    }
+}
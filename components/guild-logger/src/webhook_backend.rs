@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::LogEntry;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use stores::{config::GuildLogWebhook, Db};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tracing::{error, warn};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+// max number of entries waiting to be batched up, entries are dropped when this is full
+const QUEUE_SIZE: usize = 10_000;
+const MAX_BATCH_SIZE: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// max number of requests in flight at once, batches are dropped when all of them are busy
+const MAX_CONCURRENT_DELIVERIES: usize = 50;
+const MAX_DELIVERY_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONFIG_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Forwards batches of log entries as signed json POST requests to a guild configured endpoint
+///
+/// Each request has the following headers:
+///  - `X-Botloader-Timestamp`: unix timestamp in seconds of when the request was sent
+///  - `X-Botloader-Signature`: `sha256=<hex hmac>` of `<timestamp>.<body>` using the guild's webhook secret
+pub struct WebhookLogger {
+    tx: mpsc::Sender<LogEntry>,
+}
+
+impl WebhookLogger {
+    pub fn new(db: Db) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+
+        let http_client = reqwest::Client::builder()
+            .https_only(true)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicAddrResolver))
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("build webhook http client");
+
+        let worker = WebhookWorker {
+            db,
+            rx,
+            http_client,
+            configs: HashMap::new(),
+            pending: HashMap::new(),
+            deliveries: Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES)),
+        };
+
+        tokio::spawn(worker.run());
+
+        Self { tx }
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::GuildLoggerBackend for WebhookLogger {
+    async fn handle_entry(&self, entry: LogEntry) {
+        // this is called from the logger task, so we never wait on the webhook worker here
+        // if it can't keep up we shed the entry instead
+        if self.tx.try_send(entry).is_err() {
+            metrics::counter!(
+                "bl.guild_logger.webhook_dropped_entries_total",
+                "reason" => "queue_full"
+            )
+            .increment(1);
+        }
+    }
+}
+
+struct WebhookWorker {
+    db: Db,
+    rx: mpsc::Receiver<LogEntry>,
+    http_client: reqwest::Client,
+    configs: HashMap<Id<GuildMarker>, CachedWebhookConfig>,
+    pending: HashMap<Id<GuildMarker>, Vec<LogEntry>>,
+    deliveries: Arc<Semaphore>,
+}
+
+struct CachedWebhookConfig {
+    fetched_at: Instant,
+    webhook: Option<GuildLogWebhook>,
+}
+
+impl WebhookWorker {
+    async fn run(mut self) {
+        let mut flush_interval = tokio::time::interval(FLUSH_INTERVAL);
+
+        loop {
+            tokio::select! {
+                next = self.rx.recv() => {
+                    let Some(entry) = next else {
+                        break;
+                    };
+
+                    self.handle_entry(entry).await;
+                }
+                _ = flush_interval.tick() => {
+                    self.flush_all();
+                }
+            }
+        }
+
+        self.flush_all();
+    }
+
+    async fn handle_entry(&mut self, entry: LogEntry) {
+        let guild_id = entry.guild_id;
+        if self.get_webhook(guild_id).await.is_none() {
+            return;
+        }
+
+        let batch = self.pending.entry(guild_id).or_default();
+        batch.push(entry);
+
+        if batch.len() >= MAX_BATCH_SIZE {
+            self.flush(guild_id);
+        }
+    }
+
+    async fn get_webhook(&mut self, guild_id: Id<GuildMarker>) -> Option<GuildLogWebhook> {
+        if let Some(cached) = self.configs.get(&guild_id) {
+            if cached.fetched_at.elapsed() < CONFIG_CACHE_TTL {
+                return cached.webhook.clone();
+            }
+        }
+
+        let conf = match self.db.get_guild_meta_config_or_default(guild_id).await {
+            Ok(v) => v,
+            Err(err) => {
+                error!(%err, "failed fetching config for guild log webhook");
+                return None;
+            }
+        };
+
+        self.configs.insert(
+            guild_id,
+            CachedWebhookConfig {
+                fetched_at: Instant::now(),
+                webhook: conf.log_webhook.clone(),
+            },
+        );
+
+        conf.log_webhook
+    }
+
+    fn flush_all(&mut self) {
+        let guilds = self.pending.keys().copied().collect::<Vec<_>>();
+        for guild_id in guilds {
+            self.flush(guild_id);
+        }
+
+        // clear out stale cache entries so this does not grow forever
+        self.configs
+            .retain(|_, v| v.fetched_at.elapsed() < CONFIG_CACHE_TTL);
+    }
+
+    fn flush(&mut self, guild_id: Id<GuildMarker>) {
+        let Some(entries) = self.pending.remove(&guild_id) else {
+            return;
+        };
+
+        let Some(webhook) = self.configs.get(&guild_id).and_then(|v| v.webhook.clone()) else {
+            return;
+        };
+
+        let Ok(permit) = self.deliveries.clone().try_acquire_owned() else {
+            metrics::counter!(
+                "bl.guild_logger.webhook_dropped_entries_total",
+                "reason" => "overloaded"
+            )
+            .increment(entries.len() as u64);
+            return;
+        };
+
+        tokio::spawn(deliver(
+            self.http_client.clone(),
+            webhook,
+            guild_id,
+            entries,
+            permit,
+        ));
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    guild_id: Id<GuildMarker>,
+    entries: &'a [LogEntry],
+}
+
+async fn deliver(
+    http_client: reqwest::Client,
+    webhook: GuildLogWebhook,
+    guild_id: Id<GuildMarker>,
+    entries: Vec<LogEntry>,
+    _permit: OwnedSemaphorePermit,
+) {
+    let body = match serde_json::to_vec(&WebhookPayload {
+        guild_id,
+        entries: &entries,
+    }) {
+        Ok(v) => v,
+        Err(err) => {
+            error!(%err, "failed encoding guild log webhook payload");
+            return;
+        }
+    };
+
+    // the resolver is only used for domain names, the urls are validated to have one but
+    // don't send anything if an address slipped through somehow
+    match url::Url::parse(&webhook.url) {
+        Ok(url) if matches!(url.host(), Some(url::Host::Domain(_))) => {}
+        _ => {
+            warn!(%guild_id, "guild log webhook url does not have a domain name as the host");
+            return;
+        }
+    }
+
+    for attempt in 0..MAX_DELIVERY_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        let resp = http_client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Botloader-Timestamp", &timestamp)
+            .header(
+                "X-Botloader-Signature",
+                format!("sha256={}", sign(&webhook.secret, &timestamp, &body)),
+            )
+            .body(body.clone())
+            .send()
+            .await;
+
+        match resp {
+            Ok(resp) if resp.status().is_success() => {
+                metrics::counter!("bl.guild_logger.webhook_delivered_entries_total")
+                    .increment(entries.len() as u64);
+                return;
+            }
+            Ok(resp)
+                if resp.status().is_client_error()
+                    && resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                // the endpoint rejected the request, retrying won't help
+                warn!(%guild_id, status = %resp.status(), "guild log webhook rejected batch");
+                break;
+            }
+            Ok(resp) => {
+                warn!(%guild_id, status = %resp.status(), attempt, "guild log webhook request failed");
+            }
+            Err(err) => {
+                warn!(%guild_id, %err, attempt, "guild log webhook request failed");
+            }
+        }
+    }
+
+    metrics::counter!(
+        "bl.guild_logger.webhook_dropped_entries_total",
+        "reason" => "delivery_failed"
+    )
+    .increment(entries.len() as u64);
+}
+
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Resolves webhook hosts while refusing to connect to anything but public addresses
+///
+/// The addresses are checked when connecting rather than when the url is set, a domain can
+/// point somewhere else by the time we send anything to it.
+struct PublicAddrResolver;
+
+impl reqwest::dns::Resolve for PublicAddrResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_addr(addr.ip()))
+                .collect::<Vec<_>>();
+
+            if addrs.is_empty() {
+                return Err(format!("{host} does not resolve to any public addresses").into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn is_public_addr(addr: IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_v4(v4);
+            }
+
+            is_public_v6(v6)
+        }
+    }
+}
+
+fn is_public_v4(addr: Ipv4Addr) -> bool {
+    let [a, b, c, _] = addr.octets();
+
+    !(addr.is_unspecified()
+        || addr.is_private()
+        || addr.is_loopback()
+        || addr.is_link_local()
+        || addr.is_broadcast()
+        || addr.is_documentation()
+        || addr.is_multicast()
+        // "this network"
+        || a == 0
+        // shared address space (carrier grade nat)
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // ietf protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking
+        || (a == 198 && (b & 0b1111_1110) == 18)
+        // reserved
+        || a >= 240)
+}
+
+fn is_public_v6(addr: Ipv6Addr) -> bool {
+    let segments = addr.segments();
+
+    !(addr.is_unspecified()
+        || addr.is_loopback()
+        || addr.is_multicast()
+        // unique local
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link local
+        || (segments[0] & 0xffc0) == 0xfe80
+        // documentation
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        // nat64, the address it maps to could be anything
+        || (segments[0] == 0x64 && segments[1] == 0xff9b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for addr in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public_addr(addr.parse().unwrap()), "{addr}");
+        }
+    }
+
+    #[test]
+    fn public_addresses_are_public() {
+        for addr in ["1.1.1.1", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(is_public_addr(addr.parse().unwrap()), "{addr}");
+        }
+    }
+}
@@ -3,6 +3,7 @@ use stores::Db;
 
 mod job;
 mod left_guilds;
+mod plugin_rollouts;
 mod plugin_stats;
 
 pub async fn run(
@@ -35,6 +36,10 @@ pub async fn run(
                 discord_config: discord_config.clone(),
             }),
             Box::new(plugin_stats::PluginStatsJobSpawner { db: db.clone() }),
+            Box::new(plugin_rollouts::PluginRolloutsJobSpawner {
+                db: db.clone(),
+                bot_rpc_addr: common_conf.bot_rpc_connect_addr.clone(),
+            }),
         ],
         stop_future,
     )
@@ -0,0 +1,147 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use common::plugin::PluginVersionMeta;
+use stores::Db;
+use tracing::{error, info, warn};
+
+use crate::job::{Job, JobSpawner, OutputFuture};
+
+// the percentages of the auto updating guilds a version is rolled out to, in order
+const ROLLOUT_STEPS: &[u8] = &[1, 5, 10, 25, 50, 100];
+
+// how long a version stays at a step before moving on to the next one
+const STEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// the rollout is paused if more than this fraction of the guilds running the version logged errors from it
+const MAX_ERROR_RATE: f64 = 0.2;
+
+// the rollout is held at its step until this many guilds are running the version,
+// authors can still advance it themselves
+const MIN_SAMPLE_GUILDS: u64 = 10;
+
+pub struct PluginRolloutsJobSpawner {
+    pub db: Db,
+    pub bot_rpc_addr: String,
+}
+
+impl JobSpawner for PluginRolloutsJobSpawner {
+    fn name(&self) -> &'static str {
+        "plugin_rollouts"
+    }
+
+    fn spawn(&self) -> Arc<dyn Job> {
+        Arc::new(PluginRolloutsJob {
+            db: self.db.clone(),
+            bot_rpc_addr: self.bot_rpc_addr.clone(),
+        })
+    }
+
+    fn interval(&self) -> std::time::Duration {
+        Duration::from_secs(60)
+    }
+}
+
+pub struct PluginRolloutsJob {
+    db: Db,
+    bot_rpc_addr: String,
+}
+
+impl Job for PluginRolloutsJob {
+    fn status(&self) -> String {
+        "Running".to_string()
+    }
+
+    fn run(self: std::sync::Arc<Self>) -> OutputFuture {
+        Box::pin(async move {
+            let rollouts = self.db.get_plugin_rollouts_in_progress().await?;
+            if rollouts.is_empty() {
+                return Ok(());
+            }
+
+            let bot_rpc_client = botrpc::Client::new(self.bot_rpc_addr.clone()).await?;
+
+            for rollout in rollouts {
+                let plugin_id = rollout.plugin_id;
+                let version_number = rollout.version_number;
+                if let Err(err) = self.check_rollout(&bot_rpc_client, rollout).await {
+                    error!(%err, plugin_id, version_number, "failed checking plugin rollout");
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl PluginRolloutsJob {
+    async fn check_rollout(
+        &self,
+        bot_rpc_client: &botrpc::Client,
+        version: PluginVersionMeta,
+    ) -> Result<(), anyhow::Error> {
+        let stats = self
+            .db
+            .get_plugin_rollout_stats(version.plugin_id, version.version_number)
+            .await?;
+
+        // too few guilds to tell whether the version is healthy
+        if stats.running_guilds < MIN_SAMPLE_GUILDS {
+            return Ok(());
+        }
+
+        let error_rate = stats.erroring_guilds as f64 / stats.running_guilds as f64;
+        if error_rate > MAX_ERROR_RATE {
+            warn!(
+                plugin_id = version.plugin_id,
+                version_number = version.version_number,
+                error_rate,
+                "pausing plugin rollout"
+            );
+
+            let reason = format!(
+                "{} out of {} guilds running this version logged errors from it",
+                stats.erroring_guilds, stats.running_guilds
+            );
+            self.db
+                .pause_plugin_rollout(version.plugin_id, version.version_number, &reason)
+                .await?;
+            return Ok(());
+        }
+
+        let at_step_for = (Utc::now() - version.rollout.updated_at)
+            .to_std()
+            .unwrap_or_default();
+        if at_step_for < STEP_INTERVAL {
+            return Ok(());
+        }
+
+        let Some(next_step) = ROLLOUT_STEPS
+            .iter()
+            .copied()
+            .find(|step| *step > version.rollout.percentage)
+        else {
+            return Ok(());
+        };
+
+        info!(
+            plugin_id = version.plugin_id,
+            version_number = version.version_number,
+            percentage = next_step,
+            "advancing plugin rollout"
+        );
+
+        let guilds = self
+            .db
+            .advance_plugin_rollout(version.plugin_id, version.version_number, next_step)
+            .await?;
+
+        for guild_id in guilds {
+            if let Err(err) = bot_rpc_client.restart_guild_vm(guild_id).await {
+                error!(%err, %guild_id, "failed reloading guild vm");
+            }
+        }
+
+        Ok(())
+    }
+}
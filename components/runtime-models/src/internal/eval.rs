@@ -0,0 +1,26 @@
+use serde::Deserialize;
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+#[derive(Clone, Debug, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/internal/EvalOutputMessage.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct EvalOutputMessage {
+    pub eval_id: NotBigU64,
+    pub kind: EvalOutputKind,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/internal/EvalOutputKind.ts")]
+#[serde(rename_all = "camelCase")]
+pub enum EvalOutputKind {
+    ConsoleLog,
+    ConsoleWarn,
+    ConsoleError,
+    Result,
+    Exception,
+}
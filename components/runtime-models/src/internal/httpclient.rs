@@ -16,6 +16,11 @@ pub struct ClientHttpRequest {
     #[serde(default)]
     #[ts(optional)]
     pub script_id: Option<NotBigU64>,
+    // the caller captured when the request was created, the stack no longer leads back to it
+    // once the request is sent from a `then`
+    #[serde(default)]
+    #[ts(optional)]
+    pub caller_token: Option<String>,
     #[serde(default)]
     #[ts(optional)]
     pub body_resource_id: Option<u32>,
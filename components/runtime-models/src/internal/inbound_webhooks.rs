@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::util::NotBigU64;
+
+#[derive(Clone, Debug, Serialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/internal/InboundWebhookRequest.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct InboundWebhookRequest {
+    pub request_id: NotBigU64,
+    pub webhook_id: NotBigU64,
+    pub name: String,
+
+    #[ts(type = "unknown")]
+    pub body: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Deserialize, TS)]
+#[ts(export)]
+#[ts(export_to = "bindings/internal/InboundWebhookResponse.ts")]
+#[serde(rename_all = "camelCase")]
+pub struct InboundWebhookResponse {
+    pub request_id: NotBigU64,
+
+    #[ts(type = "unknown")]
+    pub body: Option<serde_json::Value>,
+}
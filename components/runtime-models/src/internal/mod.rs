@@ -1,8 +1,10 @@
 pub mod channel;
 pub mod console;
 pub mod emoji;
+pub mod eval;
 pub mod events;
 pub mod httpclient;
+pub mod inbound_webhooks;
 pub mod interaction;
 pub mod interactions;
 pub mod invite;
@@ -0,0 +1,462 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use common::plugin::PluginCapabilities;
+use deno_core::{v8, OpState};
+use url::Url;
+use vm::{AnyError, ScriptsStateStoreHandle};
+
+// deep enough to get past the botloader library frames between the script and the op
+const MAX_STACK_FRAMES: usize = 32;
+
+// requests that are never sent leave their callers behind, the oldest are dropped past this
+const MAX_CAPTURED_CALLERS: usize = 1000;
+
+/// Something a plugin needs to be granted before it can call the ops behind it
+#[derive(Debug, Clone, Copy)]
+pub enum Capability<'a> {
+    Http { host: &'a str },
+    Moderation,
+    Storage,
+}
+
+/// The script making an op call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCaller {
+    GuildScript,
+    Plugin(u64),
+    /// No script frames on the stack, e.g. an op passed straight to `then` as a callback
+    Unknown,
+}
+
+/// Finds the script making the op call by walking the stack for the innermost frame belonging to
+/// a guild script or a plugin, only the botloader library frames in between are skipped
+pub fn op_caller(scope: &mut v8::HandleScope) -> OpCaller {
+    let Some(trace) = v8::StackTrace::current_stack_trace(scope, MAX_STACK_FRAMES) else {
+        return OpCaller::Unknown;
+    };
+
+    let mut script_names = Vec::new();
+    for i in 0..trace.get_frame_count() {
+        let script_name = trace.get_frame(scope, i).and_then(|frame| {
+            // code from eval and `new Function` isn't part of any script
+            if frame.is_eval() || frame.is_wasm() {
+                return None;
+            }
+
+            frame
+                .get_script_name(scope)
+                .map(|name| name.to_rust_string_lossy(scope))
+        });
+        script_names.push(script_name);
+    }
+
+    caller_from_frames(script_names.iter().map(Option::as_deref))
+}
+
+/// Takes the script names of the stack frames from the innermost one, none for frames that can't
+/// be traced back to a script, e.g. code from eval
+fn caller_from_frames<'a>(script_names: impl IntoIterator<Item = Option<&'a str>>) -> OpCaller {
+    for script_name in script_names {
+        // anything could have installed this frame, e.g. as a wrapper around a library function a
+        // guild script calls, so we can't look past it
+        let Some(url) = script_name.and_then(|name| Url::parse(name).ok()) else {
+            return OpCaller::Unknown;
+        };
+
+        if let Some(plugin_id) = vm::moduleloader::plugin_id_from_url(&url) {
+            return OpCaller::Plugin(plugin_id);
+        }
+
+        let path = url.path();
+        if path.starts_with("/guild_scripts/") || path.starts_with("/vendor/guild/") {
+            return OpCaller::GuildScript;
+        }
+
+        if !is_library_module(&url) {
+            return OpCaller::Unknown;
+        }
+    }
+
+    OpCaller::Unknown
+}
+
+/// Whether the module is part of the botloader runtime or deno's own extensions
+fn is_library_module(url: &Url) -> bool {
+    url.scheme() == "ext"
+        || crate::jsmodules::MODULE_MAP
+            .iter()
+            .any(|(specifier, _)| specifier == url)
+}
+
+/// Callers captured while the stack still leads back to them, for library code that makes the op
+/// call after an `await` where it no longer does
+#[derive(Default)]
+pub struct CapturedCallers {
+    callers: HashMap<String, OpCaller>,
+    order: VecDeque<String>,
+}
+
+impl CapturedCallers {
+    fn insert(&mut self, caller: OpCaller) -> String {
+        // has to be unguessable, otherwise a plugin could use the ones captured for guild scripts
+        let token = uuid::Uuid::new_v4().to_string();
+
+        if self.order.len() >= MAX_CAPTURED_CALLERS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.callers.remove(&oldest);
+            }
+        }
+
+        self.callers.insert(token.clone(), caller);
+        self.order.push_back(token.clone());
+        token
+    }
+
+    fn get(&self, token: &str) -> OpCaller {
+        self.callers
+            .get(token)
+            .copied()
+            .unwrap_or(OpCaller::Unknown)
+    }
+}
+
+/// Captures the script making the op call, returning a token to pass to
+/// [`check_captured_capability`] later on
+pub fn capture_caller(scope: &mut v8::HandleScope, state: &Rc<RefCell<OpState>>) -> String {
+    let caller = op_caller(scope);
+
+    let mut state = state.borrow_mut();
+    if !state.has::<CapturedCallers>() {
+        state.put(CapturedCallers::default());
+    }
+    state.borrow_mut::<CapturedCallers>().insert(caller)
+}
+
+/// What the script making an op call is allowed to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallerGrant {
+    /// Guild scripts can do everything
+    All,
+    /// Plugins are limited to what they've been granted, calls that can't be traced back to a
+    /// script are limited to what every plugin in the vm has been granted as any of them could
+    /// have made it
+    Plugins(Vec<(u64, PluginCapabilities)>),
+}
+
+impl CallerGrant {
+    fn new(caller: OpCaller, plugins: &[(u64, PluginCapabilities)]) -> Self {
+        match caller {
+            OpCaller::GuildScript => Self::All,
+            // plugins no longer in the vm, e.g. removed while running, get nothing
+            OpCaller::Plugin(plugin_id) => Self::Plugins(vec![plugins
+                .iter()
+                .find(|(id, _)| *id == plugin_id)
+                .cloned()
+                .unwrap_or((plugin_id, PluginCapabilities::default()))]),
+            OpCaller::Unknown => Self::Plugins(plugins.to_vec()),
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match self {
+            Self::All => true,
+            Self::Plugins(plugins) => plugins
+                .iter()
+                .all(|(_, granted)| is_granted(granted, capability)),
+        }
+    }
+
+    /// Whether the caller can use the storage buckets of the plugin, or the guild's own buckets
+    /// if none, plugins can only use their own
+    pub fn allows_buckets_of(&self, plugin_id: Option<u64>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Plugins(plugins) => plugins.iter().all(|(id, _)| plugin_id == Some(*id)),
+        }
+    }
+}
+
+/// Checks that the script making the op call has been granted the capability, guild scripts have
+/// all of them
+pub fn check_capability(
+    scope: &mut v8::HandleScope,
+    state: &Rc<RefCell<OpState>>,
+    capability: Capability,
+) -> Result<CallerGrant, AnyError> {
+    check_caller_capability(op_caller(scope), state, capability)
+}
+
+/// Like [`check_capability`] but for the caller captured with [`capture_caller`], falling back to
+/// the stack if there's no token
+pub fn check_captured_capability(
+    scope: &mut v8::HandleScope,
+    state: &Rc<RefCell<OpState>>,
+    token: Option<&str>,
+    capability: Capability,
+) -> Result<CallerGrant, AnyError> {
+    let caller = match token {
+        Some(token) => state
+            .borrow()
+            .try_borrow::<CapturedCallers>()
+            .map(|captured| captured.get(token))
+            .unwrap_or(OpCaller::Unknown),
+        None => op_caller(scope),
+    };
+
+    check_caller_capability(caller, state, capability)
+}
+
+fn check_caller_capability(
+    caller: OpCaller,
+    state: &Rc<RefCell<OpState>>,
+    capability: Capability,
+) -> Result<CallerGrant, AnyError> {
+    let plugins = {
+        let state = state.borrow();
+        let scripts = state.borrow::<ScriptsStateStoreHandle>().borrow();
+        scripts
+            .scripts
+            .iter()
+            .filter_map(|s| {
+                let plugin_id = s.script.plugin_id?;
+                Some((
+                    plugin_id,
+                    s.script.plugin_capabilities.clone().unwrap_or_default(),
+                ))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let grant = CallerGrant::new(caller, &plugins);
+    ensure_granted(caller, &grant, capability)?;
+    Ok(grant)
+}
+
+fn ensure_granted(
+    caller: OpCaller,
+    grant: &CallerGrant,
+    capability: Capability,
+) -> Result<(), AnyError> {
+    if grant.allows(capability) {
+        return Ok(());
+    }
+
+    match caller {
+        OpCaller::Plugin(plugin_id) => Err(anyhow::anyhow!(
+            "plugin {plugin_id} has not been granted the {} capability",
+            capability_name(capability)
+        )),
+        _ => Err(anyhow::anyhow!(
+            "could not determine the script making this call and not every plugin on this server \
+             has been granted the {} capability, call it from a function in your script instead \
+             of passing it directly as a callback",
+            capability_name(capability)
+        )),
+    }
+}
+
+fn is_granted(granted: &PluginCapabilities, capability: Capability) -> bool {
+    match capability {
+        Capability::Http { host } => granted.allows_http_host(host),
+        Capability::Moderation => granted.moderation,
+        Capability::Storage => granted.storage,
+    }
+}
+
+fn capability_name(capability: Capability) -> String {
+    match capability {
+        Capability::Http { host } => format!("http access to {host}"),
+        Capability::Moderation => "moderation".to_string(),
+        Capability::Storage => "storage".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugins() -> Vec<(u64, PluginCapabilities)> {
+        vec![
+            (
+                1,
+                PluginCapabilities {
+                    http_hosts: vec!["*.example.com".to_string()],
+                    storage: true,
+                    ..Default::default()
+                },
+            ),
+            (
+                2,
+                PluginCapabilities {
+                    http_hosts: vec!["api.example.com".to_string()],
+                    moderation: true,
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    fn check(
+        caller: OpCaller,
+        plugins: &[(u64, PluginCapabilities)],
+        capability: Capability,
+    ) -> bool {
+        let grant = CallerGrant::new(caller, plugins);
+        ensure_granted(caller, &grant, capability).is_ok()
+    }
+
+    #[test]
+    fn guild_scripts_have_every_capability() {
+        let plugins = plugins();
+        assert!(check(
+            OpCaller::GuildScript,
+            &plugins,
+            Capability::Moderation
+        ));
+        assert!(check(
+            OpCaller::GuildScript,
+            &plugins,
+            Capability::Http { host: "other.com" }
+        ));
+        assert!(CallerGrant::new(OpCaller::GuildScript, &plugins).allows_buckets_of(None));
+    }
+
+    #[test]
+    fn plugins_are_limited_to_their_grant() {
+        let plugins = plugins();
+        assert!(check(OpCaller::Plugin(1), &plugins, Capability::Storage));
+        assert!(!check(
+            OpCaller::Plugin(1),
+            &plugins,
+            Capability::Moderation
+        ));
+        assert!(check(
+            OpCaller::Plugin(1),
+            &plugins,
+            Capability::Http {
+                host: "cdn.example.com"
+            }
+        ));
+        assert!(!check(
+            OpCaller::Plugin(2),
+            &plugins,
+            Capability::Http {
+                host: "cdn.example.com"
+            }
+        ));
+
+        let grant = CallerGrant::new(OpCaller::Plugin(1), &plugins);
+        assert!(grant.allows_buckets_of(Some(1)));
+        assert!(!grant.allows_buckets_of(Some(2)));
+        assert!(!grant.allows_buckets_of(None));
+    }
+
+    #[test]
+    fn plugins_no_longer_in_the_vm_get_nothing() {
+        assert!(!check(OpCaller::Plugin(3), &plugins(), Capability::Storage));
+    }
+
+    #[test]
+    fn callers_are_found_past_library_frames() {
+        assert_eq!(
+            caller_from_frames([
+                Some("ext:core/01_core.js"),
+                Some("file:///op_wrappers.js"),
+                Some("file:///guild_scripts/some_script.js"),
+            ]),
+            OpCaller::GuildScript
+        );
+        assert_eq!(
+            caller_from_frames([
+                Some("file:///op_wrappers.js"),
+                Some("file:///plugins/5/some_script.js"),
+                Some("file:///guild_scripts/some_script.js"),
+            ]),
+            OpCaller::Plugin(5)
+        );
+        assert_eq!(
+            caller_from_frames([Some("file:///op_wrappers.js")]),
+            OpCaller::Unknown
+        );
+    }
+
+    #[test]
+    fn unnamed_frames_stop_the_walk() {
+        // a `new Function` built wrapper installed by a plugin, called from a guild script
+        let caller = caller_from_frames([
+            Some("file:///op_wrappers.js"),
+            None,
+            Some("file:///guild_scripts/some_script.js"),
+        ]);
+        assert_eq!(caller, OpCaller::Unknown);
+        assert_ne!(CallerGrant::new(caller, &plugins()), CallerGrant::All);
+
+        // same for frames from modules we don't know of
+        assert_eq!(
+            caller_from_frames([
+                Some("file:///eval/1.js"),
+                Some("file:///guild_scripts/some_script.js"),
+            ]),
+            OpCaller::Unknown
+        );
+    }
+
+    #[test]
+    fn captured_callers_are_looked_up_by_token() {
+        let mut captured = CapturedCallers::default();
+        let guild_token = captured.insert(OpCaller::GuildScript);
+        let plugin_token = captured.insert(OpCaller::Plugin(1));
+
+        assert_eq!(captured.get(&guild_token), OpCaller::GuildScript);
+        assert_eq!(captured.get(&plugin_token), OpCaller::Plugin(1));
+        assert_eq!(captured.get("made up"), OpCaller::Unknown);
+
+        for _ in 0..MAX_CAPTURED_CALLERS {
+            captured.insert(OpCaller::Unknown);
+        }
+        assert_eq!(captured.get(&guild_token), OpCaller::Unknown);
+    }
+
+    #[test]
+    fn unknown_callers_without_plugins_are_allowed() {
+        assert!(check(OpCaller::Unknown, &[], Capability::Moderation));
+        assert!(CallerGrant::new(OpCaller::Unknown, &[]).allows_buckets_of(None));
+    }
+
+    #[test]
+    fn unknown_callers_get_the_least_privileged_plugin_grant() {
+        let plugins = plugins();
+
+        // both plugins can reach this host
+        assert!(check(
+            OpCaller::Unknown,
+            &plugins,
+            Capability::Http {
+                host: "api.example.com"
+            }
+        ));
+        // but only one of them has these
+        assert!(!check(OpCaller::Unknown, &plugins, Capability::Storage));
+        assert!(!check(OpCaller::Unknown, &plugins, Capability::Moderation));
+        assert!(!check(
+            OpCaller::Unknown,
+            &plugins,
+            Capability::Http {
+                host: "cdn.example.com"
+            }
+        ));
+
+        let grant = CallerGrant::new(OpCaller::Unknown, &plugins);
+        assert!(!grant.allows_buckets_of(None));
+        assert!(!grant.allows_buckets_of(Some(1)));
+
+        // with a single plugin in the vm it could only have been that one
+        let single = CallerGrant::new(OpCaller::Unknown, &plugins[..1]);
+        assert!(single.allows(Capability::Storage));
+        assert!(single.allows_buckets_of(Some(1)));
+    }
+}
@@ -3,7 +3,7 @@ use chrono::TimeZone;
 use common::DiscordConfig;
 use deno_core::{
     error::{custom_error, get_custom_error_class},
-    op2, OpState,
+    op2, v8, OpState,
 };
 use futures::TryFutureExt;
 use pin_project::pin_project;
@@ -76,7 +76,11 @@ use vm::AnyError;
 
 use super::{get_guild_channel, parse_discord_id, parse_get_guild_channel, parse_str_snowflake_id};
 use crate::{
-    extensions::parse_str_snowflake_ids, get_rt_ctx, limits::RateLimiters, RuntimeContext,
+    capabilities::{check_capability, Capability},
+    extensions::parse_str_snowflake_ids,
+    get_rt_ctx,
+    limits::RateLimiters,
+    RuntimeContext,
 };
 
 deno_core::extension!(
@@ -422,12 +426,32 @@ pub fn not_found_error(message: impl Into<Cow<'static, str>>) -> AnyError {
 
 #[op2(async)]
 #[serde]
-pub async fn op_easyops_async(
+pub fn op_easyops_async(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] op: EasyOpsASync,
-) -> Result<serde_json::Value, AnyError> {
-    let handler = EasyOpsHandler { state };
-    handle_async_op(&handler, op).await
+) -> impl Future<Output = Result<serde_json::Value, AnyError>> {
+    let allowed = match easyop_capability(&op) {
+        Some(capability) => check_capability(scope, &state, capability).map(|_| ()),
+        None => Ok(()),
+    };
+
+    async move {
+        allowed?;
+
+        let handler = EasyOpsHandler { state };
+        handle_async_op(&handler, op).await
+    }
+}
+
+fn easyop_capability(op: &EasyOpsASync) -> Option<Capability<'static>> {
+    match op {
+        EasyOpsASync::discord_create_role(_)
+        | EasyOpsASync::discord_update_role(_)
+        | EasyOpsASync::discord_update_role_positions(_)
+        | EasyOpsASync::discord_delete_role(_) => Some(Capability::Moderation),
+        _ => None,
+    }
 }
 
 struct EasyOpsHandler {
@@ -2566,123 +2590,151 @@ async fn fetch_members_through_api(
 }
 
 #[op2(async)]
-pub async fn op_discord_add_member_role(
+pub fn op_discord_add_member_role(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] role_id: Id<RoleMarker>,
-) -> Result<(), AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<(), AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    discord_request(&state, async move {
-        rt_ctx
-            .discord_config
-            .client
-            .add_guild_member_role(rt_ctx.guild_id, user_id, role_id)
-            .await
-    })
-    .await?;
+    async move {
+        allowed?;
 
-    Ok(())
+        let rt_ctx = get_rt_ctx(&state);
+
+        discord_request(&state, async move {
+            rt_ctx
+                .discord_config
+                .client
+                .add_guild_member_role(rt_ctx.guild_id, user_id, role_id)
+                .await
+        })
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[op2(async)]
-pub async fn op_discord_remove_member_role(
+pub fn op_discord_remove_member_role(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] role_id: Id<RoleMarker>,
-) -> Result<(), AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<(), AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    discord_request(&state, async move {
-        rt_ctx
-            .discord_config
-            .client
-            .remove_guild_member_role(rt_ctx.guild_id, user_id, role_id)
-            .await
-    })
-    .await?;
+    async move {
+        allowed?;
 
-    Ok(())
+        let rt_ctx = get_rt_ctx(&state);
+
+        discord_request(&state, async move {
+            rt_ctx
+                .discord_config
+                .client
+                .remove_guild_member_role(rt_ctx.guild_id, user_id, role_id)
+                .await
+        })
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_discord_update_member(
+pub fn op_discord_update_member(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] fields: UpdateGuildMemberFields,
-) -> Result<runtime_models::internal::member::Member, AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<runtime_models::internal::member::Member, AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    Ok(discord_request_with_extra_error(&state, async move {
-        let mut builder = rt_ctx
-            .discord_config
-            .client
-            .update_guild_member(rt_ctx.guild_id, user_id);
+    async move {
+        allowed?;
 
-        if let Some(maybe_cid) = fields.channel_id {
-            builder = builder.channel_id(maybe_cid);
-        }
+        let rt_ctx = get_rt_ctx(&state);
 
-        if let Some(deaf) = fields.deaf {
-            builder = builder.deaf(deaf);
-        }
+        Ok(discord_request_with_extra_error(&state, async move {
+            let mut builder = rt_ctx
+                .discord_config
+                .client
+                .update_guild_member(rt_ctx.guild_id, user_id);
 
-        if let Some(mute) = fields.mute {
-            builder = builder.mute(mute);
-        }
+            if let Some(maybe_cid) = fields.channel_id {
+                builder = builder.channel_id(maybe_cid);
+            }
 
-        if let Some(maybe_nick) = &fields.nick {
-            builder = builder.nick(maybe_nick.as_deref())
-        }
+            if let Some(deaf) = fields.deaf {
+                builder = builder.deaf(deaf);
+            }
 
-        if let Some(roles) = &fields.roles {
-            builder = builder.roles(roles);
-        }
+            if let Some(mute) = fields.mute {
+                builder = builder.mute(mute);
+            }
 
-        if let Some(ts) = &fields.communication_disabled_until {
-            builder = builder.communication_disabled_until(
-                ts.map(|v| twilight_model::util::Timestamp::from_micros(v.0 as i64 * 1000))
-                    .transpose()?,
-            );
-        }
+            if let Some(maybe_nick) = &fields.nick {
+                builder = builder.nick(maybe_nick.as_deref())
+            }
 
-        Ok(builder.await)
-    })
-    .await?
-    .model()
-    .await?
-    .into())
+            if let Some(roles) = &fields.roles {
+                builder = builder.roles(roles);
+            }
+
+            if let Some(ts) = &fields.communication_disabled_until {
+                builder = builder.communication_disabled_until(
+                    ts.map(|v| twilight_model::util::Timestamp::from_micros(v.0 as i64 * 1000))
+                        .transpose()?,
+                );
+            }
+
+            Ok(builder.await)
+        })
+        .await?
+        .model()
+        .await?
+        .into())
+    }
 }
 
 // Bans
 #[op2(async)]
-pub async fn op_discord_create_ban(
+pub fn op_discord_create_ban(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] extras: CreateBanFields,
-) -> Result<(), AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<(), AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    discord_request_with_extra_error(&state, async move {
-        let mut req = rt_ctx
-            .discord_config
-            .client
-            .create_ban(rt_ctx.guild_id, user_id);
+    async move {
+        allowed?;
 
-        if let Some(days) = extras.delete_message_days {
-            req = req.delete_message_seconds(days * 24 * 60 * 60);
-        }
+        let rt_ctx = get_rt_ctx(&state);
 
-        if let Some(reason) = &extras.audit_log_reason {
-            req = req.reason(reason);
-        }
+        discord_request_with_extra_error(&state, async move {
+            let mut req = rt_ctx
+                .discord_config
+                .client
+                .create_ban(rt_ctx.guild_id, user_id);
 
-        Ok(req.await)
-    })
-    .await?;
+            if let Some(days) = extras.delete_message_days {
+                req = req.delete_message_seconds(days * 24 * 60 * 60);
+            }
 
-    Ok(())
+            if let Some(reason) = &extras.audit_log_reason {
+                req = req.reason(reason);
+            }
+
+            Ok(req.await)
+        })
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[op2(async)]
@@ -2722,54 +2774,68 @@ pub async fn op_discord_get_bans(state: Rc<RefCell<OpState>>) -> Result<Vec<Ban>
 }
 
 #[op2(async)]
-pub async fn op_discord_delete_ban(
+pub fn op_discord_delete_ban(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] extras: AuditLogExtras,
-) -> Result<(), AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<(), AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    discord_request_with_extra_error(&state, async move {
-        let mut req = rt_ctx
-            .discord_config
-            .client
-            .delete_ban(rt_ctx.guild_id, user_id);
+    async move {
+        allowed?;
 
-        if let Some(reason) = &extras.audit_log_reason {
-            req = req.reason(reason);
-        }
+        let rt_ctx = get_rt_ctx(&state);
 
-        Ok(req.await)
-    })
-    .await?;
+        discord_request_with_extra_error(&state, async move {
+            let mut req = rt_ctx
+                .discord_config
+                .client
+                .delete_ban(rt_ctx.guild_id, user_id);
 
-    Ok(())
+            if let Some(reason) = &extras.audit_log_reason {
+                req = req.reason(reason);
+            }
+
+            Ok(req.await)
+        })
+        .await?;
+
+        Ok(())
+    }
 }
 
 // Other
 #[op2(async)]
-pub async fn op_discord_remove_member(
+pub fn op_discord_remove_member(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] user_id: Id<UserMarker>,
     #[serde] extras: AuditLogExtras,
-) -> Result<(), AnyError> {
-    let rt_ctx = get_rt_ctx(&state);
+) -> impl Future<Output = Result<(), AnyError>> {
+    let allowed = check_capability(scope, &state, Capability::Moderation);
 
-    discord_request_with_extra_error(&state, async move {
-        let mut req = rt_ctx
-            .discord_config
-            .client
-            .remove_guild_member(rt_ctx.guild_id, user_id);
+    async move {
+        allowed?;
 
-        if let Some(reason) = &extras.audit_log_reason {
-            req = req.reason(reason);
-        }
+        let rt_ctx = get_rt_ctx(&state);
 
-        Ok(req.await)
-    })
-    .await?;
+        discord_request_with_extra_error(&state, async move {
+            let mut req = rt_ctx
+                .discord_config
+                .client
+                .remove_guild_member(rt_ctx.guild_id, user_id);
 
-    Ok(())
+            if let Some(reason) = &extras.audit_log_reason {
+                req = req.reason(reason);
+            }
+
+            Ok(req.await)
+        })
+        .await?;
+
+        Ok(())
+    }
 }
 
 #[op2(async)]
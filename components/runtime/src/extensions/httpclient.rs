@@ -1,14 +1,15 @@
 use std::{
-    borrow::Cow, cell::RefCell, collections::HashMap, pin::Pin, rc::Rc, str::FromStr,
-    time::Duration,
+    borrow::Cow, cell::RefCell, collections::HashMap, future::Future, pin::Pin, rc::Rc,
+    str::FromStr, time::Duration,
 };
 
+use common::plugin::PluginCapabilities;
 use deno_core::{
-    op2, AsyncRefCell, AsyncResult, BufView, CancelFuture, CancelHandle, CancelTryFuture, OpState,
-    RcRef, Resource, ResourceId, WriteOutcome,
+    op2, v8, AsyncRefCell, AsyncResult, BufView, CancelFuture, CancelHandle, CancelTryFuture,
+    OpState, RcRef, Resource, ResourceId, WriteOutcome,
 };
 use futures::Stream;
-use reqwest::Body;
+use reqwest::{redirect, Body};
 use runtime_models::internal::httpclient::{ClientHttpRequest, ClientHttpResponse};
 use tokio::{io::AsyncReadExt, sync::mpsc};
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
@@ -17,13 +18,77 @@ use tracing::info;
 use url::Url;
 use vm::AnyError;
 
-use crate::limits::RateLimiters;
+use crate::{
+    capabilities::{capture_caller, check_captured_capability, CallerGrant, Capability},
+    limits::RateLimiters,
+};
 
 deno_core::extension!(
     bl_http,
-    ops = [op_bl_http_client_stream, op_bl_http_request_send,],
+    ops = [
+        op_bl_http_client_stream,
+        op_bl_http_capture_caller,
+        op_bl_http_request_send,
+    ],
 );
 
+// the same limit reqwest's default redirect policy has
+const MAX_REDIRECTS: usize = 10;
+
+/// The http client the requests made by scripts go through
+pub struct ScriptHttpClient {
+    client: reqwest::Client,
+    proxy: Option<String>,
+}
+
+impl ScriptHttpClient {
+    pub fn new(proxy: Option<String>) -> Self {
+        Self {
+            client: build_client(proxy.as_deref(), redirect::Policy::default()),
+            proxy,
+        }
+    }
+
+    /// Returns a client that only follows redirects to hosts the caller is allowed to reach
+    fn for_caller(&self, grant: CallerGrant) -> reqwest::Client {
+        let plugins = match grant {
+            CallerGrant::Plugins(plugins) if !plugins.is_empty() => plugins,
+            _ => return self.client.clone(),
+        };
+
+        let granted = plugins
+            .into_iter()
+            .map(|(_, granted)| granted)
+            .collect::<Vec<PluginCapabilities>>();
+
+        build_client(
+            self.proxy.as_deref(),
+            redirect::Policy::custom(move |attempt| {
+                let host = attempt.url().host_str().unwrap_or_default().to_owned();
+                if attempt.previous().len() > MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if granted.iter().all(|g| g.allows_http_host(&host)) {
+                    attempt.follow()
+                } else {
+                    attempt.error(format!(
+                        "redirected to {host}, which the plugin has not been granted http access to"
+                    ))
+                }
+            }),
+        )
+    }
+}
+
+fn build_client(proxy: Option<&str>, redirect_policy: redirect::Policy) -> reqwest::Client {
+    let mut builder = reqwest::ClientBuilder::new().redirect(redirect_policy);
+    if let Some(proxy_addr) = proxy {
+        let proxy = reqwest::Proxy::all(proxy_addr).expect("valid http proxy address");
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build().expect("valid http client")
+}
+
 // pub fn extension() -> Extension {
 //     Extension::builder("bl_http")
 //         .ops(vec![
@@ -50,42 +115,73 @@ pub fn op_bl_http_client_stream(state: &mut OpState) -> Result<(ResourceId, Reso
     Ok((s_rid, r_rid))
 }
 
+/// Called when a request is created, it's usually sent from a `then` where the stack no longer
+/// leads back to the script that created it
+#[op2]
+#[string]
+pub fn op_bl_http_capture_caller(
+    scope: &mut v8::HandleScope,
+    state: Rc<RefCell<OpState>>,
+) -> String {
+    capture_caller(scope, &state)
+}
+
 #[op2(async)]
 #[serde]
-pub async fn op_bl_http_request_send(
+pub fn op_bl_http_request_send(
+    scope: &mut v8::HandleScope,
     state_rc: Rc<RefCell<OpState>>,
     #[serde] args: ClientHttpRequest,
-) -> Result<ClientHttpResponse, AnyError> {
-    RateLimiters::user_http(&state_rc).await;
-
-    // lookup the body stream resource
-    let req_resource = if let Some(rid) = args.body_resource_id {
-        let mut state = state_rc.borrow_mut();
-        Some(state.resource_table.take::<RequestBodyReceiver>(rid)?)
-    } else {
-        None
-    };
+) -> impl Future<Output = Result<ClientHttpResponse, AnyError>> {
+    // plugins can only reach the hosts they've been granted, redirects included
+    let checked = Url::parse(&args.path)
+        .map_err(AnyError::from)
+        .and_then(|url| {
+            let host = url.host_str().unwrap_or_default();
+            let grant = check_captured_capability(
+                scope,
+                &state_rc,
+                args.caller_token.as_deref(),
+                Capability::Http { host },
+            )?;
+            Ok((url, grant))
+        });
 
-    let parsed_url = Url::parse(&args.path)?;
+    async move {
+        let (parsed_url, grant) = checked?;
 
-    let client = { state_rc.borrow_mut().borrow::<reqwest::Client>().clone() };
-    let mut builder = client.request(reqwest::Method::from_str(&args.method)?, parsed_url);
+        RateLimiters::user_http(&state_rc).await;
 
-    // add headers
-    for (k, v) in args.headers {
-        builder = builder.header(k, v);
-    }
+        // lookup the body stream resource
+        let req_resource = if let Some(rid) = args.body_resource_id {
+            let mut state = state_rc.borrow_mut();
+            Some(state.resource_table.take::<RequestBodyReceiver>(rid)?)
+        } else {
+            None
+        };
 
-    // set the body
-    if let Some(req_resource) = req_resource {
-        // let rx = req_resource
-        let inner = Rc::<RequestBodyReceiver>::into_inner(req_resource).unwrap();
-        builder = builder.body(Body::wrap_stream(ReceiverStream::new(inner.rx)))
-    }
+        let client = state_rc
+            .borrow()
+            .borrow::<ScriptHttpClient>()
+            .for_caller(grant);
+        let mut builder = client.request(reqwest::Method::from_str(&args.method)?, parsed_url);
+
+        // add headers
+        for (k, v) in args.headers {
+            builder = builder.header(k, v);
+        }
 
-    let res = builder.send().await;
+        // set the body
+        if let Some(req_resource) = req_resource {
+            // let rx = req_resource
+            let inner = Rc::<RequestBodyReceiver>::into_inner(req_resource).unwrap();
+            builder = builder.body(Body::wrap_stream(ReceiverStream::new(inner.rx)))
+        }
+
+        let res = builder.send().await;
 
-    handle_response(state_rc, res?)
+        handle_response(state_rc, res?)
+    }
 }
 
 fn handle_response(
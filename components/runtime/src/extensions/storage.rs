@@ -1,7 +1,7 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{cell::RefCell, future::Future, rc::Rc, time::Duration};
 
 use anyhow::anyhow;
-use deno_core::{op2, OpState};
+use deno_core::{op2, v8, OpState};
 use runtime_models::{
     internal::storage::{
         OpStorageBucketEntry, OpStorageBucketEntryId, OpStorageBucketIncr, OpStorageBucketList,
@@ -14,7 +14,10 @@ use tracing::{info, instrument};
 use twilight_model::id::{marker::GuildMarker, Id};
 use vm::AnyError;
 
-use crate::RuntimeContext;
+use crate::{
+    capabilities::{check_capability, Capability},
+    RuntimeContext,
+};
 
 deno_core::extension!(
     bl_storage,
@@ -72,279 +75,356 @@ struct StorageState {
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_set(
+pub fn op_botloader_bucket_storage_set(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketSetValue,
-) -> Result<OpStorageBucketEntry, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
-
-    check_validate_value_len(&args.value)?;
-    check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
-
-    let entry = rt_ctx
-        .db
-        .set(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key,
-            args.value,
-            args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
-        )
-        .await?;
-
-    Ok(entry.into())
+) -> impl Future<Output = Result<OpStorageBucketEntry, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        check_validate_value_len(&args.value)?;
+        check_validate_key_len(&args.key)?;
+        check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+
+        let entry = rt_ctx
+            .db
+            .set(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key,
+                args.value,
+                args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
+            )
+            .await?;
+
+        Ok(entry.into())
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_set_if(
+pub fn op_botloader_bucket_storage_set_if(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketSetIf,
-) -> Result<Option<OpStorageBucketEntry>, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
-
-    check_validate_value_len(&args.value)?;
-    check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
-
-    let entry = rt_ctx
-        .db
-        .set_if(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key,
-            args.value,
-            args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
-            args.cond,
-        )
-        .await?;
-
-    Ok(entry.map(Into::into))
+) -> impl Future<Output = Result<Option<OpStorageBucketEntry>, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        check_validate_value_len(&args.value)?;
+        check_validate_key_len(&args.key)?;
+        check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+
+        let entry = rt_ctx
+            .db
+            .set_if(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key,
+                args.value,
+                args.ttl.map(|ttl| Duration::from_secs(ttl as u64)),
+                args.cond,
+            )
+            .await?;
+
+        Ok(entry.map(Into::into))
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_get(
+pub fn op_botloader_bucket_storage_get(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketEntryId,
-) -> Result<Option<OpStorageBucketEntry>, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
-
-    let entry = rt_ctx
-        .db
-        .get(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key,
-        )
-        .await?;
-
-    Ok(entry.map(Into::into))
+) -> impl Future<Output = Result<Option<OpStorageBucketEntry>, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let entry = rt_ctx
+            .db
+            .get(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key,
+            )
+            .await?;
+
+        Ok(entry.map(Into::into))
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_del(
+pub fn op_botloader_bucket_storage_del(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketEntryId,
-) -> Result<Option<OpStorageBucketEntry>, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
+) -> impl Future<Output = Result<Option<OpStorageBucketEntry>, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let entry = rt_ctx
+            .db
+            .del(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key,
+            )
+            .await?;
+
+        if entry.is_some() {
+            let mut state = state.borrow_mut();
+            let storage_ctx = state.borrow_mut::<StorageState>();
 
-    let entry = rt_ctx
-        .db
-        .del(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key,
-        )
-        .await?;
-
-    if entry.is_some() {
-        let mut state = state.borrow_mut();
-        let storage_ctx = state.borrow_mut::<StorageState>();
+            // re-check in case were at the limti
+            storage_ctx.hit_limit = false;
+        }
 
-        // re-check in case were at the limti
-        storage_ctx.hit_limit = false;
+        Ok(entry.map(Into::into))
     }
-
-    Ok(entry.map(Into::into))
 }
 
 #[op2(async)]
 #[number]
-pub async fn op_botloader_bucket_storage_del_many(
+pub fn op_botloader_bucket_storage_del_many(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] plugin_id: Option<PluginId>,
     #[string] bucket_name: String,
     #[string] key_pattern: String,
-) -> Result<u64, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
+) -> impl Future<Output = Result<u64, AnyError>> {
+    let allowed = check_storage_access(scope, &state, plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let res = rt_ctx
+            .db
+            .del_many(
+                rt_ctx.guild_id,
+                plugin_id.map(Into::into),
+                bucket_name,
+                key_pattern,
+            )
+            .await?;
+
+        if res > 0 {
+            let mut state = state.borrow_mut();
+            let storage_ctx = state.borrow_mut::<StorageState>();
 
-    let res = rt_ctx
-        .db
-        .del_many(
-            rt_ctx.guild_id,
-            plugin_id.map(Into::into),
-            bucket_name,
-            key_pattern,
-        )
-        .await?;
-
-    if res > 0 {
-        let mut state = state.borrow_mut();
-        let storage_ctx = state.borrow_mut::<StorageState>();
+            // re-check in case were at the limti
+            storage_ctx.hit_limit = false;
+        }
 
-        // re-check in case were at the limti
-        storage_ctx.hit_limit = false;
+        Ok(res)
     }
-
-    Ok(res)
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_list(
+pub fn op_botloader_bucket_storage_list(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketList,
-) -> Result<Vec<OpStorageBucketEntry>, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
+) -> impl Future<Output = Result<Vec<OpStorageBucketEntry>, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
 
-    let limit = if let Some(limit) = args.limit {
-        if limit < 100 {
-            limit
-        } else {
-            100
-        }
-    } else {
-        25
-    };
+    async move {
+        allowed?;
 
-    let entries = rt_ctx
-        .db
-        .get_many(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key_pattern.unwrap_or_else(|| "%".to_string()),
-            args.after.unwrap_or_default(),
-            limit,
-        )
-        .await?;
-
-    Ok(entries.into_iter().map(Into::into).collect())
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let limit = if let Some(limit) = args.limit {
+            if limit < 100 {
+                limit
+            } else {
+                100
+            }
+        } else {
+            25
+        };
+
+        let entries = rt_ctx
+            .db
+            .get_many(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key_pattern.unwrap_or_else(|| "%".to_string()),
+                args.after.unwrap_or_default(),
+                limit,
+            )
+            .await?;
+
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
 }
 
 #[op2(async)]
 #[number]
-pub async fn op_botloader_bucket_storage_count(
+pub fn op_botloader_bucket_storage_count(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] plugin_id: Option<PluginId>,
     #[string] bucket_name: String,
     #[string] key_pattern: String,
-) -> Result<u64, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
-
-    let res = rt_ctx
-        .db
-        .count(
-            rt_ctx.guild_id,
-            plugin_id.map(Into::into),
-            bucket_name,
-            key_pattern,
-        )
-        .await?;
-
-    Ok(res)
+) -> impl Future<Output = Result<u64, AnyError>> {
+    let allowed = check_storage_access(scope, &state, plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let res = rt_ctx
+            .db
+            .count(
+                rt_ctx.guild_id,
+                plugin_id.map(Into::into),
+                bucket_name,
+                key_pattern,
+            )
+            .await?;
+
+        Ok(res)
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_incr(
+pub fn op_botloader_bucket_storage_incr(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketIncr,
-) -> Result<OpStorageBucketEntry, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
-
-    check_validate_key_len(&args.key)?;
-    check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
-
-    let entry = rt_ctx
-        .db
-        .incr(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.key,
-            args.amount,
-        )
-        .await?;
-
-    Ok(entry.into())
+) -> impl Future<Output = Result<OpStorageBucketEntry, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
+
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        check_validate_key_len(&args.key)?;
+        check_validate_storage_usage(rt_ctx.guild_id, &rt_ctx, state.clone()).await?;
+
+        let entry = rt_ctx
+            .db
+            .incr(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.key,
+                args.amount,
+            )
+            .await?;
+
+        Ok(entry.into())
+    }
 }
 
 #[op2(async)]
 #[serde]
-pub async fn op_botloader_bucket_storage_sorted_list(
+pub fn op_botloader_bucket_storage_sorted_list(
+    scope: &mut v8::HandleScope,
     state: Rc<RefCell<OpState>>,
     #[serde] args: OpStorageBucketSortedList,
-) -> Result<Vec<OpStorageBucketEntry>, AnyError> {
-    let rt_ctx = {
-        let state = state.borrow();
-        state.borrow::<RuntimeContext>().clone()
-    };
+) -> impl Future<Output = Result<Vec<OpStorageBucketEntry>, AnyError>> {
+    let allowed = check_storage_access(scope, &state, args.plugin_id);
+
+    async move {
+        allowed?;
 
-    let limit = if let Some(limit) = args.limit {
-        if limit < 100 {
-            limit
+        let rt_ctx = {
+            let state = state.borrow();
+            state.borrow::<RuntimeContext>().clone()
+        };
+
+        let limit = if let Some(limit) = args.limit {
+            if limit < 100 {
+                limit
+            } else {
+                100
+            }
         } else {
-            100
-        }
-    } else {
-        25
-    };
+            25
+        };
+
+        let entries = rt_ctx
+            .db
+            .sorted_entries(
+                rt_ctx.guild_id,
+                args.plugin_id.map(Into::into),
+                args.bucket_name,
+                args.order,
+                args.offset.unwrap_or_default(),
+                limit,
+            )
+            .await?;
+
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
+}
 
-    let entries = rt_ctx
-        .db
-        .sorted_entries(
-            rt_ctx.guild_id,
-            args.plugin_id.map(Into::into),
-            args.bucket_name,
-            args.order,
-            args.offset.unwrap_or_default(),
-            limit,
-        )
-        .await?;
-
-    Ok(entries.into_iter().map(Into::into).collect())
+/// Plugins need the storage capability, and can only use their own buckets
+fn check_storage_access(
+    scope: &mut v8::HandleScope,
+    state: &Rc<RefCell<OpState>>,
+    plugin_id: Option<PluginId>,
+) -> Result<(), AnyError> {
+    let grant = check_capability(scope, state, Capability::Storage)?;
+    if grant.allows_buckets_of(plugin_id.map(u64::from)) {
+        Ok(())
+    } else {
+        Err(anyhow!("plugins can only access their own storage buckets"))
+    }
 }
 
 fn check_validate_value_len(val: &OpStorageBucketValue) -> Result<(), AnyError> {
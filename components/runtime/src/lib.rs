@@ -4,10 +4,17 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use common::DiscordConfig;
+use common::{
+    dispatch_event::{EvalOutput, EvalOutputKind, WebhookResponse},
+    DiscordConfig,
+};
 use deno_core::{op2, Extension, OpState, ResourceId, ResourceTable};
 use guild_logger::{entry::CreateLogEntry, GuildLogSender};
-use runtime_models::internal::script::{ScriptMeta, SettingsOptionValue};
+use runtime_models::internal::{
+    eval::{self, EvalOutputMessage},
+    inbound_webhooks::InboundWebhookResponse,
+    script::{ScriptMeta, SettingsOptionValue},
+};
 use stores::{config::PremiumSlotTier, Db};
 use tokio::sync::mpsc;
 use tracing::info;
@@ -15,24 +22,22 @@ use twilight_model::id::marker::GuildMarker;
 use twilight_model::id::Id;
 use vm::{AnyError, JsValue};
 
-use crate::limits::RateLimiters;
+use crate::{extensions::httpclient::ScriptHttpClient, limits::RateLimiters};
 
+pub mod capabilities;
 pub mod extensions;
 pub mod jsmodules;
 pub mod limits;
 
 pub fn create_extensions(ctx: CreateRuntimeContext) -> Vec<Extension> {
-    let mut http_client_builder: reqwest::ClientBuilder = reqwest::ClientBuilder::new();
     if let Some(proxy_addr) = &ctx.script_http_client_proxy {
         info!("using http client proxy: {}", proxy_addr);
-        let proxy = reqwest::Proxy::all(proxy_addr).expect("valid http proxy address");
-        http_client_builder = http_client_builder.proxy(proxy);
     } else {
         #[cfg(not(debug_assertions))]
         tracing::warn!("no proxy set in release!");
     }
 
-    let http_client = http_client_builder.build().expect("valid http client");
+    let http_client = ScriptHttpClient::new(ctx.script_http_client_proxy.clone());
     let premium_tier = *ctx.premium_tier.read().unwrap();
     let core_ctx = CoreRuntimeContext {
         event_tx: ctx.event_tx.clone(),
@@ -85,11 +90,13 @@ deno_core::extension!(
         op_get_current_guild_id,
         op_get_run_mode,
         op_get_settings,
+        op_botloader_eval_output,
+        op_botloader_webhook_response,
     ],
     options = {
         ctx: CoreRuntimeContext,
         rt_ctx: RuntimeContext,
-        http_client: reqwest::Client,
+        http_client: ScriptHttpClient,
         premium_tier: Option<PremiumSlotTier>,
     },
     middleware = |op_decl|match op_decl.name {
@@ -116,10 +123,12 @@ deno_core::extension!(
         op_get_current_guild_id,
         op_get_run_mode,
         op_get_settings,
+        op_botloader_eval_output,
+        op_botloader_webhook_response,
     ],
     options = {
         ctx: CoreRuntimeContext,
-        http_client: reqwest::Client,
+        http_client: ScriptHttpClient,
         premium_tier: Option<PremiumSlotTier>,
     },
     middleware = |op_decl|match op_decl.name {
@@ -261,6 +270,37 @@ pub fn op_get_settings(
     Ok(script_settings_entry.settings_values.clone())
 }
 
+#[op2]
+pub fn op_botloader_eval_output(state: &mut OpState, #[serde] args: EvalOutputMessage) {
+    let core_ctx = state.borrow::<CoreRuntimeContext>();
+
+    let kind = match args.kind {
+        eval::EvalOutputKind::ConsoleLog => EvalOutputKind::ConsoleLog,
+        eval::EvalOutputKind::ConsoleWarn => EvalOutputKind::ConsoleWarn,
+        eval::EvalOutputKind::ConsoleError => EvalOutputKind::ConsoleError,
+        eval::EvalOutputKind::Result => EvalOutputKind::Result,
+        eval::EvalOutputKind::Exception => EvalOutputKind::Exception,
+    };
+
+    let _ = core_ctx.event_tx.send(RuntimeEvent::EvalOutput(EvalOutput {
+        eval_id: args.eval_id.0,
+        kind,
+        message: args.message,
+    }));
+}
+
+#[op2]
+pub fn op_botloader_webhook_response(state: &mut OpState, #[serde] args: InboundWebhookResponse) {
+    let core_ctx = state.borrow::<CoreRuntimeContext>();
+
+    let _ = core_ctx
+        .event_tx
+        .send(RuntimeEvent::WebhookResponse(WebhookResponse {
+            request_id: args.request_id.0,
+            body: args.body.filter(|v| !v.is_null()),
+        }));
+}
+
 pub(crate) fn validate_script_meta(meta: &ScriptMeta) -> Result<(), anyhow::Error> {
     let mut out_buf = String::new();
 
@@ -315,6 +355,8 @@ pub fn try_insert_resource_table<T: deno_core::Resource>(
 pub enum RuntimeEvent {
     ScriptStarted(ScriptMeta),
     NewTaskScheduled,
+    EvalOutput(EvalOutput),
+    WebhookResponse(WebhookResponse),
 }
 
 impl RuntimeEvent {
@@ -322,6 +364,8 @@ impl RuntimeEvent {
         match self {
             RuntimeEvent::ScriptStarted(_) => "RuntimeEvent::ScriptStarted",
             RuntimeEvent::NewTaskScheduled => "RuntimeEvent::NewTaskScheduled",
+            RuntimeEvent::EvalOutput(_) => "RuntimeEvent::EvalOutput",
+            RuntimeEvent::WebhookResponse(_) => "RuntimeEvent::WebhookResponse",
         }
     }
 }
@@ -39,6 +39,16 @@ export namespace Commands {
             this.commands.push(cmd);
         }
 
+        /**
+         * @internal
+         */
+        removeCommand(cmd: Command) {
+            const index = this.commands.indexOf(cmd);
+            if (index !== -1) {
+                this.commands.splice(index, 1);
+            }
+        }
+
         /**
          * @internal
          */
@@ -0,0 +1,64 @@
+import { OpWrappers } from "./op_wrappers";
+import type { EvalOutputKind } from "./generated/internal/index";
+
+const non_json = ["boolean", "number", "string", "undefined", "bigint", "symbol", "function"];
+
+/**
+ * Runs snippets sent through the eval websocket command, the vm wraps the snippet in a async
+ * function and passes it here.
+ *
+ * The console passed to the snippet sends its output back to the eval session instead of the guild logs.
+ *
+ * @internal
+ */
+export namespace Eval {
+    export async function run(evalId: number, snippet: (console: EvalConsole) => Promise<unknown>) {
+        const evalConsole: EvalConsole = {
+            log: (...args: any[]) => output(evalId, "consoleLog", formatItems(args)),
+            warn: (...args: any[]) => output(evalId, "consoleWarn", formatItems(args)),
+            error: (...args: any[]) => output(evalId, "consoleError", formatItems(args)),
+        };
+
+        try {
+            const result = await snippet(evalConsole);
+            output(evalId, "result", formatItem(result));
+        } catch (e) {
+            if (e instanceof Error) {
+                output(evalId, "exception", e.stack ?? `${e.name}: ${e.message}`);
+            } else {
+                output(evalId, "exception", formatItem(e));
+            }
+        }
+    }
+
+    export interface EvalConsole {
+        log: (...args: any[]) => void,
+        warn: (...args: any[]) => void,
+        error: (...args: any[]) => void,
+    }
+
+    function output(evalId: number, kind: EvalOutputKind, message: string) {
+        OpWrappers.evalOutput({
+            evalId,
+            kind,
+            message,
+        })
+    }
+
+    function formatItems(items: any[]) {
+        return items.map(formatItem).join(", ");
+    }
+
+    function formatItem(item: any) {
+        if (non_json.includes(typeof item)) {
+            return String(item);
+        }
+
+        try {
+            return JSON.stringify(item, undefined, 2);
+        } catch {
+            // circular structures and such
+            return String(item);
+        }
+    }
+}
@@ -34,6 +34,7 @@ import {
     MentionableSelectMenuInteraction
 } from './discord/index';
 import * as Internal from './generated/internal/index';
+import { OpWrappers } from './op_wrappers';
 
 export namespace EventSystem {
 
@@ -46,6 +47,8 @@ export namespace EventSystem {
 
     const modalSubmitListeners: { name: string, cb: (data: ModalSubmitInteraction, extra: any) => any }[] = [];
 
+    const webhookListeners: { name: string, cb: (request: WebhookRequest) => any }[] = [];
+
     /**
      * @internal
      */
@@ -60,6 +63,16 @@ export namespace EventSystem {
         eventMuxers.push(muxer)
     }
 
+    /**
+     * @internal
+     */
+    export function unregisterEventMuxer(muxer: Muxer) {
+        const index = eventMuxers.indexOf(muxer);
+        if (index !== -1) {
+            eventMuxers.splice(index, 1);
+        }
+    }
+
     /**
      * @internal
      */
@@ -76,6 +89,8 @@ export namespace EventSystem {
             commandSystem.handleInteractionCreate(data);
         } else if (evt.name == "BOTLOADER_MODAL_SUBMIT_INTERACTION_CREATE") {
             handleModalSubmitInteraction(data);
+        } else if (evt.name == "BOTLOADER_WEBHOOK_REQUEST") {
+            handleWebhookRequest(data);
         } else {
             for (let muxer of eventMuxers) {
                 muxer.handleEvent(evt.name, data);
@@ -244,6 +259,83 @@ export namespace EventSystem {
         modalSubmitListeners.push({ name: name, cb: cb })
     }
 
+    /**
+     * @internal
+     */
+    export function onWebhook(name: string, cb: (request: WebhookRequest) => any) {
+        webhookListeners.push({ name: name, cb: cb })
+    }
+
+    /**
+     * @internal
+     */
+    export function removeWebhookListener(cb: (request: WebhookRequest) => any) {
+        const index = webhookListeners.findIndex(v => v.cb === cb);
+        if (index !== -1) {
+            webhookListeners.splice(index, 1);
+        }
+    }
+
+    /**
+     * A request sent to one of the server's inbound webhooks, the signature has already been verified
+     * by the time it reaches the script
+     */
+    export interface WebhookRequest {
+        webhookId: number,
+        /**
+         * Name of the webhook as configured on the dashboard
+         */
+        name: string,
+        /**
+         * The json body of the request
+         */
+        body: unknown,
+    }
+
+    async function handleWebhookRequest(request: Internal.InboundWebhookRequest) {
+        let body: unknown = null;
+        try {
+            const listener = webhookListeners.find((elem) => elem.name === request.name);
+            if (listener) {
+                body = await listener.cb({
+                    webhookId: request.webhookId,
+                    name: request.name,
+                    body: request.body,
+                });
+            }
+        } finally {
+            // always respond so the caller isn't left waiting, errors are still logged as usual
+            OpWrappers.webhookResponse({
+                requestId: request.requestId,
+                body: body ?? null,
+            });
+        }
+    }
+
+    /**
+     * Removes a interaction listener registered with one of the onInteraction* functions
+     * 
+     * @internal
+     */
+    export function removeInteractionListener(cb: (...args: any[]) => any) {
+        const lists: { name: string, cb: (...args: any[]) => any }[][] = [
+            buttonComponentListeners,
+            selectMenuListeners,
+            userSelectMenuListeners,
+            channelSelectMenuListeners,
+            roleSelectMenuListeners,
+            mentionableSelectMenuListeners,
+            modalSubmitListeners,
+        ];
+
+        for (const list of lists) {
+            const index = list.findIndex(v => v.cb === cb);
+            if (index !== -1) {
+                list.splice(index, 1);
+            }
+        }
+    }
+
     async function handleComponentInteraction(interaction: Internal.MessageComponentInteraction) {
         if (!interaction.customId.startsWith("0:")) {
             return;
@@ -5,5 +5,6 @@ export interface ClientHttpRequest {
   method: string;
   headers: Record<string, string>;
   scriptId?: number;
+  callerToken?: string;
   bodyResourceId?: number;
 }
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EvalOutputKind } from "./EvalOutputKind";
+
+export interface EvalOutputMessage {
+  evalId: number;
+  kind: EvalOutputKind;
+  message: string;
+}
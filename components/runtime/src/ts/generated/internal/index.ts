@@ -32,6 +32,8 @@ export * from './EasyOpsASync'
 export * from './EasyOpsReturnTypesASync'
 export * from './EditChannelMessage'
 export * from './EditChannel'
+export * from './EvalOutputKind'
+export * from './EvalOutputMessage'
 export * from './EventMemberRemove'
 export * from './EventMessageReactionAdd'
 export * from './EventMessageUpdate'
@@ -60,6 +62,8 @@ export * from './IMessage'
 export * from './IModalCallbackData'
 export * from './IModalInteractionDataComponent'
 export * from './IModalInteraction'
+export * from './InboundWebhookRequest'
+export * from './InboundWebhookResponse'
 export * from './InteractionCallbackData'
 export * from './InteractionCallback'
 export * from './InteractionChannel'
@@ -141,9 +141,13 @@ export namespace HttpClient {
         path: string;
         method: string;
 
+        // requests are usually sent from `then`, after the script that created them left the stack
+        private callerToken: string;
+
         constructor(method: string, path: string, init?: RequestInit) {
             this.path = path;
             this.method = method;
+            this.callerToken = OpWrappers.http.captureCaller();
 
             this.headers = init?.headers;
             // this.redirect = init?.redirect;
@@ -174,6 +178,7 @@ export namespace HttpClient {
                 method: this.method,
                 path: this.path,
                 scriptId: this.scriptId ?? 0,
+                callerToken: this.callerToken,
                 bodyResourceId: reqBodyRid,
             });
 
@@ -1,3 +1,4 @@
 declare let BotloaderCore: {
     dispatchEvent: (evt: { name: string, data: any }) => void;
+    unloadScript: (scriptId: number) => boolean;
 };
\ No newline at end of file
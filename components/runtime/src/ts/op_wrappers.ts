@@ -103,6 +103,10 @@ export namespace OpWrappers {
             return Deno.core.ops.op_bl_http_client_stream()
         }
 
+        export function captureCaller(): string {
+            return Deno.core.ops.op_bl_http_capture_caller()
+        }
+
         export function requestSend(args: Internal.ClientHttpRequest): Promise<Internal.ClientHttpResponse> {
             return op_bl_http_request_send(args)
         }
@@ -154,6 +158,18 @@ export namespace OpWrappers {
         );
     }
 
+    export function evalOutput(args: Internal.EvalOutputMessage) {
+        Deno.core.ops.op_botloader_eval_output(
+            args
+        );
+    }
+
+    export function webhookResponse(args: Internal.InboundWebhookResponse) {
+        Deno.core.ops.op_botloader_webhook_response(
+            args
+        );
+    }
+
     export async function callAsyncOp<T extends Internal.EasyOpsASync>(call: T): Promise<Internal.EasyOpsReturnTypesASync[T["kind"]]> {
         return await op_easyops_async(call)
     }
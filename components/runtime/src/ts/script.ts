@@ -11,6 +11,19 @@ import { Tasks } from "./scheduled_tasks";
 import { ComponentInteraction, SelectMenuInteraction, ModalSubmitInteraction, UserSelectMenu, RoleSelectMenuInteraction, UserSelectMenuInteraction, ChannelSelectMenuInteraction, MentionableSelectMenuInteraction } from "./discord/index";
 import { SettingsManager } from "./settings";
 
+// scripts that have been run, used to unload a script when it's reloaded
+const loadedScripts = new Map<number, Script>();
+
+BotloaderCore.unloadScript = (scriptId: number) => {
+    const script = loadedScripts.get(scriptId);
+    if (!script) {
+        return false;
+    }
+
+    script.unload();
+    return true;
+};
+
 /**
  * The script class is the main way you interact with botloader and discord.
  */
@@ -26,6 +39,8 @@ export class Script {
     private storageBuckets: Storage.Bucket<unknown>[] = [];
     private taskHandlers: Internal.TaskBucketId[] = [];
     private commands: Commands.Command[] = [];
+    private interactionListeners: ((...args: any[]) => any)[] = [];
+    private webhookListeners: ((request: EventSystem.WebhookRequest) => any)[] = [];
     settings: SettingsManager;
 
     private runCalled = false;
@@ -254,6 +269,7 @@ export class Script {
      */
     onInteractionButton<T>(name: string, cb: (interaction: ComponentInteraction, extraData: T) => any) {
         EventSystem.onInteractionButton(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -261,6 +277,7 @@ export class Script {
      */
     onInteractionSelectMenu<T>(name: string, cb: (interaction: SelectMenuInteraction, extraData: T) => any) {
         EventSystem.onInteractionSelectMenu(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -268,6 +285,7 @@ export class Script {
      */
     onInteractionUserSelectMenu<T>(name: string, cb: (interaction: UserSelectMenuInteraction, extraData: T) => any) {
         EventSystem.onInteractionUserSelectMenu(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -275,6 +293,7 @@ export class Script {
      */
     onInteractionRoleSelectMenu<T>(name: string, cb: (interaction: RoleSelectMenuInteraction, extraData: T) => any) {
         EventSystem.onInteractionRoleSelectMenu(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -282,6 +301,7 @@ export class Script {
      */
     onInteractionChannelSelectMenu<T>(name: string, cb: (interaction: ChannelSelectMenuInteraction, extraData: T) => any) {
         EventSystem.onInteractionChannelSelectMenu(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -289,6 +309,7 @@ export class Script {
      */
     onInteractionMentionableSelectMenu<T>(name: string, cb: (interaction: MentionableSelectMenuInteraction, extraData: T) => any) {
         EventSystem.onInteractionMentionableSelectMenu(name, cb);
+        this.interactionListeners.push(cb);
     }
 
     /**
@@ -296,6 +317,32 @@ export class Script {
      */
     onInteractionModalSubmit<T>(name: string, cb: (interaction: ModalSubmitInteraction, customData: T) => any) {
         EventSystem.onInteractionModalSubmit(name, cb);
+        this.interactionListeners.push(cb);
+    }
+
+    /**
+     * Register a handler for requests to one of the server's inbound webhooks
+     * 
+     * Webhooks are created on the dashboard, external services (GitHub, CI, game servers...) can then
+     * send signed json requests to them. Whatever the handler returns is sent back as the json body of the response,
+     * if it returns nothing the response has no body.
+     * 
+     * Only one handler is run per request, if multiple scripts register a handler for the same webhook the first one is used.
+     * 
+     * @param name The name of the webhook as configured on the dashboard
+     * 
+     * @example 
+     * ```ts
+     * script.onWebhook("github", async (request) => {
+     *     const payload = request.body as { ref: string };
+     *     await Discord.createMessage("123", { content: `pushed to ${payload.ref}` });
+     *     return { ok: true };
+     * });
+     * ```
+     */
+    onWebhook(name: string, cb: (request: EventSystem.WebhookRequest) => any) {
+        EventSystem.onWebhook(name, cb);
+        this.webhookListeners.push(cb);
     }
 
     /**
@@ -375,6 +422,7 @@ export class Script {
         }
 
         this.runCalled = true;
+        loadedScripts.set(this.scriptId, this);
 
         const [cmds, groups] = this.genCommandsBinding();
 
@@ -394,6 +442,37 @@ export class Script {
         this.events.on("BOTLOADER_INTERVAL_TIMER_FIRED", this.handleIntervalEvent.bind(this));
     }
 
+    /**
+     * Unregisters everything this script registered so a new version of it can be loaded in its place
+     * 
+     * Timers and promises created by the script itself are left running
+     * 
+     * @internal
+     */
+    unload() {
+        EventSystem.unregisterEventMuxer(this.events);
+
+        for (const cmd of this.commands) {
+            EventSystem.commandSystem.removeCommand(cmd);
+        }
+
+        for (const cb of this.interactionListeners) {
+            EventSystem.removeInteractionListener(cb);
+        }
+
+        for (const cb of this.webhookListeners) {
+            EventSystem.removeWebhookListener(cb);
+        }
+
+        this.commands = [];
+        this.interactionListeners = [];
+        this.webhookListeners = [];
+        this.intervalTimers = [];
+        this.taskHandlers = [];
+
+        loadedScripts.delete(this.scriptId);
+    }
+
     private async handleIntervalEvent(evt: Internal.IntervalTimerEvent) {
         const timer = this.intervalTimers.find(
             timer => timer.timer.name === evt.name && this.pluginId === evt.pluginId
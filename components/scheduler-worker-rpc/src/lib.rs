@@ -1,19 +1,36 @@
 use std::collections::HashMap;
 
-use common::dispatch_event::VmDispatchEvent;
+use common::dispatch_event::{EvalOutput, VmDispatchEvent, WebhookResponse};
 use runtime_models::internal::script::ScriptMeta;
 use serde::{Deserialize, Serialize};
-use stores::config::{PremiumSlotTier, Script};
+use simpleproto::Codec;
+use stores::config::{PremiumSlotTier, Script, VendoredModule};
 use twilight_model::id::{marker::GuildMarker, Id};
 use vm::vm::ShutdownReason;
 
+pub mod remote_auth;
+
+/// Bumped whenever the messages change in a way that isn't compatible with older workers
+pub const PROTOCOL_VERSION: u32 = 3;
+
 #[derive(Deserialize, Serialize)]
 pub enum SchedulerMessage {
     Dispatch(VmDispatchEvent),
     /// stops the current vm and creates a new one to run the provided scripts
     CreateScriptsVm(CreateScriptsVmReq),
+    /// replaces a single script in the current vm, restarting it if that fails
+    UpdateScript(UpdateScriptReq),
     Complete,
     Shutdown,
+    /// sent to remote workers right after they connect, answered with [`WorkerMessage::RemoteHello`]
+    AuthChallenge(String),
+    /// health check for remote workers, answered with [`WorkerMessage::Pong`]
+    Ping,
+    /// sent in response to the worker hello, the rest of the messages are encoded with the codec
+    Welcome(Codec),
+    /// sent in response to the remote worker hello with the scheduler's side of the proof,
+    /// see [`remote_auth::Handshake`], the rest of the messages are signed frames
+    RemoteWelcome(Codec, String),
 }
 
 impl SchedulerMessage {
@@ -21,8 +38,13 @@ impl SchedulerMessage {
         match self {
             SchedulerMessage::Dispatch(_) => None,
             SchedulerMessage::CreateScriptsVm(v) => Some(v.guild_id),
+            SchedulerMessage::UpdateScript(_) => None,
             SchedulerMessage::Complete => None,
             SchedulerMessage::Shutdown => None,
+            SchedulerMessage::AuthChallenge(_) => None,
+            SchedulerMessage::Ping => None,
+            SchedulerMessage::Welcome(_) => None,
+            SchedulerMessage::RemoteWelcome(..) => None,
         }
     }
 
@@ -30,8 +52,13 @@ impl SchedulerMessage {
         match self {
             SchedulerMessage::Dispatch(_) => "SchedulerMessage::Dispatch",
             SchedulerMessage::CreateScriptsVm(_) => "SchedulerMessage::CreateScriptsVm",
+            SchedulerMessage::UpdateScript(_) => "SchedulerMessage::UpdateScript",
             SchedulerMessage::Complete => "SchedulerMessage::Complete",
             SchedulerMessage::Shutdown => "SchedulerMessage::Shutdown",
+            SchedulerMessage::AuthChallenge(_) => "SchedulerMessage::AuthChallenge",
+            SchedulerMessage::Ping => "SchedulerMessage::Ping",
+            SchedulerMessage::Welcome(_) => "SchedulerMessage::Welcome",
+            SchedulerMessage::RemoteWelcome(..) => "SchedulerMessage::RemoteWelcome",
         }
     }
 }
@@ -43,6 +70,13 @@ pub struct CreateScriptsVmReq {
     pub premium_tier: Option<PremiumSlotTier>,
     pub guild_id: Id<GuildMarker>,
     pub scripts: Vec<Script>,
+    pub vendored_modules: Vec<VendoredModule>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UpdateScriptReq {
+    pub seq: u64,
+    pub script: Script,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -61,8 +95,12 @@ pub enum WorkerMessage {
     NonePending,
     TaskScheduled,
     GuildLog(guild_logger::LogEntry),
-    Hello(u64),
+    Hello(WorkerHello),
+    RemoteHello(RemoteWorkerHello),
+    Pong,
     Metric(String, MetricEvent, HashMap<String, String>),
+    EvalOutput(EvalOutput),
+    WebhookResponse(WebhookResponse),
 }
 
 impl WorkerMessage {
@@ -76,11 +114,39 @@ impl WorkerMessage {
             WorkerMessage::TaskScheduled => "TaskScheduled",
             WorkerMessage::GuildLog(_) => "GuildLog",
             WorkerMessage::Hello(_) => "Hello",
+            WorkerMessage::RemoteHello(_) => "RemoteHello",
+            WorkerMessage::Pong => "Pong",
             WorkerMessage::Metric(_, _, _) => "Metric",
+            WorkerMessage::EvalOutput(_) => "EvalOutput",
+            WorkerMessage::WebhookResponse(_) => "WebhookResponse",
         }
     }
 }
 
+/// Sent by workers spawned by the scheduler when they connect
+#[derive(Deserialize, Serialize)]
+pub struct WorkerHello {
+    pub worker_id: u64,
+    pub protocol_version: u32,
+    /// The codecs the worker supports, the scheduler picks one and sends it back in the welcome
+    pub codecs: Vec<Codec>,
+}
+
+/// Sent by workers connecting over tcp in response to the auth challenge
+#[derive(Deserialize, Serialize)]
+pub struct RemoteWorkerHello {
+    /// Name of the worker, used in logs and status output
+    pub name: String,
+    /// The premium tier the worker provides capacity for
+    pub premium_tier: Option<PremiumSlotTier>,
+    /// The worker's challenge for the scheduler
+    pub challenge: String,
+    /// The worker's side of the proof, see [`remote_auth::Handshake`]
+    pub signature: String,
+    pub protocol_version: u32,
+    pub codecs: Vec<Codec>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub enum MetricEvent {
     Gauge(GaugeEvent),
@@ -99,3 +165,307 @@ pub enum CounterEvent {
     Incr(u64),
     Absolute(u64),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::{
+        dispatch_event::{
+            EvalOutput, EvalOutputKind, EventSource, VmDispatchEvent, WebhookResponse,
+            WEBHOOK_EVENT_NAME,
+        },
+        plugin::{LibraryModule, PluginCapabilities, PluginDependency, ReleaseChannel},
+    };
+    use guild_logger::{
+        entry::{ScriptContext, StackFrame},
+        LogEntry, LogLevel,
+    };
+    use runtime_models::{
+        internal::{
+            interaction::CommandType,
+            script::{
+                Command, CommandOption, CommandOptionChoice, CommandOptionChoiceValue,
+                CommandOptionType, ExtraCommandOptions, IntervalTimer, IntervalType, ScriptMeta,
+                SettingsOptionValue, TaskBucketId,
+            },
+        },
+        util::{NotBigU64, PluginId},
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use simpleproto::Codec;
+    use stores::{
+        config::{
+            IntervalTimerContrib, PremiumSlotTier, Script, ScriptContributes, SettingsProblem,
+            VendoredModule, VendoredModuleOwner,
+        },
+        timers,
+    };
+    use twilight_model::id::Id;
+    use vm::vm::ShutdownReason;
+
+    use super::*;
+
+    fn scheduler_messages() -> Vec<SchedulerMessage> {
+        vec![
+            SchedulerMessage::Dispatch(VmDispatchEvent {
+                name: "MESSAGE_CREATE".to_string(),
+                seq: 5,
+                value: serde_json::json!({
+                    "id": "1234567890123456789",
+                    "content": "hello",
+                    "embeds": [],
+                    "nonce": 1.5,
+                    "tts": false,
+                    "edited": null,
+                }),
+                source: EventSource::Discord,
+                source_timestamp: chrono::Utc::now(),
+            }),
+            SchedulerMessage::Dispatch(VmDispatchEvent {
+                name: WEBHOOK_EVENT_NAME.to_string(),
+                seq: 6,
+                value: serde_json::json!({
+                    "requestId": 1,
+                    "webhookId": 2,
+                    "name": "github",
+                    "body": { "ref": "refs/heads/main" },
+                }),
+                source: EventSource::Webhook,
+                source_timestamp: chrono::Utc::now(),
+            }),
+            SchedulerMessage::CreateScriptsVm(CreateScriptsVmReq {
+                seq: 1,
+                session_id: u64::MAX,
+                premium_tier: Some(PremiumSlotTier::Lite),
+                guild_id: Id::new(123),
+                scripts: vec![Script {
+                    id: 10,
+                    name: "main".to_string(),
+                    original_source: "console.log(\"hi\")\n".to_string(),
+                    enabled: true,
+                    contributes: ScriptContributes {
+                        commands: vec![],
+                        interval_timers: vec![IntervalTimerContrib {
+                            name: "every 5".to_string(),
+                            interval: timers::IntervalType::Minutes(5),
+                            plugin_id: None,
+                        }],
+                    },
+                    plugin_id: Some(3),
+                    plugin_auto_update: None,
+                    plugin_version_number: Some(2),
+                    settings_definitions: None,
+                    settings_values: vec![SettingsOptionValue {
+                        name: "channel".to_string(),
+                        value: serde_json::json!(["a", 1, { "b": null }]),
+                    }],
+                    is_library: false,
+                    library_modules: vec![LibraryModule {
+                        name: "util".to_string(),
+                        source: "export const a = 1;".to_string(),
+                    }],
+                    plugin_channel: ReleaseChannel::Beta,
+                    plugin_capabilities: Some(PluginCapabilities {
+                        http_hosts: vec!["*.example.com".to_string()],
+                        moderation: false,
+                        storage: true,
+                        direct_messages: false,
+                    }),
+                    plugin_dependencies: vec![PluginDependency {
+                        plugin_id: 4,
+                        min_version: 1,
+                        max_version: Some(3),
+                    }],
+                    settings_problems: vec![SettingsProblem {
+                        option: "channel".to_string(),
+                        message: "can't convert \"a\" to integer".to_string(),
+                    }],
+                }],
+                vendored_modules: vec![VendoredModule {
+                    id: 1,
+                    owner: VendoredModuleOwner::Guild(Id::new(123)),
+                    name: "lodash".to_string(),
+                    source: "export default {}".to_string(),
+                    content_hash: "abcd".to_string(),
+                    size_bytes: 17,
+                    created_at: chrono::Utc::now(),
+                }],
+            }),
+            SchedulerMessage::UpdateScript(UpdateScriptReq {
+                seq: 2,
+                script: Script {
+                    id: 11,
+                    name: "other".to_string(),
+                    original_source: "script.on(\"MESSAGE_CREATE\", () => {})".to_string(),
+                    enabled: true,
+                    contributes: ScriptContributes {
+                        commands: vec![],
+                        interval_timers: vec![],
+                    },
+                    plugin_id: None,
+                    plugin_auto_update: None,
+                    plugin_version_number: None,
+                    settings_definitions: None,
+                    settings_values: vec![],
+                    is_library: false,
+                    library_modules: vec![],
+                    plugin_channel: ReleaseChannel::Stable,
+                    plugin_capabilities: None,
+                    plugin_dependencies: vec![],
+                    settings_problems: vec![],
+                },
+            }),
+            SchedulerMessage::Complete,
+            SchedulerMessage::Shutdown,
+            SchedulerMessage::AuthChallenge("challenge".to_string()),
+            SchedulerMessage::Ping,
+            SchedulerMessage::Welcome(Codec::MessagePack),
+            SchedulerMessage::RemoteWelcome(Codec::Json, "abcd".to_string()),
+        ]
+    }
+
+    fn worker_messages() -> Vec<WorkerMessage> {
+        vec![
+            WorkerMessage::Ack(7),
+            WorkerMessage::Shutdown(VmSessionShutdownEvent {
+                vm_session_id: 2,
+                guild_id: Id::new(123),
+                reason: Some(ShutdownReason::OutOfMemory),
+            }),
+            WorkerMessage::ScriptStarted(ScriptMeta {
+                description: "test".to_string(),
+                script_id: NotBigU64(10),
+                plugin_id: Some(PluginId(3)),
+                commands: vec![Command {
+                    name: "echo".to_string(),
+                    description: "echoes".to_string(),
+                    options: vec![CommandOption {
+                        name: "what".to_string(),
+                        description: "what to echo".to_string(),
+                        kind: CommandOptionType::String,
+                        required: true,
+                        extra_options: ExtraCommandOptions {
+                            min_value: None,
+                            max_value: Some(10.0),
+                            channel_types: None,
+                            choices: Some(vec![
+                                CommandOptionChoice {
+                                    name: "a".to_string(),
+                                    value: CommandOptionChoiceValue::String("a".to_string()),
+                                },
+                                CommandOptionChoice {
+                                    name: "b".to_string(),
+                                    value: CommandOptionChoiceValue::Number(1.5),
+                                },
+                            ]),
+                        },
+                        autocomplete_enabled: false,
+                    }],
+                    group: None,
+                    sub_group: Some("sub".to_string()),
+                    kind: CommandType::Chat,
+                }],
+                command_groups: vec![],
+                interval_timers: vec![IntervalTimer {
+                    name: "timer".to_string(),
+                    interval: IntervalType::Cron("0 * * * *".to_string()),
+                }],
+                task_buckets: vec![TaskBucketId {
+                    name: "bucket".to_string(),
+                    plugin_id: None,
+                }],
+                settings: vec![],
+            }),
+            WorkerMessage::ScriptsInit,
+            WorkerMessage::NonePending,
+            WorkerMessage::TaskScheduled,
+            WorkerMessage::GuildLog(LogEntry {
+                guild_id: Id::new(123),
+                message: "something went wrong".to_string(),
+                script_context: Some(ScriptContext {
+                    filename: "main.ts".to_string(),
+                    line_col: Some((1, 2)),
+                }),
+                level: LogLevel::Error,
+                stack: vec![StackFrame {
+                    filename: "main.ts".to_string(),
+                    line_col: None,
+                    function_name: Some("run".to_string()),
+                }],
+            }),
+            WorkerMessage::Hello(WorkerHello {
+                worker_id: 5,
+                protocol_version: PROTOCOL_VERSION,
+                codecs: Codec::ALL.to_vec(),
+            }),
+            WorkerMessage::RemoteHello(RemoteWorkerHello {
+                name: "remote-1".to_string(),
+                premium_tier: None,
+                challenge: "challenge".to_string(),
+                signature: "abcd".to_string(),
+                protocol_version: PROTOCOL_VERSION,
+                codecs: vec![Codec::Json],
+            }),
+            WorkerMessage::Pong,
+            WorkerMessage::Metric(
+                "bl.vm.thing".to_string(),
+                MetricEvent::Histogram(0.25),
+                HashMap::from([("guild_id".to_string(), "123".to_string())]),
+            ),
+            WorkerMessage::Metric(
+                "bl.vm.thing".to_string(),
+                MetricEvent::Gauge(GaugeEvent::Incr(-1.0)),
+                HashMap::new(),
+            ),
+            WorkerMessage::Metric(
+                "bl.vm.thing".to_string(),
+                MetricEvent::Counter(CounterEvent::Absolute(u64::MAX)),
+                HashMap::new(),
+            ),
+            WorkerMessage::EvalOutput(EvalOutput {
+                eval_id: 1,
+                kind: EvalOutputKind::Result,
+                message: "2".to_string(),
+            }),
+            WorkerMessage::WebhookResponse(WebhookResponse {
+                request_id: 3,
+                body: Some(serde_json::json!({"ok": true})),
+            }),
+            WorkerMessage::WebhookResponse(WebhookResponse {
+                request_id: 4,
+                body: None,
+            }),
+        ]
+    }
+
+    // compares the json representation since the messages don't implement PartialEq
+    fn assert_round_trip<T: Serialize + DeserializeOwned>(codec: Codec, msg: &T) {
+        let encoded = codec.encode(msg).unwrap();
+        let decoded: T = codec.decode(&encoded).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(msg).unwrap(),
+            serde_json::to_value(&decoded).unwrap(),
+            "{codec:?} round trip"
+        );
+    }
+
+    #[test]
+    fn scheduler_messages_round_trip() {
+        for codec in Codec::ALL {
+            for msg in scheduler_messages() {
+                assert_round_trip(codec, &msg);
+            }
+        }
+    }
+
+    #[test]
+    fn worker_messages_round_trip() {
+        for codec in Codec::ALL {
+            for msg in worker_messages() {
+                assert_round_trip(codec, &msg);
+            }
+        }
+    }
+}
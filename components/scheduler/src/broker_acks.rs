@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+
+use common::shutdown::DrainSignal;
+use tokio::sync::Notify;
+
+/// Keeps track of which events received from the broker have been handled
+///
+/// Acks are cumulative, so we can only ack up until the oldest event still on its way to a vm.
+/// Everything we haven't acked is handed to the scheduler taking over the guild if we go away,
+/// a new one of these is used for every connection as the sequence numbers restart.
+#[derive(Clone)]
+pub struct BrokerAcks {
+    inner: Arc<Mutex<AcksInner>>,
+    changed: Arc<Notify>,
+    drain: DrainSignal,
+}
+
+struct AcksInner {
+    last_received: u64,
+    // events received but not handled yet
+    pending: BTreeSet<u64>,
+}
+
+impl BrokerAcks {
+    pub fn new(drain: DrainSignal) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(AcksInner {
+                last_received: 0,
+                pending: BTreeSet::new(),
+            })),
+            changed: Arc::new(Notify::new()),
+            drain,
+        }
+    }
+
+    /// Records a message that is handled as soon as it's received
+    pub fn received(&self, seq: u64) {
+        self.inner.lock().unwrap().last_received = seq;
+    }
+
+    /// Records an event that is handled once the returned ack is dropped or marked as done
+    pub fn track(&self, seq: u64) -> BrokerEventAck {
+        let mut inner = self.inner.lock().unwrap();
+        inner.last_received = seq;
+        inner.pending.insert(seq);
+
+        BrokerEventAck {
+            seq,
+            acks: self.clone(),
+            finished: false,
+        }
+    }
+
+    /// The sequence number up until which all the events have been handled
+    pub fn ackable(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        match inner.pending.first() {
+            Some(oldest) => oldest - 1,
+            None => inner.last_received,
+        }
+    }
+
+    /// Resolves once an event has been handled since the last call
+    pub async fn wait_changed(&self) {
+        self.changed.notified().await
+    }
+
+    fn handled(&self, seq: u64) {
+        self.inner.lock().unwrap().pending.remove(&seq);
+        self.changed.notify_one();
+    }
+}
+
+/// Held on to while a broker event is on its way to a vm
+///
+/// Dropping it marks the event as handled unless we're draining, events that haven't made it
+/// to a vm by then are left unacked for the broker to hand to the next owner of the guild.
+pub struct BrokerEventAck {
+    seq: u64,
+    acks: BrokerAcks,
+    finished: bool,
+}
+
+impl BrokerEventAck {
+    /// Marks the event as handled, use this once it's been dispatched or deliberately skipped
+    pub fn done(mut self) {
+        self.finished = true;
+        self.acks.handled(self.seq);
+    }
+
+    /// Dropping the ack leaves the event unacked while draining, so there's no point in waiting
+    /// around to deliver it
+    pub fn is_draining(&self) -> bool {
+        self.acks.drain.is_draining()
+    }
+}
+
+impl Drop for BrokerEventAck {
+    fn drop(&mut self) {
+        if !self.finished && !self.acks.drain.is_draining() {
+            self.acks.handled(self.seq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::shutdown::drain_pair;
+
+    use super::*;
+
+    #[test]
+    fn acks_up_until_oldest_pending() {
+        let (_trigger, signal) = drain_pair();
+        let acks = BrokerAcks::new(signal);
+
+        let first = acks.track(1);
+        let second = acks.track(2);
+        acks.received(3);
+        assert_eq!(acks.ackable(), 0);
+
+        second.done();
+        assert_eq!(acks.ackable(), 0);
+
+        first.done();
+        assert_eq!(acks.ackable(), 3);
+    }
+
+    #[test]
+    fn dropped_events_are_acked_when_not_draining() {
+        let (_trigger, signal) = drain_pair();
+        let acks = BrokerAcks::new(signal);
+
+        // e.g. events for a suspended guild
+        drop(acks.track(1));
+        assert_eq!(acks.ackable(), 1);
+    }
+
+    #[test]
+    fn drain_keeps_queued_events_unacked() {
+        let (trigger, signal) = drain_pair();
+        let acks = BrokerAcks::new(signal);
+
+        let dispatched = acks.track(1);
+        // waiting on a guild that's restarting
+        let queued = vec![acks.track(2), acks.track(3)];
+        let in_flight = acks.track(4);
+        acks.received(5);
+        dispatched.done();
+
+        trigger.start_draining();
+
+        // the guild handlers finish what they have before shutting down
+        in_flight.done();
+        // and the queued events are thrown away as the scheduler shuts down
+        drop(queued);
+
+        // the broker hands everything from the first queued event onwards to the next owner
+        assert_eq!(acks.ackable(), 1);
+    }
+}
@@ -1,26 +1,69 @@
 use std::time::Duration;
 
-use dbrokerapi::broker_scheduler_rpc::{BrokerEvent, SchedulerEvent};
-use tokio::{net::TcpStream, sync::mpsc::UnboundedSender};
+use common::shutdown::DrainSignal;
+use dbrokerapi::broker_scheduler_rpc::{
+    BrokerEvent, SchedulerEvent, SchedulerIdentity, SequencedBrokerEvent,
+};
+use tokio::{
+    io::BufReader,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
 use tracing::{info, instrument, warn};
 
-use crate::scheduler::SchedulerCommand;
+use crate::{broker_acks::BrokerAcks, partition::SchedulerPeers, scheduler::SchedulerCommand};
 
-pub async fn broker_client(addr: String, scheduler_tx: UnboundedSender<SchedulerCommand>) {
+pub async fn broker_client(
+    addr: String,
+    identity: SchedulerIdentity,
+    peers: SchedulerPeers,
+    scheduler_tx: UnboundedSender<SchedulerCommand>,
+    drain: DrainSignal,
+) {
     loop {
-        if scheduler_tx.is_closed() {
+        if scheduler_tx.is_closed() || drain.is_draining() {
             return;
         }
 
         if let Ok(conn) = TcpStream::connect(&addr).await {
             info!("connected to broker");
             conn.set_nodelay(true).unwrap();
+            let (reader, mut writer) = conn.into_split();
+
+            if let Err(err) =
+                simpleproto::write_message(&SchedulerEvent::Identify(identity.clone()), &mut writer)
+                    .await
+            {
+                warn!(%err, "failed identifying to broker, retrying in a second");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+
+            // read in a separate task as reading a message can't be cancelled half way through
+            let (events_tx, events_rx) = mpsc::unbounded_channel();
+            let reader_task = tokio::spawn(read_broker_events(BufReader::new(reader), events_tx));
+
             let client = BrokerConn {
                 scheduler_tx: scheduler_tx.clone(),
-                stream: conn,
+                peers: peers.clone(),
+                drain: drain.clone(),
+                acks: BrokerAcks::new(drain.clone()),
+                events_rx,
+                writer,
+                last_acked: 0,
             };
             let dc = client.run().await;
+            reader_task.abort();
             info!("disconnected from broker: {:?}", dc);
+            if drain.is_draining() {
+                // the scheduler is already shutting down its guilds
+                return;
+            }
+
+            peers.set(Vec::new());
             let _ = scheduler_tx.send(SchedulerCommand::BrokerDisconnected);
         } else {
             warn!("failed connecting to broker, retrying in a second");
@@ -29,9 +72,33 @@ pub async fn broker_client(addr: String, scheduler_tx: UnboundedSender<Scheduler
     }
 }
 
+async fn read_broker_events(
+    mut reader: BufReader<OwnedReadHalf>,
+    tx: UnboundedSender<std::io::Result<SequencedBrokerEvent>>,
+) {
+    loop {
+        let next = simpleproto::read_message(&mut reader).await;
+        let failed = next.is_err();
+        if tx.send(next).is_err() || failed {
+            return;
+        }
+    }
+}
+
+// acks are sent once there's no more events buffered or after this many events
+const ACK_BATCH_SIZE: u64 = 64;
+
 struct BrokerConn {
-    stream: TcpStream,
+    events_rx: UnboundedReceiver<std::io::Result<SequencedBrokerEvent>>,
+    writer: OwnedWriteHalf,
     scheduler_tx: UnboundedSender<SchedulerCommand>,
+    peers: SchedulerPeers,
+    drain: DrainSignal,
+
+    // events are only acked once they've been dispatched to a vm,
+    // the broker hands the ones we haven't acked to another scheduler if we go away
+    acks: BrokerAcks,
+    last_acked: u64,
 }
 
 enum ContinueState {
@@ -44,21 +111,67 @@ impl BrokerConn {
         let _ = self.scheduler_tx.send(SchedulerCommand::BrokerConnected);
 
         loop {
-            let next: BrokerEvent = simpleproto::read_message(&mut self.stream).await?;
-            match self.handle_broker_message(next).await? {
-                ContinueState::Continue => {}
-                ContinueState::Stop => return Ok(()),
+            tokio::select! {
+                next = self.events_rx.recv() => {
+                    let Some(next) = next else {
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    };
+
+                    match self.handle_broker_message(next?)? {
+                        ContinueState::Continue => {}
+                        ContinueState::Stop => return Ok(()),
+                    }
+                }
+                _ = self.acks.wait_changed() => {}
+                _ = self.drain.wait_draining() => return self.drain().await,
             }
+
+            let buffered = !self.events_rx.is_empty();
+            self.send_ack(!buffered).await?;
         }
     }
 
+    /// Stops taking in new events and waits for the scheduler to finish up the ones it has
+    ///
+    /// Only the events that made it to a vm are acked,
+    /// the broker hands the rest to the scheduler taking over our guilds.
+    async fn drain(mut self) -> std::io::Result<()> {
+        info!("draining, no longer taking in events from the broker");
+
+        loop {
+            self.send_ack(true).await?;
+
+            tokio::select! {
+                _ = self.acks.wait_changed() => {}
+                // the scheduler is done once it drops its receiver
+                _ = self.scheduler_tx.closed() => break,
+            }
+        }
+
+        self.send_ack(true).await?;
+        info!("scheduler drained, disconnecting from broker");
+        Ok(())
+    }
+
+    async fn send_ack(&mut self, flush: bool) -> std::io::Result<()> {
+        let ackable = self.acks.ackable();
+        if ackable > self.last_acked && (flush || ackable - self.last_acked >= ACK_BATCH_SIZE) {
+            simpleproto::write_message(&SchedulerEvent::Ack(ackable), &mut self.writer).await?;
+            self.last_acked = ackable;
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip(self, message))]
-    async fn handle_broker_message(
+    fn handle_broker_message(
         &mut self,
-        message: BrokerEvent,
+        message: SequencedBrokerEvent,
     ) -> std::io::Result<ContinueState> {
-        match message {
+        match message.event {
             BrokerEvent::Hello(h) => {
+                self.acks.received(message.seq);
+                self.peers.set(h.schedulers.clone());
                 if self
                     .scheduler_tx
                     .send(SchedulerCommand::BrokerHello(h))
@@ -68,10 +181,28 @@ impl BrokerConn {
                     return Ok(ContinueState::Stop);
                 }
             }
+            BrokerEvent::PartitionChanged(h) => {
+                self.acks.received(message.seq);
+                info!(
+                    schedulers = h.schedulers.len(),
+                    guilds = h.connected_guilds.len(),
+                    "guild partition changed"
+                );
+
+                self.peers.set(h.schedulers.clone());
+                if self
+                    .scheduler_tx
+                    .send(SchedulerCommand::BrokerPartitionChanged(h))
+                    .is_err()
+                {
+                    return Ok(ContinueState::Stop);
+                }
+            }
             BrokerEvent::DiscordEvent(evt) => {
+                let ack = self.acks.track(message.seq);
                 if self
                     .scheduler_tx
-                    .send(SchedulerCommand::DiscordEvent(evt))
+                    .send(SchedulerCommand::DiscordEvent(evt, ack))
                     .is_err()
                 {
                     // return, close the connection, the broker will add it back to the queue
@@ -80,9 +211,6 @@ impl BrokerConn {
             }
         }
 
-        // send ack
-        simpleproto::write_message(&SchedulerEvent::Ack, &mut self.stream).await?;
-
         Ok(ContinueState::Continue)
     }
 }
@@ -173,9 +173,11 @@ impl Manager {
                 error!(%err, "failed retrieving guild scripts");
             })?;
 
+        // library scripts never run on their own so any contributes they have are stale leftovers
+        // from when they were a normal script
         let enabled_guild_scripts = all_guild_scripts
             .into_iter()
-            .filter(|v| v.enabled)
+            .filter(|v| v.enabled && !v.is_library)
             .collect::<Vec<_>>();
 
         let merged = merge_script_commands(enabled_guild_scripts);
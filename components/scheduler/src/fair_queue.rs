@@ -0,0 +1,213 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+// the smallest amount of virtual time a request is charged, so that guilds queueing
+// up several requests at once get them interleaved with other guilds
+const MIN_REQUEST_COST_MS: u64 = 100;
+
+// how far ahead of the virtual time a guild can be pushed, guilds that held on to
+// workers for a long time would otherwise be starved by guilds making short claims
+const MAX_DEBT_MS: u64 = 30_000;
+
+/// A weighted start-time fair queue of requests from guilds
+///
+/// Guilds are charged virtual time for how long they hold on to the things they request,
+/// requests are then served in order of the virtual time they were queued at.
+/// This means a guild repeatedly claiming workers ends up behind guilds that
+/// have used less of the pool recently, instead of starving them.
+///
+/// The virtual time charged is divided by the weight of the request, so a guild with twice the
+/// weight of another gets twice the share when they compete for the same things.
+pub(crate) struct FairQueue<T> {
+    entries: BTreeMap<(u64, u64), QueuedEntry<T>>,
+    // the virtual time the next request of the guild starts at
+    finish_tags: HashMap<Id<GuildMarker>, u64>,
+    virtual_time: u64,
+    seq_gen: u64,
+}
+
+pub(crate) struct QueuedEntry<T> {
+    pub guild_id: Id<GuildMarker>,
+    pub queued_at: Instant,
+    pub item: T,
+}
+
+impl<T> Default for FairQueue<T> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            finish_tags: HashMap::new(),
+            virtual_time: 0,
+            seq_gen: 0,
+        }
+    }
+}
+
+impl<T> FairQueue<T> {
+    pub fn push(&mut self, guild_id: Id<GuildMarker>, weight: u64, item: T) {
+        let finish_tag = self
+            .finish_tags
+            .entry(guild_id)
+            .or_insert(self.virtual_time);
+
+        let start_tag = (*finish_tag).max(self.virtual_time);
+        *finish_tag = start_tag + weighted_cost(MIN_REQUEST_COST_MS, weight);
+
+        self.seq_gen += 1;
+        self.entries.insert(
+            (start_tag, self.seq_gen),
+            QueuedEntry {
+                guild_id,
+                queued_at: Instant::now(),
+                item,
+            },
+        );
+    }
+
+    /// Pops the request with the earliest virtual start time out of the ones matching `filter`
+    pub fn pop_where(&mut self, filter: impl Fn(&T) -> bool) -> Option<QueuedEntry<T>> {
+        let key = *self.entries.iter().find(|(_, v)| filter(&v.item))?.0;
+        let entry = self.entries.remove(&key)?;
+
+        let (start_tag, _) = key;
+        if self.entries.is_empty() {
+            // no contention anymore, start over the next time the queue fills up
+            self.finish_tags.clear();
+        } else if start_tag > self.virtual_time {
+            self.virtual_time = start_tag;
+
+            // guilds that are behind the virtual time have no advantage left to track
+            let virtual_time = self.virtual_time;
+            self.finish_tags.retain(|_, tag| *tag > virtual_time);
+        }
+
+        Some(entry)
+    }
+
+    /// Removes the request with the latest virtual start time out of the ones matching `filter`,
+    /// used for shedding load
+    pub fn pop_last_where(&mut self, filter: impl Fn(&T) -> bool) -> Option<QueuedEntry<T>> {
+        let key = *self.entries.iter().rev().find(|(_, v)| filter(&v.item))?.0;
+        self.entries.remove(&key)
+    }
+
+    /// Charges the guild for holding on to a claim for the provided duration
+    ///
+    /// Guilds are only charged while there are requests waiting in the queue.
+    pub fn charge(&mut self, guild_id: Id<GuildMarker>, weight: u64, held_for: Duration) {
+        if self.entries.is_empty() {
+            return;
+        }
+
+        let cost = weighted_cost(
+            (held_for.as_millis() as u64).max(MIN_REQUEST_COST_MS),
+            weight,
+        );
+        let finish_tag = self
+            .finish_tags
+            .entry(guild_id)
+            .or_insert(self.virtual_time);
+
+        *finish_tag =
+            ((*finish_tag).max(self.virtual_time) + cost).min(self.virtual_time + MAX_DEBT_MS);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &QueuedEntry<T>> {
+        self.entries.values()
+    }
+}
+
+fn weighted_cost(cost_ms: u64, weight: u64) -> u64 {
+    (cost_ms / weight.max(1)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guild(id: u64) -> Id<GuildMarker> {
+        Id::new(id)
+    }
+
+    fn drain(queue: &mut FairQueue<u64>) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop_where(|_| true))
+            .map(|v| v.item)
+            .collect()
+    }
+
+    #[test]
+    fn interleaves_guilds() {
+        let mut queue = FairQueue::default();
+        queue.push(guild(1), 1, 1);
+        queue.push(guild(1), 1, 2);
+        queue.push(guild(1), 1, 3);
+        queue.push(guild(2), 1, 4);
+        queue.push(guild(2), 1, 5);
+
+        assert_eq!(drain(&mut queue), vec![1, 4, 2, 5, 3]);
+    }
+
+    #[test]
+    fn heavier_guilds_get_a_larger_share() {
+        let mut queue = FairQueue::default();
+        for i in 0..4 {
+            queue.push(guild(1), 1, i);
+        }
+        for i in 10..18 {
+            queue.push(guild(2), 2, i);
+        }
+
+        // guild 2 gets two requests served for every one of guild 1's
+        assert_eq!(
+            drain(&mut queue),
+            vec![0, 10, 11, 1, 12, 13, 2, 14, 15, 3, 16, 17]
+        );
+    }
+
+    #[test]
+    fn charged_guilds_go_behind() {
+        let mut queue = FairQueue::default();
+        queue.push(guild(1), 1, 1);
+        queue.push(guild(2), 1, 2);
+
+        // guild 1 held on to its last claim for a long time
+        queue.charge(guild(1), 1, Duration::from_secs(5));
+        queue.push(guild(1), 1, 3);
+        queue.push(guild(2), 1, 4);
+
+        assert_eq!(drain(&mut queue), vec![1, 2, 4, 3]);
+    }
+
+    #[test]
+    fn charges_are_capped() {
+        let mut queue = FairQueue::default();
+        queue.push(guild(2), 1, 1);
+
+        queue.charge(guild(1), 1, Duration::from_secs(60 * 60));
+        queue.push(guild(1), 1, 2);
+        for i in 3..1000 {
+            queue.push(guild(2), 1, i);
+        }
+
+        let order = drain(&mut queue);
+        let pos = order.iter().position(|v| *v == 2).unwrap();
+        // only the requests guild 2 queued within the max debt go ahead of it
+        assert_eq!(pos as u64, MAX_DEBT_MS / MIN_REQUEST_COST_MS);
+    }
+
+    #[test]
+    fn pops_matching_requests_in_order() {
+        let mut queue = FairQueue::default();
+        queue.push(guild(1), 1, 1);
+        queue.push(guild(2), 1, 2);
+        queue.push(guild(3), 1, 3);
+
+        assert_eq!(queue.pop_where(|v| *v >= 2).unwrap().item, 2);
+        assert_eq!(queue.pop_last_where(|v| *v < 3).unwrap().item, 1);
+        assert_eq!(drain(&mut queue), vec![3]);
+    }
+}
@@ -1,8 +1,16 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
 use crate::{
+    broker_acks::BrokerEventAck,
     command_manager,
-    vm_session::{VmSession, VmSessionEvent, VmSessionStatus},
+    guild_leases::GuildLeases,
+    vm_session::{
+        EvalOutputSender, ScriptReloadedSender, VmSession, VmSessionEvent, VmSessionStatus,
+        WebhookResponseSender,
+    },
     SchedulerConfig,
 };
 use chrono::{DateTime, Utc};
@@ -14,11 +22,23 @@ use tracing::{info, instrument};
 use twilight_model::id::{marker::GuildMarker, Id};
 
 pub enum GuildCommand {
-    BrokerEvent(DiscordEvent),
+    BrokerEvent(DiscordEvent, BrokerEventAck),
     Status(oneshot::Sender<Option<GuildStatus>>),
     ReloadScripts,
+    ReloadScript(u64, ScriptReloadedSender),
     PurgeCache,
+    Eval(String, EvalOutputSender),
+    Webhook(InboundWebhookDispatch, WebhookResponseSender),
     Shutdown,
+    /// Shuts down after letting the dispatches in flight finish, up until the deadline
+    Drain(Instant),
+}
+
+/// A request to one of the guild's inbound webhooks, the signature has already been verified
+pub struct InboundWebhookDispatch {
+    pub webhook_id: u64,
+    pub name: String,
+    pub body: serde_json::Value,
 }
 
 #[derive(Clone, Copy)]
@@ -47,8 +67,10 @@ pub struct GuildHandler {
     _cmd_manager_handle: command_manager::Handle,
 
     premium_tier: Arc<RwLock<PremiumTierState>>,
+    leases: GuildLeases,
 
     _id_gen: u64,
+    drain_deadline: Option<Instant>,
 
     scripts_session: VmSession,
 }
@@ -61,6 +83,7 @@ impl GuildHandler {
         logger: LogSender,
         worker_pool: crate::vmworkerpool::VmWorkerPool,
         cmd_manager_handle: crate::command_manager::Handle,
+        leases: GuildLeases,
     ) -> GuildHandle {
         let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
         let (evt_tx, evt_rx) = mpsc::unbounded_channel();
@@ -82,7 +105,9 @@ impl GuildHandler {
             guild_rx: cmd_rx,
             scheduler_tx: evt_tx,
             _id_gen: 1,
+            drain_deadline: None,
             premium_tier: premium_tier.clone(),
+            leases,
 
             _cmd_manager_handle: cmd_manager_handle.clone(),
             scripts_session: VmSession::new(
@@ -108,15 +133,69 @@ impl GuildHandler {
     }
 
     async fn run(mut self) {
+        let Some(buffered) = self.acquire_lease().await else {
+            info!("shut down before the guild was released by its previous owner");
+            return;
+        };
+
         self.setup().await;
 
-        while let Some(next) = self.next_event().await {
-            if !self.handle_next_action(next).await {
+        let mut running = true;
+        for cmd in buffered {
+            if !self
+                .handle_next_action(NextGuildAction::GuildCommand(cmd))
+                .await
+            {
+                running = false;
                 break;
             }
         }
 
+        while running {
+            let Some(next) = self.next_event().await else {
+                break;
+            };
+
+            running = self.handle_next_action(next).await;
+        }
+
         self.shutdown().await;
+        self.leases.release(self.guild_id).await;
+    }
+
+    /// Waits for the guild's lease, holding on to the commands received in the meantime
+    ///
+    /// Returns none if we were told to shut down before getting it.
+    #[instrument(skip(self), fields(guild_id = self.guild_id.get()))]
+    async fn acquire_lease(&mut self) -> Option<Vec<GuildCommand>> {
+        let mut buffered = Vec::new();
+        let mut logged = false;
+
+        loop {
+            if self.leases.acquire(self.guild_id).await {
+                return Some(buffered);
+            }
+
+            if !logged {
+                info!("guild is held by another scheduler, waiting for it to be released");
+                logged = true;
+            }
+
+            let retry = tokio::time::sleep(self.leases.retry_interval());
+            tokio::pin!(retry);
+
+            loop {
+                tokio::select! {
+                    _ = &mut retry => break,
+                    cmd = self.guild_rx.recv() => match cmd {
+                        Some(GuildCommand::Shutdown | GuildCommand::Drain(_)) | None => {
+                            return None;
+                        }
+                        Some(cmd) => buffered.push(cmd),
+                    },
+                }
+            }
+        }
     }
 
     #[instrument(skip(self), fields(guild_id = self.guild_id.get(), action = action.span_info()))]
@@ -126,6 +205,11 @@ impl GuildHandler {
                 info!("got shutdown signal");
                 return false;
             }
+            NextGuildAction::GuildCommand(GuildCommand::Drain(deadline)) => {
+                info!("got drain signal");
+                self.drain_deadline = Some(deadline);
+                return false;
+            }
             NextGuildAction::GuildCommand(cmd) => {
                 self.handle_guild_command(cmd).await;
             }
@@ -143,7 +227,7 @@ impl GuildHandler {
     #[instrument(skip(self), fields(guild_id = self.guild_id.get()))]
     async fn shutdown(&mut self) {
         info!("shutting down guild handler");
-        self.scripts_session.shutdown().await;
+        self.scripts_session.shutdown(self.drain_deadline).await;
     }
 
     async fn next_event(&mut self) -> Option<NextGuildAction> {
@@ -161,8 +245,8 @@ impl GuildHandler {
 
     async fn handle_guild_command(&mut self, cmd: GuildCommand) {
         match cmd {
-            GuildCommand::BrokerEvent(evt) => {
-                self.handle_broker_event(evt).await;
+            GuildCommand::BrokerEvent(evt, ack) => {
+                self.handle_broker_event(evt, ack).await;
             }
             // GuildCommand::Dispatch(resp, t, v) => {
             //     self.dispatch_worker_evt(t, v, PendingAck::Dispatch(Some(resp)))
@@ -171,10 +255,23 @@ impl GuildHandler {
             GuildCommand::ReloadScripts => {
                 self.scripts_session.reload_guild_scripts().await;
             }
-            GuildCommand::Shutdown => {
+            GuildCommand::ReloadScript(script_id, done) => {
+                self.scripts_session
+                    .reload_guild_script(script_id, done)
+                    .await;
+            }
+            GuildCommand::Shutdown | GuildCommand::Drain(_) => {
                 panic!("shutdown should be handled by caller")
             }
             GuildCommand::PurgeCache => {}
+            GuildCommand::Eval(source, tx) => {
+                self.scripts_session.dispatch_eval(source, tx).await;
+            }
+            GuildCommand::Webhook(req, tx) => {
+                self.scripts_session
+                    .dispatch_webhook(req.webhook_id, req.name, req.body, tx)
+                    .await;
+            }
             GuildCommand::Status(resp) => {
                 let _ = resp.send(Some(GuildStatus {
                     vm: self.scripts_session.get_status(),
@@ -210,14 +307,16 @@ impl GuildHandler {
         *w = PremiumTierState::Fetched(highest_tier);
     }
 
-    async fn handle_broker_event(&mut self, evt: DiscordEvent) {
+    async fn handle_broker_event(&mut self, evt: DiscordEvent, ack: BrokerEventAck) {
         match &evt.event {
-            DiscordEventData::GuildCreate(_) => {}
+            DiscordEventData::GuildCreate(_) => ack.done(),
             DiscordEventData::GuildDelete(_) => {
                 unreachable!("this event should not be forwarded to the guild worker");
             }
             _ => {
-                self.scripts_session.send_discord_guild_event(evt).await;
+                self.scripts_session
+                    .send_discord_guild_event(evt, ack)
+                    .await;
             }
         }
     }
@@ -244,10 +343,14 @@ impl NextGuildAction {
                 }
             },
             NextGuildAction::GuildCommand(cmd) => match cmd {
-                GuildCommand::BrokerEvent(be) => format!("GuildCommand(BrokerEvent({}))", be.t),
+                GuildCommand::BrokerEvent(be, _) => format!("GuildCommand(BrokerEvent({}))", be.t),
                 GuildCommand::ReloadScripts => "GuildCommand(ReloadScripts)".to_owned(),
+                GuildCommand::ReloadScript(..) => "GuildCommand(ReloadScript)".to_owned(),
                 GuildCommand::PurgeCache => "GuildCommand(PurgeCache)".to_owned(),
+                GuildCommand::Eval(_, _) => "GuildCommand(Eval)".to_owned(),
+                GuildCommand::Webhook(_, _) => "GuildCommand(Webhook)".to_owned(),
                 GuildCommand::Shutdown => "GuildCommand(Shutdown)".to_owned(),
+                GuildCommand::Drain(_) => "GuildCommand(Drain)".to_owned(),
                 GuildCommand::Status(_) => "GuildCommand(Status)".to_owned(),
             },
         }
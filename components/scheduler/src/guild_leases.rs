@@ -0,0 +1,144 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use stores::Db;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::scheduler::SchedulerCommand;
+
+// how long a lease lasts without being renewed, this is how long it takes for a guild to be
+// started again when the scheduler running it went away without releasing it
+const LEASE_TTL: Duration = Duration::from_secs(30);
+const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Makes sure a guild is only run by one scheduler at a time
+///
+/// Guilds change owners as schedulers join and leave, the new owner is told about it while the
+/// previous one could still be shutting the guild down. Guilds are only started once the lease
+/// has been released by the previous owner or expired, otherwise both could run its timers.
+#[derive(Clone)]
+pub struct GuildLeases {
+    scheduler_id: String,
+    stores: Db,
+    held: Arc<Mutex<HashSet<Id<GuildMarker>>>>,
+}
+
+impl GuildLeases {
+    pub fn new(scheduler_id: String, stores: Db) -> Self {
+        Self {
+            scheduler_id,
+            stores,
+            held: Default::default(),
+        }
+    }
+
+    /// Tries to take the lease of the guild, returns false if another scheduler holds it
+    pub async fn acquire(&self, guild_id: Id<GuildMarker>) -> bool {
+        match self
+            .stores
+            .acquire_guild_lease(guild_id, &self.scheduler_id, LEASE_TTL)
+            .await
+        {
+            Ok(true) => {
+                self.held.lock().unwrap().insert(guild_id);
+                true
+            }
+            Ok(false) => false,
+            Err(err) => {
+                error!(%err, %guild_id, "failed acquiring guild lease");
+                false
+            }
+        }
+    }
+
+    /// Lets the next owner start the guild, the lease expires on its own if this fails
+    pub async fn release(&self, guild_id: Id<GuildMarker>) {
+        self.held.lock().unwrap().remove(&guild_id);
+
+        if let Err(err) = self
+            .stores
+            .release_guild_lease(guild_id, &self.scheduler_id)
+            .await
+        {
+            error!(%err, %guild_id, "failed releasing guild lease");
+        }
+    }
+
+    /// How long to wait before trying to take a lease held by another scheduler again
+    pub fn retry_interval(&self) -> Duration {
+        LEASE_RENEW_INTERVAL / 4
+    }
+
+    /// Periodically renews the leases of the guilds we're running, the scheduler is told to shut
+    /// down the guilds whose leases were lost as another scheduler could be running them now
+    pub async fn run_renewals(self, scheduler_tx: mpsc::WeakUnboundedSender<SchedulerCommand>) {
+        let mut interval = tokio::time::interval(LEASE_RENEW_INTERVAL);
+        let mut last_renewed = Instant::now();
+        loop {
+            interval.tick().await;
+
+            let held = self
+                .held
+                .lock()
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>();
+            if held.is_empty() {
+                last_renewed = Instant::now();
+                continue;
+            }
+
+            let attempted_at = Instant::now();
+            let lost = match self
+                .stores
+                .renew_guild_leases(&held, &self.scheduler_id, LEASE_TTL)
+                .await
+            {
+                Ok(renewed) => {
+                    last_renewed = attempted_at;
+
+                    // the lease expired and was taken over while we couldn't reach the database
+                    let renewed = renewed.into_iter().collect::<HashSet<_>>();
+                    held.into_iter()
+                        .filter(|v| !renewed.contains(v))
+                        .collect::<Vec<_>>()
+                }
+                Err(err) => {
+                    error!(%err, "failed renewing guild leases");
+                    if last_renewed.elapsed() < LEASE_TTL {
+                        continue;
+                    }
+
+                    // they've all expired by now
+                    held
+                }
+            };
+
+            // the ones released while we were renewing them weren't lost
+            let lost = {
+                let mut held = self.held.lock().unwrap();
+                lost.into_iter()
+                    .filter(|v| held.remove(v))
+                    .collect::<Vec<_>>()
+            };
+            if lost.is_empty() {
+                continue;
+            }
+
+            warn!(
+                lost = lost.len(),
+                "lost guild leases, shutting the guilds down"
+            );
+            let Some(scheduler_tx) = scheduler_tx.upgrade() else {
+                return;
+            };
+            let _ = scheduler_tx.send(SchedulerCommand::GuildLeasesLost(lost));
+        }
+    }
+}
@@ -118,6 +118,14 @@ impl Manager {
     pub fn clear_loaded_timers(&mut self) {
         self.loaded_intervals.clear();
     }
+
+    /// Removes timers no longer defined by any of the running scripts
+    pub fn remove_timers(&mut self, ids: &[TimerId]) {
+        for id in ids {
+            self.loaded_intervals.remove(id);
+        }
+    }
+
     pub fn clear_pending_acks(&mut self) {
         self.pending.clear();
     }
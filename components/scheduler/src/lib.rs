@@ -1,16 +1,21 @@
 use std::{num::NonZeroU64, sync::Arc, time::Duration};
 
+use dbrokerapi::broker_scheduler_rpc::SchedulerIdentity;
 use stores::{config::PremiumSlotTier, Db};
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use twilight_model::id::Id;
 
+mod broker_acks;
 mod broker_client;
 mod command_manager;
 mod dispatch_conv;
+mod fair_queue;
 mod guild_handler;
+mod guild_leases;
 mod integration_testing;
 mod interval_timer_manager;
+mod partition;
 mod rpc_server;
 mod scheduled_task_manager;
 mod scheduler;
@@ -48,6 +53,12 @@ pub async fn run(
                 discord_config.clone(),
                 postgres_store.clone(),
             )))
+            .add_backend(Arc::new(guild_logger::webhook_backend::WebhookLogger::new(
+                postgres_store.clone(),
+            )))
+            .add_backend(Arc::new(
+                guild_logger::rollout_backend::PluginRolloutLogger::new(postgres_store.clone()),
+            ))
             .add_backend(guild_log_sub_backend.clone());
 
         if let Some(g) = integration_testing_guild {
@@ -61,12 +72,16 @@ pub async fn run(
         }
     };
 
+    let scheduler_id = config.scheduler_id.clone();
+
     let (scheduler_tx, scheduler_rx) = mpsc::unbounded_channel();
+    let peers = partition::SchedulerPeers::new(scheduler_id.clone());
 
     let bot_rpc_server = rpc_server::Server::new(
         guild_log_sub_backend,
         scheduler_tx.clone(),
         common_conf.bot_rpc_listen_addr.clone(),
+        peers.clone(),
     );
     tokio::spawn(bot_rpc_server.run());
 
@@ -78,14 +93,36 @@ pub async fn run(
 
     tokio::spawn(manager.run());
 
-    let worker_pool = vmworkerpool::VmWorkerPool::new(vm_worker_launch_config);
+    let worker_pool = vmworkerpool::VmWorkerPool::new(
+        vm_worker_launch_config,
+        config.max_queued_worker_requests as usize,
+    );
 
     #[cfg(target_family = "unix")]
-    worker_listener::listen_for_workers("/tmp/botloader_scheduler_workers", worker_pool.clone())
-        .await;
+    worker_listener::listen_for_workers(
+        "/tmp/botloader_scheduler_workers",
+        config.worker_codec,
+        worker_pool.clone(),
+    )
+    .await;
 
     #[cfg(target_family = "windows")]
-    worker_listener::listen_for_workers("localhost:7885", worker_pool.clone()).await;
+    worker_listener::listen_for_workers("localhost:7885", config.worker_codec, worker_pool.clone())
+        .await;
+
+    if let Some(addr) = &config.remote_workers_listen_addr {
+        let secret = config.remote_workers_secret.clone().expect(
+            "BL_SCHEDULER_REMOTE_WORKERS_SECRET is required when listening for remote workers",
+        );
+        worker_listener::listen_for_remote_workers(
+            addr,
+            secret,
+            config.worker_codec,
+            worker_pool.clone(),
+        )
+        .await;
+        tokio::spawn(worker_pool.clone().run_remote_worker_health_checks());
+    }
 
     tokio::time::sleep(Duration::from_secs(1)).await;
     info!(
@@ -102,19 +139,35 @@ pub async fn run(
         config.num_workers_premium as usize,
     );
 
+    let leases = guild_leases::GuildLeases::new(scheduler_id.clone(), postgres_store.clone());
+    tokio::spawn(leases.clone().run_renewals(scheduler_tx.downgrade()));
+
     let scheduler = scheduler::Scheduler::new(
         Arc::new(config.clone()),
         scheduler_rx,
+        &scheduler_tx,
         postgres_store,
         logger,
         cmd_man_handle,
         worker_pool,
+        leases,
     );
     let task = tokio::spawn(scheduler.run());
 
-    tokio::spawn(broker_client::broker_client(
-        config.broker_rpc_connect_adddr,
+    let (drain_trigger, drain_signal) = common::shutdown::drain_pair();
+
+    let broker_task = tokio::spawn(broker_client::broker_client(
+        config.broker_rpc_connect_adddr.clone(),
+        SchedulerIdentity {
+            id: scheduler_id,
+            bot_rpc_addr: config
+                .bot_rpc_advertise_addr
+                .clone()
+                .unwrap_or_else(|| common_conf.bot_rpc_connect_addr.clone()),
+        },
+        peers,
         scheduler_tx.clone(),
+        drain_signal,
     ));
 
     if integration_testing_guild.is_none() {
@@ -142,11 +195,20 @@ pub async fn run(
         }
     }
 
+    drain_trigger.start_draining();
     let _ = scheduler_tx.send(scheduler::SchedulerCommand::Shutdown);
 
     info!("shutting down....");
 
     let _ = task.await;
+
+    // lets the broker client ack the events that were dispatched while draining
+    if tokio::time::timeout(Duration::from_secs(5), broker_task)
+        .await
+        .is_err()
+    {
+        warn!("timed out waiting for the broker connection to close");
+    }
 }
 
 #[derive(Clone, clap::Parser, Debug)]
@@ -158,6 +220,18 @@ pub struct SchedulerConfig {
     )]
     pub(crate) broker_rpc_connect_adddr: String,
 
+    /// Unique id of this scheduler, guilds are partitioned between the schedulers connected to the broker
+    ///
+    /// Keep it the same across restarts so the scheduler is handed the same guilds again,
+    /// schedulers sharing an id would both think they own the same guilds
+    #[clap(long, env = "BL_SCHEDULER_ID")]
+    pub(crate) scheduler_id: String,
+
+    /// The address other schedulers reach this scheduler's botrpc server at,
+    /// defaults to the botrpc connect address
+    #[clap(long, env = "BL_SCHEDULER_BOT_RPC_ADVERTISE_ADDR")]
+    pub(crate) bot_rpc_advertise_addr: Option<String>,
+
     #[clap(long)]
     pub integration_tests_guild: Option<NonZeroU64>,
 
@@ -174,6 +248,36 @@ pub struct SchedulerConfig {
     #[clap(long, env = "BL_SCHEDULER_NUM_WORKERS_PREMIUM", default_value = "0")]
     pub(crate) num_workers_premium: u16,
 
+    /// Address to accept vmworkers connecting over tcp on, in addition to the ones we spawn ourselves
+    #[clap(long, env = "BL_SCHEDULER_REMOTE_WORKERS_LISTEN_ADDR")]
+    pub(crate) remote_workers_listen_addr: Option<String>,
+
+    /// Shared secret remote workers authenticate with
+    #[clap(
+        long,
+        env = "BL_SCHEDULER_REMOTE_WORKERS_SECRET",
+        hide_env_values = true
+    )]
+    pub(crate) remote_workers_secret: Option<String>,
+
+    /// Max number of requests for a worker that can be queued per premium tier,
+    /// past this the requests furthest back in line are shed
+    #[clap(
+        long,
+        env = "BL_SCHEDULER_MAX_QUEUED_WORKER_REQUESTS",
+        default_value = "500"
+    )]
+    pub(crate) max_queued_worker_requests: u32,
+
+    /// Codec used for messages between the scheduler and the workers: msgpack, or json for debugging
+    #[clap(long, env = "BL_SCHEDULER_WORKER_CODEC", default_value = "msgpack")]
+    pub(crate) worker_codec: simpleproto::Codec,
+
+    /// How long in-flight events, tasks and timers get to finish when shutting down,
+    /// the ones not acked by then are redelivered after the restart
+    #[clap(long, env = "BL_SCHEDULER_DRAIN_TIMEOUT_SECS", default_value = "30")]
+    pub(crate) drain_timeout_secs: u64,
+
     // Disables reusing vm's when the vm session has to grab a worker from the pool
     // This is useful for benchmarking and diagnostics purposes
     #[clap(long, env = "BL_SCHEDULER_NO_REUSE_VMS", default_value = "false")]
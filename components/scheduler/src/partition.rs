@@ -0,0 +1,40 @@
+use std::sync::{Arc, RwLock};
+
+use dbrokerapi::broker_scheduler_rpc::{guild_owner, SchedulerIdentity};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+/// The schedulers connected to the broker, guilds are partitioned between them
+///
+/// Used to route botrpc calls for guilds owned by another scheduler.
+#[derive(Clone)]
+pub struct SchedulerPeers {
+    own_id: String,
+    schedulers: Arc<RwLock<Vec<SchedulerIdentity>>>,
+}
+
+impl SchedulerPeers {
+    pub fn new(own_id: String) -> Self {
+        Self {
+            own_id,
+            schedulers: Default::default(),
+        }
+    }
+
+    pub fn set(&self, schedulers: Vec<SchedulerIdentity>) {
+        *self.schedulers.write().unwrap() = schedulers;
+    }
+
+    /// Returns the botrpc address of the scheduler owning the guild if that's not us
+    pub fn remote_owner(&self, guild_id: Id<GuildMarker>) -> Option<String> {
+        let schedulers = self.schedulers.read().unwrap();
+        let owner = guild_owner(guild_id, schedulers.iter().map(|v| v.id.as_str()))?;
+        if owner == self.own_id {
+            return None;
+        }
+
+        schedulers
+            .iter()
+            .find(|v| v.id == owner)
+            .map(|v| v.bot_rpc_addr.clone())
+    }
+}
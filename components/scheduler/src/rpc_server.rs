@@ -1,19 +1,43 @@
-use std::{pin::Pin, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use guild_logger::guild_subscriber_backend::GuildSubscriberBackend;
-use tokio::sync::{mpsc::UnboundedSender, oneshot};
-use tonic::{Response, Status};
+use tokio::sync::{
+    mpsc::{self, UnboundedSender},
+    oneshot, Mutex,
+};
+use tonic::{metadata::MetadataValue, Response, Status};
+use tracing::warn;
 
 use botrpc::proto;
-use twilight_model::id::Id;
+use common::dispatch_event::{EvalOutput, EvalOutputKind};
+use twilight_model::id::{marker::GuildMarker, Id};
 
-use crate::scheduler::SchedulerCommand;
+use crate::{
+    guild_handler::InboundWebhookDispatch, partition::SchedulerPeers, scheduler::SchedulerCommand,
+};
+
+// set on calls forwarded to the owning scheduler,
+// prevents calls from bouncing between schedulers that disagree on the owner
+const FORWARDED_METADATA_KEY: &str = "bl-forwarded";
+
+// how long an eval has to finish, including waiting for a vm to run it in
+const EVAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// how long to wait on a script to be reloaded before responding, it keeps going after that
+const RELOAD_SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Server {
     addr: String,
     log_subscriber: Arc<GuildSubscriberBackend>,
     scheduler_tx: UnboundedSender<SchedulerCommand>,
+    peers: SchedulerPeers,
+    peer_clients: Mutex<HashMap<String, botrpc::Client>>,
 }
 
 impl Server {
@@ -21,12 +45,42 @@ impl Server {
         log_subscriber: Arc<GuildSubscriberBackend>,
         scheduler_tx: UnboundedSender<SchedulerCommand>,
         addr: String,
+        peers: SchedulerPeers,
     ) -> Self {
         Self {
             log_subscriber,
             addr,
             scheduler_tx,
+            peers,
+            peer_clients: Default::default(),
+        }
+    }
+
+    /// Returns a client for the scheduler owning the guild if it's owned by another scheduler
+    async fn owner_client<T>(
+        &self,
+        guild_id: Id<GuildMarker>,
+        request: &tonic::Request<T>,
+    ) -> Result<Option<botrpc::Client>, Status> {
+        if request.metadata().contains_key(FORWARDED_METADATA_KEY) {
+            return Ok(None);
         }
+
+        let Some(addr) = self.peers.remote_owner(guild_id) else {
+            return Ok(None);
+        };
+
+        let mut clients = self.peer_clients.lock().await;
+        if let Some(client) = clients.get(&addr) {
+            return Ok(Some(client.clone()));
+        }
+
+        let client = botrpc::Client::new(addr.clone()).await.map_err(|err| {
+            Status::unavailable(format!("failed connecting to owning scheduler: {err}"))
+        })?;
+        clients.insert(addr, client.clone());
+
+        Ok(Some(client))
     }
 
     pub async fn run(self) {
@@ -42,13 +96,22 @@ impl Server {
 type ResponseStream =
     Pin<Box<dyn Stream<Item = Result<proto::GuildLogItem, Status>> + Send + Sync>>;
 
+type EvalResponseStream =
+    Pin<Box<dyn Stream<Item = Result<proto::EvalOutputItem, Status>> + Send + Sync>>;
+
 #[tonic::async_trait]
 impl proto::bot_service_server::BotService for Server {
     async fn reload_vm(
         &self,
         request: tonic::Request<proto::GuildScriptSpecifier>,
     ) -> Result<Response<proto::Empty>, Status> {
-        let guild_id = Id::new(request.into_inner().guild_id);
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .reload_vm(forwarded_request(request.into_inner()))
+                .await;
+        }
 
         let _ = self
             .scheduler_tx
@@ -57,11 +120,50 @@ impl proto::bot_service_server::BotService for Server {
         Ok(Response::new(proto::Empty {}))
     }
 
+    async fn reload_script(
+        &self,
+        request: tonic::Request<proto::GuildScriptId>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .reload_script(forwarded_request(request.into_inner()))
+                .await;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.scheduler_tx
+            .send(SchedulerCommand::ReloadGuildScript(
+                guild_id,
+                request.get_ref().script_id,
+                sender,
+            ))
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?;
+
+        // the sender is dropped if the reload was given up on, e.g. the guild is suspended,
+        // in which case the script is loaded the next time the vm starts
+        if tokio::time::timeout(RELOAD_SCRIPT_TIMEOUT, receiver)
+            .await
+            .is_err()
+        {
+            warn!(%guild_id, "script reload did not finish in time");
+        }
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
     async fn purge_guild_cache(
         &self,
         request: tonic::Request<proto::GuildScriptSpecifier>,
     ) -> Result<Response<proto::Empty>, Status> {
-        let guild_id = Id::new(request.into_inner().guild_id);
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .purge_guild_cache(forwarded_request(request.into_inner()))
+                .await;
+        }
 
         let _ = self
             .scheduler_tx
@@ -76,7 +178,16 @@ impl proto::bot_service_server::BotService for Server {
         &self,
         request: tonic::Request<proto::GuildSpecifier>,
     ) -> Result<Response<Self::StreamGuildLogsStream>, Status> {
-        let guild_id = Id::new(request.into_inner().guild_id);
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            let stream = client
+                .get_conn()
+                .stream_guild_logs(forwarded_request(request.into_inner()))
+                .await?
+                .into_inner();
+
+            return Ok(Response::new(Box::pin(forward_stream(stream))));
+        }
 
         let mut rx = self.log_subscriber.subscribe(guild_id);
         let out = async_stream::try_stream! {
@@ -88,6 +199,100 @@ impl proto::bot_service_server::BotService for Server {
         Ok(Response::new(Box::pin(out)))
     }
 
+    type EvalScriptStream = EvalResponseStream;
+
+    async fn eval_script(
+        &self,
+        request: tonic::Request<proto::EvalRequest>,
+    ) -> Result<Response<Self::EvalScriptStream>, Status> {
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            let stream = client
+                .get_conn()
+                .eval_script(forwarded_request(request.into_inner()))
+                .await?
+                .into_inner();
+
+            return Ok(Response::new(Box::pin(forward_stream(stream))));
+        }
+
+        let req = request.into_inner();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        self.scheduler_tx
+            .send(SchedulerCommand::Eval(guild_id, req.source, tx))
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?;
+
+        let deadline = tokio::time::Instant::now() + EVAL_TIMEOUT;
+        let out = async_stream::try_stream! {
+            loop {
+                let next = match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(next)) => next,
+                    Ok(None) => break,
+                    Err(_) => {
+                        yield proto::EvalOutputItem::from(EvalOutput {
+                            eval_id: 0,
+                            kind: EvalOutputKind::Exception,
+                            message: format!(
+                                "eval did not finish within {} seconds",
+                                EVAL_TIMEOUT.as_secs()
+                            ),
+                        });
+                        break;
+                    }
+                };
+
+                let is_final = next.kind.is_final();
+                yield proto::EvalOutputItem::from(next);
+
+                if is_final {
+                    break;
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(out)))
+    }
+
+    async fn dispatch_webhook(
+        &self,
+        request: tonic::Request<proto::WebhookRequest>,
+    ) -> Result<Response<proto::WebhookResponse>, Status> {
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .dispatch_webhook(forwarded_request(request.into_inner()))
+                .await;
+        }
+
+        let req = request.into_inner();
+        let body = serde_json::from_str(&req.body)
+            .map_err(|err| Status::invalid_argument(format!("invalid body: {err}")))?;
+
+        let (sender, receiver) = oneshot::channel();
+        self.scheduler_tx
+            .send(SchedulerCommand::Webhook(
+                guild_id,
+                InboundWebhookDispatch {
+                    webhook_id: req.webhook_id,
+                    name: req.name,
+                    body,
+                },
+                sender,
+            ))
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?;
+
+        let body = receiver
+            .await
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?
+            .map_err(Status::unavailable)?;
+
+        Ok(Response::new(proto::WebhookResponse {
+            body: body.map(|v| v.to_string()),
+        }))
+    }
+
     async fn vm_worker_status(
         &self,
         _request: tonic::Request<proto::Empty>,
@@ -102,6 +307,7 @@ impl proto::bot_service_server::BotService for Server {
         let now = Instant::now();
         Ok(Response::new(proto::VmWorkerStatusResponse {
             workers: result
+                .workers
                 .into_iter()
                 .map(|v| proto::VmWorkerStatus {
                     worker_id: v.worker_id as u32,
@@ -109,6 +315,28 @@ impl proto::bot_service_server::BotService for Server {
                     last_claimed_by_guild_id: v.claimed_last_by.map(|v| v.get()),
                     claimed_last_ms_ago: now.duration_since(v.claimed_last).as_millis() as u64,
                     returned_last_ms_ago: now.duration_since(v.returned_last).as_millis() as u64,
+                    remote_name: v.remote_name,
+                })
+                .collect(),
+            queued_requests: result
+                .queued_requests
+                .into_iter()
+                .map(|v| proto::QueuedWorkerRequest {
+                    guild_id: v.guild_id.get(),
+                    priority_index: v.priority_index as u32,
+                    waiting_ms: now.duration_since(v.queued_at).as_millis() as u64,
+                })
+                .collect(),
+            guild_waits: result
+                .guild_waits
+                .into_iter()
+                .map(|v| proto::GuildWorkerWait {
+                    guild_id: v.guild_id.get(),
+                    last_wait_ms: v.last_wait.as_millis() as u64,
+                    max_wait_ms: v.max_wait.as_millis() as u64,
+                    total_wait_ms: v.total_wait.as_millis() as u64,
+                    num_waits: v.num_waits,
+                    num_shed: v.num_shed,
                 })
                 .collect(),
         }))
@@ -118,7 +346,13 @@ impl proto::bot_service_server::BotService for Server {
         &self,
         request: tonic::Request<proto::GuildSpecifier>,
     ) -> Result<Response<proto::GuildStatusResponse>, Status> {
-        let guild_id = Id::new(request.into_inner().guild_id);
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .guild_status(forwarded_request(request.into_inner()))
+                .await;
+        }
 
         let (sender, receiver) = oneshot::channel();
         self.scheduler_tx
@@ -140,4 +374,91 @@ impl proto::bot_service_server::BotService for Server {
             Err(Status::not_found("guild not found"))
         }
     }
+
+    async fn impose_guild_suspension(
+        &self,
+        request: tonic::Request<proto::ImposeGuildSuspensionRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .impose_guild_suspension(forwarded_request(request.into_inner()))
+                .await;
+        }
+
+        let req = request.into_inner();
+        let (sender, receiver) = oneshot::channel();
+        self.scheduler_tx
+            .send(SchedulerCommand::ImposeGuildSuspension(
+                guild_id,
+                req.duration_secs.map(Duration::from_secs),
+                req.message,
+                sender,
+            ))
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?;
+
+        receiver
+            .await
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?
+            .map_err(Status::internal)?;
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn lift_guild_suspension(
+        &self,
+        request: tonic::Request<proto::GuildSpecifier>,
+    ) -> Result<Response<proto::LiftGuildSuspensionResponse>, Status> {
+        let guild_id = Id::new(request.get_ref().guild_id);
+        if let Some(client) = self.owner_client(guild_id, &request).await? {
+            return client
+                .get_conn()
+                .lift_guild_suspension(forwarded_request(request.into_inner()))
+                .await;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.scheduler_tx
+            .send(SchedulerCommand::LiftGuildSuspension(guild_id, sender))
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?;
+
+        let was_suspended = receiver
+            .await
+            .map_err(|_| Status::unavailable("scheduler is shutting down"))?
+            .map_err(Status::internal)?;
+
+        Ok(Response::new(proto::LiftGuildSuspensionResponse {
+            was_suspended,
+        }))
+    }
+}
+
+fn forwarded_request<T>(inner: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(inner);
+    request
+        .metadata_mut()
+        .insert(FORWARDED_METADATA_KEY, MetadataValue::from_static("1"));
+    request
+}
+
+/// Pipes a stream from the owning scheduler through a channel,
+/// the tonic stream itself is not Sync
+fn forward_stream<T: Send + 'static>(
+    mut stream: tonic::Streaming<T>,
+) -> impl Stream<Item = Result<T, Status>> + Send + Sync {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(item) = stream.next().await {
+            if tx.send(item).is_err() {
+                return;
+            }
+        }
+    });
+
+    async_stream::stream! {
+        while let Some(item) = rx.recv().await {
+            yield item;
+        }
+    }
 }
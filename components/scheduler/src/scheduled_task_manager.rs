@@ -155,6 +155,12 @@ impl Manager {
 
         self.clear_next();
     }
+
+    /// Stops handling tasks from the buckets, used when the script handling them was reloaded
+    pub fn remove_task_buckets(&mut self, buckets: &[TaskBucketId]) {
+        self.active_task_buckets.retain(|v| !buckets.contains(v));
+        self.clear_next();
+    }
 }
 
 pub type NextAction = crate::guild_handler::NextTimerAction;
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     pin::Pin,
     sync::Arc,
     task::Poll,
@@ -7,42 +7,89 @@ use std::{
 };
 
 use crate::{
+    broker_acks::BrokerEventAck,
     command_manager,
-    guild_handler::{GuildCommand, GuildHandle, GuildHandler, GuildStatus},
-    vm_session::VmSessionEvent,
-    vmworkerpool::WorkerStatus,
+    guild_handler::{GuildCommand, GuildHandle, GuildHandler, GuildStatus, InboundWebhookDispatch},
+    guild_leases::GuildLeases,
+    vm_session::{EvalOutputSender, ScriptReloadedSender, VmSessionEvent, WebhookResponseSender},
+    vmworkerpool::PoolStatus,
     SchedulerConfig,
 };
+use chrono::{DateTime, Utc};
+use common::dispatch_event::{EvalOutput, EvalOutputKind};
 use dbrokerapi::broker_scheduler_rpc::{DiscordEvent, DiscordEventData, HelloData};
 use guild_logger::LogEntry;
 use std::future::Future;
-use stores::Db;
+use stores::{suspensions::SuspensionReason, Db};
 use tokio::sync::{mpsc, oneshot};
-use tracing::info;
+use tracing::{error, info};
 use twilight_model::id::{marker::GuildMarker, Id};
 
 pub enum SchedulerCommand {
     BrokerConnected,
     BrokerDisconnected,
     BrokerHello(HelloData),
-    DiscordEvent(DiscordEvent),
+    BrokerPartitionChanged(HelloData),
+    DiscordEvent(DiscordEvent, BrokerEventAck),
     Shutdown,
     ReloadGuildScripts(Id<GuildMarker>),
+    /// Replaces a single script in the guild's vm, falling back to a full reload if needed
+    ReloadGuildScript(Id<GuildMarker>, u64, ScriptReloadedSender),
     PurgeGuildCache(Id<GuildMarker>),
-    WorkerStatus(oneshot::Sender<Vec<WorkerStatus>>),
+    Eval(Id<GuildMarker>, String, EvalOutputSender),
+    /// Passes a request to one of the guild's inbound webhooks on to its scripts
+    Webhook(
+        Id<GuildMarker>,
+        InboundWebhookDispatch,
+        WebhookResponseSender,
+    ),
+    WorkerStatus(oneshot::Sender<PoolStatus>),
     GuildStatus(Id<GuildMarker>, oneshot::Sender<Option<GuildStatus>>),
+    /// Suspends the guild until lifted, or for the provided duration
+    ImposeGuildSuspension(
+        Id<GuildMarker>,
+        Option<Duration>,
+        Option<String>,
+        oneshot::Sender<Result<(), String>>,
+    ),
+    /// Lifts all active suspensions of the guild, responding with whether it was suspended
+    LiftGuildSuspension(Id<GuildMarker>, oneshot::Sender<Result<bool, String>>),
+    /// The leases of these guilds could not be renewed, another scheduler could be running them
+    GuildLeasesLost(Vec<Id<GuildMarker>>),
+
+    // the ones below are sent by the scheduler itself once the database calls it spawned complete
+    /// The active suspensions loaded from the database, merged into the ones we know about
+    SuspensionsLoaded(Vec<stores::suspensions::GuildSuspension>),
+    /// An automatic suspension was stored, carrying the duration from the guild's offense level
+    GuildSuspended(Id<GuildMarker>, GuildSuspension),
+    /// An admin suspension was stored and can be applied
+    GuildSuspensionImposed(
+        Id<GuildMarker>,
+        GuildSuspension,
+        oneshot::Sender<Result<(), String>>,
+    ),
+    /// The guild's suspensions were lifted in the database, with the number of them lifted
+    GuildSuspensionLifted(Id<GuildMarker>, u64, oneshot::Sender<Result<bool, String>>),
 }
 
+// automatic suspensions within this window count as repeat offenses,
+// each of them doubling the duration of the next suspension
+const OFFENSE_WINDOW: Duration = Duration::from_secs(60 * 60 * 24);
+const MAX_SUSPENSION_DURATION: Duration = Duration::from_secs(60 * 60 * 24);
+
 pub struct Scheduler {
     guilds: HashMap<Id<GuildMarker>, GuildHandle>,
     cmd_rx: mpsc::UnboundedReceiver<SchedulerCommand>,
-    queued_events: Vec<DiscordEvent>,
+    // weak so the scheduler still shuts down once everything else dropped their senders
+    cmd_tx: mpsc::WeakUnboundedSender<SchedulerCommand>,
+    queued_events: Vec<(DiscordEvent, BrokerEventAck)>,
     pending_starts: Vec<Id<GuildMarker>>,
     stores: Db,
     logger: guild_logger::LogSender,
     cmd_manager_handle: command_manager::Handle,
     worker_pool: crate::vmworkerpool::VmWorkerPool,
     config: Arc<SchedulerConfig>,
+    leases: GuildLeases,
 
     suspended_guilds: HashMap<Id<GuildMarker>, GuildSuspension>,
 }
@@ -51,10 +98,12 @@ impl Scheduler {
     pub fn new(
         config: Arc<SchedulerConfig>,
         scheduler_rx: mpsc::UnboundedReceiver<SchedulerCommand>,
+        scheduler_tx: &mpsc::UnboundedSender<SchedulerCommand>,
         stores: Db,
         logger: guild_logger::LogSender,
         cmd_manager_handle: command_manager::Handle,
         worker_pool: crate::vmworkerpool::VmWorkerPool,
+        leases: GuildLeases,
     ) -> Self {
         Self {
             stores,
@@ -62,9 +111,11 @@ impl Scheduler {
             cmd_manager_handle,
             worker_pool,
             config,
+            leases,
 
             guilds: HashMap::new(),
             cmd_rx: scheduler_rx,
+            cmd_tx: scheduler_tx.downgrade(),
             queued_events: Vec::new(),
             pending_starts: Vec::new(),
             suspended_guilds: HashMap::new(),
@@ -72,6 +123,11 @@ impl Scheduler {
     }
 
     pub async fn run(mut self) {
+        // nothing is running yet, so there's no harm in waiting for these
+        if let Some(suspensions) = fetch_active_suspensions(&self.stores).await {
+            self.apply_suspensions(suspensions);
+        }
+
         loop {
             match self.next_action().await {
                 SchedulerAction::Cmd(Some(SchedulerCommand::Shutdown)) => {
@@ -95,7 +151,7 @@ impl Scheduler {
             }
         }
 
-        self.shutdown_all();
+        self.drain_all();
         self.wait_all_shutdown().await;
     }
 
@@ -135,7 +191,7 @@ impl Scheduler {
             .queued_events
             .iter()
             .enumerate()
-            .filter_map(|(i, v)| {
+            .filter_map(|(i, (v, _))| {
                 if v.guild_id == guild_id {
                     Some(i)
                 } else {
@@ -155,8 +211,8 @@ impl Scheduler {
         // reverse back in the proper order
         evts.reverse();
 
-        for evt in evts {
-            self.send_or_queue_broker_evt(evt);
+        for (evt, ack) in evts {
+            self.send_or_queue_broker_evt(evt, ack);
             // self.handle_broker_evt(evt).await;
         }
     }
@@ -183,14 +239,229 @@ impl Scheduler {
         }
     }
 
+    /// Suspends the guild right away, the duration depends on its recent offenses
+    /// so it's updated once those are counted and the suspension is stored
     fn mark_guild_as_suspended(&mut self, guild_id: Id<GuildMarker>, reason: SuspensionReason) {
-        info!("guild {guild_id} marked as suspended, reason: {reason:?}");
-        self.suspended_guilds
-            .insert(guild_id, GuildSuspension::new(guild_id, reason));
+        let provisional =
+            Utc::now() + chrono::Duration::from_std(suspension_duration(reason, 0)).unwrap();
+        self.suspend_guild(
+            guild_id,
+            GuildSuspension {
+                reason,
+                expires_at: Some(provisional),
+            },
+        );
+
+        let stores = self.stores.clone();
+        let logger = self.logger.clone();
+        self.spawn_db_task(async move {
+            let since = Utc::now() - chrono::Duration::from_std(OFFENSE_WINDOW).unwrap();
+            let offense_level = match stores.count_recent_guild_offenses(guild_id, since).await {
+                Ok(v) => v,
+                Err(err) => {
+                    error!(%err, %guild_id, "failed counting recent guild offenses");
+                    0
+                }
+            };
+
+            let duration = suspension_duration(reason, offense_level);
+            info!(
+                %guild_id,
+                ?reason,
+                offense_level,
+                duration_secs = duration.as_secs(),
+                "guild marked as suspended"
+            );
+
+            let expires_at = Utc::now() + chrono::Duration::from_std(duration).unwrap();
+            if let Err(err) = stores
+                .create_guild_suspension(guild_id, reason, None, offense_level, Some(expires_at))
+                .await
+            {
+                error!(%err, %guild_id, "failed storing guild suspension");
+            }
+
+            let mut msg = format!(
+                "your server has been suspended for {}: {}.",
+                format_duration(duration),
+                reason_description(reason)
+            );
+            if offense_level > 0 {
+                msg.push_str(&format!(
+                    " this is suspension number {} in the last 24 hours, repeated suspensions \
+                     last longer.",
+                    offense_level + 1
+                ));
+            }
+            logger.log(LogEntry::critical(guild_id, msg));
+
+            Some(SchedulerCommand::GuildSuspended(
+                guild_id,
+                GuildSuspension {
+                    reason,
+                    expires_at: Some(expires_at),
+                },
+            ))
+        });
+    }
+
+    /// Runs a database call off the scheduler loop, handling the command it results in
+    /// once it completes
+    fn spawn_db_task<F>(&self, fut: F)
+    where
+        F: Future<Output = Option<SchedulerCommand>> + Send + 'static,
+    {
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            if let Some(cmd) = fut.await {
+                if let Some(tx) = cmd_tx.upgrade() {
+                    let _ = tx.send(cmd);
+                }
+            }
+        });
+    }
+
+    fn suspend_guild(&mut self, guild_id: Id<GuildMarker>, suspension: GuildSuspension) {
+        self.suspended_guilds.insert(guild_id, suspension);
 
         // remove all queued starts and events for this guild
         self.pending_starts.retain(|v| *v != guild_id);
-        self.queued_events.retain(|v| v.guild_id != guild_id);
+        self.queued_events.retain(|(v, _)| v.guild_id != guild_id);
+    }
+
+    /// Reloads the suspensions from the database,
+    /// they could have been imposed by another scheduler or before a restart
+    fn load_suspensions(&self) {
+        let stores = self.stores.clone();
+        self.spawn_db_task(async move {
+            fetch_active_suspensions(&stores)
+                .await
+                .map(SchedulerCommand::SuspensionsLoaded)
+        });
+    }
+
+    /// Merges the stored suspensions into the ones we know about
+    ///
+    /// Suspensions imposed while these were loading may not have been stored yet,
+    /// so the longest lasting one wins instead of replacing the map.
+    fn apply_suspensions(&mut self, suspensions: Vec<stores::suspensions::GuildSuspension>) {
+        for susp in suspensions {
+            let loaded = GuildSuspension {
+                reason: susp.reason,
+                expires_at: susp.expires_at,
+            };
+
+            match self.suspended_guilds.get(&susp.guild_id) {
+                Some(existing) if existing.outlasts(&loaded) => {}
+                _ => {
+                    self.suspended_guilds.insert(susp.guild_id, loaded);
+                }
+            }
+        }
+
+        // guilds could have been started while these were loading
+        let suspended = self.suspended_guilds.keys().copied().collect::<Vec<_>>();
+        for guild_id in suspended {
+            if !self.try_unsuspend_guild(guild_id) {
+                self.pending_starts.retain(|v| *v != guild_id);
+                self.queued_events.retain(|(v, _)| v.guild_id != guild_id);
+                self.shutdown_guild(guild_id);
+            }
+        }
+
+        info!(
+            num_suspended = self.suspended_guilds.len(),
+            "loaded guild suspensions"
+        );
+    }
+
+    fn shutdown_guild(&mut self, guild_id: Id<GuildMarker>) {
+        if let Some(worker) = self.guilds.get_mut(&guild_id) {
+            if let Some(tx) = worker.tx.take() {
+                let _ = tx.send(GuildCommand::Shutdown);
+            }
+        }
+    }
+
+    fn impose_guild_suspension(
+        &self,
+        guild_id: Id<GuildMarker>,
+        duration: Option<Duration>,
+        message: Option<String>,
+        resp: oneshot::Sender<Result<(), String>>,
+    ) {
+        let expires_at = match duration
+            .map(|v| chrono::Duration::from_std(v).map(|v| Utc::now() + v))
+            .transpose()
+        {
+            Ok(v) => v,
+            Err(_) => {
+                let _ = resp.send(Err("duration out of range".to_owned()));
+                return;
+            }
+        };
+
+        let stores = self.stores.clone();
+        let logger = self.logger.clone();
+        self.spawn_db_task(async move {
+            if let Err(err) = stores
+                .create_guild_suspension(
+                    guild_id,
+                    SuspensionReason::Admin,
+                    message.as_deref(),
+                    0,
+                    expires_at,
+                )
+                .await
+            {
+                let _ = resp.send(Err(err.to_string()));
+                return None;
+            }
+
+            info!(%guild_id, ?duration, "guild suspended by admin");
+
+            let mut msg = match duration {
+                Some(duration) => format!(
+                    "your server has been suspended for {} by a botloader admin.",
+                    format_duration(duration)
+                ),
+                None => "your server has been suspended by a botloader admin until further \
+                         notice."
+                    .to_owned(),
+            };
+            if let Some(message) = &message {
+                msg.push_str(&format!(" reason: {message}"));
+            }
+            logger.log(LogEntry::critical(guild_id, msg));
+
+            Some(SchedulerCommand::GuildSuspensionImposed(
+                guild_id,
+                GuildSuspension {
+                    reason: SuspensionReason::Admin,
+                    expires_at,
+                },
+                resp,
+            ))
+        });
+    }
+
+    fn lift_guild_suspension(
+        &self,
+        guild_id: Id<GuildMarker>,
+        resp: oneshot::Sender<Result<bool, String>>,
+    ) {
+        let stores = self.stores.clone();
+        self.spawn_db_task(async move {
+            match stores.lift_guild_suspensions(guild_id).await {
+                Ok(lifted) => Some(SchedulerCommand::GuildSuspensionLifted(
+                    guild_id, lifted, resp,
+                )),
+                Err(err) => {
+                    let _ = resp.send(Err(err.to_string()));
+                    None
+                }
+            }
+        });
     }
 
     async fn handle_scheduler_command(&mut self, cmd: SchedulerCommand) {
@@ -200,6 +471,7 @@ impl Scheduler {
             SchedulerCommand::BrokerHello(d) => {
                 info!("new broker connected");
                 self.shutdown_all();
+                self.load_suspensions();
 
                 self.pending_starts = Vec::new();
 
@@ -217,11 +489,62 @@ impl Scheduler {
                 }
             }
 
+            // another scheduler joined or left, hand off the guilds we no longer own
+            // and start the ones we were handed
+            SchedulerCommand::BrokerPartitionChanged(d) => {
+                let owned = d.connected_guilds.into_iter().collect::<HashSet<_>>();
+
+                // we could have been handed guilds suspended by another scheduler
+                self.load_suspensions();
+
+                let mut removed = 0;
+                for (guild_id, worker) in &mut self.guilds {
+                    if owned.contains(guild_id) {
+                        continue;
+                    }
+
+                    if let Some(tx) = worker.tx.take() {
+                        let _ = tx.send(GuildCommand::Shutdown);
+                        removed += 1;
+                    }
+                }
+
+                self.pending_starts.retain(|v| owned.contains(v));
+                self.queued_events
+                    .retain(|(v, _)| owned.contains(&v.guild_id));
+
+                let mut added = 0;
+                for g in owned {
+                    if !self.try_unsuspend_guild(g) {
+                        continue;
+                    }
+
+                    match self.guilds.get(&g) {
+                        // handed back to us while still shutting down, start it again once it's done
+                        Some(worker) if worker.tx.is_none() => {
+                            if !self.pending_starts.contains(&g) {
+                                self.pending_starts.push(g);
+                            }
+                        }
+                        Some(_) => {}
+                        None => {
+                            self.get_or_start_guild(g);
+                            added += 1;
+                        }
+                    }
+                }
+
+                info!(removed, added, "guild partition changed");
+            }
+
             SchedulerCommand::BrokerDisconnected => {
                 self.shutdown_all();
+
+                // these were never acked, the broker delivers them again
+                self.queued_events.clear();
             }
             SchedulerCommand::BrokerConnected => {}
-            SchedulerCommand::DiscordEvent(evt) => {
+            SchedulerCommand::DiscordEvent(evt, ack) => {
                 if !self.try_unsuspend_guild(evt.guild_id) {
                     return;
                 }
@@ -234,7 +557,7 @@ impl Scheduler {
                         }
                     }
                 } else {
-                    self.send_or_queue_broker_evt(evt)
+                    self.send_or_queue_broker_evt(evt, ack)
                 }
             }
             SchedulerCommand::Shutdown => {
@@ -245,9 +568,14 @@ impl Scheduler {
                 if self.try_unsuspend_guild(guild_id) {
                     self.get_or_start_guild(guild_id);
                 } else {
+                    let msg = self
+                        .suspended_guilds
+                        .get(&guild_id)
+                        .map(|v| v.remaining_description())
+                        .unwrap_or_default();
                     self.logger.log(LogEntry::error(
                         guild_id,
-                        "can't unsuspend your guild yet, please wait 10 minutes".to_owned(),
+                        format!("can't unsuspend your server yet, {msg}"),
                     ));
                     return;
                 }
@@ -258,6 +586,33 @@ impl Scheduler {
                     }
                 }
             }
+            SchedulerCommand::ReloadGuildScript(guild_id, script_id, done) => {
+                if !self.try_unsuspend_guild(guild_id) {
+                    let msg = self
+                        .suspended_guilds
+                        .get(&guild_id)
+                        .map(|v| v.remaining_description())
+                        .unwrap_or_default();
+                    self.logger.log(LogEntry::error(
+                        guild_id,
+                        format!("can't unsuspend your server yet, {msg}"),
+                    ));
+                    return;
+                }
+
+                if !self.guilds.contains_key(&guild_id) {
+                    // a fresh guild handler loads the latest version of all the scripts
+                    self.get_or_start_guild(guild_id);
+                    let _ = done.send(());
+                    return;
+                }
+
+                if let Some(g) = self.guilds.get(&guild_id) {
+                    if let Some(tx) = &g.tx {
+                        let _ = tx.send(GuildCommand::ReloadScript(script_id, done));
+                    }
+                }
+            }
             SchedulerCommand::PurgeGuildCache(guild_id) => {
                 if let Some(g) = self.guilds.get(&guild_id) {
                     if let Some(tx) = &g.tx {
@@ -265,9 +620,52 @@ impl Scheduler {
                     }
                 }
             }
+            SchedulerCommand::Eval(guild_id, source, tx) => {
+                // evals go through the normal dispatch path so suspensions apply to them aswell
+                if !self.try_unsuspend_guild(guild_id) {
+                    let _ = tx.send(EvalOutput {
+                        eval_id: 0,
+                        kind: EvalOutputKind::Exception,
+                        message: "your server is currently suspended".to_owned(),
+                    });
+                    return;
+                }
+
+                let worker = self.get_or_start_guild(guild_id);
+                match &worker.tx {
+                    Some(guild_tx) => {
+                        let _ = guild_tx.send(GuildCommand::Eval(source, tx));
+                    }
+                    None => {
+                        let _ = tx.send(EvalOutput {
+                            eval_id: 0,
+                            kind: EvalOutputKind::Exception,
+                            message: "the vm is currently restarting, try again in a bit"
+                                .to_owned(),
+                        });
+                    }
+                }
+            }
+            SchedulerCommand::Webhook(guild_id, req, tx) => {
+                if !self.try_unsuspend_guild(guild_id) {
+                    let _ = tx.send(Err("the server is currently suspended".to_owned()));
+                    return;
+                }
+
+                let worker = self.get_or_start_guild(guild_id);
+                match &worker.tx {
+                    Some(guild_tx) => {
+                        let _ = guild_tx.send(GuildCommand::Webhook(req, tx));
+                    }
+                    None => {
+                        let _ = tx.send(Err(
+                            "the vm is currently restarting, try again in a bit".to_owned()
+                        ));
+                    }
+                }
+            }
             SchedulerCommand::WorkerStatus(req) => {
-                let statuses = self.worker_pool.worker_statuses();
-                let _ = req.send(statuses);
+                let _ = req.send(self.worker_pool.status());
             }
             SchedulerCommand::GuildStatus(guild_id, resp) => {
                 if let Some(g) = self.guilds.get(&guild_id) {
@@ -278,22 +676,63 @@ impl Scheduler {
                 }
                 let _ = resp.send(None);
             }
+            SchedulerCommand::ImposeGuildSuspension(guild_id, duration, message, resp) => {
+                self.impose_guild_suspension(guild_id, duration, message, resp);
+            }
+            SchedulerCommand::LiftGuildSuspension(guild_id, resp) => {
+                self.lift_guild_suspension(guild_id, resp);
+            }
+            SchedulerCommand::GuildLeasesLost(guild_ids) => {
+                for guild_id in guild_ids {
+                    // the events were never acked, the broker delivers them to the new owner
+                    self.pending_starts.retain(|v| *v != guild_id);
+                    self.queued_events.retain(|(v, _)| v.guild_id != guild_id);
+                    self.shutdown_guild(guild_id);
+                }
+            }
+            SchedulerCommand::SuspensionsLoaded(suspensions) => {
+                self.apply_suspensions(suspensions);
+            }
+            SchedulerCommand::GuildSuspended(guild_id, suspension) => {
+                // only replace the provisional suspension, it could have been lifted meanwhile
+                if self.suspended_guilds.contains_key(&guild_id) {
+                    self.suspend_guild(guild_id, suspension);
+                }
+            }
+            SchedulerCommand::GuildSuspensionImposed(guild_id, suspension, resp) => {
+                self.suspend_guild(guild_id, suspension);
+                self.shutdown_guild(guild_id);
+                let _ = resp.send(Ok(()));
+            }
+            SchedulerCommand::GuildSuspensionLifted(guild_id, lifted, resp) => {
+                let was_suspended = self.suspended_guilds.remove(&guild_id).is_some() || lifted > 0;
+                if was_suspended {
+                    info!(%guild_id, "guild suspension lifted by admin");
+                    self.logger.log(LogEntry::info(
+                        guild_id,
+                        "your server's suspension has been lifted by a botloader admin".to_owned(),
+                    ));
+                    self.get_or_start_guild(guild_id);
+                }
+
+                let _ = resp.send(Ok(was_suspended));
+            }
         }
     }
 
-    fn send_or_queue_broker_evt(&mut self, evt: DiscordEvent) {
+    fn send_or_queue_broker_evt(&mut self, evt: DiscordEvent, ack: BrokerEventAck) {
         let worker = self.get_or_start_guild(evt.guild_id);
 
         if let Some(tx) = &worker.tx {
-            if let Err(e) = tx.send(GuildCommand::BrokerEvent(evt)) {
+            if let Err(e) = tx.send(GuildCommand::BrokerEvent(evt, ack)) {
                 // dropped, push it to the queue
-                if let GuildCommand::BrokerEvent(evt) = e.0 {
-                    self.queued_events.push(evt);
+                if let GuildCommand::BrokerEvent(evt, ack) = e.0 {
+                    self.queued_events.push((evt, ack));
                 }
             }
         } else {
             // in shutting down state
-            self.queued_events.push(evt);
+            self.queued_events.push((evt, ack));
         }
     }
 
@@ -306,10 +745,28 @@ impl Scheduler {
                 self.logger.clone(),
                 self.worker_pool.clone(),
                 self.cmd_manager_handle.clone(),
+                self.leases.clone(),
             )
         })
     }
 
+    /// Shuts down all guilds, letting them finish the dispatches they have in flight first
+    fn drain_all(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(self.config.drain_timeout_secs);
+        info!(guilds = self.guilds.len(), "draining guilds");
+
+        // nothing new is started while draining, the queued events are left unacked
+        // so the broker hands them to the scheduler taking over the guilds
+        self.pending_starts.clear();
+        self.queued_events.clear();
+
+        for worker in self.guilds.values_mut() {
+            if let Some(tx) = worker.tx.take() {
+                let _ = tx.send(GuildCommand::Drain(deadline));
+            }
+        }
+    }
+
     fn shutdown_all(&mut self) {
         for worker in self.guilds.values_mut() {
             if let Some(tx) = worker.tx.take() {
@@ -320,7 +777,7 @@ impl Scheduler {
 
     fn try_unsuspend_guild(&mut self, guild_id: Id<GuildMarker>) -> bool {
         if let Some(susp) = self.suspended_guilds.get(&guild_id) {
-            if susp.can_remove() {
+            if susp.is_expired() {
                 self.suspended_guilds.remove(&guild_id);
             } else {
                 return false;
@@ -362,39 +819,83 @@ impl<'a> Future for SchedulerNextActionFuture<'a> {
     }
 }
 
-#[derive(Debug)]
-enum SuspensionReason {
-    ExcessCpu,
-    ExcessInvalidDiscordRequests,
+pub struct GuildSuspension {
+    reason: SuspensionReason,
+    // none if it has to be lifted manually
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl GuildSuspension {
+    fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|v| v < Utc::now())
+    }
+
+    fn outlasts(&self, other: &GuildSuspension) -> bool {
+        match (self.expires_at, other.expires_at) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(a), Some(b)) => a >= b,
+        }
+    }
+
+    fn remaining_description(&self) -> String {
+        match self.expires_at {
+            Some(expires_at) => {
+                let remaining = (expires_at - Utc::now()).to_std().unwrap_or_default();
+                format!(
+                    "it's suspended for another {} because {}",
+                    format_duration(remaining),
+                    reason_description(self.reason)
+                )
+            }
+            None => format!(
+                "it's suspended until further notice because {}",
+                reason_description(self.reason)
+            ),
+        }
+    }
 }
 
-impl SuspensionReason {
-    fn duration(&self) -> Duration {
-        match self {
-            Self::ExcessCpu => Duration::from_secs(15),
-            Self::ExcessInvalidDiscordRequests => Duration::from_secs(60 * 10),
+async fn fetch_active_suspensions(
+    stores: &Db,
+) -> Option<Vec<stores::suspensions::GuildSuspension>> {
+    match stores.get_active_guild_suspensions().await {
+        Ok(v) => Some(v),
+        Err(err) => {
+            error!(%err, "failed loading guild suspensions");
+            None
         }
     }
 }
 
-struct GuildSuspension {
-    _guild_id: Id<GuildMarker>,
-    reason: SuspensionReason,
-    suspended_at: Instant,
+fn suspension_duration(reason: SuspensionReason, offense_level: u32) -> Duration {
+    let base = match reason {
+        SuspensionReason::ExcessCpu => Duration::from_secs(15),
+        SuspensionReason::ExcessInvalidDiscordRequests => Duration::from_secs(60 * 10),
+        SuspensionReason::Admin => MAX_SUSPENSION_DURATION,
+    };
+
+    base.saturating_mul(1 << offense_level.min(16))
+        .min(MAX_SUSPENSION_DURATION)
 }
 
-impl GuildSuspension {
-    fn new(guild_id: Id<GuildMarker>, reason: SuspensionReason) -> Self {
-        Self {
-            _guild_id: guild_id,
-            reason,
-            suspended_at: Instant::now(),
+fn reason_description(reason: SuspensionReason) -> &'static str {
+    match reason {
+        SuspensionReason::ExcessCpu => "your scripts used too much cpu time",
+        SuspensionReason::ExcessInvalidDiscordRequests => {
+            "your scripts made too many invalid requests to discord"
         }
+        SuspensionReason::Admin => "it was suspended by a botloader admin",
     }
+}
 
-    fn can_remove(&self) -> bool {
-        let dur = self.reason.duration();
-
-        dur < self.suspended_at.elapsed()
+fn format_duration(dur: Duration) -> String {
+    let secs = dur.as_secs().max(1);
+    if secs < 60 {
+        format!("{secs} seconds")
+    } else if secs < 60 * 60 {
+        format!("{} minutes", secs.div_ceil(60))
+    } else {
+        format!("{} hours", secs.div_ceil(60 * 60))
     }
 }
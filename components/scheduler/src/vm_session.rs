@@ -5,6 +5,7 @@ use std::{
 };
 
 use crate::{
+    broker_acks::BrokerEventAck,
     command_manager,
     guild_handler::PremiumTierState,
     interval_timer_manager::{self, TimerId},
@@ -13,17 +14,28 @@ use crate::{
     SchedulerConfig,
 };
 use chrono::{DateTime, Utc};
-use common::dispatch_event::{EventSource, VmDispatchEvent};
+use common::dispatch_event::{
+    EvalOutput, EvalOutputKind, EvalRequest, EventSource, VmDispatchEvent, WebhookResponse,
+    EVAL_EVENT_NAME, WEBHOOK_EVENT_NAME,
+};
 use dbrokerapi::broker_scheduler_rpc::DiscordEvent;
 use guild_logger::{entry::CreateLogEntry, GuildLogSender};
-use runtime_models::{internal::script::ScriptMeta, util::PluginId};
-use scheduler_worker_rpc::{CreateScriptsVmReq, MetricEvent, SchedulerMessage, WorkerMessage};
+use runtime_models::{
+    internal::{
+        inbound_webhooks::InboundWebhookRequest,
+        script::{ScriptMeta, SettingsOptionDefinition},
+    },
+    util::{NotBigU64, PluginId},
+};
+use scheduler_worker_rpc::{
+    CreateScriptsVmReq, MetricEvent, SchedulerMessage, UpdateScriptReq, WorkerMessage,
+};
 use stores::{
-    config::{IntervalTimerContrib, Script, ScriptContributes, UpdateScript},
+    config::{IntervalTimerContrib, Script, ScriptContributes, UpdateScript, VendoredModule},
     timers::{IntervalTimer, ScheduledTask},
     Db,
 };
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 use tracing::{error, info, instrument, warn};
 use twilight_model::id::{marker::GuildMarker, Id};
 use vm::vm::ShutdownReason;
@@ -42,11 +54,21 @@ pub struct VmSession {
     premium_tier: Arc<RwLock<PremiumTierState>>,
 
     pending_acks: HashMap<u64, PendingAck>,
+    pending_evals: HashMap<u64, EvalOutputSender>,
+    pending_webhooks: HashMap<u64, WebhookResponseSender>,
     current_worker: Option<WorkerHandle>,
     force_load_scripts_next: bool,
+    // set while shutting down, no new timers or tasks are dispatched
+    draining: bool,
     scripts: Vec<Script>,
+    vendored_modules: Vec<VendoredModule>,
+    // what the scripts running in the current vm contributed, used to replace the contributions
+    // of a single script when it's reloaded
+    loaded_scripts: HashMap<u64, ScriptMeta>,
 
     dispatch_id_gen: u64,
+    eval_id_gen: u64,
+    webhook_request_id_gen: u64,
     current_vm_session_id: u64,
 
     last_claimed_worker_id: Option<u64>,
@@ -77,11 +99,18 @@ impl VmSession {
             worker_pool,
             premium_tier,
             dispatch_id_gen: 1,
+            eval_id_gen: 0,
+            webhook_request_id_gen: 0,
             current_vm_session_id: 1,
             pending_acks: HashMap::new(),
+            pending_evals: HashMap::new(),
+            pending_webhooks: HashMap::new(),
             current_worker: None,
             scripts: Vec::new(),
+            vendored_modules: Vec::new(),
+            loaded_scripts: HashMap::new(),
             force_load_scripts_next: false,
+            draining: false,
 
             interval_timers_man: interval_timer_man,
             cmd_manager_handle,
@@ -93,6 +122,12 @@ impl VmSession {
         }
     }
 
+    /// Library scripts are only ever run when imported, so there's no point in spinning up a vm
+    /// if there are no other scripts
+    fn has_entrypoint_scripts(&self) -> bool {
+        self.scripts.iter().any(|v| !v.is_library)
+    }
+
     pub fn _set_guild_scripts(&mut self, scripts: Vec<Script>) {
         self.scripts = scripts;
         self.force_load_scripts_next = true;
@@ -132,6 +167,7 @@ impl VmSession {
                     // we will be receiving a new set of tasks and timers for the new vm
                     self.scheduled_tasks_man.clear_task_names();
                     self.interval_timers_man.clear_loaded_timers();
+                    self.loaded_scripts.clear();
                 }
 
                 match shutdown.reason {
@@ -203,14 +239,20 @@ impl VmSession {
         if let Some(current) = self.current_worker.take() {
             self.last_claimed_worker_id = Some(current.worker_id);
             self.last_returned_worker_at = Instant::now();
+            self.fail_pending_evals();
+            self.fail_pending_webhooks();
 
             self.worker_pool.return_worker(current, false);
         }
     }
 
-    pub async fn shutdown(&mut self) {
+    pub async fn shutdown(&mut self, drain_deadline: Option<Instant>) {
         info!("shutting down vm session");
 
+        if let Some(deadline) = drain_deadline {
+            self.drain(deadline).await;
+        }
+
         // wait until the vm has finished it's work
         if let Some(worker) = &mut self.current_worker {
             if worker.tx.send(SchedulerMessage::Complete).is_err() {
@@ -244,6 +286,44 @@ impl VmSession {
         }
     }
 
+    /// Waits for the dispatches in flight to be acked, up until the deadline
+    async fn drain(&mut self, deadline: Instant) {
+        self.draining = true;
+
+        while self.current_worker.is_some() && !self.pending_acks.is_empty() {
+            let next = tokio::select! {
+                next = self.next_action() => next,
+                _ = tokio::time::sleep_until(deadline.into()) => break,
+            };
+
+            match next {
+                NextAction::WorkerMessage(Some(WorkerMessage::Shutdown(evt))) => {
+                    if evt.vm_session_id == self.current_vm_session_id {
+                        // the vm stopped on its own, there's nothing left to wait for
+                        self.return_worker();
+                        self.force_load_scripts_next = true;
+                        return;
+                    }
+                }
+                NextAction::WorkerMessage(Some(msg)) => self.handle_worker_msg(msg).await,
+                NextAction::WorkerMessage(None) => {
+                    self.broken_worker().await;
+                    return;
+                }
+                NextAction::CheckScheduledTasks | NextAction::CheckIntervalTimers => {}
+            }
+        }
+
+        if !self.pending_acks.is_empty() {
+            // scheduled tasks are only deleted, and interval timers only have their last run time
+            // updated, once acked so these are picked up again after the restart
+            warn!(
+                pending = self.pending_acks.len(),
+                "drain deadline reached before all dispatches were acked"
+            );
+        }
+    }
+
     fn get_premium_tier(&self) -> PremiumTierState {
         let r = self.premium_tier.read().unwrap();
         *r
@@ -252,7 +332,7 @@ impl VmSession {
     pub async fn start_fresh_vm(&mut self) {
         info!("loading contribs");
 
-        if self.scripts.is_empty() {
+        if !self.has_entrypoint_scripts() {
             self.cmd_manager_handle
                 .send_no_scripts_enabled(self.guild_id);
         }
@@ -265,7 +345,7 @@ impl VmSession {
             // if we have not claimed a worker then there's no pending acks
             self.clear_loaded_timers_and_tasks();
 
-            if self.scripts.is_empty() {
+            if !self.has_entrypoint_scripts() {
                 return;
             }
 
@@ -276,9 +356,10 @@ impl VmSession {
 
     async fn try_retry_load_guild_scripts(&mut self) {
         loop {
-            match self.stores.list_scripts(self.guild_id).await {
-                Ok(scripts) => {
-                    self.scripts = scripts.into_iter().filter(|v| v.enabled).collect();
+            match self.load_guild_scripts().await {
+                Ok((scripts, vendored_modules)) => {
+                    self.scripts = scripts;
+                    self.vendored_modules = vendored_modules;
                     return;
                 }
                 Err(err) => {
@@ -289,7 +370,37 @@ impl VmSession {
         }
     }
 
+    async fn load_guild_scripts(
+        &self,
+    ) -> Result<(Vec<Script>, Vec<VendoredModule>), stores::config::ConfigStoreError> {
+        let scripts = self
+            .stores
+            .list_scripts(self.guild_id)
+            .await?
+            .into_iter()
+            .filter(|v| v.enabled)
+            .collect::<Vec<_>>();
+
+        let plugin_ids = scripts
+            .iter()
+            .filter_map(|v| v.plugin_id)
+            .collect::<Vec<_>>();
+        let vendored_modules = self
+            .stores
+            .list_vm_vendored_modules(self.guild_id, &plugin_ids)
+            .await?;
+
+        Ok((scripts, vendored_modules))
+    }
+
     pub async fn next_action(&mut self) -> NextAction {
+        if self.draining {
+            return match &mut self.current_worker {
+                Some(worker) => NextAction::WorkerMessage(worker.rx.recv().await),
+                None => std::future::pending().await,
+            };
+        }
+
         let scheduled_task_sleep_check = match self.scheduled_tasks_man.next_action() {
             scheduled_task_manager::NextAction::None => tokio::time::sleep(Duration::MAX),
             scheduled_task_manager::NextAction::Wait(until) => {
@@ -362,6 +473,9 @@ impl VmSession {
                         PendingAckType::IntervalTimer(timer) => {
                             self.interval_timers_man.timer_ack(&timer).await;
                         }
+                        PendingAckType::ReloadScript(done) => {
+                            let _ = done.send(());
+                        }
                         PendingAckType::Restart => {}
                     }
                 }
@@ -385,10 +499,56 @@ impl VmSession {
                 // handled when connection is established, not applicable here
                 unreachable!();
             }
+            WorkerMessage::RemoteHello(_) => {
+                // remote workers are less trusted, don't take the scheduler down over it
+                warn!("remote worker sent a second hello, ignoring it");
+            }
+            WorkerMessage::Pong => {
+                // response to a health check while the worker was idle in the pool
+            }
             WorkerMessage::Shutdown(_) => {
                 // handled in caller
             }
             WorkerMessage::Metric(name, m, labels) => self.handle_metric(name, m, labels),
+            WorkerMessage::EvalOutput(output) => self.handle_eval_output(output),
+            WorkerMessage::WebhookResponse(resp) => self.handle_webhook_response(resp),
+        }
+    }
+
+    fn handle_eval_output(&mut self, output: EvalOutput) {
+        let is_final = output.kind.is_final();
+        let eval_id = output.eval_id;
+
+        if let Some(tx) = self.pending_evals.get(&eval_id) {
+            if tx.send(output).is_err() || is_final {
+                // either the client went away or the eval is done
+                self.pending_evals.remove(&eval_id);
+            }
+        }
+    }
+
+    // the vm the evals were running in is gone so they will never complete
+    fn fail_pending_evals(&mut self) {
+        for (eval_id, tx) in self.pending_evals.drain() {
+            let _ = tx.send(EvalOutput {
+                eval_id,
+                kind: EvalOutputKind::Exception,
+                message: "the vm finished or shut down before the eval completed".to_string(),
+            });
+        }
+    }
+
+    fn handle_webhook_response(&mut self, resp: WebhookResponse) {
+        if let Some(tx) = self.pending_webhooks.remove(&resp.request_id) {
+            let _ = tx.send(Ok(resp.body));
+        }
+    }
+
+    fn fail_pending_webhooks(&mut self) {
+        for (_, tx) in self.pending_webhooks.drain() {
+            let _ = tx.send(Err(
+                "the vm finished or shut down before the request was handled".to_string(),
+            ));
         }
     }
 
@@ -429,6 +589,46 @@ impl VmSession {
         self.start_fresh_vm().await;
     }
 
+    /// Replaces a single script in the running vm, keeping the state of the other scripts
+    ///
+    /// Starts a fresh vm instead if the change can't be applied to the script in place, `done` is
+    /// notified once the vm runs the new version
+    pub async fn reload_guild_script(&mut self, script_id: u64, done: ScriptReloadedSender) {
+        let previous = self.scripts.iter().find(|v| v.id == script_id).cloned();
+        self.try_retry_load_guild_scripts().await;
+        let updated = self.scripts.iter().find(|v| v.id == script_id).cloned();
+
+        let (Some(previous), Some(updated)) = (previous, updated) else {
+            // the script was enabled or disabled
+            self.start_fresh_vm().await;
+            let _ = done.send(());
+            return;
+        };
+
+        if !can_reload_in_place(&previous, &updated) {
+            self.start_fresh_vm().await;
+            let _ = done.send(());
+            return;
+        }
+
+        match self.ensure_claim_worker().await {
+            // the new vm was created with the new version of the script
+            ClaimWorkerResult::Reloaded => {
+                let _ = done.send(());
+            }
+            ClaimWorkerResult::QueueFull => {
+                // the vm that might be sitting in the pool still runs the old version
+                self.force_load_scripts_next = true;
+            }
+            ClaimWorkerResult::Reused => {
+                if self.send_update_script(updated, done).is_err() {
+                    self.broken_worker().await;
+                    self.force_load_scripts_next = true;
+                }
+            }
+        }
+    }
+
     async fn dispatch_scheduled_task(&mut self, task: ScheduledTask) {
         info!("dispatching scheduled task");
         let task_id = task.id;
@@ -440,6 +640,7 @@ impl VmSession {
             PendingAckType::ScheduledTask(task_id),
             EventSource::Timer,
             Utc::now(),
+            None,
         )
         .await;
     }
@@ -458,11 +659,86 @@ impl VmSession {
             PendingAckType::IntervalTimer(TimerId::new(timer.plugin_id, timer.name)),
             EventSource::Timer,
             Utc::now(),
+            None,
         )
         .await;
     }
 
-    pub async fn send_discord_guild_event(&mut self, evt: DiscordEvent) {
+    pub async fn dispatch_eval(&mut self, source: String, tx: EvalOutputSender) {
+        self.eval_id_gen += 1;
+        let eval_id = self.eval_id_gen;
+
+        // evals whose caller gave up waiting, e.g. because they timed out
+        self.pending_evals.retain(|_, tx| !tx.is_closed());
+
+        if !self.has_entrypoint_scripts() {
+            let _ = tx.send(EvalOutput {
+                eval_id,
+                kind: EvalOutputKind::Exception,
+                message: "there are no scripts enabled on this server, so there is no vm to \
+                          evaluate in"
+                    .to_string(),
+            });
+            return;
+        }
+
+        info!("dispatching eval");
+        let serialized = serde_json::to_value(EvalRequest { eval_id, source }).unwrap();
+        self.dispatch_worker_evt(
+            EVAL_EVENT_NAME.to_string(),
+            serialized,
+            PendingAckType::Dispatch(None),
+            EventSource::Eval,
+            Utc::now(),
+            None,
+        )
+        .await;
+
+        // inserted after dispatching since claiming a worker could create a new vm, failing all pending evals
+        self.pending_evals.insert(eval_id, tx);
+    }
+
+    pub async fn dispatch_webhook(
+        &mut self,
+        webhook_id: u64,
+        name: String,
+        body: serde_json::Value,
+        tx: WebhookResponseSender,
+    ) {
+        if !self.has_entrypoint_scripts() {
+            let _ = tx.send(Err(
+                "there are no scripts enabled on this server to handle the request".to_string(),
+            ));
+            return;
+        }
+
+        self.webhook_request_id_gen += 1;
+        let request_id = self.webhook_request_id_gen;
+
+        info!("dispatching webhook request");
+        let serialized = serde_json::to_value(InboundWebhookRequest {
+            request_id: NotBigU64(request_id),
+            webhook_id: NotBigU64(webhook_id),
+            name,
+            body,
+        })
+        .unwrap();
+
+        self.dispatch_worker_evt(
+            WEBHOOK_EVENT_NAME.to_string(),
+            serialized,
+            PendingAckType::Dispatch(None),
+            EventSource::Webhook,
+            Utc::now(),
+            None,
+        )
+        .await;
+
+        // same as with evals, claiming a worker could create a new vm failing all pending requests
+        self.pending_webhooks.insert(request_id, tx);
+    }
+
+    pub async fn send_discord_guild_event(&mut self, evt: DiscordEvent, ack: BrokerEventAck) {
         let t_clone = evt.t.clone();
         let ts_clone = evt.timestamp;
         match crate::dispatch_conv::discord_event_to_dispatch(evt) {
@@ -473,14 +749,17 @@ impl VmSession {
                     PendingAckType::Dispatch(None),
                     EventSource::Discord,
                     ts_clone,
+                    Some(ack),
                 )
                 .await;
             }
             Ok(None) => {
-                tracing::warn!(t = t_clone, "skipped converting dispatch event")
+                tracing::warn!(t = t_clone, "skipped converting dispatch event");
+                ack.done();
             }
             Err(err) => {
-                error!(%err, t=t_clone, "failed converting dispatch event")
+                error!(%err, t=t_clone, "failed converting dispatch event");
+                ack.done();
             }
         }
     }
@@ -492,13 +771,39 @@ impl VmSession {
         ack: PendingAckType,
         source: EventSource,
         ts: DateTime<Utc>,
+        broker_ack: Option<BrokerEventAck>,
     ) {
-        if self.scripts.is_empty() {
+        if !self.has_entrypoint_scripts() {
+            if let Some(broker_ack) = broker_ack {
+                broker_ack.done();
+            }
             return;
         }
 
+        let mut queue_full_backoff = QUEUE_FULL_MIN_BACKOFF;
         loop {
-            self.ensure_claim_worker().await;
+            if let ClaimWorkerResult::QueueFull = self.ensure_claim_worker().await {
+                match &broker_ack {
+                    // holding on to the event leaves it unacked, which slows the broker down
+                    // instead of dropping events it already considers delivered
+                    Some(broker_ack) if !broker_ack.is_draining() => {
+                        warn!(t, backoff = ?queue_full_backoff, "worker queue full, retrying");
+                        tokio::time::sleep(queue_full_backoff).await;
+                        queue_full_backoff = (queue_full_backoff * 2).min(QUEUE_FULL_MAX_BACKOFF);
+                        continue;
+                    }
+                    // left unacked, the next owner of the guild gets it
+                    Some(_) => {}
+                    None => {
+                        self.logger.log(CreateLogEntry::error(format!(
+                            "dropped {t} event, the scheduler is overloaded"
+                        )));
+                    }
+                }
+
+                self.release_pending_ack_kind(ack);
+                return;
+            }
 
             let evt_id = self.gen_dispatch_id();
 
@@ -518,6 +823,9 @@ impl VmSession {
                                 kind: ack,
                             },
                         );
+                        if let Some(broker_ack) = broker_ack {
+                            broker_ack.done();
+                        }
                         return;
                     }
                     Err(_) => {
@@ -537,10 +845,14 @@ impl VmSession {
         }
 
         loop {
-            let (worker, wr) = self
+            let Ok((worker, wr)) = self
                 .worker_pool
                 .req_worker(self.guild_id, self.get_premium_tier().option())
-                .await;
+                .await
+            else {
+                warn!("worker request was shed, too many requests queued");
+                return ClaimWorkerResult::QueueFull;
+            };
 
             let should_create_vm = self.should_send_scripts(wr);
 
@@ -569,6 +881,10 @@ impl VmSession {
 
     #[instrument(skip_all)]
     async fn send_create_scripts_vm(&mut self) -> Result<(), ()> {
+        // evals and webhook requests running in the old vm will never complete
+        self.fail_pending_evals();
+        self.fail_pending_webhooks();
+
         let evt_id = self.gen_dispatch_id();
         let new_session_id = self.invalidate_create_new_session_id();
 
@@ -581,6 +897,7 @@ impl VmSession {
                     guild_id: self.guild_id,
                     premium_tier: self.get_premium_tier().option(),
                     scripts: self.scripts.clone(),
+                    vendored_modules: self.vendored_modules.clone(),
                 }))
                 .is_err()
             {
@@ -601,6 +918,32 @@ impl VmSession {
         Ok(())
     }
 
+    fn send_update_script(&mut self, script: Script, done: ScriptReloadedSender) -> Result<(), ()> {
+        let evt_id = self.gen_dispatch_id();
+
+        let Some(worker) = &self.current_worker else {
+            return Err(());
+        };
+
+        worker
+            .tx
+            .send(SchedulerMessage::UpdateScript(UpdateScriptReq {
+                seq: evt_id,
+                script,
+            }))
+            .map_err(|_| ())?;
+
+        self.pending_acks.insert(
+            evt_id,
+            PendingAck {
+                dispatched_session_id: self.current_vm_session_id,
+                kind: PendingAckType::ReloadScript(done),
+            },
+        );
+
+        Ok(())
+    }
+
     async fn broken_worker(&mut self) {
         if let Some(mut worker) = self.current_worker.take() {
             self.last_claimed_worker_id = Some(worker.worker_id);
@@ -611,6 +954,8 @@ impl VmSession {
             }
 
             self.worker_pool.return_worker(worker, true);
+            self.fail_pending_evals();
+            self.fail_pending_webhooks();
             self.clear_loaded_timers_and_tasks();
             self.clear_all_pending_timer_acks();
             self.pending_acks.clear();
@@ -640,7 +985,12 @@ impl VmSession {
             return;
         };
 
-        match pending.kind {
+        self.release_pending_ack_kind(pending.kind);
+    }
+
+    // makes timers and tasks that will never be acked available to be triggered again
+    fn release_pending_ack_kind(&mut self, kind: PendingAckType) {
+        match kind {
             PendingAckType::Dispatch(_) => {}
             PendingAckType::ScheduledTask(task) => {
                 self.scheduled_tasks_man.remove_pending(task);
@@ -648,11 +998,12 @@ impl VmSession {
             PendingAckType::IntervalTimer(timer) => {
                 self.interval_timers_man.remove_pending(timer);
             }
-            PendingAckType::Restart => {}
+            PendingAckType::Restart | PendingAckType::ReloadScript(_) => {}
         }
     }
 
     fn clear_loaded_timers_and_tasks(&mut self) {
+        self.loaded_scripts.clear();
         self.interval_timers_man.clear_loaded_timers();
         self.scheduled_tasks_man.clear_task_names();
         self.scheduled_tasks_man.clear_next();
@@ -705,6 +1056,11 @@ impl VmSession {
         self.update_db_contribs(&evt, interval_contribs.clone())
             .await;
 
+        // a reloaded script replaces what the previous version of it contributed
+        if let Some(previous) = self.loaded_scripts.insert(evt.script_id.0, evt.clone()) {
+            self.remove_stale_contribs(&previous);
+        }
+
         self.interval_timers_man
             .script_started(interval_contribs)
             .await;
@@ -715,6 +1071,39 @@ impl VmSession {
             .send_loaded_script(self.guild_id, evt);
     }
 
+    /// Removes the timers and task buckets of a previous script version
+    /// that are no longer provided by any of the loaded scripts
+    fn remove_stale_contribs(&mut self, previous: &ScriptMeta) {
+        let plugin_id = previous.plugin_id.map(|v| v.0);
+        let timers = previous
+            .interval_timers
+            .iter()
+            .map(|v| TimerId::new(plugin_id, v.name.clone()))
+            .filter(|id| {
+                !self.loaded_scripts.values().any(|meta| {
+                    meta.interval_timers
+                        .iter()
+                        .any(|v| TimerId::new(meta.plugin_id.map(|v| v.0), v.name.clone()) == *id)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let task_buckets = previous
+            .task_buckets
+            .iter()
+            .filter(|bucket| {
+                !self
+                    .loaded_scripts
+                    .values()
+                    .any(|meta| meta.task_buckets.contains(bucket))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.interval_timers_man.remove_timers(&timers);
+        self.scheduled_tasks_man.remove_task_buckets(&task_buckets);
+    }
+
     async fn update_db_contribs(
         &mut self,
         evt: &ScriptMeta,
@@ -742,7 +1131,7 @@ impl VmSession {
             error!(%err, script_id = evt.script_id.0, "failed updating db contribs",);
         }
 
-        if let Err(err) = self
+        match self
             .stores
             .update_script(
                 self.guild_id,
@@ -755,15 +1144,73 @@ impl VmSession {
                     plugin_version_number: None,
                     settings_definitions: Some(evt.settings.clone()),
                     settings_values: None,
+                    is_library: None,
+                    library_modules: None,
                 },
+                None,
             )
             .await
         {
-            error!(%err, script_id = evt.script_id.0, "failed updating db contribs (settings)");
+            Ok(script) => self.report_settings_problems(&evt.settings, script).await,
+            Err(err) => {
+                error!(%err, script_id = evt.script_id.0, "failed updating db contribs (settings)");
+            }
+        }
+    }
+
+    /// Checks the settings values against the options the script registered, the script falls
+    /// back to the defaults for values that don't fit so the guild admins are told about them
+    /// instead
+    ///
+    /// Problems stick around until the admins save the settings again, so only new ones are logged
+    async fn report_settings_problems(
+        &self,
+        definitions: &[SettingsOptionDefinition],
+        mut script: Script,
+    ) {
+        let new_problems =
+            validation::web::check_settings_values(definitions, &script.settings_values)
+                .into_iter()
+                .filter(|v| !script.settings_problems.contains(v))
+                .collect::<Vec<_>>();
+
+        if new_problems.is_empty() {
+            return;
+        }
+
+        for problem in &new_problems {
+            self.logger.log(CreateLogEntry::script_warning(
+                format!(
+                    "the value of the setting {} is not valid, the default is used instead: {}",
+                    problem.option, problem.message
+                ),
+                format!("{}.ts", script.name),
+                None,
+            ));
+        }
+
+        script.settings_problems.extend(new_problems);
+        if let Err(err) = self
+            .stores
+            .set_script_settings_problems(self.guild_id, script.id, &script.settings_problems)
+            .await
+        {
+            error!(%err, script_id = script.id, "failed storing settings problems");
         }
     }
 }
 
+pub type EvalOutputSender = mpsc::UnboundedSender<EvalOutput>;
+
+/// Receives the body the scripts responded with, or why the request couldn't be handled
+pub type WebhookResponseSender = oneshot::Sender<Result<Option<serde_json::Value>, String>>;
+
+/// Notified once the new version of a script is running, or the vm has been restarted with it
+///
+/// Dropped without being notified if the reload was given up on, the script is then picked up
+/// the next time the vm starts
+pub type ScriptReloadedSender = oneshot::Sender<()>;
+
 pub enum NextAction {
     WorkerMessage(Option<WorkerMessage>),
     CheckScheduledTasks,
@@ -785,13 +1232,20 @@ pub enum PendingAckType {
     ScheduledTask(u64),
     IntervalTimer(TimerId),
     Restart,
+    ReloadScript(ScriptReloadedSender),
 }
 
+// how long to wait before trying to claim a worker again for a broker event after being shed
+const QUEUE_FULL_MIN_BACKOFF: Duration = Duration::from_millis(250);
+const QUEUE_FULL_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
 enum ClaimWorkerResult {
     // A worker was already claimed or we managed to reuse the worker from the pool we had last time
     Reused,
     // We had to reload our VM
     Reloaded,
+    // The pool is overloaded and our request was shed
+    QueueFull,
 }
 
 pub struct VmSessionStatus {
@@ -801,3 +1255,21 @@ pub struct VmSessionStatus {
     pub returned_worker_at: Instant,
     pub num_pending_acks: usize,
 }
+
+/// Only changes to the source of a script can be applied in place, anything else changes what
+/// the vm is created with
+fn can_reload_in_place(previous: &Script, updated: &Script) -> bool {
+    let same_settings = previous.settings_values.len() == updated.settings_values.len()
+        && previous
+            .settings_values
+            .iter()
+            .zip(&updated.settings_values)
+            .all(|(a, b)| a.name == b.name && a.value == b.value);
+
+    !previous.is_library
+        && !updated.is_library
+        && previous.name == updated.name
+        && previous.plugin_id == updated.plugin_id
+        && previous.library_modules == updated.library_modules
+        && same_settings
+}
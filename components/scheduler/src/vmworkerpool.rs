@@ -1,23 +1,33 @@
 use std::{
     collections::{HashMap, VecDeque},
     process::Stdio,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 use metrics::counter;
-use simpleproto::{message_reader, message_writer};
+use scheduler_worker_rpc::{
+    remote_auth::{self, FrameMac},
+    RemoteWorkerHello, SchedulerMessage,
+};
+use simpleproto::{message_reader, message_writer, Codec};
 use stores::config::PremiumSlotTier;
 use tokio::{
+    net::TcpStream,
     process::{Child, Command},
     sync::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
 };
-use tracing::{error, info, instrument};
+use tracing::{error, info, instrument, warn};
 use twilight_model::id::{marker::GuildMarker, Id};
 
+use crate::fair_queue::FairQueue;
+
 pub enum WorkerRetrieved {
     SameGuild,
     OtherGuild,
@@ -30,7 +40,10 @@ struct PoolInner {
     claimed_workers: [VecDeque<ClaimedWorker>; MAX_PREMIUM_SLOT_TIER + 1],
 
     pools: [Vec<WorkerHandle>; MAX_PREMIUM_SLOT_TIER + 1],
-    req_queues: [VecDeque<QueuedWorkerRequest>; MAX_PREMIUM_SLOT_TIER + 1],
+    // requests of all tiers share a queue, weighted by their tier
+    req_queue: FairQueue<WorkerRequest>,
+
+    guild_waits: HashMap<Id<GuildMarker>, GuildWaitStats>,
 }
 
 impl PoolInner {
@@ -50,28 +63,84 @@ impl PoolInner {
         }
     }
 
+    fn num_queued_requests(&self, priority_index: usize) -> usize {
+        self.req_queue
+            .iter()
+            .filter(|v| v.item.priority_index == priority_index)
+            .count()
+    }
+
+    fn update_queued_requests_gauge(&self, priority_index: usize) {
+        metrics::gauge!("bl.scheduler.workerpool_queued_requests", "priority_index" => priority_index.to_string()).set(self.num_queued_requests(priority_index) as f64);
+    }
+
     fn track_claimed_worker(&mut self, claim: ClaimedWorker) {
         self.claimed_workers[claim.priority_index].push_back(claim);
     }
+
+    fn record_wait(&mut self, guild_id: Id<GuildMarker>, priority_index: usize, waited: Duration) {
+        metrics::histogram!("bl.scheduler.workerpool_queue_wait_seconds", "priority_index" => priority_index.to_string()).record(waited.as_secs_f64());
+
+        if waited.is_zero() {
+            return;
+        }
+
+        self.prune_guild_waits();
+        let stats = self
+            .guild_waits
+            .entry(guild_id)
+            .or_insert_with(GuildWaitStats::new);
+        stats.last_wait = waited;
+        stats.max_wait = stats.max_wait.max(waited);
+        stats.total_wait += waited;
+        stats.num_waits += 1;
+        stats.last_event_at = Instant::now();
+    }
+
+    fn record_shed(&mut self, guild_id: Id<GuildMarker>, priority_index: usize) {
+        metrics::counter!("bl.scheduler.workerpool_shed_requests_total", "priority_index" => priority_index.to_string()).increment(1);
+
+        self.prune_guild_waits();
+        let stats = self
+            .guild_waits
+            .entry(guild_id)
+            .or_insert_with(GuildWaitStats::new);
+        stats.num_shed += 1;
+        stats.last_event_at = Instant::now();
+    }
+
+    fn prune_guild_waits(&mut self) {
+        if self.guild_waits.len() >= MAX_TRACKED_GUILD_WAITS {
+            self.guild_waits
+                .retain(|_, v| v.last_event_at.elapsed() < GUILD_WAIT_STATS_RETENTION);
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct VmWorkerPool {
     inner: Arc<Mutex<PoolInner>>,
     launch_config: WorkerLaunchConfig,
+    max_queued_requests: usize,
 }
 
+/// Returned when the request was shed because too many requests were queued for a worker
+#[derive(Debug)]
+pub struct WorkerQueueFull;
+
 impl VmWorkerPool {
-    pub fn new(launch_config: WorkerLaunchConfig) -> VmWorkerPool {
+    pub fn new(launch_config: WorkerLaunchConfig, max_queued_requests: usize) -> VmWorkerPool {
         Self {
             inner: Arc::new(Mutex::new(PoolInner {
                 pools: Default::default(),
                 claimed_workers: Default::default(),
-                req_queues: Default::default(),
+                req_queue: Default::default(),
                 worker_id_gen: 1,
                 pending_starts: HashMap::new(),
+                guild_waits: HashMap::new(),
             })),
             launch_config,
+            max_queued_requests,
         }
     }
 
@@ -80,28 +149,27 @@ impl VmWorkerPool {
         &self,
         guild_id: Id<GuildMarker>,
         premium_tier: Option<PremiumSlotTier>,
-    ) -> (WorkerHandle, WorkerRetrieved) {
-        let mut worker = self.inner_get_worker(guild_id, premium_tier).await;
+    ) -> Result<(WorkerHandle, WorkerRetrieved), WorkerQueueFull> {
+        let priority_index = premium_tier_index(premium_tier);
+        let mut worker = self.inner_get_worker(guild_id, priority_index).await?;
         let wr = if matches!(worker.last_active_guild, Some(g) if g == guild_id) {
             WorkerRetrieved::SameGuild
         } else {
             WorkerRetrieved::OtherGuild
         };
 
-        worker.claim(guild_id);
-        (worker, wr)
+        worker.claim(guild_id, priority_index);
+        Ok((worker, wr))
     }
 
     async fn inner_get_worker(
         &self,
         guild_id: Id<GuildMarker>,
-        premium_tier: Option<PremiumSlotTier>,
-    ) -> WorkerHandle {
+        priority_index: usize,
+    ) -> Result<WorkerHandle, WorkerQueueFull> {
         let rx = {
             let mut w = self.inner.lock().unwrap();
 
-            let priority_index = premium_tier_index(premium_tier);
-
             // try to find one with identical guild id, avoids us having to reload all scripts
             let mut i = priority_index;
             loop {
@@ -116,8 +184,9 @@ impl VmWorkerPool {
                     metrics::gauge!("bl.scheduler.workerpool_available_workers", "priority_index" => i.to_string()).decrement(1.0);
                     let worker = pool.remove(pref_worker);
                     w.track_claimed_worker(ClaimedWorker::new_claim(guild_id, &worker));
+                    w.record_wait(guild_id, priority_index, Duration::ZERO);
                     info!("found worker in preferred search");
-                    return worker;
+                    return Ok(worker);
                 }
 
                 if i == 0 {
@@ -146,8 +215,9 @@ impl VmWorkerPool {
                     metrics::gauge!("bl.scheduler.workerpool_available_workers", "priority_index" => i.to_string()).decrement(1.0);
                     let worker = pool.remove(can);
                     w.track_claimed_worker(ClaimedWorker::new_claim(guild_id, &worker));
+                    w.record_wait(guild_id, priority_index, Duration::ZERO);
                     info!("found worker in least recently used search");
-                    return worker;
+                    return Ok(worker);
                 }
 
                 if i == 0 {
@@ -161,13 +231,30 @@ impl VmWorkerPool {
 
             // no available workers, queue the request
             let (tx, rx) = oneshot::channel();
-            let queue = &mut w.req_queues[priority_index];
-            queue.push_back(QueuedWorkerRequest { guild_id, tx });
+            w.req_queue.push(
+                guild_id,
+                priority_weight(priority_index),
+                WorkerRequest { priority_index, tx },
+            );
+
+            if w.num_queued_requests(priority_index) > self.max_queued_requests {
+                // shed the request of the tier furthest back in line,
+                // which could be the one we just queued
+                if let Some(shed) = w
+                    .req_queue
+                    .pop_last_where(|v| v.priority_index == priority_index)
+                {
+                    warn!(guild_id = %shed.guild_id, "worker request queue full, shedding request");
+                    w.record_shed(shed.guild_id, priority_index);
+                }
+            }
+
+            w.update_queued_requests_gauge(priority_index);
             rx
         };
 
-        // the tx end should never be dropped
-        rx.await.unwrap()
+        // the tx end is only dropped if the request was shed
+        rx.await.map_err(|_| WorkerQueueFull)
     }
 
     pub fn spawn_workers(&self, tier: Option<PremiumSlotTier>, n: usize) {
@@ -187,6 +274,11 @@ impl VmWorkerPool {
         {
             let mut w = self.inner.lock().unwrap();
             w.tracking_remove_claimed_worker(worker.priority_index, worker.worker_id);
+
+            if let Some(guild_id) = worker.last_active_guild {
+                let weight = priority_weight(worker.requested_priority_index);
+                w.req_queue.charge(guild_id, weight, elapsed);
+            }
         }
 
         if broken {
@@ -195,41 +287,54 @@ impl VmWorkerPool {
                 worker.last_active_guild
             );
             metrics::counter!("bl.scheduler.broken_workers_total").increment(1);
-            self.spawn_worker(worker.priority_index);
+
+            if let Some(remote) = &worker.remote {
+                // we can't start remote workers back up, they reconnect on their own
+                warn!(name = %remote.name, "dropping broken remote worker");
+                metrics::gauge!("bl.scheduler.remote_workers", "priority_index" => worker.priority_index.to_string()).decrement(1.0);
+            } else {
+                self.spawn_worker(worker.priority_index);
+            }
         } else {
             info!(
                 tier = worker.priority_index,
                 dur = elapsed.as_secs_f64(),
                 "returned worker to the pool"
             );
+
+            if let Some(remote) = &worker.remote {
+                // claimed workers aren't pinged, so don't count the time it was claimed against it
+                remote.health.mark_seen();
+            }
             self.add_worker_to_pool(worker);
         }
     }
 
-    fn add_worker_to_pool(&self, worker: WorkerHandle) {
+    fn add_worker_to_pool(&self, mut worker: WorkerHandle) {
         let mut w = self.inner.lock().unwrap();
 
-        // potentially hand over to next queued request
-        let mut i = MAX_PREMIUM_SLOT_TIER;
-        loop {
-            let queue = &mut w.req_queues[i];
-            if let Some(req) = queue.pop_front() {
-                let claim = ClaimedWorker::new_claim(req.guild_id, &worker);
-
-                if req.tx.send(worker).is_err() {
-                    panic!("worker request dropped")
+        // potentially hand over to next queued request the worker can serve
+        let worker_priority_index = worker.priority_index;
+        while let Some(req) = w
+            .req_queue
+            .pop_where(|v| v.priority_index >= worker_priority_index)
+        {
+            let priority_index = req.item.priority_index;
+            w.update_queued_requests_gauge(priority_index);
+
+            let claim = ClaimedWorker::new_claim(req.guild_id, &worker);
+            match req.item.tx.send(worker) {
+                Ok(()) => {
+                    w.track_claimed_worker(claim);
+                    w.record_wait(req.guild_id, priority_index, req.queued_at.elapsed());
+                    return;
+                }
+                Err(returned) => {
+                    // the guild stopped waiting for it, e.g. because its handler shut down
+                    warn!(guild_id = %req.guild_id, "worker request dropped, trying the next one");
+                    worker = returned;
                 }
-
-                w.track_claimed_worker(claim);
-
-                return;
-            }
-
-            if i == worker.priority_index {
-                break;
             }
-
-            i -= 1;
         }
 
         // no pending worker requests
@@ -277,20 +382,120 @@ impl VmWorkerPool {
         #[cfg(target_family = "windows")] stream: tokio::net::TcpStream,
         #[cfg(target_family = "unix")] stream: tokio::net::UnixStream,
         id: u64,
+        codec: Codec,
     ) {
         let mut w = self.inner.lock().unwrap();
         if let Some(pending) = w.pending_starts.remove(&id) {
             drop(w);
 
-            let full = init_worker_handles(pending, stream);
+            let full = init_worker_handles(pending, stream, codec);
             info!(tier = full.priority_index, "worker connected");
             self.add_worker_to_pool(full);
         }
     }
 
-    pub fn worker_statuses(&self) -> Vec<WorkerStatus> {
+    pub fn remote_worker_connected(
+        &self,
+        stream: TcpStream,
+        hello: RemoteWorkerHello,
+        codec: Codec,
+        session: (FrameMac, FrameMac),
+    ) {
+        let worker_id = self.gen_id();
+        let priority_index = premium_tier_index(hello.premium_tier);
+
+        let full = init_remote_worker_handle(
+            stream,
+            hello.name,
+            worker_id,
+            priority_index,
+            codec,
+            session,
+        );
+        info!(
+            tier = priority_index,
+            worker_id,
+            name = full.remote.as_ref().map(|v| v.name.as_str()),
+            "remote worker connected"
+        );
+        metrics::gauge!("bl.scheduler.remote_workers", "priority_index" => priority_index.to_string()).increment(1.0);
+        self.add_worker_to_pool(full);
+    }
+
+    /// Periodically pings the idle remote workers and evicts the ones that disconnected
+    /// or haven't responded in a while
+    ///
+    /// Claimed workers are not checked, the vm session returns them as broken if they disconnect.
+    pub async fn run_remote_worker_health_checks(self) {
+        let mut interval = tokio::time::interval(REMOTE_WORKER_PING_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check_remote_workers();
+        }
+    }
+
+    fn check_remote_workers(&self) {
+        let mut w = self.inner.lock().unwrap();
+
+        for (priority_index, pool) in w.pools.iter_mut().enumerate() {
+            pool.retain(|worker| {
+                let Some(remote) = &worker.remote else {
+                    return true;
+                };
+
+                let healthy = remote.health.is_healthy()
+                    && worker.tx.send(SchedulerMessage::Ping).is_ok();
+                if !healthy {
+                    warn!(
+                        name = %remote.name,
+                        worker_id = worker.worker_id,
+                        "evicting unhealthy remote worker"
+                    );
+                    metrics::counter!("bl.scheduler.remote_workers_evicted_total").increment(1);
+                    metrics::gauge!("bl.scheduler.remote_workers", "priority_index" => priority_index.to_string()).decrement(1.0);
+                    metrics::gauge!("bl.scheduler.workerpool_available_workers", "priority_index" => priority_index.to_string()).decrement(1.0);
+                }
+
+                healthy
+            });
+        }
+    }
+
+    pub fn status(&self) -> PoolStatus {
         let w = self.inner.lock().unwrap();
 
+        let queued_requests = w
+            .req_queue
+            .iter()
+            .map(|req| QueuedRequestStatus {
+                guild_id: req.guild_id,
+                priority_index: req.item.priority_index,
+                queued_at: req.queued_at,
+            })
+            .collect();
+
+        let guild_waits = w
+            .guild_waits
+            .iter()
+            .filter(|(_, v)| v.last_event_at.elapsed() < GUILD_WAIT_STATS_RETENTION)
+            .map(|(guild_id, v)| GuildWaitStatus {
+                guild_id: *guild_id,
+                last_wait: v.last_wait,
+                max_wait: v.max_wait,
+                total_wait: v.total_wait,
+                num_waits: v.num_waits,
+                num_shed: v.num_shed,
+            })
+            .collect();
+
+        PoolStatus {
+            workers: Self::worker_statuses(&w),
+            queued_requests,
+            guild_waits,
+        }
+    }
+
+    fn worker_statuses(w: &PoolInner) -> Vec<WorkerStatus> {
         let mut result = Vec::with_capacity(20);
         for pool in w.pools.iter() {
             for worker in pool.iter() {
@@ -301,6 +506,7 @@ impl VmWorkerPool {
                     priority_index: worker.priority_index,
                     claimed_last_by: worker.last_active_guild,
                     currently_claimed_by: None,
+                    remote_name: worker.remote.as_ref().map(|v| v.name.clone()),
                 });
             }
         }
@@ -314,6 +520,7 @@ impl VmWorkerPool {
                     priority_index: claim.priority_index,
                     claimed_last_by: None,
                     currently_claimed_by: Some(claim.guild_id),
+                    remote_name: claim.remote_name.clone(),
                 })
             }
         }
@@ -324,10 +531,14 @@ impl VmWorkerPool {
 
 #[derive(Debug)]
 pub struct WorkerHandle {
-    pub child: Child,
+    // none for remote workers
+    pub child: Option<Child>,
+    pub remote: Option<RemoteWorker>,
     pub tx: UnboundedSender<scheduler_worker_rpc::SchedulerMessage>,
     pub rx: UnboundedReceiver<scheduler_worker_rpc::WorkerMessage>,
     last_active_guild: Option<Id<GuildMarker>>,
+    // the tier of the guild that last claimed it, can be higher than the worker's own tier
+    requested_priority_index: usize,
     pub returned_at: Instant,
     pub claimed_at: Instant,
     pub worker_id: u64,
@@ -335,18 +546,43 @@ pub struct WorkerHandle {
 }
 
 impl WorkerHandle {
-    fn claim(&mut self, guild_id: Id<GuildMarker>) {
+    fn claim(&mut self, guild_id: Id<GuildMarker>, requested_priority_index: usize) {
         self.last_active_guild = Some(guild_id);
+        self.requested_priority_index = requested_priority_index;
         self.claimed_at = Instant::now();
     }
 }
 
+#[derive(Debug)]
+pub struct RemoteWorker {
+    pub name: String,
+    health: Arc<RemoteWorkerHealth>,
+}
+
+#[derive(Debug)]
+struct RemoteWorkerHealth {
+    last_seen: Mutex<Instant>,
+    disconnected: AtomicBool,
+}
+
+impl RemoteWorkerHealth {
+    fn mark_seen(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    fn is_healthy(&self) -> bool {
+        !self.disconnected.load(Ordering::SeqCst)
+            && self.last_seen.lock().unwrap().elapsed() < REMOTE_WORKER_TIMEOUT
+    }
+}
+
 pub struct ClaimedWorker {
     pub worker_id: u64,
     pub guild_id: Id<GuildMarker>,
     pub claimed_at: Instant,
     pub returned_last: Instant,
     pub priority_index: usize,
+    pub remote_name: Option<String>,
 }
 
 impl ClaimedWorker {
@@ -357,10 +593,16 @@ impl ClaimedWorker {
             priority_index: worker.priority_index,
             returned_last: worker.returned_at,
             worker_id: worker.worker_id,
+            remote_name: worker.remote.as_ref().map(|v| v.name.clone()),
         }
     }
 }
 
+struct WorkerRequest {
+    priority_index: usize,
+    tx: oneshot::Sender<WorkerHandle>,
+}
+
 struct PendingWorkerHandle {
     child: Child,
     worker_id: u64,
@@ -371,22 +613,25 @@ fn init_worker_handles(
     pending: PendingWorkerHandle,
     #[cfg(target_family = "windows")] stream: tokio::net::TcpStream,
     #[cfg(target_family = "unix")] stream: tokio::net::UnixStream,
+    codec: Codec,
 ) -> WorkerHandle {
     let (scheduler_msg_tx, scheduler_msg_rx) = mpsc::unbounded_channel();
     let (worker_msg_tx, worker_msg_rx) = mpsc::unbounded_channel();
 
     let (mut reader, mut writer) = stream.into_split();
 
-    tokio::spawn(async move { message_reader(&mut reader, worker_msg_tx).await });
+    tokio::spawn(async move { message_reader(&mut reader, worker_msg_tx, codec).await });
 
-    tokio::spawn(async move { message_writer(&mut writer, scheduler_msg_rx).await });
+    tokio::spawn(async move { message_writer(&mut writer, scheduler_msg_rx, codec).await });
 
     WorkerHandle {
-        child: pending.child,
+        child: Some(pending.child),
+        remote: None,
         tx: scheduler_msg_tx,
         rx: worker_msg_rx,
 
         last_active_guild: None,
+        requested_priority_index: 0,
         returned_at: Instant::now(),
         claimed_at: Instant::now(),
         worker_id: pending.worker_id,
@@ -394,6 +639,68 @@ fn init_worker_handles(
     }
 }
 
+fn init_remote_worker_handle(
+    stream: TcpStream,
+    name: String,
+    worker_id: u64,
+    priority_index: usize,
+    codec: Codec,
+    (send_mac, mut recv_mac): (FrameMac, FrameMac),
+) -> WorkerHandle {
+    let (scheduler_msg_tx, scheduler_msg_rx) = mpsc::unbounded_channel();
+    let (worker_msg_tx, worker_msg_rx) = mpsc::unbounded_channel();
+
+    let health = Arc::new(RemoteWorkerHealth {
+        last_seen: Mutex::new(Instant::now()),
+        disconnected: AtomicBool::new(false),
+    });
+
+    let (mut reader, mut writer) = stream.into_split();
+
+    let reader_health = health.clone();
+    tokio::spawn(async move {
+        loop {
+            match remote_auth::read_message(&mut reader, codec, &mut recv_mac).await {
+                Ok(msg) => {
+                    reader_health.mark_seen();
+                    if worker_msg_tx.send(msg).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    info!(%err, "remote worker connection closed");
+                    break;
+                }
+            }
+        }
+        reader_health.disconnected.store(true, Ordering::SeqCst);
+    });
+
+    let writer_health = health.clone();
+    tokio::spawn(async move {
+        if let Err(err) =
+            remote_auth::message_writer(&mut writer, scheduler_msg_rx, codec, send_mac).await
+        {
+            info!(%err, "failed writing to remote worker");
+        }
+        writer_health.disconnected.store(true, Ordering::SeqCst);
+    });
+
+    WorkerHandle {
+        child: None,
+        remote: Some(RemoteWorker { name, health }),
+        tx: scheduler_msg_tx,
+        rx: worker_msg_rx,
+
+        last_active_guild: None,
+        requested_priority_index: 0,
+        returned_at: Instant::now(),
+        claimed_at: Instant::now(),
+        worker_id,
+        priority_index,
+    }
+}
+
 #[derive(Clone)]
 pub struct WorkerLaunchConfig {
     pub cmd: String,
@@ -402,6 +709,11 @@ pub struct WorkerLaunchConfig {
 
 const MAX_PREMIUM_SLOT_TIER: usize = 2;
 
+const REMOTE_WORKER_PING_INTERVAL: Duration = Duration::from_secs(10);
+
+// remote workers we haven't heard from in this long are evicted from the pool
+const REMOTE_WORKER_TIMEOUT: Duration = Duration::from_secs(35);
+
 fn premium_tier_index(tier: Option<PremiumSlotTier>) -> usize {
     match tier {
         None => 0,
@@ -410,9 +722,57 @@ fn premium_tier_index(tier: Option<PremiumSlotTier>) -> usize {
     }
 }
 
-struct QueuedWorkerRequest {
-    guild_id: Id<GuildMarker>,
-    tx: oneshot::Sender<WorkerHandle>,
+/// The share of the workers guilds of a tier get compared to guilds without premium,
+/// when they're waiting for the same workers
+fn priority_weight(priority_index: usize) -> u64 {
+    1 << priority_index
+}
+
+const MAX_TRACKED_GUILD_WAITS: usize = 1000;
+const GUILD_WAIT_STATS_RETENTION: Duration = Duration::from_secs(60 * 10);
+
+struct GuildWaitStats {
+    last_wait: Duration,
+    max_wait: Duration,
+    total_wait: Duration,
+    num_waits: u32,
+    num_shed: u32,
+    last_event_at: Instant,
+}
+
+impl GuildWaitStats {
+    fn new() -> Self {
+        Self {
+            last_wait: Duration::ZERO,
+            max_wait: Duration::ZERO,
+            total_wait: Duration::ZERO,
+            num_waits: 0,
+            num_shed: 0,
+            last_event_at: Instant::now(),
+        }
+    }
+}
+
+pub struct PoolStatus {
+    pub workers: Vec<WorkerStatus>,
+    pub queued_requests: Vec<QueuedRequestStatus>,
+    pub guild_waits: Vec<GuildWaitStatus>,
+}
+
+pub struct QueuedRequestStatus {
+    pub guild_id: Id<GuildMarker>,
+    pub priority_index: usize,
+    pub queued_at: Instant,
+}
+
+/// Stats of the guild's waits for a worker over the last 10 minutes
+pub struct GuildWaitStatus {
+    pub guild_id: Id<GuildMarker>,
+    pub last_wait: Duration,
+    pub max_wait: Duration,
+    pub total_wait: Duration,
+    pub num_waits: u32,
+    pub num_shed: u32,
 }
 
 pub struct WorkerStatus {
@@ -422,4 +782,5 @@ pub struct WorkerStatus {
     pub claimed_last_by: Option<Id<GuildMarker>>,
     pub claimed_last: Instant,
     pub returned_last: Instant,
+    pub remote_name: Option<String>,
 }
@@ -1,9 +1,18 @@
-use scheduler_worker_rpc::WorkerMessage;
-use tracing::error;
+use std::{sync::Arc, time::Duration};
+
+use scheduler_worker_rpc::{
+    remote_auth::{self, FrameMac, Handshake, Side},
+    RemoteWorkerHello, SchedulerMessage, WorkerMessage, PROTOCOL_VERSION,
+};
+use simpleproto::Codec;
+use tracing::{error, info, warn};
 
 use crate::vmworkerpool::VmWorkerPool;
 
-pub async fn listen_for_workers(path_or_addr: &str, worker_pool: VmWorkerPool) {
+/// Listens for the workers we spawned ourselves
+///
+/// `codec` is the codec we prefer, the workers might not support it in which case we fall back to json.
+pub async fn listen_for_workers(path_or_addr: &str, codec: Codec, worker_pool: VmWorkerPool) {
     #[cfg(target_family = "unix")]
     let listener = {
         let _ = std::fs::remove_file(path_or_addr);
@@ -20,8 +29,24 @@ pub async fn listen_for_workers(path_or_addr: &str, worker_pool: VmWorkerPool) {
 
             tokio::spawn(async move {
                 match simpleproto::read_message(&mut stream).await {
-                    Ok(WorkerMessage::Hello(id)) => {
-                        cloned_pool.worker_connected(stream, id);
+                    Ok(WorkerMessage::Hello(hello)) => {
+                        let Some(codec) =
+                            negotiate_codec(codec, hello.protocol_version, &hello.codecs)
+                        else {
+                            return;
+                        };
+
+                        if let Err(err) = simpleproto::write_message(
+                            &SchedulerMessage::Welcome(codec),
+                            &mut stream,
+                        )
+                        .await
+                        {
+                            error!(%err, "failed writing worker welcome");
+                            return;
+                        }
+
+                        cloned_pool.worker_connected(stream, hello.worker_id, codec);
                     }
                     Ok(_) => {
                         error!("first worker mesasge not hello");
@@ -34,3 +59,110 @@ pub async fn listen_for_workers(path_or_addr: &str, worker_pool: VmWorkerPool) {
         }
     });
 }
+
+/// Listens for vmworkers connecting over tcp, they have to prove they know the shared secret
+/// before being added to the pool, and every frame after that is signed, see [`remote_auth`]
+pub async fn listen_for_remote_workers(
+    addr: &str,
+    secret: String,
+    codec: Codec,
+    worker_pool: VmWorkerPool,
+) {
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    info!(addr, "listening for remote workers");
+
+    let secret = Arc::new(secret);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, peer_addr) = match listener.accept().await {
+                Ok(v) => v,
+                Err(err) => {
+                    error!(%err, "failed accepting remote worker connection");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            let cloned_pool = worker_pool.clone();
+            let secret = secret.clone();
+
+            tokio::spawn(async move {
+                let _ = stream.set_nodelay(true);
+
+                match tokio::time::timeout(
+                    REMOTE_HANDSHAKE_TIMEOUT,
+                    remote_worker_handshake(&mut stream, &secret, codec),
+                )
+                .await
+                {
+                    Ok(Ok((hello, codec, session))) => {
+                        cloned_pool.remote_worker_connected(stream, hello, codec, session)
+                    }
+                    Ok(Err(err)) => {
+                        warn!(%err, %peer_addr, "remote worker handshake failed");
+                    }
+                    Err(_) => {
+                        warn!(%peer_addr, "remote worker handshake timed out");
+                    }
+                }
+            });
+        }
+    });
+}
+
+const REMOTE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn remote_worker_handshake(
+    stream: &mut tokio::net::TcpStream,
+    secret: &str,
+    preferred_codec: Codec,
+) -> anyhow::Result<(RemoteWorkerHello, Codec, (FrameMac, FrameMac))> {
+    let challenge = remote_auth::new_challenge();
+    simpleproto::write_message(&SchedulerMessage::AuthChallenge(challenge.clone()), stream).await?;
+
+    match simpleproto::read_message(stream).await? {
+        WorkerMessage::RemoteHello(hello) => {
+            let handshake = Handshake {
+                secret,
+                scheduler_challenge: &challenge,
+                worker_challenge: &hello.challenge,
+            };
+            if !handshake.verify(Side::Worker, &hello.signature) {
+                return Err(anyhow::anyhow!("invalid signature from {}", hello.name));
+            }
+
+            let Some(codec) =
+                negotiate_codec(preferred_codec, hello.protocol_version, &hello.codecs)
+            else {
+                return Err(anyhow::anyhow!("incompatible worker {}", hello.name));
+            };
+
+            let welcome = SchedulerMessage::RemoteWelcome(codec, handshake.sign(Side::Scheduler));
+            simpleproto::write_message(&welcome, stream).await?;
+
+            let session = handshake.session(Side::Scheduler);
+            Ok((hello, codec, session))
+        }
+        other => Err(anyhow::anyhow!(
+            "first remote worker message not hello: {}",
+            other.name()
+        )),
+    }
+}
+
+fn negotiate_codec(preferred: Codec, protocol_version: u32, offered: &[Codec]) -> Option<Codec> {
+    if protocol_version != PROTOCOL_VERSION {
+        error!(
+            protocol_version,
+            expected = PROTOCOL_VERSION,
+            "worker is running a different protocol version"
+        );
+        return None;
+    }
+
+    let codec = Codec::negotiate(preferred, offered);
+    if codec.is_none() {
+        error!(?offered, "worker does not support any of our codecs");
+    }
+    codec
+}
@@ -1,11 +1,74 @@
-use serde::{de::DeserializeOwned, Serialize};
+use std::str::FromStr;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     sync::mpsc,
 };
 
+/// The format message payloads are encoded with
+///
+/// Connections start out with json and switch to the codec negotiated during their handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Codec {
+    /// Slower, but human readable which is useful when debugging
+    Json,
+    MessagePack,
+}
+
+impl Codec {
+    /// All the supported codecs, in order of preference
+    pub const ALL: [Codec; 2] = [Codec::MessagePack, Codec::Json];
+
+    /// Picks the preferred codec if the other side supports it, falling back to json
+    pub fn negotiate(preferred: Codec, supported_by_peer: &[Codec]) -> Option<Codec> {
+        if supported_by_peer.contains(&preferred) {
+            Some(preferred)
+        } else if supported_by_peer.contains(&Codec::Json) {
+            Some(Codec::Json)
+        } else {
+            None
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, msg: &T) -> std::io::Result<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(msg)?),
+            Codec::MessagePack => rmp_serde::to_vec_named(msg)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> std::io::Result<T> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(payload)?),
+            Codec::MessagePack => rmp_serde::from_slice(payload)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Codec::Json),
+            "msgpack" => Ok(Codec::MessagePack),
+            other => Err(format!("unknown codec: {other}, expected json or msgpack")),
+        }
+    }
+}
+
 pub async fn read_message<T: DeserializeOwned>(
     src: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<T> {
+    read_message_with_codec(src, Codec::Json).await
+}
+
+pub async fn read_message_with_codec<T: DeserializeOwned>(
+    src: &mut (impl AsyncRead + Unpin),
+    codec: Codec,
 ) -> std::io::Result<T> {
     let len = src.read_u32().await?;
 
@@ -16,19 +79,26 @@ pub async fn read_message<T: DeserializeOwned>(
     // let s = String::from_utf8_lossy(&payload_buf);
     // dbg!(s);
 
-    let decoded = serde_json::from_slice(&payload_buf)?;
-    Ok(decoded)
+    codec.decode(&payload_buf)
 }
 
 pub async fn write_message<T: Serialize>(
     msg: &T,
     dst: &mut (impl AsyncWrite + Unpin),
 ) -> std::io::Result<()> {
-    let encoded = serde_json::to_string(msg)?;
+    write_message_with_codec(msg, dst, Codec::Json).await
+}
+
+pub async fn write_message_with_codec<T: Serialize>(
+    msg: &T,
+    dst: &mut (impl AsyncWrite + Unpin),
+    codec: Codec,
+) -> std::io::Result<()> {
+    let encoded = codec.encode(msg)?;
     assert!(encoded.len() < 0xffffffff);
 
     dst.write_u32(encoded.len() as u32).await?;
-    dst.write_all(encoded.as_bytes()).await?;
+    dst.write_all(&encoded).await?;
 
     Ok(())
 }
@@ -36,9 +106,10 @@ pub async fn write_message<T: Serialize>(
 pub async fn message_writer<T: Serialize>(
     dst: &mut (impl AsyncWrite + Unpin),
     mut rx: mpsc::UnboundedReceiver<T>,
+    codec: Codec,
 ) -> std::io::Result<()> {
     while let Some(next) = rx.recv().await {
-        write_message(&next, dst).await?
+        write_message_with_codec(&next, dst, codec).await?
     }
 
     Ok(())
@@ -47,9 +118,10 @@ pub async fn message_writer<T: Serialize>(
 pub async fn message_reader<T: DeserializeOwned>(
     src: &mut (impl AsyncRead + Unpin),
     tx: mpsc::UnboundedSender<T>,
+    codec: Codec,
 ) -> std::io::Result<()> {
     loop {
-        match read_message(src).await {
+        match read_message_with_codec(src, codec).await {
             Ok(msg) => {
                 if tx.send(msg).is_err() {
                     return Ok(());
@@ -59,3 +131,56 @@ pub async fn message_reader<T: DeserializeOwned>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Codec;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    enum TestMessage {
+        Unit,
+        Value(serde_json::Value),
+        Struct { id: u64, name: Option<String> },
+    }
+
+    #[tokio::test]
+    async fn framed_round_trip() {
+        let messages = [
+            TestMessage::Unit,
+            TestMessage::Value(serde_json::json!({"a": [1, -2, 3.5, "b", null, true]})),
+            TestMessage::Struct {
+                id: u64::MAX,
+                name: None,
+            },
+        ];
+
+        for codec in Codec::ALL {
+            let (mut a, mut b) = tokio::io::duplex(1024);
+            for msg in &messages {
+                super::write_message_with_codec(msg, &mut a, codec)
+                    .await
+                    .unwrap();
+                let decoded: TestMessage =
+                    super::read_message_with_codec(&mut b, codec).await.unwrap();
+                assert_eq!(&decoded, msg);
+            }
+        }
+    }
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            Codec::negotiate(Codec::MessagePack, &Codec::ALL),
+            Some(Codec::MessagePack)
+        );
+        assert_eq!(
+            Codec::negotiate(Codec::MessagePack, &[Codec::Json]),
+            Some(Codec::Json)
+        );
+        assert_eq!(
+            Codec::negotiate(Codec::Json, &Codec::ALL),
+            Some(Codec::Json)
+        );
+        assert_eq!(Codec::negotiate(Codec::MessagePack, &[]), None);
+    }
+}
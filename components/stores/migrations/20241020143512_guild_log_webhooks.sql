@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE guild_meta_configs
+    ADD COLUMN log_webhook_url TEXT;
+
+ALTER TABLE guild_meta_configs
+    ADD COLUMN log_webhook_secret TEXT;
@@ -0,0 +1,13 @@
+-- library scripts are importable by other scripts but never run as an entrypoint
+ALTER TABLE guild_scripts
+    ADD COLUMN is_library BOOLEAN NOT NULL DEFAULT false;
+
+-- library modules shipped with the plugin version this script was installed from
+ALTER TABLE guild_scripts
+    ADD COLUMN library_modules JSONB NOT NULL DEFAULT '[]';
+
+ALTER TABLE plugins
+    ADD COLUMN script_published_library_modules JSONB NOT NULL DEFAULT '[]';
+
+ALTER TABLE plugins
+    ADD COLUMN script_dev_library_modules JSONB NOT NULL DEFAULT '[]';
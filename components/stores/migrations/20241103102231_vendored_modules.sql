@@ -0,0 +1,20 @@
+-- pinned third party es module bundles scripts can import using "vendor:<name>"
+CREATE TABLE IF NOT EXISTS vendored_modules (
+    id bigserial NOT NULL PRIMARY KEY,
+    guild_id bigint,
+    plugin_id bigint REFERENCES plugins (id) ON DELETE CASCADE,
+    name text NOT NULL,
+    source text NOT NULL,
+    content_hash text NOT NULL,
+    size_bytes integer NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+
+    -- owned by either a guild or a plugin
+    CHECK ((guild_id IS NULL) <> (plugin_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS vendored_modules_guild_id_name_idx ON vendored_modules (guild_id, name)
+WHERE (guild_id IS NOT NULL);
+
+CREATE UNIQUE INDEX IF NOT EXISTS vendored_modules_plugin_id_name_idx ON vendored_modules (plugin_id, name)
+WHERE (plugin_id IS NOT NULL);
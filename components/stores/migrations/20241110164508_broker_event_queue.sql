@@ -0,0 +1,10 @@
+-- events the broker could not deliver to the scheduler, replayed in order once it reconnects
+CREATE TABLE IF NOT EXISTS broker_event_queue (
+    id bigserial NOT NULL PRIMARY KEY,
+    queue_name text NOT NULL,
+    guild_id bigint NOT NULL,
+    event jsonb NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS broker_event_queue_queue_name_id_idx ON broker_event_queue (queue_name, id);
@@ -0,0 +1,15 @@
+-- guilds whose scripts are not run, either because they misbehaved or because an admin suspended them
+CREATE TABLE IF NOT EXISTS guild_suspensions (
+    id bigserial NOT NULL PRIMARY KEY,
+    guild_id bigint NOT NULL,
+    reason text NOT NULL,
+    message text,
+    -- how many times the guild was automatically suspended recently before this one
+    offense_level integer NOT NULL DEFAULT 0,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    -- null means it has to be lifted manually
+    expires_at timestamp with time zone,
+    lifted_at timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS guild_suspensions_guild_id_created_at_idx ON guild_suspensions (guild_id, created_at);
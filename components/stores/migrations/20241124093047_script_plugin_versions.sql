@@ -0,0 +1,18 @@
+-- every published version of a script plugin, guilds can roll back or pin to any of these
+CREATE TABLE IF NOT EXISTS script_plugin_versions (
+    plugin_id BIGINT NOT NULL REFERENCES plugins(id) ON DELETE CASCADE,
+    version_number INT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    changelog TEXT NOT NULL DEFAULT '',
+    source TEXT NOT NULL,
+    library_modules JSONB NOT NULL DEFAULT '[]',
+
+    PRIMARY KEY (plugin_id, version_number)
+);
+
+-- earlier versions are gone, but we can keep the current one around
+INSERT INTO script_plugin_versions (plugin_id, version_number, created_at, source, library_modules)
+SELECT id, current_version_number, COALESCE(script_published_version_updated_at, created_at),
+    script_published_source, script_published_library_modules
+FROM plugins
+WHERE script_published_source IS NOT NULL AND current_version_number > 0;
@@ -0,0 +1,24 @@
+-- guilds can opt into the beta versions of the plugins they have installed
+ALTER TABLE guild_scripts
+    ADD COLUMN plugin_channel TEXT NOT NULL DEFAULT 'stable';
+
+ALTER TABLE script_plugin_versions
+    ADD COLUMN channel TEXT NOT NULL DEFAULT 'stable',
+    -- percentage of the auto updating guilds that have been updated to this version
+    ADD COLUMN rollout_percentage SMALLINT NOT NULL DEFAULT 100,
+    ADD COLUMN rollout_updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    ADD COLUMN rollout_paused_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN rollout_pause_reason TEXT;
+
+-- guilds that logged errors from a plugin version while it was being rolled out
+CREATE TABLE IF NOT EXISTS script_plugin_version_errors (
+    plugin_id BIGINT NOT NULL,
+    version_number INT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    error_count INT NOT NULL DEFAULT 1,
+    last_error_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (plugin_id, version_number, guild_id),
+    FOREIGN KEY (plugin_id, version_number)
+        REFERENCES script_plugin_versions (plugin_id, version_number) ON DELETE CASCADE
+);
@@ -0,0 +1,21 @@
+-- snapshot of a guild script after every change to its source or settings
+CREATE TABLE IF NOT EXISTS guild_script_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    script_id BIGINT NOT NULL REFERENCES guild_scripts(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    -- null for changes made by botloader itself, e.g. plugin auto updates
+    edited_by BIGINT,
+    source TEXT NOT NULL,
+    library_modules JSONB NOT NULL DEFAULT '[]',
+    settings_values JSONB NOT NULL DEFAULT '[]',
+    plugin_version_number INT
+);
+
+CREATE INDEX IF NOT EXISTS guild_script_revisions_script_id_idx ON guild_script_revisions (script_id, id);
+
+-- start the history off with the current state of every script
+INSERT INTO guild_script_revisions (guild_id, script_id, source, library_modules, settings_values, plugin_version_number)
+SELECT guild_id, id, original_source, library_modules, COALESCE(settings_values, '[]'),
+    plugin_version_number
+FROM guild_scripts;
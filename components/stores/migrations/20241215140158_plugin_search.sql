@@ -0,0 +1,14 @@
+ALTER TABLE plugins
+    ADD COLUMN category TEXT,
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    -- kept up to date by the plugin stats job
+    ADD COLUMN rating_average REAL NOT NULL DEFAULT 0,
+    ADD COLUMN rating_count INT NOT NULL DEFAULT 0,
+    ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', name), 'A') ||
+        setweight(to_tsvector('english', short_description), 'B') ||
+        setweight(to_tsvector('english', long_description), 'C')
+    ) STORED;
+
+CREATE INDEX IF NOT EXISTS plugins_search_vector_idx ON plugins USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS plugins_tags_idx ON plugins USING GIN (tags);
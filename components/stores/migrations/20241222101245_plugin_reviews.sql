@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS plugin_reviews (
+    plugin_id BIGINT NOT NULL REFERENCES plugins(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL,
+    -- the guild the user had the plugin installed in when posting the review
+    guild_id BIGINT NOT NULL,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    body TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+
+    author_reply TEXT,
+    author_replied_at TIMESTAMP WITH TIME ZONE,
+
+    -- hidden reviews are left out of the listings and the plugin's rating
+    hidden_at TIMESTAMP WITH TIME ZONE,
+    hidden_reason TEXT,
+
+    PRIMARY KEY (plugin_id, user_id)
+);
@@ -0,0 +1,19 @@
+-- what each plugin version needs access to, versions published before manifests existed
+-- could do anything so they keep doing so
+ALTER TABLE script_plugin_versions
+    ADD COLUMN capabilities JSONB NOT NULL
+        DEFAULT '{"http_hosts": ["*"], "moderation": true, "storage": true, "direct_messages": true}';
+
+ALTER TABLE script_plugin_versions
+    ALTER COLUMN capabilities SET DEFAULT '{}';
+
+-- the capabilities the guild admins consented to when installing the plugin, or when updating to a
+-- version that asked for more
+ALTER TABLE guild_scripts
+    ADD COLUMN plugin_capabilities JSONB,
+    ADD COLUMN plugin_capabilities_granted_by BIGINT,
+    ADD COLUMN plugin_capabilities_granted_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE guild_scripts
+SET plugin_capabilities = '{"http_hosts": ["*"], "moderation": true, "storage": true, "direct_messages": true}'
+WHERE plugin_id IS NOT NULL;
@@ -0,0 +1,3 @@
+-- the plugins a version needs installed on the guild, along with the versions it works with
+ALTER TABLE script_plugin_versions
+    ADD COLUMN dependencies JSONB NOT NULL DEFAULT '[]';
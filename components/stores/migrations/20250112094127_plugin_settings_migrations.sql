@@ -0,0 +1,8 @@
+-- changes to the settings of a plugin applied to the guild installs updated to the version
+ALTER TABLE script_plugin_versions
+    ADD COLUMN settings_migrations JSONB NOT NULL DEFAULT '[]';
+
+-- settings values that didn't survive an update, shown to the guild admins until they save the
+-- settings again
+ALTER TABLE guild_scripts
+    ADD COLUMN settings_problems JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,12 @@
+-- endpoints external services can call to trigger events in the guild's scripts,
+-- requests are signed with the secret
+CREATE TABLE IF NOT EXISTS guild_inbound_webhooks (
+    id bigserial NOT NULL PRIMARY KEY,
+    guild_id bigint NOT NULL,
+    name text NOT NULL,
+    secret text NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    last_triggered_at timestamp with time zone,
+
+    UNIQUE (guild_id, name)
+);
@@ -0,0 +1,8 @@
+-- api tokens can be named, expire and be restricted to specific guilds and operations,
+-- a null scope column means the token is not restricted on it
+ALTER TABLE web_sessions
+    ADD COLUMN name text,
+    ADD COLUMN expires_at timestamp with time zone,
+    ADD COLUMN last_used_at timestamp with time zone,
+    ADD COLUMN scope_guild_ids bigint[],
+    ADD COLUMN scope_operations smallint[];
@@ -0,0 +1,7 @@
+-- the scheduler currently running a guild, a scheduler taking over a guild waits for the previous
+-- one to release it or for the lease to expire before starting it
+CREATE TABLE IF NOT EXISTS guild_scheduler_leases (
+    guild_id bigint NOT NULL PRIMARY KEY,
+    scheduler_id text NOT NULL,
+    expires_at timestamp with time zone NOT NULL
+);
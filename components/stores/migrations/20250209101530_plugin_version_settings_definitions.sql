@@ -0,0 +1,11 @@
+-- the settings options a plugin version registered, recorded the first time a guild loads it so
+-- rolling a guild back to the version restores them
+ALTER TABLE script_plugin_versions
+    ADD COLUMN settings_definitions JSONB;
+
+UPDATE script_plugin_versions v SET settings_definitions = (
+    SELECT s.settings_definitions FROM guild_scripts s
+    WHERE s.plugin_id = v.plugin_id AND s.plugin_version_number = v.version_number
+    AND s.settings_definitions IS NOT NULL
+    LIMIT 1
+);
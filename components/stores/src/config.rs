@@ -1,15 +1,20 @@
 use std::num::NonZeroU64;
 
 use super::Db;
+use crate::settings_migrations::migrate_install_settings;
 use chrono::{DateTime, Utc};
 use common::{
     plugin::{
-        Image, Plugin, PluginData, PluginImage, PluginImageKind, PluginType, ScriptPluginData,
+        Image, InstalledPluginVersion, LibraryModule, Plugin, PluginCapabilities, PluginCategory,
+        PluginData, PluginDependency, PluginImage, PluginImageKind, PluginType, PluginVersion,
+        PluginVersionMeta, PluginVersionRollout, ReleaseChannel, ScriptPluginData,
+        SettingsMigration,
     },
     user::UserMeta,
 };
 use runtime_models::internal::script::{SettingsOptionDefinition, SettingsOptionValue};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::{postgres::types::PgInterval, PgConnection};
 use thiserror::Error;
 use twilight_model::id::{
@@ -19,6 +24,7 @@ use twilight_model::id::{
 use uuid::Uuid;
 
 const GUILD_SCRIPT_COUNT_LIMIT: i64 = 100;
+const VENDORED_MODULE_COUNT_LIMIT: i64 = 20;
 
 impl Db {
     async fn get_db_script_by_name(
@@ -30,7 +36,11 @@ impl Db {
             DbScript,
             "SELECT id, guild_id, original_source, name, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values FROM guild_scripts WHERE guild_id = $1 AND \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\" FROM guild_scripts WHERE guild_id = $1 AND \
              name = $2 AND plugin_id IS NULL;",
             guild_id.get() as i64,
             script_name
@@ -53,7 +63,11 @@ impl Db {
             DbScript,
             "SELECT id, guild_id, name, original_source, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values FROM guild_scripts WHERE guild_id = $1 AND id \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\" FROM guild_scripts WHERE guild_id = $1 AND id \
              = $2;",
             guild_id.get() as i64,
             id
@@ -84,7 +98,11 @@ impl Db {
             DbScript,
             "SELECT id, guild_id, original_source, name, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values FROM guild_scripts WHERE guild_id = $1 ORDER \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\" FROM guild_scripts WHERE guild_id = $1 ORDER \
              BY id ASC",
             guild_id.get() as i64,
         )
@@ -98,6 +116,7 @@ impl Db {
         conn: &mut PgConnection,
         guild_id: Id<GuildMarker>,
         script: CreateScript,
+        created_by: Option<Id<UserMarker>>,
     ) -> ConfigStoreResult<Script> {
         let count = Self::get_guild_script_count(conn, guild_id).await?;
         if count > GUILD_SCRIPT_COUNT_LIMIT {
@@ -110,11 +129,15 @@ impl Db {
         let res = sqlx::query_as!(
             DbScript,
             "INSERT INTO guild_scripts (guild_id, name, original_source, enabled, plugin_id, \
-             plugin_auto_update, plugin_version_number) 
-VALUES ($1, $2, $3, $4, $5, $6, $7)
+             plugin_auto_update, plugin_version_number, is_library, library_modules) 
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
 RETURNING id, guild_id, name, original_source, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values;",
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";",
             guild_id.get() as i64,
             script.name,
             script.original_source,
@@ -122,10 +145,14 @@ RETURNING id, guild_id, name, original_source, enabled, contributes_commands, \
             script.plugin_id.map(|v| v as i64),
             script.plugin_auto_update,
             script.plugin_version_number.map(|v| v as i32),
+            script.is_library,
+            serde_json::to_value(script.library_modules).unwrap(),
         )
-        .fetch_one(conn)
+        .fetch_one(&mut *conn)
         .await?;
 
+        Self::record_script_revisions(conn, &[res.id], created_by).await?;
+
         Ok(res.into())
     }
 
@@ -170,11 +197,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
 FROM plugins WHERE id = $1"#,
             plugin_id as i64,
         )
@@ -275,15 +308,29 @@ impl Db {
         &self,
         guild_id: Id<GuildMarker>,
         script: CreateScript,
+        created_by: Option<Id<UserMarker>>,
     ) -> ConfigStoreResult<Script> {
-        Self::inner_create_script(&mut *self.pool.acquire().await?, guild_id, script).await
+        let mut tx = self.pool.begin().await?;
+        let script = Self::inner_create_script(&mut tx, guild_id, script, created_by).await?;
+        tx.commit().await?;
+
+        Ok(script)
     }
 
+    /// Updates the script, recording a new revision of it if the source or settings changed
+    ///
+    /// Fails with [`ConfigStoreError::PluginRequiredByDependent`] if the script is a plugin being
+    /// disabled while another enabled plugin on the guild depends on it
     pub async fn update_script(
         &self,
         guild_id: Id<GuildMarker>,
         script: UpdateScript,
+        edited_by: Option<Id<UserMarker>>,
     ) -> ConfigStoreResult<Script> {
+        let creates_revision = script.original_source.is_some()
+            || script.settings_values.is_some()
+            || script.library_modules.is_some();
+
         let commands_enc = script.contributes.map(|v| serde_json::to_value(v).unwrap());
         let settings_definitions = script
             .settings_definitions
@@ -291,6 +338,27 @@ impl Db {
         let settings_values = script
             .settings_values
             .map(|v| serde_json::to_value(v).unwrap());
+        let library_modules = script
+            .library_modules
+            .map(|v| serde_json::to_value(v).unwrap());
+
+        let mut tx = self.pool.begin().await?;
+
+        if script.enabled == Some(false) {
+            let current = sqlx::query!(
+                "SELECT plugin_id FROM guild_scripts WHERE guild_id = $1 AND id = $2 FOR UPDATE;",
+                guild_id.get() as i64,
+                script.id as i64,
+            )
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+            if let Some(plugin_id) = current.plugin_id {
+                Self::check_plugin_removal_dependencies(&mut tx, guild_id, plugin_id as u64, true)
+                    .await?;
+            }
+        }
 
         let res = sqlx::query_as!(
             DbScript,
@@ -301,11 +369,19 @@ impl Db {
                     contributes_commands = COALESCE($5, guild_scripts.contributes_commands),
                     plugin_version_number = COALESCE($6, guild_scripts.plugin_version_number),
                     settings_definitions = COALESCE($7, guild_scripts.settings_definitions),
-                    settings_values = COALESCE($8, guild_scripts.settings_values)
+                    settings_values = COALESCE($8, guild_scripts.settings_values),
+                    settings_problems = CASE WHEN $8::JSONB IS NULL
+                    THEN guild_scripts.settings_problems ELSE '[]' END,
+                    is_library = COALESCE($9, guild_scripts.is_library),
+                    library_modules = COALESCE($10, guild_scripts.library_modules)
                     WHERE guild_id = $1 AND id=$2
                     RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values;
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
                 ",
             guild_id.get() as i64,
             script.id as i64,
@@ -315,10 +391,35 @@ impl Db {
             script.plugin_version_number.map(|v| v as i32),
             settings_definitions,
             settings_values,
+            script.is_library,
+            library_modules,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        if let (Some(definitions), Some(plugin_id), Some(version_number)) = (
+            &settings_definitions,
+            res.plugin_id,
+            res.plugin_version_number,
+        ) {
+            // remembered so guilds rolling back to this version get its settings options back
+            sqlx::query!(
+                "UPDATE script_plugin_versions SET settings_definitions = $3 WHERE plugin_id = $1 \
+                 AND version_number = $2 AND settings_definitions IS DISTINCT FROM $3",
+                plugin_id,
+                version_number,
+                definitions,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        if creates_revision {
+            Self::record_script_revisions(&mut tx, &[res.id], edited_by).await?;
+        }
+
+        tx.commit().await?;
+
         Ok(res.into())
     }
 
@@ -340,7 +441,11 @@ impl Db {
                     WHERE guild_id = $1 AND id=$2
                     RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
              contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
-             settings_definitions, settings_values;
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
                 ",
             guild_id.get() as i64,
             script_id as i64,
@@ -353,24 +458,40 @@ impl Db {
         Ok(res.into())
     }
 
+    /// Fails with [`ConfigStoreError::PluginRequiredByDependent`] if the script is a plugin another
+    /// plugin on the guild depends on
     pub async fn del_script(
         &self,
         guild_id: Id<GuildMarker>,
         script_name: String,
     ) -> ConfigStoreResult<()> {
-        let res = sqlx::query!(
+        let mut tx = self.pool.begin().await?;
+
+        let script = sqlx::query!(
+            "SELECT plugin_id FROM guild_scripts WHERE guild_id = $1 AND name = $2 FOR UPDATE;",
+            guild_id.get() as i64,
+            script_name
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+        if let Some(plugin_id) = script.plugin_id {
+            Self::check_plugin_removal_dependencies(&mut tx, guild_id, plugin_id as u64, false)
+                .await?;
+        }
+
+        sqlx::query!(
             "DELETE FROM guild_scripts WHERE guild_id = $1 AND name = $2;",
             guild_id.get() as i64,
             script_name
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
-        if res.rows_affected() > 0 {
-            Ok(())
-        } else {
-            Err(ConfigStoreError::ScriptNotFound)
-        }
+        tx.commit().await?;
+
+        Ok(())
     }
 
     pub async fn list_scripts(&self, guild_id: Id<GuildMarker>) -> ConfigStoreResult<Vec<Script>> {
@@ -383,8 +504,8 @@ impl Db {
     ) -> ConfigStoreResult<Option<GuildMetaConfig>> {
         match sqlx::query_as!(
             DbGuildMetaConfig,
-            "SELECT guild_id, error_channel_id FROM guild_meta_configs
-        WHERE guild_id = $1;",
+            "SELECT guild_id, error_channel_id, log_webhook_url, log_webhook_secret FROM \
+             guild_meta_configs WHERE guild_id = $1;",
             guild_id.get() as i64,
         )
         .fetch_one(&self.pool)
@@ -407,6 +528,34 @@ impl Db {
         }
     }
 
+    pub async fn update_guild_log_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook: Option<GuildLogWebhook>,
+    ) -> ConfigStoreResult<GuildMetaConfig> {
+        let (url, secret) = match webhook {
+            Some(v) => (Some(v.url), Some(v.secret)),
+            None => (None, None),
+        };
+
+        let db_conf = sqlx::query_as!(
+            DbGuildMetaConfig,
+            "INSERT INTO guild_meta_configs (guild_id, error_channel_id, log_webhook_url, \
+             log_webhook_secret) VALUES ($1, 0, $2, $3)
+            ON CONFLICT (guild_id) DO UPDATE SET
+            log_webhook_url = $2,
+            log_webhook_secret = $3
+            RETURNING guild_id, error_channel_id, log_webhook_url, log_webhook_secret;",
+            guild_id.get() as i64,
+            url,
+            secret,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(db_conf.into())
+    }
+
     // async fn update_guild_meta_config(
     //     &self,
     //     conf: &GuildMetaConfig,
@@ -682,11 +831,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at"#,
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count"#,
             create_plugin.name,
             create_plugin.short_description,
             create_plugin.long_description,
@@ -716,7 +871,9 @@ is_official = COALESCE($5, plugins.is_official),
 author_id = COALESCE($6, plugins.author_id),
 is_public = COALESCE($7, plugins.is_public),
 is_published = COALESCE($8, plugins.is_published),
-discord_thread_id = COALESCE($9, plugins.discord_thread_id)
+discord_thread_id = COALESCE($9, plugins.discord_thread_id),
+category = COALESCE($10, plugins.category),
+tags = COALESCE($11, plugins.tags)
 WHERE id = $1
 RETURNING id,
 created_at,
@@ -731,11 +888,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at"#,
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count"#,
             plugin_id as i64,
             update_plugin.name,
             update_plugin.short_description,
@@ -745,6 +908,8 @@ installed_guilds_updated_at"#,
             update_plugin.is_public,
             update_plugin.is_published,
             update_plugin.discord_thread_id.map(|v| v as i64),
+            update_plugin.category.map(|v| v.as_str()),
+            update_plugin.tags.as_deref(),
         )
         .fetch_one(&self.pool)
         .await?;
@@ -754,9 +919,25 @@ installed_guilds_updated_at"#,
         Ok(PluginAndImages(res, images).into())
     }
 
+    /// Updates the data used to rank plugins in the marketplace
     pub async fn batch_update_plugins_stats(&self) -> ConfigStoreResult<()> {
         sqlx::query!(
-            r#"UPDATE plugins SET installed_guilds = (SELECT COUNT(*) FROM guild_scripts WHERE plugin_id = plugins.id);"#,
+            r#"UPDATE plugins SET installed_guilds = (SELECT COUNT(*) FROM guild_scripts WHERE plugin_id = plugins.id), installed_guilds_updated_at = now();"#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // also kept up to date as reviews change, this catches anything that slipped through
+        sqlx::query!(
+            "UPDATE plugins SET
+            rating_average = COALESCE(r.rating_average, 0),
+            rating_count = COALESCE(r.rating_count, 0)
+            FROM plugins p
+            LEFT JOIN (
+                SELECT plugin_id, avg(rating)::REAL AS rating_average, count(*)::INT AS rating_count
+                FROM plugin_reviews WHERE hidden_at IS NULL GROUP BY plugin_id
+            ) r ON r.plugin_id = p.id
+            WHERE plugins.id = p.id;",
         )
         .execute(&self.pool)
         .await?;
@@ -822,12 +1003,14 @@ installed_guilds_updated_at"#,
         &self,
         plugin_id: u64,
         new_source: String,
+        library_modules: Vec<LibraryModule>,
     ) -> ConfigStoreResult<Plugin> {
         let res = sqlx::query_as!(
             DbPlugin,
             r#"UPDATE plugins SET
 script_dev_source = $2, 
-script_dev_version_updated_at = now()
+script_dev_version_updated_at = now(),
+script_dev_library_modules = $3
 WHERE id = $1
 RETURNING id,
 created_at,
@@ -842,14 +1025,21 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
 "#,
             plugin_id as i64,
             new_source,
+            serde_json::to_value(library_modules).unwrap(),
         )
         .fetch_one(&self.pool)
         .await?;
@@ -859,62 +1049,400 @@ installed_guilds_updated_at
         Ok(PluginAndImages(res, images).into())
     }
 
+    /// Publishes a new version of the plugin to the provided channel, returning the guilds that were
+    /// updated to it
+    ///
+    /// Only the auto updating guilds that fall within `rollout_percentage` are updated right away,
+    /// the rest are updated as the rollout advances
     pub async fn publish_script_plugin_version(
         &self,
         plugin_id: u64,
-        new_source: String,
+        version: PublishScriptPluginVersion,
     ) -> ConfigStoreResult<Vec<Id<GuildMarker>>> {
-        let plugin = sqlx::query_as!(
-            DbPlugin,
-            r#"UPDATE plugins SET
-script_published_source = $2, 
-script_published_version_updated_at = now(),
-current_version_number = current_version_number +1
-WHERE id = $1
-RETURNING id,
-created_at,
-name,
-short_description,
-long_description,
-is_published,
-is_official,
-plugin_kind,
-current_version_number,
-script_published_source,
-script_published_version_updated_at,
-script_dev_source,
-script_dev_version_updated_at,
-author_id,
-is_public,
-discord_thread_id,
-installed_guilds,
-installed_guilds_updated_at
-"#,
+        let PublishScriptPluginVersion {
+            source: new_source,
+            library_modules,
+            changelog,
+            channel,
+            rollout_percentage,
+            capabilities,
+            dependencies,
+            settings_migrations,
+        } = version;
+
+        let library_modules = serde_json::to_value(library_modules).unwrap();
+        let capabilities = serde_json::to_value(capabilities).unwrap();
+
+        let mut tx = self.pool.begin().await?;
+
+        Self::check_plugin_dependencies_available(&mut tx, plugin_id, &dependencies).await?;
+
+        // beta versions share the version numbers with the stable ones, so we lock the plugin
+        // to avoid handing out the same number twice
+        let current = sqlx::query!(
+            "SELECT current_version_number, (SELECT max(version_number) FROM \
+             script_plugin_versions WHERE plugin_id = $1) AS latest_version_number FROM plugins \
+             WHERE id = $1 FOR UPDATE",
             plugin_id as i64,
-            new_source,
         )
-        .fetch_one(&self.pool)
-        .await?;
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::PluginNotFound(plugin_id))?;
 
-        struct Row {
-            guild_id: i64,
+        let version_number = current
+            .current_version_number
+            .max(current.latest_version_number.unwrap_or_default())
+            + 1;
+
+        // the published source is what new installs get, so it only tracks the stable channel
+        if channel == ReleaseChannel::Stable {
+            sqlx::query!(
+                "UPDATE plugins SET script_published_source = $2, \
+                 script_published_version_updated_at = now(), script_published_library_modules = \
+                 $3, current_version_number = $4 WHERE id = $1",
+                plugin_id as i64,
+                new_source,
+                library_modules,
+                version_number,
+            )
+            .execute(&mut *tx)
+            .await?;
         }
 
-        let updated_guilds = sqlx::query_as!(
-            Row,
-            "UPDATE guild_scripts SET original_source = $2, plugin_version_number = $3 WHERE \
-             plugin_id = $1 AND plugin_auto_update RETURNING guild_id",
+        // a rollout still in progress on the same channel is superseded by the new version
+        sqlx::query!(
+            "UPDATE script_plugin_versions SET rollout_paused_at = now(), rollout_pause_reason = \
+             $3 WHERE plugin_id = $1 AND channel = $2 AND rollout_percentage < 100 AND \
+             rollout_paused_at IS NULL",
+            plugin_id as i64,
+            channel.as_str(),
+            format!("superseded by version {version_number}"),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO script_plugin_versions (plugin_id, version_number, changelog, source, \
+             library_modules, channel, rollout_percentage, capabilities, dependencies, \
+             settings_migrations) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
             plugin_id as i64,
+            version_number,
+            changelog,
             new_source,
-            plugin.current_version_number,
+            library_modules,
+            channel.as_str(),
+            rollout_percentage as i16,
+            capabilities,
+            serde_json::to_value(dependencies).unwrap(),
+            serde_json::to_value(settings_migrations).unwrap(),
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let updated_guilds =
+            Self::roll_out_plugin_version(&mut tx, plugin_id, version_number as u32).await?;
+
+        tx.commit().await?;
+
+        Ok(updated_guilds)
+    }
+
+    /// Returns the published versions of a plugin, newest first
+    pub async fn get_plugin_versions(
+        &self,
+        plugin_id: u64,
+    ) -> ConfigStoreResult<Vec<PluginVersionMeta>> {
+        let res = sqlx::query_as!(
+            DbPluginVersionMeta,
+            "SELECT plugin_id, version_number, created_at, changelog, channel, \
+             rollout_percentage, rollout_updated_at, rollout_paused_at, rollout_pause_reason, \
+             capabilities, dependencies, settings_migrations FROM script_plugin_versions WHERE \
+             plugin_id = $1 ORDER BY version_number DESC",
+            plugin_id as i64,
         )
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(updated_guilds
-            .into_iter()
-            .map(|v| Id::new(v.guild_id as u64))
-            .collect())
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn get_plugin_version(
+        &self,
+        plugin_id: u64,
+        version_number: u32,
+    ) -> ConfigStoreResult<PluginVersion> {
+        let res = sqlx::query_as!(
+            DbPluginVersion,
+            "SELECT plugin_id, version_number, created_at, changelog, channel, \
+             rollout_percentage, rollout_updated_at, rollout_paused_at, rollout_pause_reason, \
+             capabilities, dependencies, settings_migrations, source, library_modules FROM \
+             script_plugin_versions WHERE plugin_id = $1 AND version_number = $2",
+            plugin_id as i64,
+            version_number as i32,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::PluginVersionNotFound(
+            plugin_id,
+            version_number,
+        ))?;
+
+        Ok(res.into())
+    }
+
+    /// Installs a specific version of the plugin on the guild script, used to roll back or pin it
+    ///
+    /// Publishing new versions only updates the script if `auto_update` is set
+    ///
+    /// If the version needs more capabilities than the script has been granted,
+    /// `accepted_capabilities` has to cover them and they're granted along with the update
+    ///
+    /// Fails with [`ConfigStoreError::PluginDependencyNotSatisfied`] if the version would break
+    /// the dependencies of the guild's plugins, or its own
+    ///
+    /// The settings migrations of the versions between the current one and the new one are applied
+    /// to the settings values
+    pub async fn set_guild_script_plugin_version(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        version: PluginVersion,
+        auto_update: bool,
+        edited_by: Option<Id<UserMarker>>,
+        accepted_capabilities: Option<&PluginCapabilities>,
+    ) -> ConfigStoreResult<Script> {
+        let library_modules = serde_json::to_value(version.library_modules).unwrap();
+
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query!(
+            "SELECT plugin_capabilities, plugin_version_number, plugin_channel, settings_values, \
+             settings_problems FROM guild_scripts WHERE guild_id = $1 AND id = $2 AND plugin_id = \
+             $3 FOR UPDATE",
+            guild_id.get() as i64,
+            script_id as i64,
+            version.meta.plugin_id as i64,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+        let granted = current
+            .plugin_capabilities
+            .map(|v| serde_json::from_value::<PluginCapabilities>(v).unwrap_or_default())
+            .unwrap_or_default();
+
+        let needs_consent = !granted.covers(&version.meta.capabilities);
+        if needs_consent
+            && !accepted_capabilities.is_some_and(|v| v.covers(&version.meta.capabilities))
+        {
+            return Err(ConfigStoreError::PluginCapabilitiesNotGranted(
+                version.meta.capabilities,
+            ));
+        }
+
+        Self::check_plugin_update_dependencies(
+            &mut tx,
+            guild_id,
+            &InstalledPluginVersion {
+                plugin_id: version.meta.plugin_id,
+                version_number: version.meta.version_number,
+                dependencies: version.meta.dependencies.clone(),
+            },
+        )
+        .await?;
+
+        // rolling back leaves the settings values as they are, the migrations can't be undone
+        let from_version = current.plugin_version_number.unwrap_or_default() as u32;
+        let migration_versions = Self::get_plugin_settings_migrations(
+            &mut tx,
+            version.meta.plugin_id,
+            from_version,
+            version.meta.version_number,
+        )
+        .await?;
+        let settings = migrate_install_settings(
+            &migration_versions,
+            from_version,
+            version.meta.version_number,
+            ReleaseChannel::parse(&current.plugin_channel).unwrap_or_default(),
+            current
+                .settings_values
+                .unwrap_or_else(|| serde_json::json!([])),
+            current.settings_problems,
+        );
+
+        let res = sqlx::query_as!(
+            DbScript,
+            "
+                    UPDATE guild_scripts SET
+                    original_source = $4,
+                    plugin_version_number = $5,
+                    library_modules = $6,
+                    plugin_auto_update = $7,
+                    settings_values = $8,
+                    settings_problems = $9,
+                    settings_definitions = COALESCE((SELECT v.settings_definitions FROM \
+             script_plugin_versions v WHERE v.plugin_id = $3 AND v.version_number = $5), \
+             guild_scripts.settings_definitions)
+                    WHERE guild_id = $1 AND id = $2 AND plugin_id = $3
+                    RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
+             contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
+                ",
+            guild_id.get() as i64,
+            script_id as i64,
+            version.meta.plugin_id as i64,
+            version.source,
+            version.meta.version_number as i32,
+            library_modules,
+            auto_update,
+            settings.values,
+            settings.problems,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+        Self::record_script_revisions(&mut tx, &[res.id], edited_by).await?;
+
+        let script = if needs_consent {
+            Self::grant_plugin_capabilities(
+                &mut tx,
+                guild_id,
+                script_id,
+                &version.meta.capabilities,
+                edited_by,
+            )
+            .await?
+        } else {
+            res.into()
+        };
+
+        tx.commit().await?;
+
+        Ok(script)
+    }
+
+    /// Restores the source and settings of the script to the revision, recording it as a new revision
+    pub async fn restore_script_revision(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        revision_id: u64,
+        edited_by: Option<Id<UserMarker>>,
+    ) -> ConfigStoreResult<Script> {
+        let mut tx = self.pool.begin().await?;
+
+        // restoring the revision of a plugin can move it to a different version
+        let restored_version = sqlx::query!(
+            "SELECT guild_scripts.plugin_id, r.plugin_version_number, v.dependencies AS \
+             \"dependencies?\" FROM guild_script_revisions r INNER JOIN guild_scripts ON \
+             guild_scripts.id = r.script_id LEFT JOIN script_plugin_versions v ON v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = r.plugin_version_number \
+             WHERE r.id = $3 AND r.script_id = $2 AND guild_scripts.guild_id = $1",
+            guild_id.get() as i64,
+            script_id as i64,
+            revision_id as i64,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::ScriptRevisionNotFound(revision_id))?;
+
+        if let (Some(plugin_id), Some(version_number)) = (
+            restored_version.plugin_id,
+            restored_version.plugin_version_number,
+        ) {
+            Self::check_plugin_update_dependencies(
+                &mut tx,
+                guild_id,
+                &InstalledPluginVersion {
+                    plugin_id: plugin_id as u64,
+                    version_number: version_number as u32,
+                    dependencies: restored_version
+                        .dependencies
+                        .and_then(|v| serde_json::from_value(v).ok())
+                        .unwrap_or_default(),
+                },
+            )
+            .await?;
+        }
+
+        let res = sqlx::query_as!(
+            DbScript,
+            "
+                    UPDATE guild_scripts SET
+                    original_source = r.source,
+                    library_modules = r.library_modules,
+                    settings_values = r.settings_values,
+                    plugin_version_number = r.plugin_version_number,
+                    settings_definitions = COALESCE((SELECT v.settings_definitions FROM \
+             script_plugin_versions v WHERE v.plugin_id = guild_scripts.plugin_id AND \
+             v.version_number = r.plugin_version_number), guild_scripts.settings_definitions)
+                    FROM guild_script_revisions r
+                    WHERE r.id = $3 AND r.script_id = $2
+                    AND guild_scripts.guild_id = $1 AND guild_scripts.id = $2
+                    RETURNING guild_scripts.id, guild_scripts.name, guild_scripts.original_source, \
+             guild_scripts.guild_id, guild_scripts.enabled, guild_scripts.contributes_commands, \
+             guild_scripts.contributes_interval_timers, guild_scripts.plugin_id, \
+             guild_scripts.plugin_auto_update, guild_scripts.plugin_version_number, \
+             guild_scripts.settings_definitions, guild_scripts.settings_values, \
+             guild_scripts.is_library, guild_scripts.library_modules, \
+             guild_scripts.plugin_channel, guild_scripts.plugin_capabilities, \
+             guild_scripts.settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
+                ",
+            guild_id.get() as i64,
+            script_id as i64,
+            revision_id as i64,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::ScriptRevisionNotFound(revision_id))?;
+
+        Self::record_script_revisions(&mut tx, &[res.id], edited_by).await?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    /// Sets the channel the guild script receives auto updates of its plugin from, the script is
+    /// updated to the new channel the next time a version is published or rolled out to it
+    pub async fn set_guild_script_plugin_channel(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        channel: ReleaseChannel,
+    ) -> ConfigStoreResult<Script> {
+        let res = sqlx::query_as!(
+            DbScript,
+            "
+                    UPDATE guild_scripts SET plugin_channel = $3
+                    WHERE guild_id = $1 AND id = $2 AND plugin_id IS NOT NULL
+                    RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
+             contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
+                ",
+            guild_id.get() as i64,
+            script_id as i64,
+            channel.as_str(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+        Ok(res.into())
     }
 
     pub async fn get_user_meta(&self, user_id: u64) -> ConfigStoreResult<Option<UserMeta>> {
@@ -950,11 +1478,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
 FROM plugins WHERE id = ANY($1)
 ORDER BY id ASC"#,
             &ids,
@@ -987,11 +1521,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
 FROM plugins WHERE author_id = $1
 ORDER BY id ASC"#,
             user_id as i64,
@@ -1024,11 +1564,17 @@ script_published_source,
 script_published_version_updated_at,
 script_dev_source,
 script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
 author_id,
 is_public,
 discord_thread_id,
 installed_guilds,
-installed_guilds_updated_at
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
 FROM plugins WHERE is_published = true AND is_public = true
 ORDER BY id ASC"#,
         )
@@ -1044,11 +1590,115 @@ ORDER BY id ASC"#,
         Ok(res)
     }
 
+    /// Searches the published public plugins, returning a page of them and the cursor to the next
+    /// page if there is one
+    pub async fn search_plugins(
+        &self,
+        query: &PluginSearchQuery,
+    ) -> ConfigStoreResult<PluginSearchPage> {
+        let rows = sqlx::query!(
+            "SELECT id, sort_key FROM (
+                SELECT id, CASE $1
+                    WHEN 'recent' THEN extract(epoch FROM \
+             COALESCE(script_published_version_updated_at, created_at))::FLOAT8
+                    WHEN 'rating' THEN rating_average::FLOAT8
+                    WHEN 'relevance' THEN ts_rank(search_vector, \
+             websearch_to_tsquery('english', COALESCE($2, '')))::FLOAT8
+                    ELSE COALESCE(installed_guilds, 0)::FLOAT8
+                END AS sort_key
+                FROM plugins
+                WHERE is_published AND is_public
+                    AND ($2::TEXT IS NULL OR search_vector @@ websearch_to_tsquery('english', $2))
+                    AND ($3::TEXT IS NULL OR category = $3)
+                    AND tags @> $4
+            ) p
+            WHERE $5::FLOAT8 IS NULL OR (p.sort_key, p.id) < ($5, $6)
+            ORDER BY p.sort_key DESC, p.id DESC
+            LIMIT $7;",
+            query.sort.as_str(),
+            query.text.as_deref(),
+            query.category.map(|v| v.as_str()),
+            &query.tags,
+            query.after.map(|v| v.sort_key),
+            query.after.map(|v| v.plugin_id as i64).unwrap_or_default(),
+            // fetch one extra to know if there's a next page
+            query.limit as i64 + 1,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let has_more = rows.len() > query.limit as usize;
+        let rows = &rows[..rows.len().min(query.limit as usize)];
+
+        let next_cursor = if has_more {
+            rows.last().map(|v| PluginSearchCursor {
+                sort_key: v.sort_key.unwrap_or_default(),
+                plugin_id: v.id as u64,
+            })
+        } else {
+            None
+        };
+
+        let ids = rows.iter().map(|v| v.id).collect::<Vec<_>>();
+        let mut raw_plugins = sqlx::query_as!(
+            DbPlugin,
+            r#"SELECT id,
+created_at,
+name,
+short_description,
+long_description,
+is_published,
+is_official,
+plugin_kind,
+current_version_number,
+script_published_source,
+script_published_version_updated_at,
+script_dev_source,
+script_dev_version_updated_at,
+script_published_library_modules,
+script_dev_library_modules,
+author_id,
+is_public,
+discord_thread_id,
+installed_guilds,
+installed_guilds_updated_at,
+category,
+tags,
+rating_average,
+rating_count
+FROM plugins WHERE id = ANY($1)"#,
+            &ids,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        raw_plugins.sort_by_key(|v| ids.iter().position(|id| *id == v.id));
+
+        let mut plugins: Vec<Plugin> = Vec::new();
+        for plugin in raw_plugins.into_iter() {
+            let images = self.get_plugin_images_with_pool(plugin.id as u64).await?;
+            plugins.push(PluginAndImages(plugin, images).into());
+        }
+
+        Ok(PluginSearchPage {
+            plugins,
+            next_cursor,
+        })
+    }
+
+    /// Installs the current version of the plugin on the guild, along with the plugins it depends
+    /// on that the guild doesn't have yet
+    ///
+    /// `accepted_capabilities` are the capabilities the admin consented to, the install fails with
+    /// [`ConfigStoreError::PluginCapabilitiesNotGranted`] if they don't cover what the version and
+    /// the dependencies installed with it need
     pub async fn try_guild_add_script_plugin(
         &self,
         guild_id: Id<GuildMarker>,
         plugin_id: u64,
         auto_update: bool,
+        added_by: Option<Id<UserMarker>>,
+        accepted_capabilities: &PluginCapabilities,
     ) -> ConfigStoreResult<Script> {
         let mut tx = self.pool.begin().await?;
 
@@ -1059,8 +1709,54 @@ ORDER BY id ASC"#,
 
         let plugin = Self::inner_get_plugin(&mut tx, plugin_id).await?;
 
-        let source = match plugin.data {
-            PluginData::ScriptPlugin(d) => d.published_version.unwrap_or_default(),
+        let capabilities =
+            Self::get_plugin_version_capabilities(&mut tx, plugin_id, plugin.current_version)
+                .await?;
+        let dependencies =
+            Self::resolve_plugin_dependencies(&mut tx, guild_id, plugin_id, plugin.current_version)
+                .await?;
+
+        let needed = dependencies.iter().fold(capabilities.clone(), |acc, v| {
+            acc.union(&v.version.meta.capabilities)
+        });
+        if !accepted_capabilities.covers(&needed) {
+            return Err(ConfigStoreError::PluginCapabilitiesNotGranted(needed));
+        }
+
+        for dependency in dependencies {
+            let version = dependency.version;
+            let created = Self::inner_create_script(
+                &mut tx,
+                guild_id,
+                CreateScript {
+                    name: dependency.name,
+                    original_source: version.source,
+                    enabled: true,
+                    plugin_auto_update: Some(auto_update),
+                    plugin_id: Some(version.meta.plugin_id),
+                    plugin_version_number: Some(version.meta.version_number),
+                    is_library: false,
+                    library_modules: version.library_modules,
+                },
+                added_by,
+            )
+            .await?;
+
+            Self::grant_plugin_capabilities(
+                &mut tx,
+                guild_id,
+                created.id,
+                &version.meta.capabilities,
+                added_by,
+            )
+            .await?;
+        }
+
+        let (source, library_modules) = match plugin.data {
+            PluginData::ScriptPlugin(d) => (
+                d.published_version.unwrap_or_default(),
+                d.published_library_modules,
+            ),
         };
 
         let created = Self::inner_create_script(
@@ -1073,15 +1769,77 @@ ORDER BY id ASC"#,
                 plugin_auto_update: Some(auto_update),
                 plugin_id: Some(plugin_id),
                 plugin_version_number: Some(plugin.current_version),
+                is_library: false,
+                library_modules,
             },
+            added_by,
         )
         .await?;
 
+        let created =
+            Self::grant_plugin_capabilities(&mut tx, guild_id, created.id, &capabilities, added_by)
+                .await?;
+
         tx.commit().await?;
 
         Ok(created)
     }
 
+    /// Plugins that have not published any versions don't need any capabilities
+    async fn get_plugin_version_capabilities(
+        conn: &mut PgConnection,
+        plugin_id: u64,
+        version_number: u32,
+    ) -> ConfigStoreResult<PluginCapabilities> {
+        let res = sqlx::query!(
+            "SELECT capabilities FROM script_plugin_versions WHERE plugin_id = $1 AND \
+             version_number = $2",
+            plugin_id as i64,
+            version_number as i32,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(res
+            .map(|v| serde_json::from_value(v.capabilities).unwrap_or_default())
+            .unwrap_or_default())
+    }
+
+    async fn grant_plugin_capabilities(
+        conn: &mut PgConnection,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        capabilities: &PluginCapabilities,
+        granted_by: Option<Id<UserMarker>>,
+    ) -> ConfigStoreResult<Script> {
+        let res = sqlx::query_as!(
+            DbScript,
+            "
+                    UPDATE guild_scripts SET
+                    plugin_capabilities = $3,
+                    plugin_capabilities_granted_by = $4,
+                    plugin_capabilities_granted_at = now()
+                    WHERE guild_id = $1 AND id = $2 AND plugin_id IS NOT NULL
+                    RETURNING id, name, original_source, guild_id, enabled, contributes_commands, \
+             contributes_interval_timers, plugin_id, plugin_auto_update, plugin_version_number, \
+             settings_definitions, settings_values, is_library, library_modules, plugin_channel, \
+             plugin_capabilities, settings_problems, \
+             (SELECT v.dependencies FROM script_plugin_versions v WHERE v.plugin_id = \
+             guild_scripts.plugin_id AND v.version_number = guild_scripts.plugin_version_number) \
+             AS \"plugin_dependencies?\";
+                ",
+            guild_id.get() as i64,
+            script_id as i64,
+            serde_json::to_value(capabilities).unwrap(),
+            granted_by.map(|v| v.get() as i64),
+        )
+        .fetch_optional(conn)
+        .await?
+        .ok_or(ConfigStoreError::ScriptNotFound)?;
+
+        Ok(res.into())
+    }
+
     pub async fn get_plugin_image(
         &self,
         plugin_id: u64,
@@ -1131,6 +1889,166 @@ ORDER BY id ASC"#,
     pub async fn soft_delete_image(&self, _id: Uuid) -> ConfigStoreResult<Uuid> {
         todo!()
     }
+
+    /// Uploads a new vendored module, replacing the previous one with the same name
+    pub async fn upsert_vendored_module(
+        &self,
+        owner: VendoredModuleOwner,
+        name: String,
+        source: String,
+    ) -> ConfigStoreResult<VendoredModule> {
+        let (guild_id, plugin_id) = owner.db_ids();
+        let content_hash = hex::encode(Sha256::digest(source.as_bytes()));
+        let size_bytes = source.len() as i32;
+
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query!(
+            "DELETE FROM vendored_modules WHERE guild_id IS NOT DISTINCT FROM $1 AND plugin_id IS \
+             NOT DISTINCT FROM $2 AND name = $3;",
+            guild_id,
+            plugin_id,
+            name,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if existing.rows_affected() == 0 {
+            let count = sqlx::query!(
+                "SELECT count(*) FROM vendored_modules WHERE guild_id IS NOT DISTINCT FROM $1 AND \
+                 plugin_id IS NOT DISTINCT FROM $2;",
+                guild_id,
+                plugin_id,
+            )
+            .fetch_one(&mut *tx)
+            .await?
+            .count
+            .unwrap_or_default();
+
+            if count >= VENDORED_MODULE_COUNT_LIMIT {
+                return Err(ConfigStoreError::VendoredModuleLimitReached(
+                    VENDORED_MODULE_COUNT_LIMIT as u64,
+                ));
+            }
+        }
+
+        let res = sqlx::query_as!(
+            DbVendoredModule,
+            "INSERT INTO vendored_modules (guild_id, plugin_id, name, source, content_hash, \
+             size_bytes, created_at)
+VALUES ($1, $2, $3, $4, $5, $6, now())
+RETURNING id, guild_id, plugin_id, name, source, content_hash, size_bytes, created_at;",
+            guild_id,
+            plugin_id,
+            name,
+            source,
+            content_hash,
+            size_bytes,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    pub async fn del_vendored_module(
+        &self,
+        owner: VendoredModuleOwner,
+        name: &str,
+    ) -> ConfigStoreResult<()> {
+        let (guild_id, plugin_id) = owner.db_ids();
+
+        let res = sqlx::query!(
+            "DELETE FROM vendored_modules WHERE guild_id IS NOT DISTINCT FROM $1 AND plugin_id IS \
+             NOT DISTINCT FROM $2 AND name = $3;",
+            guild_id,
+            plugin_id,
+            name,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() > 0 {
+            Ok(())
+        } else {
+            Err(ConfigStoreError::VendoredModuleNotFound)
+        }
+    }
+
+    pub async fn list_vendored_modules(
+        &self,
+        owner: VendoredModuleOwner,
+    ) -> ConfigStoreResult<Vec<VendoredModule>> {
+        let (guild_id, plugin_id) = owner.db_ids();
+
+        let res = sqlx::query_as!(
+            DbVendoredModule,
+            "SELECT id, guild_id, plugin_id, name, source, content_hash, size_bytes, created_at \
+             FROM vendored_modules WHERE guild_id IS NOT DISTINCT FROM $1 AND plugin_id IS NOT \
+             DISTINCT FROM $2 ORDER BY name ASC;",
+            guild_id,
+            plugin_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns all the vendored modules available to a guild's vm, that is the guild's own modules
+    /// and the modules of the provided plugins
+    pub async fn list_vm_vendored_modules(
+        &self,
+        guild_id: Id<GuildMarker>,
+        plugin_ids: &[u64],
+    ) -> ConfigStoreResult<Vec<VendoredModule>> {
+        let plugin_ids = plugin_ids.iter().map(|v| *v as i64).collect::<Vec<_>>();
+
+        let res = sqlx::query_as!(
+            DbVendoredModule,
+            "SELECT id, guild_id, plugin_id, name, source, content_hash, size_bytes, created_at \
+             FROM vendored_modules WHERE guild_id = $1 OR plugin_id = ANY($2::BIGINT[]);",
+            guild_id.get() as i64,
+            &plugin_ids,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+}
+
+struct DbVendoredModule {
+    id: i64,
+    guild_id: Option<i64>,
+    plugin_id: Option<i64>,
+    name: String,
+    source: String,
+    content_hash: String,
+    size_bytes: i32,
+    created_at: DateTime<Utc>,
+}
+
+impl From<DbVendoredModule> for VendoredModule {
+    fn from(value: DbVendoredModule) -> Self {
+        let owner = match (value.guild_id, value.plugin_id) {
+            (_, Some(plugin_id)) => VendoredModuleOwner::Plugin(plugin_id as u64),
+            (Some(guild_id), None) => VendoredModuleOwner::Guild(Id::new(guild_id as u64)),
+            (None, None) => panic!("vendored module {} has no owner", value.id),
+        };
+
+        Self {
+            id: value.id as u64,
+            owner,
+            name: value.name,
+            source: value.source,
+            content_hash: value.content_hash,
+            size_bytes: value.size_bytes as u32,
+            created_at: value.created_at,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -1147,6 +2065,12 @@ struct DbScript {
     plugin_version_number: Option<i32>,
     settings_definitions: serde_json::Value,
     settings_values: serde_json::Value,
+    is_library: bool,
+    library_modules: serde_json::Value,
+    plugin_channel: String,
+    plugin_capabilities: Option<serde_json::Value>,
+    plugin_dependencies: Option<serde_json::Value>,
+    settings_problems: serde_json::Value,
 }
 
 impl From<DbScript> for Script {
@@ -1158,6 +2082,7 @@ impl From<DbScript> for Script {
         let settings_definitions =
             serde_json::from_value(script.settings_definitions).unwrap_or_default();
         let settings_values = serde_json::from_value(script.settings_values).unwrap_or_default();
+        let library_modules = serde_json::from_value(script.library_modules).unwrap_or_default();
 
         Self {
             id: script.id as u64,
@@ -1173,6 +2098,17 @@ impl From<DbScript> for Script {
             plugin_version_number: script.plugin_version_number.map(|v| v as u32),
             settings_definitions,
             settings_values,
+            is_library: script.is_library,
+            library_modules,
+            plugin_channel: ReleaseChannel::parse(&script.plugin_channel).unwrap_or_default(),
+            plugin_capabilities: script
+                .plugin_capabilities
+                .map(|v| serde_json::from_value(v).unwrap_or_default()),
+            plugin_dependencies: script
+                .plugin_dependencies
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+            settings_problems: serde_json::from_value(script.settings_problems).unwrap_or_default(),
         }
     }
 }
@@ -1180,6 +2116,8 @@ impl From<DbScript> for Script {
 struct DbGuildMetaConfig {
     pub guild_id: i64,
     pub error_channel_id: i64,
+    pub log_webhook_url: Option<String>,
+    pub log_webhook_secret: Option<String>,
 }
 
 impl From<DbGuildMetaConfig> for GuildMetaConfig {
@@ -1191,6 +2129,10 @@ impl From<DbGuildMetaConfig> for GuildMetaConfig {
             } else {
                 None
             },
+            log_webhook: match (mc.log_webhook_url, mc.log_webhook_secret) {
+                (Some(url), Some(secret)) => Some(GuildLogWebhook { url, secret }),
+                _ => None,
+            },
         }
     }
 }
@@ -1272,7 +2214,7 @@ impl From<DbPremiumSlot> for PremiumSlot {
 
 // since the int representation is specific to this postgres implementation, i don't want
 // to implement From<PremiumSlotTier> for i32
-fn tier_to_int(tier: PremiumSlotTier) -> i32 {
+pub(crate) fn tier_to_int(tier: PremiumSlotTier) -> i32 {
     match tier {
         PremiumSlotTier::Lite => 1,
         PremiumSlotTier::Premium => 2,
@@ -1304,15 +2246,99 @@ struct DbPlugin {
     script_published_version_updated_at: Option<DateTime<Utc>>,
     script_dev_source: Option<String>,
     script_dev_version_updated_at: Option<DateTime<Utc>>,
+    script_published_library_modules: serde_json::Value,
+    script_dev_library_modules: serde_json::Value,
     author_id: i64,
     is_public: bool,
     discord_thread_id: Option<i64>,
     installed_guilds: Option<i32>,
     installed_guilds_updated_at: Option<DateTime<Utc>>,
+    category: Option<String>,
+    tags: Vec<String>,
+    rating_average: f32,
+    rating_count: i32,
 }
 
 struct PluginAndImages(DbPlugin, Vec<DbPluginImage>);
 
+pub(crate) struct DbPluginVersionMeta {
+    pub(crate) plugin_id: i64,
+    pub(crate) version_number: i32,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) changelog: String,
+    pub(crate) channel: String,
+    pub(crate) rollout_percentage: i16,
+    pub(crate) rollout_updated_at: DateTime<Utc>,
+    pub(crate) rollout_paused_at: Option<DateTime<Utc>>,
+    pub(crate) rollout_pause_reason: Option<String>,
+    pub(crate) capabilities: serde_json::Value,
+    pub(crate) dependencies: serde_json::Value,
+    pub(crate) settings_migrations: serde_json::Value,
+}
+
+impl From<DbPluginVersionMeta> for PluginVersionMeta {
+    fn from(value: DbPluginVersionMeta) -> Self {
+        Self {
+            plugin_id: value.plugin_id as u64,
+            version_number: value.version_number as u32,
+            created_at: value.created_at,
+            changelog: value.changelog,
+            channel: ReleaseChannel::parse(&value.channel).unwrap_or_default(),
+            rollout: PluginVersionRollout {
+                percentage: value.rollout_percentage as u8,
+                updated_at: value.rollout_updated_at,
+                paused_at: value.rollout_paused_at,
+                pause_reason: value.rollout_pause_reason,
+            },
+            capabilities: serde_json::from_value(value.capabilities).unwrap_or_default(),
+            dependencies: serde_json::from_value(value.dependencies).unwrap_or_default(),
+            settings_migrations: serde_json::from_value(value.settings_migrations)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) struct DbPluginVersion {
+    pub(crate) plugin_id: i64,
+    pub(crate) version_number: i32,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) changelog: String,
+    pub(crate) channel: String,
+    pub(crate) rollout_percentage: i16,
+    pub(crate) rollout_updated_at: DateTime<Utc>,
+    pub(crate) rollout_paused_at: Option<DateTime<Utc>>,
+    pub(crate) rollout_pause_reason: Option<String>,
+    pub(crate) capabilities: serde_json::Value,
+    pub(crate) dependencies: serde_json::Value,
+    pub(crate) settings_migrations: serde_json::Value,
+    pub(crate) source: String,
+    pub(crate) library_modules: serde_json::Value,
+}
+
+impl From<DbPluginVersion> for PluginVersion {
+    fn from(value: DbPluginVersion) -> Self {
+        Self {
+            meta: DbPluginVersionMeta {
+                plugin_id: value.plugin_id,
+                version_number: value.version_number,
+                created_at: value.created_at,
+                changelog: value.changelog,
+                channel: value.channel,
+                rollout_percentage: value.rollout_percentage,
+                rollout_updated_at: value.rollout_updated_at,
+                rollout_paused_at: value.rollout_paused_at,
+                rollout_pause_reason: value.rollout_pause_reason,
+                capabilities: value.capabilities,
+                dependencies: value.dependencies,
+                settings_migrations: value.settings_migrations,
+            }
+            .into(),
+            source: value.source,
+            library_modules: serde_json::from_value(value.library_modules).unwrap_or_default(),
+        }
+    }
+}
+
 impl From<PluginAndImages> for Plugin {
     fn from(PluginAndImages(plugin, images): PluginAndImages) -> Self {
         Self {
@@ -1330,12 +2356,22 @@ impl From<PluginAndImages> for Plugin {
             installed_guilds: plugin.installed_guilds.map(|v| v as u32),
             installed_guilds_updated_at: plugin.installed_guilds_updated_at,
             published_version_updated_at: plugin.script_published_version_updated_at,
+            category: plugin.category.as_deref().and_then(PluginCategory::parse),
+            tags: plugin.tags,
+            rating_average: plugin.rating_average,
+            rating_count: plugin.rating_count as u32,
             data: match plugin.plugin_kind {
                 0 => PluginData::ScriptPlugin(ScriptPluginData {
                     published_version: plugin.script_published_source,
                     published_version_updated_at: plugin.script_published_version_updated_at,
+                    published_library_modules: serde_json::from_value(
+                        plugin.script_published_library_modules,
+                    )
+                    .unwrap_or_default(),
                     dev_version: plugin.script_dev_source,
                     dev_version_updated_at: plugin.script_dev_version_updated_at,
+                    dev_library_modules: serde_json::from_value(plugin.script_dev_library_modules)
+                        .unwrap_or_default(),
                 }),
                 other => {
                     panic!("unknown plugin kind: {other} for plugin id {}", plugin.id)
@@ -1405,6 +2441,40 @@ pub struct Script {
     pub plugin_version_number: Option<u32>,
     pub settings_definitions: Option<Vec<SettingsOptionDefinition>>,
     pub settings_values: Vec<SettingsOptionValue>,
+
+    /// Library scripts are only loaded when imported by other scripts, they are never run on their own
+    /// and do not contribute commands or timers
+    #[serde(default)]
+    pub is_library: bool,
+
+    /// Library modules bundled with the plugin version this script was installed from
+    #[serde(default)]
+    pub library_modules: Vec<LibraryModule>,
+
+    /// The channel auto updates of the plugin are received from
+    #[serde(default)]
+    pub plugin_channel: ReleaseChannel,
+
+    /// The capabilities the guild admins granted the plugin, versions asking for more than this
+    /// aren't installed without their consent
+    #[serde(default)]
+    pub plugin_capabilities: Option<PluginCapabilities>,
+
+    /// The dependencies of the plugin version this script was installed from
+    #[serde(default)]
+    pub plugin_dependencies: Vec<PluginDependency>,
+
+    /// Settings values that didn't survive an update of the plugin or the script, shown to the
+    /// guild admins until they save the settings again
+    #[serde(default)]
+    pub settings_problems: Vec<SettingsProblem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettingsProblem {
+    /// The option the value belongs to
+    pub option: String,
+    pub message: String,
 }
 
 /// Struct you get back from the store
@@ -1418,6 +2488,8 @@ pub struct UpdateScript {
     pub plugin_version_number: Option<u32>,
     pub settings_definitions: Option<Vec<SettingsOptionDefinition>>,
     pub settings_values: Option<Vec<SettingsOptionValue>>,
+    pub is_library: Option<bool>,
+    pub library_modules: Option<Vec<LibraryModule>>,
 }
 
 /// Struct used when creating a script
@@ -1429,6 +2501,8 @@ pub struct CreateScript {
     pub plugin_id: Option<u64>,
     pub plugin_auto_update: Option<bool>,
     pub plugin_version_number: Option<u32>,
+    pub is_library: bool,
+    pub library_modules: Vec<LibraryModule>,
 }
 
 /// Contribution points for a scripts, e.g triggers, commands etc
@@ -1445,11 +2519,41 @@ pub struct IntervalTimerContrib {
     pub plugin_id: Option<u64>,
 }
 
+/// A pinned third party es module bundle that scripts can import with `vendor:<name>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VendoredModule {
+    pub id: u64,
+    pub owner: VendoredModuleOwner,
+    pub name: String,
+    pub source: String,
+
+    /// hex encoded sha256 of the source
+    pub content_hash: String,
+    pub size_bytes: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VendoredModuleOwner {
+    Guild(Id<GuildMarker>),
+    Plugin(u64),
+}
+
+impl VendoredModuleOwner {
+    fn db_ids(&self) -> (Option<i64>, Option<i64>) {
+        match self {
+            Self::Guild(guild_id) => (Some(guild_id.get() as i64), None),
+            Self::Plugin(plugin_id) => (None, Some(*plugin_id as i64)),
+        }
+    }
+}
+
 /// A guilds config, for storing core botloader settings
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct GuildMetaConfig {
     pub guild_id: Id<GuildMarker>,
     pub error_channel_id: Option<Id<ChannelMarker>>,
+    pub log_webhook: Option<GuildLogWebhook>,
 }
 
 impl GuildMetaConfig {
@@ -1457,10 +2561,18 @@ impl GuildMetaConfig {
         Self {
             guild_id,
             error_channel_id: None,
+            log_webhook: None,
         }
     }
 }
 
+/// A external endpoint guild logs are forwarded to, the secret is used to sign the requests
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GuildLogWebhook {
+    pub url: String,
+    pub secret: String,
+}
+
 /// A joined guild, we we store all guidls were connected to in the store
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JoinedGuild {
@@ -1551,6 +2663,18 @@ pub struct CreateUpdatePluginImage {
     pub kind: PluginImageKind,
 }
 
+pub struct PublishScriptPluginVersion {
+    pub source: String,
+    pub library_modules: Vec<LibraryModule>,
+    pub changelog: String,
+    pub channel: ReleaseChannel,
+    /// Percentage of the auto updating guilds to update right away
+    pub rollout_percentage: u8,
+    pub capabilities: PluginCapabilities,
+    pub dependencies: Vec<PluginDependency>,
+    pub settings_migrations: Vec<SettingsMigration>,
+}
+
 pub struct UpdatePluginMeta {
     pub name: Option<String>,
     pub short_description: Option<String>,
@@ -1560,6 +2684,68 @@ pub struct UpdatePluginMeta {
     pub is_public: Option<bool>,
     pub is_published: Option<bool>,
     pub discord_thread_id: Option<u64>,
+    pub category: Option<PluginCategory>,
+    pub tags: Option<Vec<String>>,
+}
+
+pub struct PluginSearchQuery {
+    /// Searched for in the name and descriptions
+    pub text: Option<String>,
+    pub category: Option<PluginCategory>,
+    /// Only plugins that have all of these tags are returned
+    pub tags: Vec<String>,
+    pub sort: PluginSort,
+    pub after: Option<PluginSearchCursor>,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginSort {
+    #[default]
+    InstalledGuilds,
+    Recent,
+    Rating,
+    /// How well the plugin matches the search text
+    Relevance,
+}
+
+impl PluginSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InstalledGuilds => "installed_guilds",
+            Self::Recent => "recent",
+            Self::Rating => "rating",
+            Self::Relevance => "relevance",
+        }
+    }
+}
+
+/// Points to the last plugin of a page, the next page starts after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PluginSearchCursor {
+    pub sort_key: f64,
+    pub plugin_id: u64,
+}
+
+impl PluginSearchCursor {
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.sort_key, self.plugin_id)
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let (sort_key, plugin_id) = s.split_once('_')?;
+
+        Some(Self {
+            sort_key: sort_key.parse().ok()?,
+            plugin_id: plugin_id.parse().ok()?,
+        })
+    }
+}
+
+pub struct PluginSearchPage {
+    pub plugins: Vec<Plugin>,
+    pub next_cursor: Option<PluginSearchCursor>,
 }
 
 pub struct CreateImage {
@@ -1587,11 +2773,44 @@ pub enum ConfigStoreError {
     #[error("plugin not found: {0}")]
     PluginNotFound(u64),
 
+    #[error("plugin version not found: {0} v{1}")]
+    PluginVersionNotFound(u64, u32),
+
+    #[error("script revision not found: {0}")]
+    ScriptRevisionNotFound(u64),
+
+    #[error("plugin review not found: plugin {0} user {1}")]
+    PluginReviewNotFound(u64, u64),
+
+    #[error("the plugin version needs capabilities that haven't been granted")]
+    PluginCapabilitiesNotGranted(PluginCapabilities),
+
+    #[error("plugin dependency not satisfied: plugin {}", .0.plugin_id)]
+    PluginDependencyNotSatisfied(PluginDependency),
+
+    #[error("plugin {name} ({plugin_id}) installed on the guild depends on the plugin")]
+    PluginRequiredByDependent { plugin_id: u64, name: String },
+
     #[error("plugin is already on guild")]
     GuildAlreadyHasPlugin,
 
     #[error("image not found: {0}/{1}")]
     ImageNotFound(u64, Uuid),
+
+    #[error("vendored module not found")]
+    VendoredModuleNotFound,
+
+    #[error("reached limit of vendored modules (limit {0})")]
+    VendoredModuleLimitReached(u64),
+
+    #[error("inbound webhook not found: {0}")]
+    InboundWebhookNotFound(u64),
+
+    #[error("reached limit of inbound webhooks (limit {0})")]
+    InboundWebhookLimitReached(u64),
+
+    #[error("inbound webhook name already in use")]
+    InboundWebhookNameTaken,
 }
 
 impl ConfigStoreError {
@@ -1601,7 +2820,12 @@ impl ConfigStoreError {
             Self::ScriptNotFound
                 | Self::LinkNotFound
                 | Self::PluginNotFound(_)
+                | Self::PluginVersionNotFound(_, _)
+                | Self::ScriptRevisionNotFound(_)
+                | Self::PluginReviewNotFound(_, _)
                 | Self::ImageNotFound(_, _)
+                | Self::VendoredModuleNotFound
+                | Self::InboundWebhookNotFound(_)
         )
     }
 }
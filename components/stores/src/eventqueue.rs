@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::Db;
+
+impl Db {
+    pub async fn push_queued_broker_event(
+        &self,
+        queue_name: &str,
+        guild_id: Id<GuildMarker>,
+        event: serde_json::Value,
+    ) -> EventQueueResult<u64> {
+        let res = sqlx::query!(
+            "INSERT INTO broker_event_queue (queue_name, guild_id, event) VALUES ($1, $2, $3) \
+             RETURNING id;",
+            queue_name,
+            guild_id.get() as i64,
+            event,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.id as u64)
+    }
+
+    /// Returns the oldest events in the queue after the provided id, in the order they were queued
+    pub async fn get_queued_broker_events(
+        &self,
+        queue_name: &str,
+        after_id: u64,
+        limit: u32,
+    ) -> EventQueueResult<Vec<QueuedBrokerEvent>> {
+        let res = sqlx::query_as!(
+            DbQueuedBrokerEvent,
+            "SELECT id, guild_id, event, created_at FROM broker_event_queue
+            WHERE queue_name = $1 AND id > $2
+            ORDER BY id ASC
+            LIMIT $3;",
+            queue_name,
+            after_id as i64,
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn del_queued_broker_events(
+        &self,
+        queue_name: &str,
+        ids: &[u64],
+    ) -> EventQueueResult<u64> {
+        let ids = ids.iter().map(|v| *v as i64).collect::<Vec<_>>();
+
+        let res = sqlx::query!(
+            "DELETE FROM broker_event_queue WHERE queue_name = $1 AND id = ANY($2);",
+            queue_name,
+            &ids,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    pub async fn del_expired_queued_broker_events(
+        &self,
+        queue_name: &str,
+        older_than: DateTime<Utc>,
+    ) -> EventQueueResult<u64> {
+        let res = sqlx::query!(
+            "DELETE FROM broker_event_queue WHERE queue_name = $1 AND created_at < $2;",
+            queue_name,
+            older_than,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    pub async fn get_queued_broker_event_count(&self, queue_name: &str) -> EventQueueResult<u64> {
+        let res = sqlx::query!(
+            "SELECT count(*) FROM broker_event_queue WHERE queue_name = $1;",
+            queue_name,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.count.unwrap_or_default() as u64)
+    }
+}
+
+pub struct QueuedBrokerEvent {
+    pub id: u64,
+    pub guild_id: Id<GuildMarker>,
+    pub event: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+struct DbQueuedBrokerEvent {
+    id: i64,
+    guild_id: i64,
+    event: serde_json::Value,
+    created_at: DateTime<Utc>,
+}
+
+impl From<DbQueuedBrokerEvent> for QueuedBrokerEvent {
+    fn from(v: DbQueuedBrokerEvent) -> Self {
+        Self {
+            id: v.id as u64,
+            guild_id: Id::new(v.guild_id as u64),
+            event: v.event,
+            created_at: v.created_at,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EventQueueError {
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+}
+
+pub type EventQueueResult<T> = Result<T, EventQueueError>;
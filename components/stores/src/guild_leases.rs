@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::Db;
+
+impl Db {
+    /// Takes the lease of the guild if it's free, expired or already held by the scheduler,
+    /// returning whether the scheduler holds it now
+    pub async fn acquire_guild_lease(
+        &self,
+        guild_id: Id<GuildMarker>,
+        scheduler_id: &str,
+        ttl: Duration,
+    ) -> GuildLeaseResult<bool> {
+        let res = sqlx::query!(
+            "INSERT INTO guild_scheduler_leases (guild_id, scheduler_id, expires_at)
+            VALUES ($1, $2, now() + make_interval(secs => $3))
+            ON CONFLICT (guild_id) DO UPDATE SET
+                scheduler_id = excluded.scheduler_id,
+                expires_at = excluded.expires_at
+            WHERE guild_scheduler_leases.scheduler_id = excluded.scheduler_id
+                OR guild_scheduler_leases.expires_at < now()
+            RETURNING guild_id;",
+            guild_id.get() as i64,
+            scheduler_id,
+            ttl.as_secs_f64(),
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(res.is_some())
+    }
+
+    /// Extends the leases the scheduler holds on the provided guilds, returning the guilds it
+    /// still held
+    pub async fn renew_guild_leases(
+        &self,
+        guild_ids: &[Id<GuildMarker>],
+        scheduler_id: &str,
+        ttl: Duration,
+    ) -> GuildLeaseResult<Vec<Id<GuildMarker>>> {
+        let guild_ids = guild_ids.iter().map(|v| v.get() as i64).collect::<Vec<_>>();
+
+        let res = sqlx::query!(
+            "UPDATE guild_scheduler_leases SET expires_at = now() + make_interval(secs => $3)
+            WHERE scheduler_id = $1 AND guild_id = ANY($2)
+            RETURNING guild_id;",
+            scheduler_id,
+            &guild_ids,
+            ttl.as_secs_f64(),
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|row| Id::new(row.guild_id as u64))
+            .collect())
+    }
+
+    pub async fn release_guild_lease(
+        &self,
+        guild_id: Id<GuildMarker>,
+        scheduler_id: &str,
+    ) -> GuildLeaseResult<()> {
+        sqlx::query!(
+            "DELETE FROM guild_scheduler_leases WHERE guild_id = $1 AND scheduler_id = $2;",
+            guild_id.get() as i64,
+            scheduler_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuildLeaseError {
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+}
+
+pub type GuildLeaseResult<T> = Result<T, GuildLeaseError>;
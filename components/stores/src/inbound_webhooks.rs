@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{
+    config::{ConfigStoreError, ConfigStoreResult},
+    Db,
+};
+
+const INBOUND_WEBHOOK_COUNT_LIMIT: i64 = 10;
+
+impl Db {
+    pub async fn get_inbound_webhooks(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> ConfigStoreResult<Vec<InboundWebhook>> {
+        let res = sqlx::query_as!(
+            DbInboundWebhook,
+            "SELECT id, guild_id, name, secret, created_at, last_triggered_at
+            FROM guild_inbound_webhooks
+            WHERE guild_id = $1
+            ORDER BY id ASC;",
+            guild_id.get() as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn get_inbound_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook_id: u64,
+    ) -> ConfigStoreResult<InboundWebhook> {
+        let res = sqlx::query_as!(
+            DbInboundWebhook,
+            "SELECT id, guild_id, name, secret, created_at, last_triggered_at
+            FROM guild_inbound_webhooks
+            WHERE guild_id = $1 AND id = $2;",
+            guild_id.get() as i64,
+            webhook_id as i64,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::InboundWebhookNotFound(webhook_id))?;
+
+        Ok(res.into())
+    }
+
+    pub async fn create_inbound_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        name: &str,
+        secret: &str,
+    ) -> ConfigStoreResult<InboundWebhook> {
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query!(
+            "SELECT name FROM guild_inbound_webhooks WHERE guild_id = $1 FOR UPDATE;",
+            guild_id.get() as i64,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if existing.iter().any(|v| v.name == name) {
+            return Err(ConfigStoreError::InboundWebhookNameTaken);
+        }
+
+        if existing.len() as i64 >= INBOUND_WEBHOOK_COUNT_LIMIT {
+            return Err(ConfigStoreError::InboundWebhookLimitReached(
+                INBOUND_WEBHOOK_COUNT_LIMIT as u64,
+            ));
+        }
+
+        let res = sqlx::query_as!(
+            DbInboundWebhook,
+            "INSERT INTO guild_inbound_webhooks (guild_id, name, secret)
+            VALUES ($1, $2, $3)
+            RETURNING id, guild_id, name, secret, created_at, last_triggered_at;",
+            guild_id.get() as i64,
+            name,
+            secret,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    /// Renames the webhook and/or replaces its secret, fields that are not provided are left as is
+    pub async fn update_inbound_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook_id: u64,
+        name: Option<&str>,
+        secret: Option<&str>,
+    ) -> ConfigStoreResult<InboundWebhook> {
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(name) = name {
+            let taken = sqlx::query!(
+                "SELECT id FROM guild_inbound_webhooks WHERE guild_id = $1 AND name = $2 AND id != \
+                 $3;",
+                guild_id.get() as i64,
+                name,
+                webhook_id as i64,
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            if taken.is_some() {
+                return Err(ConfigStoreError::InboundWebhookNameTaken);
+            }
+        }
+
+        let res = sqlx::query_as!(
+            DbInboundWebhook,
+            "UPDATE guild_inbound_webhooks SET
+                name = COALESCE($3, name),
+                secret = COALESCE($4, secret)
+            WHERE guild_id = $1 AND id = $2
+            RETURNING id, guild_id, name, secret, created_at, last_triggered_at;",
+            guild_id.get() as i64,
+            webhook_id as i64,
+            name,
+            secret,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::InboundWebhookNotFound(webhook_id))?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    pub async fn delete_inbound_webhook(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook_id: u64,
+    ) -> ConfigStoreResult<()> {
+        let res = sqlx::query!(
+            "DELETE FROM guild_inbound_webhooks WHERE guild_id = $1 AND id = $2;",
+            guild_id.get() as i64,
+            webhook_id as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() > 0 {
+            Ok(())
+        } else {
+            Err(ConfigStoreError::InboundWebhookNotFound(webhook_id))
+        }
+    }
+
+    pub async fn set_inbound_webhook_triggered(
+        &self,
+        guild_id: Id<GuildMarker>,
+        webhook_id: u64,
+    ) -> ConfigStoreResult<()> {
+        sqlx::query!(
+            "UPDATE guild_inbound_webhooks SET last_triggered_at = now() WHERE guild_id = $1 AND \
+             id = $2;",
+            guild_id.get() as i64,
+            webhook_id as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// An endpoint external services can send signed requests to, which are passed on to the guild's
+/// scripts
+#[derive(Debug, Clone)]
+pub struct InboundWebhook {
+    pub id: u64,
+    pub guild_id: Id<GuildMarker>,
+    pub name: String,
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+}
+
+struct DbInboundWebhook {
+    id: i64,
+    guild_id: i64,
+    name: String,
+    secret: String,
+    created_at: DateTime<Utc>,
+    last_triggered_at: Option<DateTime<Utc>>,
+}
+
+impl From<DbInboundWebhook> for InboundWebhook {
+    fn from(value: DbInboundWebhook) -> Self {
+        Self {
+            id: value.id as u64,
+            guild_id: Id::new(value.guild_id as u64),
+            name: value.name,
+            secret: value.secret,
+            created_at: value.created_at,
+            last_triggered_at: value.last_triggered_at,
+        }
+    }
+}
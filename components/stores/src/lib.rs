@@ -1,6 +1,15 @@
 pub mod bucketstore;
 pub mod config;
+pub mod eventqueue;
+pub mod guild_leases;
+pub mod inbound_webhooks;
 pub mod inmemory;
+pub mod plugin_dependencies;
+pub mod plugin_reviews;
+pub mod plugin_rollouts;
+pub mod script_revisions;
+pub mod settings_migrations;
+pub mod suspensions;
 pub mod timers;
 pub mod web;
 
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use common::plugin::{
+    find_unsatisfied_dependency, InstalledPluginVersion, PluginDependency, PluginVersion,
+};
+use sqlx::PgConnection;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{
+    config::{ConfigStoreError, ConfigStoreResult, DbPluginVersion},
+    Db,
+};
+
+/// A plugin that needs to be installed on the guild to satisfy the dependencies of another one
+pub(crate) struct ResolvedPluginDependency {
+    pub(crate) name: String,
+    pub(crate) version: PluginVersion,
+}
+
+impl Db {
+    /// Returns the plugin versions installed on the guilds along with their dependencies
+    pub(crate) async fn get_installed_plugin_versions(
+        conn: &mut PgConnection,
+        guild_ids: &[i64],
+    ) -> ConfigStoreResult<HashMap<Id<GuildMarker>, Vec<InstalledPluginVersion>>> {
+        let res = sqlx::query!(
+            "SELECT gs.guild_id, gs.plugin_id AS \"plugin_id!\",
+                coalesce(gs.plugin_version_number, 0) AS \"version_number!\",
+                v.dependencies AS \"dependencies?\"
+            FROM guild_scripts gs
+            LEFT JOIN script_plugin_versions v
+                ON v.plugin_id = gs.plugin_id AND v.version_number = gs.plugin_version_number
+            WHERE gs.guild_id = ANY($1) AND gs.plugin_id IS NOT NULL;",
+            guild_ids,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        let mut installed: HashMap<_, Vec<_>> = HashMap::new();
+        for row in res {
+            installed
+                .entry(Id::new(row.guild_id as u64))
+                .or_default()
+                .push(InstalledPluginVersion {
+                    plugin_id: row.plugin_id as u64,
+                    version_number: row.version_number as u32,
+                    dependencies: row
+                        .dependencies
+                        .and_then(|v| serde_json::from_value(v).ok())
+                        .unwrap_or_default(),
+                });
+        }
+
+        Ok(installed)
+    }
+
+    /// Makes sure moving the guild's install of the plugin to `updated` doesn't leave it, or any of
+    /// the plugins depending on it, with unsatisfied dependencies
+    pub(crate) async fn check_plugin_update_dependencies(
+        conn: &mut PgConnection,
+        guild_id: Id<GuildMarker>,
+        updated: &InstalledPluginVersion,
+    ) -> ConfigStoreResult<()> {
+        let installed = Self::get_installed_plugin_versions(conn, &[guild_id.get() as i64])
+            .await?
+            .remove(&guild_id)
+            .unwrap_or_default();
+
+        match find_unsatisfied_dependency(&installed, updated) {
+            Some(dependency) => Err(ConfigStoreError::PluginDependencyNotSatisfied(dependency)),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes sure removing the guild's install of the plugin doesn't leave another plugin
+    /// installed on the guild without one of its dependencies
+    ///
+    /// When the plugin is only being disabled, the plugins depending on it that are disabled
+    /// themselves are left out
+    pub(crate) async fn check_plugin_removal_dependencies(
+        conn: &mut PgConnection,
+        guild_id: Id<GuildMarker>,
+        plugin_id: u64,
+        disabling: bool,
+    ) -> ConfigStoreResult<()> {
+        let dependent = sqlx::query!(
+            "SELECT gs.plugin_id AS \"plugin_id!\", plugins.name
+            FROM guild_scripts gs
+            INNER JOIN script_plugin_versions v
+                ON v.plugin_id = gs.plugin_id AND v.version_number = gs.plugin_version_number
+            INNER JOIN plugins ON plugins.id = gs.plugin_id
+            WHERE gs.guild_id = $1 AND gs.plugin_id != $2 AND (NOT $3 OR gs.enabled)
+                AND v.dependencies @> jsonb_build_array(jsonb_build_object('plugin_id', $2::BIGINT))
+            LIMIT 1;",
+            guild_id.get() as i64,
+            plugin_id as i64,
+            disabling,
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        match dependent {
+            Some(dependent) => Err(ConfigStoreError::PluginRequiredByDependent {
+                plugin_id: dependent.plugin_id as u64,
+                name: dependent.name,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Dependencies can only be declared on public plugins that have a stable version within the
+    /// range, which also rules out depending on unpublished versions of the plugin itself
+    pub(crate) async fn check_plugin_dependencies_available(
+        conn: &mut PgConnection,
+        plugin_id: u64,
+        dependencies: &[PluginDependency],
+    ) -> ConfigStoreResult<()> {
+        for dependency in dependencies {
+            if dependency.plugin_id == plugin_id
+                || Self::get_newest_satisfying_plugin_version(conn, dependency)
+                    .await?
+                    .is_none()
+            {
+                return Err(ConfigStoreError::PluginDependencyNotSatisfied(*dependency));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the plugins that need to be installed alongside the version of the plugin for its
+    /// dependencies to be satisfied, dependencies come before the plugins depending on them
+    ///
+    /// Missing dependencies get the newest stable version within the range, plugins the guild
+    /// already has installed have to be on a version within it
+    pub(crate) async fn resolve_plugin_dependencies(
+        conn: &mut PgConnection,
+        guild_id: Id<GuildMarker>,
+        plugin_id: u64,
+        version_number: u32,
+    ) -> ConfigStoreResult<Vec<ResolvedPluginDependency>> {
+        let dependencies = sqlx::query!(
+            "SELECT dependencies FROM script_plugin_versions WHERE plugin_id = $1 AND \
+             version_number = $2",
+            plugin_id as i64,
+            version_number as i32,
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .and_then(|v| serde_json::from_value::<Vec<PluginDependency>>(v.dependencies).ok())
+        .unwrap_or_default();
+
+        let mut installed = Self::get_installed_plugin_versions(conn, &[guild_id.get() as i64])
+            .await?
+            .remove(&guild_id)
+            .unwrap_or_default();
+        let already_installed = installed.len();
+
+        // the plugin itself counts as installed so that dependencies back on it are resolved
+        installed.push(InstalledPluginVersion {
+            plugin_id,
+            version_number,
+            dependencies: dependencies.clone(),
+        });
+
+        let mut resolved = Vec::new();
+        let mut pending = dependencies;
+        while let Some(dependency) = pending.pop() {
+            if installed
+                .iter()
+                .any(|v| v.plugin_id == dependency.plugin_id)
+            {
+                continue;
+            }
+
+            let Some(version) =
+                Self::get_newest_satisfying_plugin_version(conn, &dependency).await?
+            else {
+                return Err(ConfigStoreError::PluginDependencyNotSatisfied(dependency));
+            };
+
+            let name = sqlx::query!(
+                "SELECT name FROM plugins WHERE id = $1",
+                dependency.plugin_id as i64,
+            )
+            .fetch_one(&mut *conn)
+            .await?
+            .name;
+
+            pending.extend(version.meta.dependencies.iter().copied());
+            installed.push(InstalledPluginVersion {
+                plugin_id: dependency.plugin_id,
+                version_number: version.meta.version_number,
+                dependencies: version.meta.dependencies.clone(),
+            });
+            resolved.push(ResolvedPluginDependency { name, version });
+        }
+
+        // every newly installed plugin has to work with what's there, including versions picked
+        // for other dependencies
+        for new in &installed[already_installed..] {
+            if let Some(dependency) = find_unsatisfied_dependency(&installed, new) {
+                return Err(ConfigStoreError::PluginDependencyNotSatisfied(dependency));
+            }
+        }
+
+        resolved.reverse();
+        Ok(resolved)
+    }
+
+    async fn get_newest_satisfying_plugin_version(
+        conn: &mut PgConnection,
+        dependency: &PluginDependency,
+    ) -> ConfigStoreResult<Option<PluginVersion>> {
+        let res = sqlx::query_as!(
+            DbPluginVersion,
+            "SELECT v.plugin_id, v.version_number, v.created_at, v.changelog, v.channel,
+                v.rollout_percentage, v.rollout_updated_at, v.rollout_paused_at,
+                v.rollout_pause_reason, v.capabilities, v.dependencies, v.settings_migrations,
+                v.source, v.library_modules
+            FROM script_plugin_versions v
+            INNER JOIN plugins ON plugins.id = v.plugin_id
+            WHERE v.plugin_id = $1 AND plugins.is_public
+                AND v.channel = 'stable' AND v.rollout_percentage >= 100
+                AND v.version_number >= $2 AND ($3::INT IS NULL OR v.version_number <= $3)
+            ORDER BY v.version_number DESC
+            LIMIT 1;",
+            dependency.plugin_id as i64,
+            dependency.min_version as i32,
+            dependency.max_version.map(|v| v as i32),
+        )
+        .fetch_optional(conn)
+        .await?;
+
+        Ok(res.map(Into::into))
+    }
+}
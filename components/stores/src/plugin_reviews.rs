@@ -0,0 +1,255 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgConnection;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::{
+    config::{ConfigStoreError, ConfigStoreResult},
+    Db,
+};
+
+impl Db {
+    /// Returns the reviews of the plugin, most recently updated first
+    pub async fn get_plugin_reviews(
+        &self,
+        plugin_id: u64,
+        include_hidden: bool,
+    ) -> ConfigStoreResult<Vec<PluginReview>> {
+        let res = sqlx::query_as!(
+            DbPluginReview,
+            "SELECT plugin_id, user_id, guild_id, rating, body, created_at, updated_at, \
+             author_reply, author_replied_at, hidden_at, hidden_reason
+            FROM plugin_reviews
+            WHERE plugin_id = $1 AND ($2 OR hidden_at IS NULL)
+            ORDER BY updated_at DESC;",
+            plugin_id as i64,
+            include_hidden,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns the first of the guilds that has the plugin installed
+    pub async fn find_guild_with_plugin(
+        &self,
+        plugin_id: u64,
+        guild_ids: &[Id<GuildMarker>],
+    ) -> ConfigStoreResult<Option<Id<GuildMarker>>> {
+        let guild_ids = guild_ids.iter().map(|v| v.get() as i64).collect::<Vec<_>>();
+
+        let res = sqlx::query!(
+            "SELECT guild_id FROM guild_scripts WHERE plugin_id = $1 AND guild_id = ANY($2) \
+             LIMIT 1;",
+            plugin_id as i64,
+            &guild_ids,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(res.map(|v| Id::new(v.guild_id as u64)))
+    }
+
+    /// Creates or replaces the user's review of the plugin, a replaced review keeps the author's
+    /// reply and moderation state
+    pub async fn upsert_plugin_review(
+        &self,
+        plugin_id: u64,
+        user_id: Id<UserMarker>,
+        guild_id: Id<GuildMarker>,
+        rating: u8,
+        body: &str,
+    ) -> ConfigStoreResult<PluginReview> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query_as!(
+            DbPluginReview,
+            "INSERT INTO plugin_reviews (plugin_id, user_id, guild_id, rating, body)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (plugin_id, user_id) DO UPDATE SET
+            guild_id = excluded.guild_id,
+            rating = excluded.rating,
+            body = excluded.body,
+            updated_at = now()
+            RETURNING plugin_id, user_id, guild_id, rating, body, created_at, updated_at, \
+             author_reply, author_replied_at, hidden_at, hidden_reason;",
+            plugin_id as i64,
+            user_id.get() as i64,
+            guild_id.get() as i64,
+            rating as i16,
+            body,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        Self::update_plugin_rating(&mut tx, plugin_id).await?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    pub async fn delete_plugin_review(
+        &self,
+        plugin_id: u64,
+        user_id: Id<UserMarker>,
+    ) -> ConfigStoreResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query!(
+            "DELETE FROM plugin_reviews WHERE plugin_id = $1 AND user_id = $2;",
+            plugin_id as i64,
+            user_id.get() as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if res.rows_affected() < 1 {
+            return Err(ConfigStoreError::PluginReviewNotFound(
+                plugin_id,
+                user_id.get(),
+            ));
+        }
+
+        Self::update_plugin_rating(&mut tx, plugin_id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Sets the plugin author's reply to a review, `None` removes it
+    pub async fn set_plugin_review_reply(
+        &self,
+        plugin_id: u64,
+        user_id: Id<UserMarker>,
+        reply: Option<&str>,
+    ) -> ConfigStoreResult<PluginReview> {
+        let res = sqlx::query_as!(
+            DbPluginReview,
+            "UPDATE plugin_reviews SET
+            author_reply = $3,
+            author_replied_at = CASE WHEN $3::TEXT IS NULL THEN NULL ELSE now() END
+            WHERE plugin_id = $1 AND user_id = $2
+            RETURNING plugin_id, user_id, guild_id, rating, body, created_at, updated_at, \
+             author_reply, author_replied_at, hidden_at, hidden_reason;",
+            plugin_id as i64,
+            user_id.get() as i64,
+            reply,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::PluginReviewNotFound(
+            plugin_id,
+            user_id.get(),
+        ))?;
+
+        Ok(res.into())
+    }
+
+    /// Hides or unhides a review, hidden reviews don't count towards the plugin's rating
+    pub async fn set_plugin_review_hidden(
+        &self,
+        plugin_id: u64,
+        user_id: Id<UserMarker>,
+        hidden_reason: Option<&str>,
+    ) -> ConfigStoreResult<PluginReview> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query_as!(
+            DbPluginReview,
+            "UPDATE plugin_reviews SET
+            hidden_at = CASE WHEN $3::TEXT IS NULL THEN NULL ELSE now() END,
+            hidden_reason = $3
+            WHERE plugin_id = $1 AND user_id = $2
+            RETURNING plugin_id, user_id, guild_id, rating, body, created_at, updated_at, \
+             author_reply, author_replied_at, hidden_at, hidden_reason;",
+            plugin_id as i64,
+            user_id.get() as i64,
+            hidden_reason,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(ConfigStoreError::PluginReviewNotFound(
+            plugin_id,
+            user_id.get(),
+        ))?;
+
+        Self::update_plugin_rating(&mut tx, plugin_id).await?;
+
+        tx.commit().await?;
+
+        Ok(res.into())
+    }
+
+    async fn update_plugin_rating(
+        conn: &mut PgConnection,
+        plugin_id: u64,
+    ) -> ConfigStoreResult<()> {
+        sqlx::query!(
+            "UPDATE plugins SET
+            rating_average = COALESCE((SELECT avg(rating) FROM plugin_reviews
+                WHERE plugin_id = $1 AND hidden_at IS NULL), 0),
+            rating_count = (SELECT count(*) FROM plugin_reviews
+                WHERE plugin_id = $1 AND hidden_at IS NULL)
+            WHERE id = $1;",
+            plugin_id as i64,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginReview {
+    pub plugin_id: u64,
+    pub user_id: Id<UserMarker>,
+    pub guild_id: Id<GuildMarker>,
+    /// 1-5
+    pub rating: u8,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub author_reply: Option<String>,
+    pub author_replied_at: Option<DateTime<Utc>>,
+    pub hidden_at: Option<DateTime<Utc>>,
+    pub hidden_reason: Option<String>,
+}
+
+struct DbPluginReview {
+    plugin_id: i64,
+    user_id: i64,
+    guild_id: i64,
+    rating: i16,
+    body: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    author_reply: Option<String>,
+    author_replied_at: Option<DateTime<Utc>>,
+    hidden_at: Option<DateTime<Utc>>,
+    hidden_reason: Option<String>,
+}
+
+impl From<DbPluginReview> for PluginReview {
+    fn from(value: DbPluginReview) -> Self {
+        Self {
+            plugin_id: value.plugin_id as u64,
+            user_id: Id::new(value.user_id as u64),
+            guild_id: Id::new(value.guild_id as u64),
+            rating: value.rating as u8,
+            body: value.body,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+            author_reply: value.author_reply,
+            author_replied_at: value.author_replied_at,
+            hidden_at: value.hidden_at,
+            hidden_reason: value.hidden_reason,
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use common::plugin::{
+    find_unsatisfied_dependency, InstalledPluginVersion, PluginCapabilities, PluginVersionMeta,
+    ReleaseChannel,
+};
+use serde::Serialize;
+use sqlx::PgConnection;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{
+    config::{ConfigStoreError, ConfigStoreResult, DbPluginVersionMeta},
+    settings_migrations::migrate_install_settings,
+    Db,
+};
+
+impl Db {
+    /// Returns the plugin versions that are being rolled out and have not been paused
+    pub async fn get_plugin_rollouts_in_progress(
+        &self,
+    ) -> ConfigStoreResult<Vec<PluginVersionMeta>> {
+        let res = sqlx::query_as!(
+            DbPluginVersionMeta,
+            "SELECT plugin_id, version_number, created_at, changelog, channel, rollout_percentage, \
+             rollout_updated_at, rollout_paused_at, rollout_pause_reason, capabilities, \
+             dependencies, settings_migrations FROM script_plugin_versions WHERE \
+             rollout_percentage < 100 AND rollout_paused_at IS NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    /// Records an error logged by the plugin in the guild, if the version the guild is running is
+    /// being rolled out
+    pub async fn record_plugin_version_error(
+        &self,
+        guild_id: Id<GuildMarker>,
+        plugin_id: u64,
+    ) -> ConfigStoreResult<()> {
+        sqlx::query!(
+            "INSERT INTO script_plugin_version_errors (plugin_id, version_number, guild_id)
+            SELECT v.plugin_id, v.version_number, gs.guild_id
+            FROM guild_scripts gs
+            INNER JOIN script_plugin_versions v
+                ON v.plugin_id = gs.plugin_id AND v.version_number = gs.plugin_version_number
+            WHERE gs.guild_id = $1 AND gs.plugin_id = $2
+                AND v.rollout_percentage < 100 AND v.rollout_paused_at IS NULL
+            ON CONFLICT (plugin_id, version_number, guild_id) DO UPDATE SET
+            error_count = script_plugin_version_errors.error_count + 1,
+            last_error_at = now();",
+            guild_id.get() as i64,
+            plugin_id as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_plugin_rollout_stats(
+        &self,
+        plugin_id: u64,
+        version_number: u32,
+    ) -> ConfigStoreResult<PluginRolloutStats> {
+        let res = sqlx::query!(
+            "SELECT
+            (SELECT count(*) FROM guild_scripts WHERE plugin_id = $1 AND plugin_version_number = $2)
+                AS running_guilds,
+            (SELECT count(*) FROM script_plugin_version_errors
+                WHERE plugin_id = $1 AND version_number = $2) AS erroring_guilds;",
+            plugin_id as i64,
+            version_number as i32,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(PluginRolloutStats {
+            running_guilds: res.running_guilds.unwrap_or_default() as u64,
+            erroring_guilds: res.erroring_guilds.unwrap_or_default() as u64,
+        })
+    }
+
+    /// Stops the rollout where it is, the guilds already running the version keep it
+    pub async fn pause_plugin_rollout(
+        &self,
+        plugin_id: u64,
+        version_number: u32,
+        reason: &str,
+    ) -> ConfigStoreResult<()> {
+        let res = sqlx::query!(
+            "UPDATE script_plugin_versions SET rollout_paused_at = now(), rollout_pause_reason = $3
+            WHERE plugin_id = $1 AND version_number = $2;",
+            plugin_id as i64,
+            version_number as i32,
+            reason,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() < 1 {
+            return Err(ConfigStoreError::PluginVersionNotFound(
+                plugin_id,
+                version_number,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sets the rollout percentage of the version and resumes it if it was paused, returning the
+    /// guilds that were updated to it
+    pub async fn advance_plugin_rollout(
+        &self,
+        plugin_id: u64,
+        version_number: u32,
+        percentage: u8,
+    ) -> ConfigStoreResult<Vec<Id<GuildMarker>>> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query!(
+            "UPDATE script_plugin_versions SET rollout_percentage = $3, rollout_updated_at = now(),
+            rollout_paused_at = NULL, rollout_pause_reason = NULL
+            WHERE plugin_id = $1 AND version_number = $2;",
+            plugin_id as i64,
+            version_number as i32,
+            percentage as i16,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if res.rows_affected() < 1 {
+            return Err(ConfigStoreError::PluginVersionNotFound(
+                plugin_id,
+                version_number,
+            ));
+        }
+
+        let updated_guilds =
+            Self::roll_out_plugin_version(&mut tx, plugin_id, version_number).await?;
+
+        tx.commit().await?;
+
+        Ok(updated_guilds)
+    }
+
+    /// Updates the auto updating guilds that fall within the rollout percentage of the version
+    /// and are on its channel, guilds already on a newer version are left alone
+    ///
+    /// Which guilds fall within the percentage is offset by the plugin id so that the same guilds
+    /// don't always get the new versions first
+    ///
+    /// Guilds that haven't granted the capabilities the version needs are held back until their
+    /// admins consent to them
+    ///
+    /// Guilds where the version would break the dependencies of their plugins, or where its own
+    /// dependencies aren't satisfied, are held back as well
+    ///
+    /// The settings migrations of the versions each guild skipped over are applied along the way
+    ///
+    /// A revision is recorded for every updated script
+    pub(crate) async fn roll_out_plugin_version(
+        conn: &mut PgConnection,
+        plugin_id: u64,
+        version_number: u32,
+    ) -> ConfigStoreResult<Vec<Id<GuildMarker>>> {
+        let candidates = sqlx::query!(
+            "SELECT guild_scripts.id, guild_scripts.guild_id, guild_scripts.plugin_capabilities,
+                guild_scripts.plugin_version_number, guild_scripts.plugin_channel,
+                guild_scripts.settings_values, guild_scripts.settings_problems,
+                v.capabilities, v.dependencies
+            FROM guild_scripts
+            INNER JOIN script_plugin_versions v
+                ON v.plugin_id = guild_scripts.plugin_id AND v.version_number = $2
+            WHERE guild_scripts.plugin_id = $1
+                AND guild_scripts.plugin_auto_update
+                AND coalesce(guild_scripts.plugin_version_number, 0) < v.version_number
+                AND (v.channel = 'stable' OR guild_scripts.plugin_channel = v.channel)
+                AND mod(mod(guild_scripts.guild_id, 100) + mod($1, 100), 100) < v.rollout_percentage;",
+            plugin_id as i64,
+            version_number as i32,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let guild_ids = candidates.iter().map(|v| v.guild_id).collect::<Vec<_>>();
+        let installed = Self::get_installed_plugin_versions(conn, &guild_ids).await?;
+
+        let oldest_version = candidates
+            .iter()
+            .map(|v| v.plugin_version_number.unwrap_or_default() as u32)
+            .min()
+            .unwrap_or(version_number);
+        let migration_versions =
+            Self::get_plugin_settings_migrations(conn, plugin_id, oldest_version, version_number)
+                .await?;
+
+        let mut script_ids = Vec::new();
+        let mut settings_values = Vec::new();
+        let mut settings_problems = Vec::new();
+        for candidate in candidates {
+            let granted: PluginCapabilities = candidate
+                .plugin_capabilities
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default();
+            let needed = serde_json::from_value(candidate.capabilities).unwrap_or_default();
+
+            let updated = InstalledPluginVersion {
+                plugin_id,
+                version_number,
+                dependencies: serde_json::from_value(candidate.dependencies).unwrap_or_default(),
+            };
+            let guild_installed = installed
+                .get(&Id::new(candidate.guild_id as u64))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            if !granted.covers(&needed)
+                || find_unsatisfied_dependency(guild_installed, &updated).is_some()
+            {
+                continue;
+            }
+
+            let settings = migrate_install_settings(
+                &migration_versions,
+                candidate.plugin_version_number.unwrap_or_default() as u32,
+                version_number,
+                ReleaseChannel::parse(&candidate.plugin_channel).unwrap_or_default(),
+                candidate
+                    .settings_values
+                    .unwrap_or_else(|| serde_json::json!([])),
+                candidate.settings_problems,
+            );
+
+            script_ids.push(candidate.id);
+            settings_values.push(settings.values);
+            settings_problems.push(settings.problems);
+        }
+
+        let res = sqlx::query!(
+            "UPDATE guild_scripts SET
+            original_source = v.source,
+            plugin_version_number = v.version_number,
+            library_modules = v.library_modules,
+            settings_values = migrated.settings_values,
+            settings_problems = migrated.settings_problems
+            FROM script_plugin_versions v,
+                unnest($3::BIGINT[], $4::JSONB[], $5::JSONB[])
+                    AS migrated(id, settings_values, settings_problems)
+            WHERE v.plugin_id = $1 AND v.version_number = $2
+                AND guild_scripts.id = migrated.id
+            RETURNING guild_scripts.id, guild_scripts.guild_id;",
+            plugin_id as i64,
+            version_number as i32,
+            &script_ids,
+            &settings_values,
+            &settings_problems,
+        )
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let script_ids = res.iter().map(|v| v.id).collect::<Vec<_>>();
+        Self::record_script_revisions(conn, &script_ids, None).await?;
+
+        Ok(res
+            .into_iter()
+            .map(|v| Id::new(v.guild_id as u64))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PluginRolloutStats {
+    /// Guilds currently running the version
+    pub running_guilds: u64,
+    /// Guilds that logged errors from the version during the rollout
+    pub erroring_guilds: u64,
+}
@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use common::plugin::LibraryModule;
+use runtime_models::internal::script::SettingsOptionValue;
+use serde::Serialize;
+use sqlx::PgConnection;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::{
+    config::{ConfigStoreError, ConfigStoreResult, PremiumSlotTier},
+    Db,
+};
+
+impl Db {
+    /// Returns the revisions of the script, newest first
+    pub async fn get_script_revisions(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+    ) -> ConfigStoreResult<Vec<ScriptRevisionMeta>> {
+        let res = sqlx::query_as!(
+            DbScriptRevisionMeta,
+            "SELECT id, script_id, created_at, edited_by, plugin_version_number
+            FROM guild_script_revisions
+            WHERE guild_id = $1 AND script_id = $2
+            ORDER BY id DESC;",
+            guild_id.get() as i64,
+            script_id as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res.into_iter().map(Into::into).collect())
+    }
+
+    pub async fn get_script_revision(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        revision_id: u64,
+    ) -> ConfigStoreResult<ScriptRevision> {
+        let res = sqlx::query_as!(
+            DbScriptRevision,
+            "SELECT id, script_id, created_at, edited_by, plugin_version_number, source, \
+             library_modules, settings_values
+            FROM guild_script_revisions
+            WHERE guild_id = $1 AND script_id = $2 AND id = $3;",
+            guild_id.get() as i64,
+            script_id as i64,
+            revision_id as i64,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ConfigStoreError::ScriptRevisionNotFound(revision_id))?;
+
+        Ok(res.into())
+    }
+
+    /// Snapshots the current state of the scripts as new revisions, dropping the oldest ones past
+    /// the limit of the guild's premium tier
+    pub(crate) async fn record_script_revisions(
+        conn: &mut PgConnection,
+        script_ids: &[i64],
+        edited_by: Option<Id<UserMarker>>,
+    ) -> ConfigStoreResult<()> {
+        if script_ids.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            "INSERT INTO guild_script_revisions (guild_id, script_id, edited_by, source, \
+             library_modules, settings_values, plugin_version_number)
+            SELECT guild_id, id, $2, original_source, library_modules, \
+             COALESCE(settings_values, '[]'), plugin_version_number
+            FROM guild_scripts WHERE id = ANY($1);",
+            script_ids,
+            edited_by.map(|v| v.get() as i64),
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM guild_script_revisions WHERE id IN (
+                SELECT id FROM (
+                    SELECT r.id,
+                    row_number() OVER (PARTITION BY r.script_id ORDER BY r.id DESC) AS position,
+                    (SELECT max(tier) FROM premium_slots WHERE attached_guild_id = r.guild_id) AS tier
+                    FROM guild_script_revisions r
+                    WHERE r.script_id = ANY($1)
+                ) ranked
+                WHERE ranked.position > CASE ranked.tier
+                    WHEN $3::INT THEN $4::BIGINT
+                    WHEN $5::INT THEN $6::BIGINT
+                    ELSE $2::BIGINT
+                END
+            );",
+            script_ids,
+            script_revision_limit(None) as i64,
+            crate::config::tier_to_int(PremiumSlotTier::Lite),
+            script_revision_limit(Some(PremiumSlotTier::Lite)) as i64,
+            crate::config::tier_to_int(PremiumSlotTier::Premium),
+            script_revision_limit(Some(PremiumSlotTier::Premium)) as i64,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Max number of revisions kept per script
+pub fn script_revision_limit(tier: Option<PremiumSlotTier>) -> u32 {
+    match tier {
+        None => 20,
+        Some(PremiumSlotTier::Lite) => 100,
+        Some(PremiumSlotTier::Premium) => 250,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptRevisionMeta {
+    pub id: u64,
+    pub script_id: u64,
+    pub created_at: DateTime<Utc>,
+    /// The user that made the change, none if it was made by botloader itself (e.g plugin auto updates)
+    pub edited_by: Option<Id<UserMarker>>,
+    pub plugin_version_number: Option<u32>,
+}
+
+/// Snapshot of a guild script's source and settings after a change to them
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptRevision {
+    #[serde(flatten)]
+    pub meta: ScriptRevisionMeta,
+    pub source: String,
+    pub library_modules: Vec<LibraryModule>,
+    pub settings_values: Vec<SettingsOptionValue>,
+}
+
+struct DbScriptRevisionMeta {
+    id: i64,
+    script_id: i64,
+    created_at: DateTime<Utc>,
+    edited_by: Option<i64>,
+    plugin_version_number: Option<i32>,
+}
+
+impl From<DbScriptRevisionMeta> for ScriptRevisionMeta {
+    fn from(value: DbScriptRevisionMeta) -> Self {
+        Self {
+            id: value.id as u64,
+            script_id: value.script_id as u64,
+            created_at: value.created_at,
+            edited_by: value.edited_by.map(|v| Id::new(v as u64)),
+            plugin_version_number: value.plugin_version_number.map(|v| v as u32),
+        }
+    }
+}
+
+struct DbScriptRevision {
+    id: i64,
+    script_id: i64,
+    created_at: DateTime<Utc>,
+    edited_by: Option<i64>,
+    plugin_version_number: Option<i32>,
+    source: String,
+    library_modules: serde_json::Value,
+    settings_values: serde_json::Value,
+}
+
+impl From<DbScriptRevision> for ScriptRevision {
+    fn from(value: DbScriptRevision) -> Self {
+        Self {
+            meta: ScriptRevisionMeta {
+                id: value.id as u64,
+                script_id: value.script_id as u64,
+                created_at: value.created_at,
+                edited_by: value.edited_by.map(|v| Id::new(v as u64)),
+                plugin_version_number: value.plugin_version_number.map(|v| v as u32),
+            },
+            source: value.source,
+            library_modules: serde_json::from_value(value.library_modules).unwrap_or_default(),
+            settings_values: serde_json::from_value(value.settings_values).unwrap_or_default(),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use common::plugin::{ReleaseChannel, SettingsMigration};
+use runtime_models::internal::script::SettingsOptionValue;
+use sqlx::PgConnection;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::{
+    config::{ConfigStoreResult, SettingsProblem},
+    Db,
+};
+
+/// The settings migrations declared by a plugin version
+pub(crate) struct VersionSettingsMigrations {
+    pub(crate) version_number: u32,
+    pub(crate) channel: ReleaseChannel,
+    pub(crate) migrations: Vec<SettingsMigration>,
+}
+
+/// The settings of a guild's install after being migrated to a new version
+pub(crate) struct MigratedSettings {
+    pub(crate) values: serde_json::Value,
+    pub(crate) problems: serde_json::Value,
+}
+
+impl Db {
+    /// Returns the settings migrations of the versions after `after_version` up to and including
+    /// `up_to_version`, oldest first
+    pub(crate) async fn get_plugin_settings_migrations(
+        conn: &mut PgConnection,
+        plugin_id: u64,
+        after_version: u32,
+        up_to_version: u32,
+    ) -> ConfigStoreResult<Vec<VersionSettingsMigrations>> {
+        let res = sqlx::query!(
+            "SELECT version_number, channel, settings_migrations FROM script_plugin_versions
+            WHERE plugin_id = $1 AND version_number > $2 AND version_number <= $3
+                AND settings_migrations != '[]'
+            ORDER BY version_number ASC;",
+            plugin_id as i64,
+            after_version as i32,
+            up_to_version as i32,
+        )
+        .fetch_all(conn)
+        .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|v| VersionSettingsMigrations {
+                version_number: v.version_number as u32,
+                channel: ReleaseChannel::parse(&v.channel).unwrap_or_default(),
+                migrations: serde_json::from_value(v.settings_migrations).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn set_script_settings_problems(
+        &self,
+        guild_id: Id<GuildMarker>,
+        script_id: u64,
+        problems: &[SettingsProblem],
+    ) -> ConfigStoreResult<()> {
+        sqlx::query!(
+            "UPDATE guild_scripts SET settings_problems = $3 WHERE guild_id = $1 AND id = $2;",
+            guild_id.get() as i64,
+            script_id as i64,
+            serde_json::to_value(problems).unwrap(),
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Migrates the settings of a guild's install going from `from_version` to `to_version`, the
+/// problems that come up are added to the ones it already has
+///
+/// Only the migrations of the versions on the channel the guild is on are applied, along with the
+/// ones of the version it's going to
+pub(crate) fn migrate_install_settings(
+    versions: &[VersionSettingsMigrations],
+    from_version: u32,
+    to_version: u32,
+    channel: ReleaseChannel,
+    values: serde_json::Value,
+    problems: serde_json::Value,
+) -> MigratedSettings {
+    let migrations = versions
+        .iter()
+        .filter(|v| v.version_number > from_version && v.version_number <= to_version)
+        .filter(|v| {
+            v.channel == ReleaseChannel::Stable
+                || v.channel == channel
+                || v.version_number == to_version
+        })
+        .flat_map(|v| &v.migrations)
+        .collect::<Vec<_>>();
+
+    if migrations.is_empty() {
+        return MigratedSettings { values, problems };
+    }
+
+    let values: Vec<SettingsOptionValue> = serde_json::from_value(values).unwrap_or_default();
+    let mut problems: Vec<SettingsProblem> = serde_json::from_value(problems).unwrap_or_default();
+
+    let (values, new_problems) = apply_settings_migrations(values, migrations);
+    for problem in new_problems {
+        if !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    }
+
+    MigratedSettings {
+        values: serde_json::to_value(values).unwrap(),
+        problems: serde_json::to_value(problems).unwrap(),
+    }
+}
+
+/// Applies the migrations to the settings values in order, values that can't be converted are
+/// dropped so that the script falls back to the default, and reported as problems
+pub fn apply_settings_migrations<'a>(
+    mut values: Vec<SettingsOptionValue>,
+    migrations: impl IntoIterator<Item = &'a SettingsMigration>,
+) -> (Vec<SettingsOptionValue>, Vec<SettingsProblem>) {
+    let mut problems = Vec::new();
+
+    for migration in migrations {
+        match migration {
+            SettingsMigration::Rename { from, to } => {
+                if values.iter().any(|v| &v.name == to) {
+                    // already migrated
+                    values.retain(|v| &v.name != from);
+                } else if let Some(value) = values.iter_mut().find(|v| &v.name == from) {
+                    value.name = to.clone();
+                }
+            }
+            SettingsMigration::FillDefault { name, value } => {
+                if !values.iter().any(|v| &v.name == name) {
+                    values.push(SettingsOptionValue {
+                        name: name.clone(),
+                        value: value.clone(),
+                    });
+                }
+            }
+            SettingsMigration::Convert { name, to } => {
+                let Some(index) = values.iter().position(|v| &v.name == name) else {
+                    continue;
+                };
+
+                match to.convert(&values[index].value) {
+                    Ok(converted) => values[index].value = converted,
+                    Err(err) => {
+                        values.remove(index);
+                        problems.push(SettingsProblem {
+                            option: name.clone(),
+                            message: format!("{err}, the default value is used instead"),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    (values, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use common::plugin::SettingsValueConversion;
+    use serde_json::json;
+
+    use super::*;
+
+    fn value(name: &str, value: serde_json::Value) -> SettingsOptionValue {
+        SettingsOptionValue {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    fn as_json(values: &[SettingsOptionValue]) -> serde_json::Value {
+        serde_json::to_value(values).unwrap()
+    }
+
+    fn rename(from: &str, to: &str) -> SettingsMigration {
+        SettingsMigration::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    fn convert(name: &str, to: SettingsValueConversion) -> SettingsMigration {
+        SettingsMigration::Convert {
+            name: name.to_string(),
+            to,
+        }
+    }
+
+    #[test]
+    fn rename_moves_the_value() {
+        let (values, problems) =
+            apply_settings_migrations(vec![value("old", json!(1))], &[rename("old", "new")]);
+
+        assert_eq!(as_json(&values), json!([{"name": "new", "value": 1}]));
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn rename_keeps_existing_value() {
+        let (values, _) = apply_settings_migrations(
+            vec![value("old", json!(1)), value("new", json!(2))],
+            &[rename("old", "new")],
+        );
+
+        assert_eq!(as_json(&values), json!([{"name": "new", "value": 2}]));
+    }
+
+    #[test]
+    fn fill_default_only_fills_missing_values() {
+        let migrations = [
+            SettingsMigration::FillDefault {
+                name: "set".to_string(),
+                value: json!("default"),
+            },
+            SettingsMigration::FillDefault {
+                name: "missing".to_string(),
+                value: json!("default"),
+            },
+        ];
+        let (values, problems) =
+            apply_settings_migrations(vec![value("set", json!("custom"))], &migrations);
+
+        assert_eq!(
+            as_json(&values),
+            json!([
+                {"name": "set", "value": "custom"},
+                {"name": "missing", "value": "default"},
+            ])
+        );
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn convert_drops_values_that_dont_fit() {
+        let (values, problems) = apply_settings_migrations(
+            vec![value("count", json!("12")), value("ratio", json!("abc"))],
+            &[
+                convert("count", SettingsValueConversion::Integer),
+                convert("ratio", SettingsValueConversion::Float),
+            ],
+        );
+
+        assert_eq!(as_json(&values), json!([{"name": "count", "value": 12}]));
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].option, "ratio");
+    }
+
+    #[test]
+    fn migrates_several_versions_in_order() {
+        let versions = [
+            VersionSettingsMigrations {
+                version_number: 2,
+                channel: ReleaseChannel::Stable,
+                migrations: vec![rename("channel", "channels")],
+            },
+            VersionSettingsMigrations {
+                version_number: 3,
+                channel: ReleaseChannel::Beta,
+                migrations: vec![SettingsMigration::FillDefault {
+                    name: "beta_only".to_string(),
+                    value: json!(true),
+                }],
+            },
+            VersionSettingsMigrations {
+                version_number: 4,
+                channel: ReleaseChannel::Stable,
+                migrations: vec![convert("channels", SettingsValueConversion::List)],
+            },
+        ];
+
+        let migrated = migrate_install_settings(
+            &versions,
+            1,
+            4,
+            ReleaseChannel::Stable,
+            json!([{"name": "channel", "value": "123"}]),
+            json!([]),
+        );
+
+        // the rename has to happen before the conversion for it to apply, and the beta version
+        // is skipped by guilds on the stable channel
+        assert_eq!(
+            migrated.values,
+            json!([{"name": "channels", "value": ["123"]}])
+        );
+        assert_eq!(migrated.problems, json!([]));
+
+        // guilds already past a version don't get its migrations again
+        let migrated = migrate_install_settings(
+            &versions,
+            2,
+            4,
+            ReleaseChannel::Beta,
+            json!([{"name": "channels", "value": "123"}]),
+            json!([]),
+        );
+        assert_eq!(
+            migrated.values,
+            json!([
+                {"name": "channels", "value": ["123"]},
+                {"name": "beta_only", "value": true},
+            ])
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use twilight_model::id::{marker::GuildMarker, Id};
+
+use crate::Db;
+
+impl Db {
+    pub async fn create_guild_suspension(
+        &self,
+        guild_id: Id<GuildMarker>,
+        reason: SuspensionReason,
+        message: Option<&str>,
+        offense_level: u32,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> SuspensionStoreResult<GuildSuspension> {
+        let res = sqlx::query_as!(
+            DbGuildSuspension,
+            "INSERT INTO guild_suspensions (guild_id, reason, message, offense_level, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, guild_id, reason, message, offense_level, created_at, expires_at, \
+             lifted_at;",
+            guild_id.get() as i64,
+            reason.as_str(),
+            message,
+            offense_level as i32,
+            expires_at,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        res.try_into()
+    }
+
+    /// Returns the suspensions that have not expired or been lifted yet
+    pub async fn get_active_guild_suspensions(
+        &self,
+    ) -> SuspensionStoreResult<Vec<GuildSuspension>> {
+        let res = sqlx::query_as!(
+            DbGuildSuspension,
+            "SELECT id, guild_id, reason, message, offense_level, created_at, expires_at, lifted_at
+            FROM guild_suspensions
+            WHERE lifted_at IS NULL AND (expires_at IS NULL OR expires_at > now())
+            ORDER BY id ASC;",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        res.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Returns the most recent suspensions of the guild, including the ones no longer active
+    pub async fn get_guild_suspension_history(
+        &self,
+        guild_id: Id<GuildMarker>,
+        limit: u32,
+    ) -> SuspensionStoreResult<Vec<GuildSuspension>> {
+        let res = sqlx::query_as!(
+            DbGuildSuspension,
+            "SELECT id, guild_id, reason, message, offense_level, created_at, expires_at, lifted_at
+            FROM guild_suspensions
+            WHERE guild_id = $1
+            ORDER BY id DESC
+            LIMIT $2;",
+            guild_id.get() as i64,
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        res.into_iter().map(TryInto::try_into).collect()
+    }
+
+    /// Number of automatic suspensions the guild received since the provided time,
+    /// the ones imposed by admins are not counted
+    pub async fn count_recent_guild_offenses(
+        &self,
+        guild_id: Id<GuildMarker>,
+        since: DateTime<Utc>,
+    ) -> SuspensionStoreResult<u32> {
+        let res = sqlx::query!(
+            "SELECT count(*) FROM guild_suspensions
+            WHERE guild_id = $1 AND created_at > $2 AND reason != $3;",
+            guild_id.get() as i64,
+            since,
+            SuspensionReason::Admin.as_str(),
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(res.count.unwrap_or_default() as u32)
+    }
+
+    /// Lifts all the active suspensions of the guild, returning how many there were
+    pub async fn lift_guild_suspensions(
+        &self,
+        guild_id: Id<GuildMarker>,
+    ) -> SuspensionStoreResult<u64> {
+        let res = sqlx::query!(
+            "UPDATE guild_suspensions SET lifted_at = now()
+            WHERE guild_id = $1 AND lifted_at IS NULL AND (expires_at IS NULL OR expires_at > now());",
+            guild_id.get() as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuspensionReason {
+    ExcessCpu,
+    ExcessInvalidDiscordRequests,
+    /// Imposed manually by a botloader admin
+    Admin,
+}
+
+impl SuspensionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ExcessCpu => "excess_cpu",
+            Self::ExcessInvalidDiscordRequests => "excess_invalid_discord_requests",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "excess_cpu" => Some(Self::ExcessCpu),
+            "excess_invalid_discord_requests" => Some(Self::ExcessInvalidDiscordRequests),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuildSuspension {
+    pub id: u64,
+    pub guild_id: Id<GuildMarker>,
+    pub reason: SuspensionReason,
+    pub message: Option<String>,
+    pub offense_level: u32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub lifted_at: Option<DateTime<Utc>>,
+}
+
+impl GuildSuspension {
+    pub fn is_active(&self) -> bool {
+        self.lifted_at.is_none() && self.expires_at.map(|v| v > Utc::now()).unwrap_or(true)
+    }
+}
+
+struct DbGuildSuspension {
+    id: i64,
+    guild_id: i64,
+    reason: String,
+    message: Option<String>,
+    offense_level: i32,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    lifted_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<DbGuildSuspension> for GuildSuspension {
+    type Error = SuspensionStoreError;
+
+    fn try_from(v: DbGuildSuspension) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: v.id as u64,
+            guild_id: Id::new(v.guild_id as u64),
+            reason: SuspensionReason::parse(&v.reason)
+                .ok_or(SuspensionStoreError::UnknownReason(v.reason))?,
+            message: v.message,
+            offense_level: v.offense_level as u32,
+            created_at: v.created_at,
+            expires_at: v.expires_at,
+            lifted_at: v.lifted_at,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SuspensionStoreError {
+    #[error(transparent)]
+    Sql(#[from] sqlx::Error),
+
+    #[error("unknown suspension reason: {0}")]
+    UnknownReason(String),
+}
+
+pub type SuspensionStoreResult<T> = Result<T, SuspensionStoreError>;
@@ -4,7 +4,10 @@ use oauth2::{basic::BasicTokenType, EmptyExtraTokenFields, StandardTokenResponse
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use twilight_model::{
-    id::{marker::UserMarker, Id},
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
     user::CurrentUser,
     util::ImageHash,
 };
@@ -13,6 +16,9 @@ use crate::Db;
 
 const USER_API_KEY_LIMIT: i64 = 100;
 
+// last used is only bumped when it's older than this, to avoid a write on every request
+const LAST_USED_UPDATE_INTERVAL: chrono::Duration = chrono::Duration::minutes(1);
+
 pub type OauthToken = StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>;
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -33,10 +39,65 @@ pub enum Error {
     Sql(#[from] sqlx::Error),
 }
 
+/// Operations an api token can be restricted to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenOperation {
+    /// Reading the scripts, settings and other configuration of guilds
+    ReadScripts,
+    /// Changing the scripts, settings and other configuration of guilds
+    WriteScripts,
+    /// Updating the development source of the user's plugins and publishing new versions of them
+    PublishPluginVersions,
+    /// Streaming the logs of guilds
+    ReadLogs,
+    /// Running code in the vms of guilds through evals, these can do anything the scripts can
+    RunEvals,
+    /// Reloading the vms of guilds
+    ReloadVms,
+}
+
+/// Restrictions on what a session can be used for, fields that are not set are not restricted
+///
+/// Only api tokens are restricted, sessions from logging in always have the full power of the user
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ApiTokenScope {
+    pub guild_ids: Option<Vec<Id<GuildMarker>>>,
+    pub operations: Option<Vec<ApiTokenOperation>>,
+}
+
+impl ApiTokenScope {
+    pub fn is_restricted(&self) -> bool {
+        self.guild_ids.is_some() || self.operations.is_some()
+    }
+
+    pub fn allows_guild(&self, guild_id: Id<GuildMarker>) -> bool {
+        self.guild_ids
+            .as_ref()
+            .map(|ids| ids.contains(&guild_id))
+            .unwrap_or(true)
+    }
+
+    pub fn allows_operation(&self, operation: ApiTokenOperation) -> bool {
+        self.operations
+            .as_ref()
+            .map(|ops| ops.contains(&operation))
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CreateApiToken {
+    pub name: Option<String>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub scope: ApiTokenScope,
+}
+
 impl Db {
     async fn get_api_key_count(&self, user_id: Id<UserMarker>) -> Result<i64, Error> {
         let result = sqlx::query!(
-            "SELECT count(*) FROM web_sessions WHERE user_id = $1 AND kind = $2;",
+            "SELECT count(*) FROM web_sessions WHERE user_id = $1 AND kind = $2 AND (expires_at IS \
+             NULL OR expires_at > now());",
             user_id.get() as i64,
             i16::from(SessionType::ApiKey),
         )
@@ -87,15 +148,42 @@ impl Db {
         kind: SessionType,
     ) -> Result<Session, Error> {
         if matches!(kind, SessionType::ApiKey) {
-            let count = self.get_api_key_count(user.id).await?;
-            if count > USER_API_KEY_LIMIT {
-                return Err(Error::ApiKeyLimitReached(
-                    count as u64,
-                    USER_API_KEY_LIMIT as u64,
-                ));
-            }
+            return self.create_api_token(user, CreateApiToken::default()).await;
+        }
+
+        self.insert_session(user, kind, CreateApiToken::default())
+            .await
+    }
+
+    pub async fn create_api_token(
+        &self,
+        user: CurrentUser,
+        create: CreateApiToken,
+    ) -> Result<Session, Error> {
+        sqlx::query!(
+            "DELETE FROM web_sessions WHERE user_id = $1 AND expires_at < now();",
+            user.id.get() as i64,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let count = self.get_api_key_count(user.id).await?;
+        if count > USER_API_KEY_LIMIT {
+            return Err(Error::ApiKeyLimitReached(
+                count as u64,
+                USER_API_KEY_LIMIT as u64,
+            ));
         }
 
+        self.insert_session(user, SessionType::ApiKey, create).await
+    }
+
+    async fn insert_session(
+        &self,
+        user: CurrentUser,
+        kind: SessionType,
+        create: CreateApiToken,
+    ) -> Result<Session, Error> {
         let oauth_token = sqlx::query_as!(
             DbOauthToken,
             "SELECT user_id, discord_bearer_token, discord_refresh_token, discord_token_expires_at
@@ -110,8 +198,10 @@ impl Db {
         let resp = sqlx::query_as!(
             DbSession,
             "INSERT INTO web_sessions (token, kind, user_id, discriminator, username, avatar, \
-             created_at) VALUES ($1, $2, $3, $4, $5, $6, now())
-            RETURNING token, kind, user_id, discriminator, username, avatar, created_at;",
+             created_at, name, expires_at, scope_guild_ids, scope_operations)
+            VALUES ($1, $2, $3, $4, $5, $6, now(), $7, $8, $9, $10)
+            RETURNING token, kind, user_id, discriminator, username, avatar, created_at, name, \
+             expires_at, last_used_at, scope_guild_ids, scope_operations;",
             &token,
             i16::from(kind),
             user.id.get() as i64,
@@ -121,17 +211,22 @@ impl Db {
                 .as_ref()
                 .map(ToString::to_string)
                 .unwrap_or_default(),
+            create.name,
+            create.expires_at,
+            create.scope.guild_ids.map(|ids| ids
+                .into_iter()
+                .map(|id| id.get() as i64)
+                .collect::<Vec<_>>()) as Option<Vec<i64>>,
+            create
+                .scope
+                .operations
+                .map(|ops| ops.into_iter().map(i16::from).collect::<Vec<_>>())
+                as Option<Vec<i16>>,
         )
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(Session {
-            oauth_token: oauth_token.into(),
-            created_at: resp.created_at,
-            token,
-            kind,
-            user,
-        })
+        Ok(resp.into_session(oauth_token.into()))
     }
 
     pub async fn get_oauth_token(
@@ -152,8 +247,9 @@ impl Db {
     pub async fn get_session(&self, token: &str) -> Result<Option<Session>, Error> {
         let session = match sqlx::query_as!(
             DbSession,
-            "SELECT token, kind, user_id, discriminator, username, avatar, created_at FROM \
-             web_sessions WHERE token = $1;",
+            "SELECT token, kind, user_id, discriminator, username, avatar, created_at, name, \
+             expires_at, last_used_at, scope_guild_ids, scope_operations
+            FROM web_sessions WHERE token = $1;",
             token
         )
         .fetch_one(&self.pool)
@@ -173,13 +269,7 @@ impl Db {
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(Some(Session {
-            token: token.to_string(),
-            kind: SessionType::from(session.kind),
-            oauth_token: oauth_token.into(),
-            created_at: session.created_at,
-            user: session.into(),
-        }))
+        Ok(Some(session.into_session(oauth_token.into())))
     }
 
     pub async fn get_all_sessions(&self, user_id: Id<UserMarker>) -> Result<Vec<Session>, Error> {
@@ -195,8 +285,9 @@ impl Db {
 
         let sessions = sqlx::query_as!(
             DbSession,
-            "SELECT token, kind, user_id, discriminator, username, avatar, created_at FROM \
-             web_sessions WHERE user_id = $1",
+            "SELECT token, kind, user_id, discriminator, username, avatar, created_at, name, \
+             expires_at, last_used_at, scope_guild_ids, scope_operations
+            FROM web_sessions WHERE user_id = $1",
             user_id.get() as i64,
         )
         .fetch_all(&self.pool)
@@ -204,16 +295,21 @@ impl Db {
 
         Ok(sessions
             .into_iter()
-            .map(|e| Session {
-                token: e.token.clone(),
-                kind: e.kind.into(),
-                oauth_token: oauth_token.clone(),
-                created_at: e.created_at,
-                user: e.into(),
-            })
+            .map(|e| e.into_session(oauth_token.clone()))
             .collect())
     }
 
+    pub async fn set_session_last_used(&self, token: &str) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE web_sessions SET last_used_at = now() WHERE token = $1",
+            token,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn del_session(&self, token: &str) -> Result<bool, Error> {
         let res = sqlx::query!("DELETE FROM web_sessions WHERE token= $1", token,)
             .execute(&self.pool)
@@ -260,6 +356,38 @@ struct DbSession {
     username: String,
     avatar: String,
     created_at: chrono::DateTime<chrono::Utc>,
+    name: Option<String>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    scope_guild_ids: Option<Vec<i64>>,
+    scope_operations: Option<Vec<i16>>,
+}
+
+impl DbSession {
+    fn into_session(self, oauth_token: DiscordOauthToken) -> Session {
+        let scope = ApiTokenScope {
+            guild_ids: self
+                .scope_guild_ids
+                .as_ref()
+                .map(|ids| ids.iter().map(|id| Id::new(*id as u64)).collect()),
+            operations: self
+                .scope_operations
+                .as_ref()
+                .map(|ops| ops.iter().map(|op| ApiTokenOperation::from(*op)).collect()),
+        };
+
+        Session {
+            token: self.token.clone(),
+            kind: self.kind.into(),
+            created_at: self.created_at,
+            name: self.name.clone(),
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+            scope,
+            oauth_token,
+            user: self.into(),
+        }
+    }
 }
 
 impl From<DbSession> for CurrentUser {
@@ -306,6 +434,33 @@ impl From<i16> for SessionType {
     }
 }
 
+impl From<ApiTokenOperation> for i16 {
+    fn from(op: ApiTokenOperation) -> Self {
+        match op {
+            ApiTokenOperation::ReadScripts => 1,
+            ApiTokenOperation::WriteScripts => 2,
+            ApiTokenOperation::PublishPluginVersions => 3,
+            ApiTokenOperation::ReadLogs => 4,
+            ApiTokenOperation::RunEvals => 5,
+            ApiTokenOperation::ReloadVms => 6,
+        }
+    }
+}
+
+impl From<i16> for ApiTokenOperation {
+    fn from(op: i16) -> Self {
+        match op {
+            1 => ApiTokenOperation::ReadScripts,
+            2 => ApiTokenOperation::WriteScripts,
+            3 => ApiTokenOperation::PublishPluginVersions,
+            4 => ApiTokenOperation::ReadLogs,
+            5 => ApiTokenOperation::RunEvals,
+            6 => ApiTokenOperation::ReloadVms,
+            _ => panic!("unknown variant of apitokenoperation: {op}"),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Session {
     pub oauth_token: DiscordOauthToken,
@@ -313,6 +468,25 @@ pub struct Session {
     pub kind: SessionType,
     pub user: CurrentUser,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub name: Option<String>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub scope: ApiTokenScope,
+}
+
+impl Session {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|v| v <= chrono::Utc::now())
+            .unwrap_or(false)
+    }
+
+    /// Whether last used is outdated enough that it should be updated
+    pub fn should_update_last_used(&self) -> bool {
+        self.last_used_at
+            .map(|v| chrono::Utc::now() - v > LAST_USED_UPDATE_INTERVAL)
+            .unwrap_or(true)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -358,3 +532,108 @@ pub fn gen_token() -> String {
     let random_bytes: Vec<u8> = (0..32).map(|_| thread_rng().gen::<u8>()).collect();
     base64::encode_config(random_bytes, base64::URL_SAFE_NO_PAD)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_token(
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        scope_guild_ids: Option<Vec<i64>>,
+        scope_operations: Option<Vec<i16>>,
+    ) -> Session {
+        let db_session = DbSession {
+            token: gen_token(),
+            kind: SessionType::ApiKey.into(),
+            user_id: 1,
+            discriminator: 0,
+            username: "user".to_string(),
+            avatar: String::new(),
+            created_at: chrono::Utc::now(),
+            name: None,
+            expires_at,
+            last_used_at: None,
+            scope_guild_ids,
+            scope_operations,
+        };
+
+        db_session.into_session(DiscordOauthToken {
+            user_id: Id::new(1),
+            access_token: String::new(),
+            refresh_token: String::new(),
+            token_expires: chrono::Utc::now(),
+        })
+    }
+
+    #[test]
+    fn unrestricted_scope_allows_everything() {
+        let session = api_token(None, None, None);
+
+        assert!(!session.scope.is_restricted());
+        assert!(session.scope.allows_guild(Id::new(1)));
+        assert!(session
+            .scope
+            .allows_operation(ApiTokenOperation::WriteScripts));
+        assert!(session.scope.allows_operation(ApiTokenOperation::RunEvals));
+    }
+
+    #[test]
+    fn scope_allows_only_listed_guilds() {
+        let session = api_token(None, Some(vec![1, 2]), None);
+
+        assert!(session.scope.is_restricted());
+        assert!(session.scope.allows_guild(Id::new(1)));
+        assert!(session.scope.allows_guild(Id::new(2)));
+        assert!(!session.scope.allows_guild(Id::new(3)));
+        assert!(session
+            .scope
+            .allows_operation(ApiTokenOperation::WriteScripts));
+    }
+
+    #[test]
+    fn scope_allows_only_listed_operations() {
+        let session = api_token(
+            None,
+            None,
+            Some(vec![
+                ApiTokenOperation::ReadScripts.into(),
+                ApiTokenOperation::ReadLogs.into(),
+            ]),
+        );
+
+        assert!(session.scope.is_restricted());
+        assert!(session.scope.allows_guild(Id::new(1)));
+        assert!(session
+            .scope
+            .allows_operation(ApiTokenOperation::ReadScripts));
+        assert!(session.scope.allows_operation(ApiTokenOperation::ReadLogs));
+        assert!(!session
+            .scope
+            .allows_operation(ApiTokenOperation::WriteScripts));
+        assert!(!session.scope.allows_operation(ApiTokenOperation::RunEvals));
+        assert!(!session.scope.allows_operation(ApiTokenOperation::ReloadVms));
+    }
+
+    #[test]
+    fn operations_round_trip_through_the_db_representation() {
+        for op in [
+            ApiTokenOperation::ReadScripts,
+            ApiTokenOperation::WriteScripts,
+            ApiTokenOperation::PublishPluginVersions,
+            ApiTokenOperation::ReadLogs,
+            ApiTokenOperation::RunEvals,
+            ApiTokenOperation::ReloadVms,
+        ] {
+            assert_eq!(ApiTokenOperation::from(i16::from(op)), op);
+        }
+    }
+
+    #[test]
+    fn expired_tokens_are_filtered() {
+        let now = chrono::Utc::now();
+
+        assert!(!api_token(None, None, None).is_expired());
+        assert!(!api_token(Some(now + chrono::Duration::minutes(1)), None, None).is_expired());
+        assert!(api_token(Some(now - chrono::Duration::minutes(1)), None, None).is_expired());
+    }
+}
@@ -1,12 +1,16 @@
 use std::{rc::Rc, str::FromStr};
 
+use common::plugin::{LibraryModule, PluginCapabilities, PluginDependency, SettingsMigration};
 use lazy_static::lazy_static;
 use regex::Regex;
 use runtime_models::internal::script::{
     SettingsOption, SettingsOptionDefinition, SettingsOptionList, SettingsOptionType,
     SettingsOptionValue,
 };
-use stores::config::{CreatePlugin, CreateScript, Script, UpdatePluginMeta, UpdateScript};
+use stores::{
+    config::{CreatePlugin, CreateScript, Script, SettingsProblem, UpdatePluginMeta, UpdateScript},
+    web::ApiTokenScope,
+};
 use twilight_model::id::Id;
 
 use crate::{ValidationContext, Validator};
@@ -42,6 +46,13 @@ impl Validator for UpdateScript {
             check_script_source(ctx, "original_source", source);
         }
 
+        if self.is_library == Some(true) && ctx_data.script.plugin_id.is_some() {
+            ctx.push_field_error(
+                "is_library",
+                "plugin scripts can't be turned into library scripts",
+            );
+        }
+
         if let Some(values) = &self.settings_values {
             let definitions = self
                 .settings_definitions
@@ -105,6 +116,45 @@ impl Validator for SettingsOptionValue {
     }
 }
 
+/// Checks the stored settings values of a script against the options it registered, used to point
+/// the guild admins at values that no longer work after the script or plugin was updated
+pub fn check_settings_values(
+    definitions: &[SettingsOptionDefinition],
+    values: &[SettingsOptionValue],
+) -> Vec<SettingsProblem> {
+    let mut problems = Vec::new();
+
+    for value in values {
+        let Some(definition) = definitions.iter().find(|v| v.name() == value.name) else {
+            problems.push(SettingsProblem {
+                option: value.name.clone(),
+                message: "the option no longer exists".to_string(),
+            });
+            continue;
+        };
+
+        let mut ctx = ValidationContext::new();
+        value.validate(
+            &mut ctx,
+            &SettingsValueValidationContext {
+                definition: definition.clone(),
+                guild_data: None,
+            },
+        );
+
+        problems.extend(ctx.errs.into_iter().map(|err| SettingsProblem {
+            option: value.name.clone(),
+            message: if err.field.is_empty() {
+                err.msg
+            } else {
+                format!("{}: {}", err.field, err.msg)
+            },
+        }));
+    }
+
+    problems
+}
+
 pub(crate) fn validate_settings_option_value_option(
     ctx: &mut ValidationContext,
     value: &serde_json::Value,
@@ -598,6 +648,32 @@ impl Validator for UpdatePluginMeta {
         if let Some(long_description) = &self.long_description {
             check_plugin_long_description(ctx, long_description);
         }
+
+        if let Some(tags) = &self.tags {
+            check_plugin_tags(ctx, tags);
+        }
+    }
+}
+
+fn check_plugin_tags(ctx: &mut ValidationContext, tags: &[String]) {
+    if tags.len() > 10 {
+        ctx.push_field_error("tags", "a plugin can have max 10 tags".to_string());
+    }
+
+    for (i, tag) in tags.iter().enumerate() {
+        if tag.is_empty() || tag.chars().count() > 24 {
+            ctx.push_field_error("tags", "tags have to be between 1 and 24 characters long");
+        } else if !tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            ctx.push_field_error(
+                "tags",
+                "tags can only contain lowercase letters, numbers and dashes",
+            );
+        } else if tags[..i].contains(tag) {
+            ctx.push_field_error("tags", format!("duplicate tag: {tag}"));
+        }
     }
 }
 
@@ -660,3 +736,309 @@ pub fn check_script_source(ctx: &mut ValidationContext, field_name: &str, source
         ctx.push_field_error(field_name, "source can be max 100KiB".to_string());
     }
 }
+
+const MAX_LIBRARY_MODULES: usize = 25;
+
+/// Library modules live next to their script, so they can't share its name
+pub fn check_library_modules(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    script_name: &str,
+    modules: &[LibraryModule],
+) {
+    if modules.len() > MAX_LIBRARY_MODULES {
+        ctx.push_field_error(
+            field_name,
+            format!("can have max {MAX_LIBRARY_MODULES} library modules"),
+        );
+    }
+
+    ctx.push_field(field_name);
+    for (i, module) in modules.iter().enumerate() {
+        ctx.push_index(i);
+
+        check_script_name(ctx, &module.name);
+        if modules[..i].iter().any(|v| v.name == module.name) {
+            ctx.push_field_error("name", "duplicate module name");
+        }
+        if module.name == script_name {
+            ctx.push_field_error("name", "module can't have the same name as the script");
+        }
+
+        check_script_source(ctx, "source", &module.source);
+
+        ctx.pop_field();
+    }
+    ctx.pop_field();
+}
+
+const MAX_VENDORED_MODULE_SIZE: usize = 512 * 1024;
+
+pub fn check_vendored_module_name(ctx: &mut ValidationContext, field_name: &str, name: &str) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^[a-z0-9_\-.]{1,64}$"#).unwrap();
+    }
+
+    if !RE.is_match(name) {
+        ctx.push_field_error(
+            field_name,
+            "name has to be 1-64 characters long and can only contain 'a-z', '0-9', '.', '-' and \
+             '_'",
+        );
+    }
+}
+
+pub fn check_vendored_module_source(ctx: &mut ValidationContext, field_name: &str, source: &str) {
+    if source.len() > MAX_VENDORED_MODULE_SIZE {
+        ctx.push_field_error(field_name, "bundle can be max 512KiB");
+    }
+
+    // imports of urls, dynamic ones included, are rejected by the vm's module loader
+}
+
+pub fn check_log_webhook_url(ctx: &mut ValidationContext, field_name: &str, url: &str) {
+    if url.len() > 1000 {
+        ctx.push_field_error(
+            field_name,
+            "url can be max 1000 characters long".to_string(),
+        );
+        return;
+    }
+
+    let parsed = match url::Url::parse(url) {
+        Ok(v) => v,
+        Err(err) => {
+            ctx.push_field_error(field_name, format!("invalid url: {err}"));
+            return;
+        }
+    };
+
+    if parsed.scheme() != "https" {
+        ctx.push_field_error(field_name, "url has to use https".to_string());
+    }
+
+    // don't let guilds point the webhook at our internal network, the addresses the domain
+    // resolves to are checked by the guild logger when sending
+    match parsed.host() {
+        Some(url::Host::Domain(domain)) if domain != "localhost" => {}
+        _ => {
+            ctx.push_field_error(field_name, "url has to have a domain name as the host");
+        }
+    }
+}
+
+pub fn check_inbound_webhook_name(ctx: &mut ValidationContext, field_name: &str, name: &str) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^[a-z0-9_\-]{1,32}$"#).unwrap();
+    }
+
+    if !RE.is_match(name) {
+        ctx.push_field_error(
+            field_name,
+            "name has to be 1-32 characters long and can only contain 'a-z', '0-9', '-' and '_'",
+        );
+    }
+}
+
+pub fn check_api_token_name(ctx: &mut ValidationContext, field_name: &str, name: &str) {
+    if name.trim().is_empty() || name.chars().count() > 64 {
+        ctx.push_field_error(
+            field_name,
+            "name has to be between 1 and 64 characters long",
+        );
+    }
+}
+
+pub fn check_api_token_expiry(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) {
+    if expires_at <= chrono::Utc::now() {
+        ctx.push_field_error(field_name, "expiry has to be in the future");
+    }
+}
+
+const MAX_API_TOKEN_SCOPE_GUILDS: usize = 100;
+
+pub fn check_api_token_scope(ctx: &mut ValidationContext, field_name: &str, scope: &ApiTokenScope) {
+    ctx.push_field(field_name);
+
+    if let Some(guild_ids) = &scope.guild_ids {
+        if guild_ids.is_empty() || guild_ids.len() > MAX_API_TOKEN_SCOPE_GUILDS {
+            ctx.push_field_error(
+                "guild_ids",
+                format!(
+                    "has to have between 1 and {MAX_API_TOKEN_SCOPE_GUILDS} servers, leave it out \
+                     to allow all servers"
+                ),
+            );
+        }
+    }
+
+    if let Some(operations) = &scope.operations {
+        if operations.is_empty() {
+            ctx.push_field_error(
+                "operations",
+                "has to have at least 1 operation, leave it out to allow all operations",
+            );
+        }
+    }
+
+    ctx.pop_field();
+}
+
+// admin imposed suspensions longer than this should be left indefinite and lifted manually
+const MAX_SUSPENSION_DURATION_SECS: u64 = 60 * 60 * 24 * 365;
+
+pub fn check_suspension_duration(ctx: &mut ValidationContext, field_name: &str, secs: u64) {
+    if secs == 0 || secs > MAX_SUSPENSION_DURATION_SECS {
+        ctx.push_field_error(
+            field_name,
+            "duration has to be between 1 second and 365 days",
+        );
+    }
+}
+
+pub fn check_plugin_changelog(ctx: &mut ValidationContext, field_name: &str, changelog: &str) {
+    if changelog.chars().count() > 4000 {
+        ctx.push_field_error(field_name, "changelog can be max 4000 characters long");
+    }
+}
+
+pub fn check_plugin_rollout_percentage(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    percentage: u8,
+) {
+    if !(1..=100).contains(&percentage) {
+        ctx.push_field_error(field_name, "rollout percentage has to be between 1 and 100");
+    }
+}
+
+pub fn check_plugin_capabilities(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    capabilities: &PluginCapabilities,
+) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"^(\*\.)?([a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?\.)*[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?$"#
+        )
+        .unwrap();
+    }
+
+    if capabilities.http_hosts.len() > 25 {
+        ctx.push_field_error(field_name, "a plugin can list max 25 http hosts");
+    }
+
+    for (i, host) in capabilities.http_hosts.iter().enumerate() {
+        if host.len() > 253 || (host != "*" && !RE.is_match(host)) {
+            ctx.push_field_error(
+                field_name,
+                format!(
+                    "invalid http host: {host}, has to be a lowercase host name, optionally \
+                     prefixed by *. to include subdomains, or * for every host"
+                ),
+            );
+        } else if capabilities.http_hosts[..i].contains(host) {
+            ctx.push_field_error(field_name, format!("duplicate http host: {host}"));
+        }
+    }
+}
+
+pub fn check_plugin_dependencies(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    dependencies: &[PluginDependency],
+) {
+    if dependencies.len() > 10 {
+        ctx.push_field_error(field_name, "a plugin can depend on max 10 other plugins");
+    }
+
+    for (i, dependency) in dependencies.iter().enumerate() {
+        if dependency
+            .max_version
+            .is_some_and(|max| max < dependency.min_version)
+        {
+            ctx.push_field_error(
+                field_name,
+                format!(
+                    "max version of plugin {} is lower than the min version",
+                    dependency.plugin_id
+                ),
+            );
+        } else if dependencies[..i]
+            .iter()
+            .any(|v| v.plugin_id == dependency.plugin_id)
+        {
+            ctx.push_field_error(
+                field_name,
+                format!("duplicate dependency on plugin {}", dependency.plugin_id),
+            );
+        }
+    }
+}
+
+pub fn check_plugin_settings_migrations(
+    ctx: &mut ValidationContext,
+    field_name: &str,
+    migrations: &[SettingsMigration],
+) {
+    if migrations.len() > 50 {
+        ctx.push_field_error(field_name, "a version can have max 50 settings migrations");
+    }
+
+    for migration in migrations {
+        let names = match migration {
+            SettingsMigration::Rename { from, to } => {
+                if from == to {
+                    ctx.push_field_error(
+                        field_name,
+                        format!("option {from} is renamed to the same name"),
+                    );
+                }
+
+                vec![from, to]
+            }
+            SettingsMigration::FillDefault { name, value } => {
+                if value.to_string().len() > 10_000 {
+                    ctx.push_field_error(
+                        field_name,
+                        format!("default value of {name} can be max 10000 bytes"),
+                    );
+                }
+
+                vec![name]
+            }
+            SettingsMigration::Convert { name, .. } => vec![name],
+        };
+
+        for name in names {
+            if name.is_empty() || name.chars().count() > 42 {
+                ctx.push_field_error(
+                    field_name,
+                    "option names have to be between 1 and 42 characters long",
+                );
+            }
+        }
+    }
+}
+
+pub fn check_plugin_review_rating(ctx: &mut ValidationContext, field_name: &str, rating: u8) {
+    if !(1..=5).contains(&rating) {
+        ctx.push_field_error(field_name, "rating has to be between 1 and 5");
+    }
+}
+
+pub fn check_plugin_review_text(ctx: &mut ValidationContext, field_name: &str, text: &str) {
+    if text.chars().count() > 1000 {
+        ctx.push_field_error(field_name, "can be max 1000 characters long");
+    }
+}
+
+pub fn check_suspension_message(ctx: &mut ValidationContext, field_name: &str, message: &str) {
+    if message.chars().count() > 1000 {
+        ctx.push_field_error(field_name, "message can be max 1000 characters long");
+    }
+}
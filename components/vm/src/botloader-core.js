@@ -3,6 +3,7 @@
 
     $window.BotloaderCore = {
         dispatchEvent: () => {},
+        unloadScript: () => false,
         dispatchWrapper: async (evt) => {
             $window.BotloaderCore.dispatchEvent(evt);
         },
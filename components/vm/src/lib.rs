@@ -1,6 +1,8 @@
 use std::{cell::RefCell, rc::Rc};
 
-use deno_core::{v8_set_flags, JsRuntime, SourceMapGetter};
+use common::plugin::LibraryModule;
+use deno_core::{error::JsStackFrame, v8_set_flags, JsRuntime, SourceMapGetter};
+use guild_logger::entry::StackFrame;
 use stores::config::Script;
 use tscompiler::CompiledItem;
 use url::Url;
@@ -79,14 +81,24 @@ pub struct ScriptState {
     pub url: url::Url,
     pub state: ScriptLoadState,
     pub compiled: Option<CompiledItem>,
+    pub library_modules: Vec<LibraryModuleState>,
 }
 
 impl ScriptState {
     pub fn can_run(&self) -> bool {
-        matches!(self.state, ScriptLoadState::Unloaded) && self.compiled.is_some()
+        !self.script.is_library
+            && matches!(self.state, ScriptLoadState::Unloaded)
+            && self.compiled.is_some()
     }
 }
 
+/// A compiled library module bundled with a plugin script
+#[derive(Clone)]
+pub struct LibraryModuleState {
+    pub url: url::Url,
+    pub compiled: CompiledItem,
+}
+
 #[derive(Clone)]
 pub enum ScriptLoadState {
     Unloaded,
@@ -100,12 +112,16 @@ pub type ScriptsStateStoreHandle = Rc<RefCell<ScriptsStateStore>>;
 #[derive(Clone)]
 pub struct ScriptsStateStore {
     pub scripts: Vec<ScriptState>,
+
+    // bumped every time a script is replaced in place, gives the new module a url of its own
+    reload_generation: u64,
 }
 
 impl ScriptsStateStore {
     pub fn new() -> Self {
         Self {
             scripts: Vec::new(),
+            reload_generation: 0,
         }
     }
 
@@ -118,18 +134,27 @@ impl ScriptsStateStore {
     }
 
     pub fn compile_add_script(&mut self, script: Script) -> Result<ScriptState, String> {
-        let prefixed_source = prepend_script_source_header(&script.original_source, Some(&script));
-
-        match tscompiler::compile_typescript(
-            &prefixed_source,
-            script_url(&script, "ts").to_string(),
-        ) {
-            Ok(compiled) => {
+        // library scripts are plain modules, they don't get a script instance of their own
+        let source = if script.is_library {
+            script.original_source.clone()
+        } else {
+            prepend_script_source_header(&script.original_source, Some(&script))
+        };
+
+        let compiled =
+            tscompiler::compile_typescript(&source, script_url(&script, "ts").to_string())
+                .and_then(|compiled| {
+                    compile_library_modules(&script).map(|modules| (compiled, modules))
+                });
+
+        match compiled {
+            Ok((compiled, library_modules)) => {
                 let item = ScriptState {
                     compiled: Some(compiled),
                     url: script_url(&script, "js"),
                     script,
                     state: ScriptLoadState::Unloaded,
+                    library_modules,
                 };
 
                 self.scripts.push(item.clone());
@@ -142,6 +167,7 @@ impl ScriptsStateStore {
                     url: script_url(&script, "js"),
                     script,
                     state: ScriptLoadState::FailedCompilation,
+                    library_modules: Vec::new(),
                 };
 
                 self.scripts.push(item.clone());
@@ -151,6 +177,50 @@ impl ScriptsStateStore {
         }
     }
 
+    /// Compiles a new version of a already added script, replacing it if the compilation succeeded
+    ///
+    /// The new version gets a url of its own as the module of the previous version is still
+    /// registered in the isolate.
+    pub fn compile_replace_script(&mut self, script: Script) -> Result<ScriptState, String> {
+        let source = prepend_script_source_header(&script.original_source, Some(&script));
+
+        let (compiled, library_modules) =
+            tscompiler::compile_typescript(&source, script_url(&script, "ts").to_string())
+                .and_then(|compiled| {
+                    compile_library_modules(&script).map(|modules| (compiled, modules))
+                })?;
+
+        self.reload_generation += 1;
+        let mut url = script_url(&script, "js");
+        url.set_query(Some(&format!("reload={}", self.reload_generation)));
+
+        let item = ScriptState {
+            compiled: Some(compiled),
+            url,
+            script,
+            state: ScriptLoadState::Unloaded,
+            library_modules,
+        };
+
+        match self.get_script_mut(item.script.id) {
+            Some(current) => *current = item.clone(),
+            None => self.scripts.push(item.clone()),
+        }
+
+        Ok(item)
+    }
+
+    /// Returns the url of the currently loaded version of the script module,
+    /// imports of scripts that have been replaced in place need to be pointed at the new module
+    pub fn current_script_url(&self, url: &Url) -> Option<Url> {
+        self.scripts.iter().find_map(|v| {
+            let mut base = v.url.clone();
+            base.set_query(None);
+
+            (v.url.query().is_some() && &base == url).then(|| v.url.clone())
+        })
+    }
+
     pub fn set_state(&mut self, script_id: u64, new_state: ScriptLoadState) {
         if let Some(current) = self.get_script_mut(script_id) {
             current.state = new_state;
@@ -164,6 +234,69 @@ impl ScriptsStateStore {
     pub fn get_script_mut(&mut self, script_id: u64) -> Option<&mut ScriptState> {
         self.scripts.iter_mut().find(|v| v.script.id == script_id)
     }
+
+    /// Maps a stack frame from a js error back to the original typescript source
+    ///
+    /// Frames already pointing at the typescript source (deno applies the source map on some paths)
+    /// are left as is
+    pub fn map_stack_frame(&self, frame: &JsStackFrame) -> StackFrame {
+        let function_name = frame.function_name.clone().filter(|v| !v.is_empty());
+
+        let (Some(file_name), Some(line), Some(col)) =
+            (&frame.file_name, frame.line_number, frame.column_number)
+        else {
+            return StackFrame {
+                filename: frame.file_name.clone().unwrap_or_default(),
+                line_col: None,
+                function_name,
+            };
+        };
+
+        if let Some(compiled) = self.find_compiled(file_name) {
+            // js stack frames are 1 based while source maps are 0 based
+            if let Some(token) = compiled.source_map.lookup_token(
+                (line as u32).saturating_sub(1),
+                (col as u32).saturating_sub(1),
+            ) {
+                return StackFrame {
+                    filename: token.get_source().unwrap_or(file_name).to_string(),
+                    line_col: Some((token.get_src_line() + 1, token.get_src_col() + 1)),
+                    function_name,
+                };
+            }
+        }
+
+        StackFrame {
+            filename: file_name.clone(),
+            line_col: Some((line as u32, col as u32)),
+            function_name,
+        }
+    }
+
+    /// Returns true if the provided file name belongs to one of the loaded scripts, either the compiled
+    /// js or the original typescript source
+    pub fn is_script_file(&self, file_name: &str) -> bool {
+        self.scripts.iter().any(|v| {
+            is_module_file(&v.url, file_name)
+                || v.library_modules
+                    .iter()
+                    .any(|m| is_module_file(&m.url, file_name))
+        })
+    }
+
+    /// Finds the compiled output of a script or library module by its js url
+    pub fn find_compiled(&self, url: &str) -> Option<&CompiledItem> {
+        self.scripts.iter().find_map(|v| {
+            if v.url.as_str() == url {
+                return v.compiled.as_ref();
+            }
+
+            v.library_modules
+                .iter()
+                .find(|m| m.url.as_str() == url)
+                .map(|m| &m.compiled)
+        })
+    }
 }
 
 impl Default for ScriptsStateStore {
@@ -177,18 +310,9 @@ pub(crate) struct ScriptStateStoreWrapper(pub(crate) ScriptsStateStoreHandle);
 impl SourceMapGetter for ScriptStateStoreWrapper {
     fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
         let state = self.0.borrow();
-        if let Some(script_load) = state
-            .scripts
-            .iter()
-            .find(|v| v.url.as_str() == file_name)
-            .cloned()
-        {
-            if let Some(compiled) = script_load.compiled {
-                return Some(compiled.source_map_raw.as_bytes().into());
-            }
-        }
-
-        None
+        state
+            .find_compiled(file_name)
+            .map(|compiled| compiled.source_map_raw.as_bytes().into())
     }
 
     fn get_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
@@ -196,6 +320,47 @@ impl SourceMapGetter for ScriptStateStoreWrapper {
     }
 }
 
+/// Checks if the file name is either the compiled js url or the typescript source of a module
+fn is_module_file(js_url: &Url, file_name: &str) -> bool {
+    let js_url = js_url.as_str();
+
+    // scripts replaced in place have a query identifying the version
+    let base_url = js_url
+        .split_once('?')
+        .map(|(base, _)| base)
+        .unwrap_or(js_url);
+    js_url == file_name
+        || base_url
+            .strip_suffix(".js")
+            .is_some_and(|base| file_name.strip_suffix(".ts") == Some(base))
+}
+
+fn compile_library_modules(script: &Script) -> Result<Vec<LibraryModuleState>, String> {
+    script
+        .library_modules
+        .iter()
+        .map(|module| {
+            let compiled = tscompiler::compile_typescript(
+                &module.source,
+                library_module_url(script, module, "ts").to_string(),
+            )
+            .map_err(|err| format!("library module {}.ts: {err}", module.name))?;
+
+            Ok(LibraryModuleState {
+                url: library_module_url(script, module, "js"),
+                compiled,
+            })
+        })
+        .collect()
+}
+
+/// Library modules live next to the script they're bundled with
+fn library_module_url(script: &Script, module: &LibraryModule, suffix: &str) -> Url {
+    script_url(script, suffix)
+        .join(&format!("{}.{suffix}", module.name))
+        .unwrap()
+}
+
 fn script_url(script: &Script, suffix: &str) -> Url {
     if let Some(plugin_id) = &script.plugin_id {
         return Url::parse(&format!(
@@ -223,5 +388,8 @@ deno_core::extension!(bl_core,
 );
 
 pub fn init_v8_platform() {
+    // code from eval and `new Function` can't be traced back to the script that made it,
+    // which op calls are checked against
+    init_v8_flags(&["--disallow-code-generation-from-strings".to_owned()]);
     JsRuntime::init_platform(None);
 }
@@ -1,6 +1,9 @@
+use std::{cell::RefCell, collections::HashMap};
+
 use deno_core::{
     ModuleLoadResponse, ModuleLoader, ModuleSource, ModuleType, RequestedModuleType, ResolutionKind,
 };
+use stores::config::{VendoredModule, VendoredModuleOwner};
 use url::Url;
 
 use crate::{ScriptLoadState, ScriptsStateStoreHandle};
@@ -8,9 +11,31 @@ use crate::{ScriptLoadState, ScriptsStateStoreHandle};
 pub struct ModuleManager {
     pub module_map: Vec<ModuleEntry>,
     pub guild_scripts: ScriptsStateStoreHandle,
+    pub vendored_modules: Vec<VendoredModule>,
+
+    /// static imports between script modules, used to detect import cycles
+    pub import_graph: RefCell<HashMap<Url, Vec<Url>>>,
 }
 
 impl ModuleManager {
+    /// Clears state tied to the current isolate, needs to be called when the vm is restarted
+    pub fn reset(&self) {
+        self.import_graph.borrow_mut().clear();
+    }
+
+    /// Returns true if another script module statically imports the provided one
+    pub fn is_imported_by_scripts(&self, url: &Url) -> bool {
+        self.import_graph
+            .borrow()
+            .iter()
+            .any(|(referrer, imports)| referrer != url && imports.contains(url))
+    }
+
+    /// Forgets the imports of a script module that has been replaced
+    pub fn remove_script_imports(&self, url: &Url) {
+        self.import_graph.borrow_mut().remove(url);
+    }
+
     fn try_load_std_module(
         &self,
         module_specifier: &deno_core::ModuleSpecifier,
@@ -41,18 +66,327 @@ impl ModuleManager {
             if let Some(compiled) = &script.compiled {
                 script.state = ScriptLoadState::Loaded;
 
-                return Some(ModuleSource::new(
-                    ModuleType::JavaScript,
-                    deno_core::ModuleSourceCode::Bytes(deno_core::ModuleCodeBytes::Boxed(
-                        Box::from(compiled.output.as_bytes()),
-                    )),
-                    module_specifier,
-                    None,
+                return Some(js_module_source(&compiled.output, module_specifier));
+            }
+        }
+
+        store
+            .scripts
+            .iter()
+            .flat_map(|v| &v.library_modules)
+            .find(|v| &v.url == module_specifier)
+            .map(|v| js_module_source(&v.compiled.output, module_specifier))
+    }
+
+    fn try_load_vendored_module(
+        &self,
+        module_specifier: &deno_core::ModuleSpecifier,
+    ) -> Option<ModuleSource> {
+        self.vendored_modules
+            .iter()
+            .find(|v| vendored_module_url(v) == *module_specifier && self.is_owner_loaded(v))
+            .map(|v| js_module_source(&v.source, module_specifier))
+    }
+
+    /// Plugin modules stick around until the vm is restarted, so make sure the plugin is still
+    /// installed before handing them out
+    fn is_owner_loaded(&self, module: &VendoredModule) -> bool {
+        match module.owner {
+            VendoredModuleOwner::Guild(_) => true,
+            VendoredModuleOwner::Plugin(plugin_id) => self
+                .guild_scripts
+                .borrow()
+                .scripts
+                .iter()
+                .any(|v| v.script.plugin_id == Some(plugin_id)),
+        }
+    }
+
+    /// Resolves `vendor:<name>` or `vendor:<name>@<hash>` imports
+    ///
+    /// Plugins only have access to their own vendored modules, everything else uses the guild's
+    fn resolve_vendored_module(&self, name: &str, referrer: &str) -> Result<Url, anyhow::Error> {
+        let (name, pinned_hash) = match name.split_once('@') {
+            Some((name, hash)) => (name, Some(hash)),
+            None => (name, None),
+        };
+
+        let owner_plugin_id = Url::parse(referrer)
+            .ok()
+            .and_then(|referrer| plugin_id_from_url(&referrer));
+
+        let Some(module) = self.vendored_modules.iter().find(|v| {
+            v.name == name
+                && self.is_owner_loaded(v)
+                && match (v.owner, owner_plugin_id) {
+                    (VendoredModuleOwner::Plugin(id), Some(referrer_plugin)) => {
+                        id == referrer_plugin
+                    }
+                    (VendoredModuleOwner::Guild(_), None) => true,
+                    _ => false,
+                }
+        }) else {
+            return Err(anyhow::anyhow!(
+                "cannot find vendored module {name}, make sure it has been uploaded"
+            ));
+        };
+
+        if let Some(pinned_hash) = pinned_hash {
+            if module.content_hash != pinned_hash {
+                return Err(anyhow::anyhow!(
+                    "vendored module {name} is pinned to {pinned_hash} but the uploaded bundle \
+                     has hash {}, pins have to use the full hash",
+                    module.content_hash
                 ));
             }
         }
 
-        None
+        Ok(vendored_module_url(module))
+    }
+
+    /// Resolves `plugin:<plugin id>` imports to the script of the plugin and
+    /// `plugin:<plugin id>/<module name>` to one of its library modules
+    ///
+    /// Plugins can only import the plugins they depend on, and only if the installed version is
+    /// one they work with, guild scripts can import any plugin installed on the guild
+    fn resolve_plugin_module(&self, name: &str, referrer: &str) -> Result<Url, anyhow::Error> {
+        let (plugin_id, module_name) = match name.split_once('/') {
+            Some((plugin_id, module_name)) => (plugin_id, Some(module_name)),
+            None => (name, None),
+        };
+
+        let Ok(plugin_id) = plugin_id.parse::<u64>() else {
+            return Err(anyhow::anyhow!(
+                "invalid plugin import plugin:{name}, expected plugin:<plugin id> or \
+                 plugin:<plugin id>/<module name>"
+            ));
+        };
+
+        let store = self.guild_scripts.borrow();
+        let find_plugin = |id: u64| {
+            store
+                .scripts
+                .iter()
+                .find(|v| v.script.plugin_id == Some(id))
+        };
+
+        let Some(imported) = find_plugin(plugin_id) else {
+            return Err(anyhow::anyhow!(
+                "cannot import plugin {plugin_id}, it is not installed on this server"
+            ));
+        };
+
+        let referrer_plugin_id = Url::parse(referrer)
+            .ok()
+            .and_then(|referrer| plugin_id_from_url(&referrer));
+
+        if let Some(referrer_plugin_id) = referrer_plugin_id {
+            let Some(dependency) = find_plugin(referrer_plugin_id).and_then(|v| {
+                v.script
+                    .plugin_dependencies
+                    .iter()
+                    .find(|dep| dep.plugin_id == plugin_id)
+            }) else {
+                return Err(anyhow::anyhow!(
+                    "plugin {referrer_plugin_id} has to declare plugin {plugin_id} as a \
+                     dependency to import it"
+                ));
+            };
+
+            let installed_version = imported.script.plugin_version_number.unwrap_or_default();
+            if !dependency.is_satisfied_by(installed_version) {
+                return Err(anyhow::anyhow!(
+                    "plugin {referrer_plugin_id} does not work with version {installed_version} \
+                     of plugin {plugin_id}"
+                ));
+            }
+        }
+
+        let Some(module_name) = module_name else {
+            return Ok(imported.url.clone());
+        };
+
+        let module_url = imported.url.join(&format!("{module_name}.js"))?;
+        if !imported.library_modules.iter().any(|v| v.url == module_url) {
+            return Err(anyhow::anyhow!(
+                "plugin {plugin_id} has no library module named {module_name}"
+            ));
+        }
+
+        Ok(module_url)
+    }
+
+    fn module_not_found_error(
+        &self,
+        module_specifier: &deno_core::ModuleSpecifier,
+        maybe_referrer: Option<&deno_core::ModuleSpecifier>,
+    ) -> anyhow::Error {
+        let imported_from = maybe_referrer
+            .map(|v| format!(" (imported from {})", display_module_name(v)))
+            .unwrap_or_default();
+
+        if !is_script_module(module_specifier) {
+            return anyhow::anyhow!("failed finding module {}{imported_from}", module_specifier);
+        }
+
+        let store = self.guild_scripts.borrow();
+        let failed_compilation = store.scripts.iter().any(|v| {
+            &v.url == module_specifier && matches!(v.state, ScriptLoadState::FailedCompilation)
+        });
+
+        if failed_compilation {
+            anyhow::anyhow!(
+                "module {} failed to compile{imported_from}",
+                display_module_name(module_specifier)
+            )
+        } else {
+            anyhow::anyhow!(
+                "cannot find module {}{imported_from}, make sure a enabled script or library with \
+                 that name exists",
+                display_module_name(module_specifier)
+            )
+        }
+    }
+
+    /// Records static imports between script modules, dynamic imports are evaluated lazily so
+    /// cycles through them are fine
+    fn track_script_import(
+        &self,
+        kind: ResolutionKind,
+        referrer: Url,
+        imported: &Url,
+    ) -> Result<(), anyhow::Error> {
+        if matches!(kind, ResolutionKind::Import)
+            && is_script_module(&referrer)
+            && is_script_module(imported)
+        {
+            self.add_script_import(referrer, imported)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records a static import between two script modules, returning a error if it would
+    /// introduce a import cycle
+    fn add_script_import(&self, referrer: Url, imported: &Url) -> Result<(), anyhow::Error> {
+        let mut graph = self.import_graph.borrow_mut();
+
+        if let Some(mut path) = find_import_path(&graph, imported, &referrer) {
+            path.push(imported.clone());
+            let names = path.iter().map(display_module_name).collect::<Vec<_>>();
+            return Err(anyhow::anyhow!(
+                "cyclic import detected: {}",
+                names.join(" -> ")
+            ));
+        }
+
+        let imports = graph.entry(referrer).or_default();
+        if !imports.contains(imported) {
+            imports.push(imported.clone());
+        }
+
+        Ok(())
+    }
+}
+
+/// Finds a chain of imports leading from `from` to `to` if there is one
+fn find_import_path(graph: &HashMap<Url, Vec<Url>>, from: &Url, to: &Url) -> Option<Vec<Url>> {
+    let mut stack = vec![vec![from.clone()]];
+    let mut visited = Vec::new();
+
+    while let Some(path) = stack.pop() {
+        let current = path.last().unwrap();
+        if current == to {
+            return Some(path);
+        }
+
+        if visited.contains(current) {
+            continue;
+        }
+        visited.push(current.clone());
+
+        for next in graph.get(current).into_iter().flatten() {
+            let mut next_path = path.clone();
+            next_path.push(next.clone());
+            stack.push(next_path);
+        }
+    }
+
+    None
+}
+
+fn js_module_source(source: &str, module_specifier: &deno_core::ModuleSpecifier) -> ModuleSource {
+    ModuleSource::new(
+        ModuleType::JavaScript,
+        deno_core::ModuleSourceCode::Bytes(deno_core::ModuleCodeBytes::Boxed(Box::from(
+            source.as_bytes(),
+        ))),
+        module_specifier,
+        None,
+    )
+}
+
+/// Script modules are guild scripts and plugin scripts, along with their library modules
+fn is_script_module(url: &Url) -> bool {
+    url.scheme() == "file"
+        && (url.path().starts_with("/guild_scripts/") || url.path().starts_with("/plugins/"))
+}
+
+fn vendored_module_url(module: &VendoredModule) -> Url {
+    let path = match module.owner {
+        VendoredModuleOwner::Guild(_) => format!("file:///vendor/guild/{}.js", module.name),
+        VendoredModuleOwner::Plugin(plugin_id) => {
+            format!("file:///vendor/plugins/{plugin_id}/{}.js", module.name)
+        }
+    };
+
+    Url::parse(&path).unwrap()
+}
+
+/// Makes sure modules only reach vendored modules they own, plugins their own and everything
+/// else the guild's, no matter if they were imported through a path or a full url
+fn check_vendored_module_access(resolved: &Url, referrer: &str) -> Result<(), anyhow::Error> {
+    if resolved.scheme() != "file" || !resolved.path().starts_with("/vendor/") {
+        return Ok(());
+    }
+
+    let referrer_plugin_id = Url::parse(referrer)
+        .ok()
+        .and_then(|referrer| plugin_id_from_url(&referrer));
+    let allowed = if resolved.path().starts_with("/vendor/plugins/") {
+        referrer_plugin_id.is_some() && plugin_id_from_url(resolved) == referrer_plugin_id
+    } else if resolved.path().starts_with("/vendor/guild/") {
+        referrer_plugin_id.is_none()
+    } else {
+        false
+    };
+
+    if !allowed {
+        return Err(anyhow::anyhow!(
+            "cannot import {} from {referrer}, use vendor:<name> to import vendored modules",
+            display_module_name(resolved),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the plugin a plugin script, library module or vendored module belongs to
+pub fn plugin_id_from_url(url: &Url) -> Option<u64> {
+    let path = url.path();
+    let rest = path
+        .strip_prefix("/plugins/")
+        .or_else(|| path.strip_prefix("/vendor/plugins/"))?;
+
+    rest.split('/').next()?.parse().ok()
+}
+
+/// Formats a module url the way users know it, e.g. `guild_scripts/some_script.ts`
+fn display_module_name(url: &Url) -> String {
+    let path = url.path().trim_start_matches('/');
+    match path.strip_suffix(".js") {
+        Some(base) => format!("{base}.ts"),
+        None => path.to_string(),
     }
 }
 
@@ -62,10 +396,32 @@ impl ModuleLoader for ModuleManager {
         &self,
         mut specifier: &str,
         referrer: &str,
-        _kind: ResolutionKind,
+        kind: ResolutionKind,
     ) -> Result<deno_core::ModuleSpecifier, deno_core::error::AnyError> {
         // info!("resolving module: {} - {}", specifier, referrer);
+        if let Some(name) = specifier.strip_prefix("vendor:") {
+            return self.resolve_vendored_module(name, referrer);
+        }
+
+        if let Some(name) = specifier.strip_prefix("plugin:") {
+            let resolved = self.resolve_plugin_module(name, referrer)?;
+            if let Ok(parsed_referrer) = Url::parse(referrer) {
+                self.track_script_import(kind, parsed_referrer, &resolved)?;
+            }
+
+            return Ok(resolved);
+        }
+
         if let Ok(u) = Url::parse(specifier) {
+            if matches!(u.scheme(), "http" | "https" | "data" | "blob") {
+                return Err(anyhow::anyhow!(
+                    "importing modules from urls is not allowed ({specifier}), upload it as a \
+                     vendored module instead"
+                ));
+            }
+
+            check_vendored_module_access(&u, referrer)?;
+
             return Ok(u);
         };
 
@@ -86,6 +442,18 @@ impl ModuleLoader for ModuleManager {
         let resolved = parsed_referrer
             .join(format!("{specifier}.js").as_str())
             .unwrap();
+        check_vendored_module_access(&resolved, referrer)?;
+
+        let resolved = if is_script_module(&resolved) {
+            self.guild_scripts
+                .borrow()
+                .current_script_url(&resolved)
+                .unwrap_or(resolved)
+        } else {
+            resolved
+        };
+
+        self.track_script_import(kind, parsed_referrer, &resolved)?;
 
         Ok(resolved)
     }
@@ -93,7 +461,7 @@ impl ModuleLoader for ModuleManager {
     fn load(
         &self,
         module_specifier: &deno_core::ModuleSpecifier,
-        _maybe_referrer: Option<&deno_core::ModuleSpecifier>,
+        maybe_referrer: Option<&deno_core::ModuleSpecifier>,
         _is_dyn_import: bool,
         _requested_module_type: RequestedModuleType,
     ) -> ModuleLoadResponse {
@@ -102,11 +470,10 @@ impl ModuleLoader for ModuleManager {
                 Ok(l)
             } else if let Some(l) = self.try_load_script_module(module_specifier) {
                 Ok(l)
+            } else if let Some(l) = self.try_load_vendored_module(module_specifier) {
+                Ok(l)
             } else {
-                Err(anyhow::anyhow!(
-                    "failed finding module {:?}",
-                    module_specifier
-                ))
+                Err(self.module_not_found_error(module_specifier, maybe_referrer))
             },
         )
     }
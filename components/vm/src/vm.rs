@@ -4,12 +4,15 @@ use crate::{
     ScriptsStateStoreHandle,
 };
 use chrono::Utc;
-use common::dispatch_event::VmDispatchEvent;
+use common::dispatch_event::{
+    EvalOutput, EvalOutputKind, EvalRequest, VmDispatchEvent, EVAL_EVENT_NAME,
+};
 use cpu_time::ThreadTime;
+use deno_core::error::JsError;
 use deno_core::v8::{self, CreateParams, IsolateHandle};
 use deno_core::{Extension, FastString, JsRuntime, PollEventLoopOptions, RuntimeOptions};
 use futures::{future::LocalBoxFuture, FutureExt};
-use guild_logger::entry::CreateLogEntry;
+use guild_logger::entry::{CreateLogEntry, ScriptContext};
 use guild_logger::GuildLogSender;
 use metrics::histogram;
 use serde::{Deserialize, Serialize};
@@ -22,10 +25,13 @@ use std::{
     sync::{atomic::AtomicBool, Arc, RwLock as StdRwLock},
     task::{Context, Poll, Wake},
 };
-use stores::config::Script;
+use stores::config::{Script, VendoredModule};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{error, info, instrument};
 
+// eval snippets are run under this name, imports in them resolve relative to it
+const EVAL_SCRIPT_NAME: &str = "file:///eval/snippet.js";
+
 #[derive(Debug, Clone)]
 pub enum VmCommand {
     DispatchEvent(VmDispatchEvent),
@@ -34,14 +40,18 @@ pub enum VmCommand {
     // note that this also reloads the runtime, shutting it down and starting it again
     // we send a message when that has been accomplished
     UnloadScripts(Vec<Script>),
-    UpdateScript(Script),
+    // replaces the script in place if possible, falling back to restarting the runtime
+    // we send a ScriptUpdated event with the provided id once it's running again
+    UpdateScript(Script, u64),
     Restart(Vec<Script>),
 }
 
 #[derive(Debug)]
 pub enum VmEvent {
     DispatchedEvent(u64),
+    ScriptUpdated(u64),
     VmFinished,
+    EvalOutput(EvalOutput),
 }
 
 #[derive(Serialize)]
@@ -100,6 +110,8 @@ impl Vm {
         let module_manager = Rc::new(ModuleManager {
             module_map: create_req.extension_modules,
             guild_scripts: script_store.clone(),
+            vendored_modules: create_req.vendored_modules,
+            import_graph: Default::default(),
         });
 
         let sandbox = Self::create_isolate(
@@ -232,10 +244,8 @@ impl Vm {
                 }
                 TickResult::Continue => {}
                 TickResult::VmError(e) => {
-                    self.guild_logger.log(CreateLogEntry::error(format!(
-                        "Script error occurred: {}",
-                        e
-                    )));
+                    // this is also where unhandled promise rejections end up
+                    self.log_guild_err(e);
                 }
                 TickResult::Completed => {
                     let _ = self.tx.send(VmEvent::VmFinished);
@@ -265,13 +275,16 @@ impl Vm {
             VmCommand::Restart(new_scripts) => {
                 self.restart(new_scripts).await;
             }
+            VmCommand::DispatchEvent(dispatch_event) if dispatch_event.name == EVAL_EVENT_NAME => {
+                self.eval(dispatch_event)
+            }
             VmCommand::DispatchEvent(dispatch_event) => self.dispatch_event(dispatch_event),
             VmCommand::LoadScript(script) => {
                 if let Some(script) = self.compile_script(script) {
-                    self.run_script(script.script.id).await
+                    self.run_script(script.script.id).await;
                 }
             }
-            VmCommand::UpdateScript(script) => {
+            VmCommand::UpdateScript(script, id) => {
                 let mut cloned_scripts = self
                     .script_store
                     .borrow()
@@ -288,9 +301,11 @@ impl Vm {
                     }
                 }
 
-                if need_reset {
+                if need_reset && !self.hot_reload_script(script).await {
                     self.restart(cloned_scripts).await;
                 }
+
+                let _ = self.tx.send(VmEvent::ScriptUpdated(id));
             }
             VmCommand::UnloadScripts(scripts) => {
                 let new_scripts = self
@@ -343,8 +358,9 @@ impl Vm {
         }
     }
 
+    /// Runs the script, returning true if it was loaded and evaluated without errors
     #[instrument(skip(self))]
-    async fn run_script(&mut self, script_id: u64) {
+    async fn run_script(&mut self, script_id: u64) -> bool {
         let (script, compiled) = {
             let borrow = self.script_store.borrow();
 
@@ -354,15 +370,15 @@ impl Vm {
                         (script.clone(), source.clone())
                     } else {
                         error!("script marked as can run with no compiled source",);
-                        return;
+                        return false;
                     }
                 } else {
                     info!("skipping loading script");
-                    return;
+                    return false;
                 }
             } else {
                 error!("tried to load non-existent script");
-                return;
+                return false;
             }
         };
 
@@ -380,17 +396,97 @@ impl Vm {
         match res {
             Ok(id) => {
                 let rcv = self.runtime.mod_evaluate(id);
-                self.complete_module_eval(rcv).await;
+                self.complete_module_eval(rcv).await
             }
             Err(err) => {
                 self.log_guild_err(err);
                 self.script_store
                     .borrow_mut()
                     .set_state(script_id, ScriptLoadState::Failed);
+                false
             }
         }
     }
 
+    /// Replaces a single script module in place, leaving the state of the other scripts intact
+    ///
+    /// Returns false if the script could not be replaced and the vm needs a full restart instead
+    #[instrument(skip_all, fields(script_id = script.id))]
+    async fn hot_reload_script(&mut self, script: Script) -> bool {
+        let Some(previous) = self.script_store.borrow().get_script(script.id).cloned() else {
+            return false;
+        };
+
+        // scripts importing this one would keep using the old module, and library modules
+        // would resolve to the modules already loaded for the previous version
+        if previous.script.is_library
+            || script.is_library
+            || previous.script.name != script.name
+            || previous.script.library_modules != script.library_modules
+            || self.module_manager.is_imported_by_scripts(&previous.url)
+        {
+            return false;
+        }
+
+        let name = script.name.clone();
+        let script_id = script.id;
+        if let Err(err) = self
+            .script_store
+            .borrow_mut()
+            .compile_replace_script(script)
+        {
+            // the restart reports the compilation error
+            info!(%err, "failed compiling new script version");
+            return false;
+        }
+
+        let unloaded = self.unload_script(script_id);
+        self.module_manager.remove_script_imports(&previous.url);
+        info!(unloaded, "replacing script in place");
+
+        self.guild_logger
+            .log(CreateLogEntry::info(format!("reloading {name}.ts...")));
+
+        if !self.run_script(script_id).await {
+            self.guild_logger.log(CreateLogEntry::error(format!(
+                "failed reloading {name}.ts, restarting the vm..."
+            )));
+            return false;
+        }
+
+        true
+    }
+
+    /// Unregisters the event listeners, commands, interval timers and task handlers of a script
+    ///
+    /// Returns false if the script was never run
+    fn unload_script(&mut self, script_id: u64) -> bool {
+        let global_ctx = self.runtime.main_context();
+        let ctx = global_ctx.open(self.runtime.v8_isolate());
+
+        let mut scope = self.runtime.handle_scope();
+        let globals = ctx.global(&mut scope);
+
+        let Some(core_obj) = Self::get_property(&mut scope, globals, "BotloaderCore")
+            .and_then(|v| v8::Local::<v8::Object>::try_from(v).ok())
+        else {
+            error!("BotloaderCore global not found, unable to unload script");
+            return false;
+        };
+
+        let Some(unload_fn) = Self::get_property(&mut scope, core_obj, "unloadScript")
+            .and_then(|v| v8::Local::<v8::Function>::try_from(v).ok())
+        else {
+            error!("BotloaderCore.unloadScript is not a function, unable to unload script");
+            return false;
+        };
+
+        let arg = v8::Number::new(&mut scope, script_id as f64);
+        unload_fn
+            .call(&mut scope, globals.into(), &[arg.into()])
+            .is_some_and(|v| v.is_true())
+    }
+
     fn dispatch_event(&mut self, event: VmDispatchEvent) {
         let _ = self.tx.send(VmEvent::DispatchedEvent(event.seq));
 
@@ -442,11 +538,65 @@ impl Vm {
         let class = match event.source {
             common::dispatch_event::EventSource::Discord => "discord",
             common::dispatch_event::EventSource::Timer => "timer",
+            common::dispatch_event::EventSource::Eval => "eval",
+            common::dispatch_event::EventSource::Webhook => "webhook",
         };
 
         histogram!("dispatch_event_latency", "event_source" => class).record(millis as f64)
     }
 
+    // evaluates the provided snippet as the body of a async function, the "/eval" runtime module
+    // takes care of reporting the result and console output back through ops
+    //
+    // snippets run as classic scripts, loading them as modules would keep every one of them
+    // around in the module map for the lifetime of the vm
+    #[instrument(skip_all)]
+    fn eval(&mut self, event: VmDispatchEvent) {
+        let _ = self.tx.send(VmEvent::DispatchedEvent(event.seq));
+
+        let req: EvalRequest = match serde_json::from_value(event.value) {
+            Ok(v) => v,
+            Err(err) => {
+                error!(%err, "failed decoding eval request");
+                return;
+            }
+        };
+
+        let source = format!(
+            "(async () => {{const botloader = await import(\"botloader\");\
+             const {{ Eval }} = await import(\"/eval\");\
+             await Eval.run({}, async (console) => {{\n{}\n}});}})();",
+            req.eval_id, req.source
+        );
+
+        let compiled = match tscompiler::compile_typescript(
+            &source,
+            format!("file:///eval/{}.ts", req.eval_id),
+        ) {
+            Ok(v) => v,
+            Err(err) => {
+                self.send_eval_exception(req.eval_id, format!("compilation failed: {err}"));
+                return;
+            }
+        };
+
+        // the snippet keeps running in the event loop, this only runs it up to the first await
+        if let Err(err) = self
+            .runtime
+            .execute_script(EVAL_SCRIPT_NAME, FastString::from(compiled.output))
+        {
+            self.send_eval_exception(req.eval_id, err.to_string());
+        }
+    }
+
+    fn send_eval_exception(&self, eval_id: u64, message: String) {
+        let _ = self.tx.send(VmEvent::EvalOutput(EvalOutput {
+            eval_id,
+            kind: EvalOutputKind::Exception,
+            message,
+        }));
+    }
+
     fn get_property<'a>(
         scope: &mut v8::HandleScope<'a>,
         object: v8::Local<v8::Object>,
@@ -507,7 +657,10 @@ impl Vm {
         }
     }
 
-    async fn complete_module_eval(&mut self, mut fut: impl Future<Output = Result<(), AnyError>>) {
+    async fn complete_module_eval(
+        &mut self,
+        mut fut: impl Future<Output = Result<(), AnyError>>,
+    ) -> bool {
         let mut pinned: Pin<&mut dyn Future<Output = Result<(), AnyError>>> = pin!(fut);
         loop {
             let fut = CompleteModuleEval {
@@ -517,11 +670,13 @@ impl Vm {
 
             match fut.await {
                 CompleteModuleEvalResult::Completed(res) => {
-                    match res {
-                        Ok(_) => {}
-                        Err(err) => self.log_guild_err(err),
+                    return match res {
+                        Ok(_) => true,
+                        Err(err) => {
+                            self.log_guild_err(err);
+                            false
+                        }
                     };
-                    break;
                 }
                 CompleteModuleEvalResult::VmError(err) => self.log_guild_err(err),
             }
@@ -529,10 +684,39 @@ impl Vm {
     }
 
     fn log_guild_err(&self, err: AnyError) {
-        self.guild_logger.log(CreateLogEntry::error(format!(
-            "Script error occurred: {}",
-            err
-        )));
+        let Some(js_err) = err.downcast_ref::<JsError>() else {
+            self.guild_logger.log(CreateLogEntry::error(format!(
+                "Script error occurred: {}",
+                err
+            )));
+            return;
+        };
+
+        let store = self.script_store.borrow();
+        let stack = js_err
+            .frames
+            .iter()
+            .map(|frame| store.map_stack_frame(frame))
+            .collect::<Vec<_>>();
+
+        // the error is attributed to the innermost frame that is inside one of the scripts
+        let script_context = stack
+            .iter()
+            .find(|frame| store.is_script_file(&frame.filename))
+            .map(|frame| ScriptContext {
+                filename: frame.filename.clone(),
+                line_col: frame.line_col,
+            });
+        drop(store);
+
+        let mut message = format!("Script error occurred: {}", js_err.exception_message);
+        for frame in &stack {
+            message.push_str(&format!("\n    at {frame}"));
+        }
+
+        let mut entry = CreateLogEntry::error(message).with_stack(stack);
+        entry.script_context = script_context;
+        self.guild_logger.log(entry);
     }
 
     async fn restart(&mut self, new_scripts: Vec<Script>) {
@@ -546,6 +730,7 @@ impl Vm {
             let mut borrow = self.script_store.borrow_mut();
             borrow.clear();
         };
+        self.module_manager.reset();
 
         for script in &new_scripts {
             self.compile_script(script.clone());
@@ -747,6 +932,7 @@ pub struct CreateRt {
     pub rx: UnboundedReceiver<VmCommand>,
     pub tx: UnboundedSender<VmEvent>,
     pub load_scripts: Vec<Script>,
+    pub vendored_modules: Vec<VendoredModule>,
     pub extension_factory: ExtensionFactory,
     pub extension_modules: Vec<ModuleEntry>,
 }
@@ -4,8 +4,11 @@ use common::DiscordConfig;
 use guild_logger::LogSender;
 use runtime::{CreateRuntimeContext, RuntimeEvent, ScriptSettingsValues};
 use scheduler_worker_rpc::{
-    CreateScriptsVmReq, SchedulerMessage, VmSessionShutdownEvent, WorkerMessage,
+    remote_auth::{self, Handshake, Side},
+    CreateScriptsVmReq, RemoteWorkerHello, SchedulerMessage, VmSessionShutdownEvent, WorkerHello,
+    WorkerMessage, PROTOCOL_VERSION,
 };
+use simpleproto::Codec;
 use stores::{config::PremiumSlotTier, Db};
 use tokio::sync::mpsc;
 use tracing::{error, info, instrument};
@@ -23,12 +26,21 @@ pub async fn run(config: WorkerConfig) -> Result<(), Box<dyn std::error::Error>>
 
     info!("worker starting");
 
-    #[cfg(target_family = "unix")]
-    let (scheduler_tx, scheduler_rx) =
-        connect_scheduler("/tmp/botloader_scheduler_workers", config.worker_id).await;
+    let (scheduler_tx, scheduler_rx) = if let Some(addr) = &config.remote_scheduler_addr {
+        connect_remote_scheduler(addr, &config).await
+    } else {
+        let worker_id = config
+            .worker_id
+            .expect("BL_WORKER_ID should be set by the scheduler");
 
-    #[cfg(target_family = "windows")]
-    let (scheduler_tx, scheduler_rx) = connect_scheduler("localhost:7885", config.worker_id).await;
+        #[cfg(target_family = "unix")]
+        let conn = connect_scheduler("/tmp/botloader_scheduler_workers", worker_id).await;
+
+        #[cfg(target_family = "windows")]
+        let conn = connect_scheduler("localhost:7885", worker_id).await;
+
+        conn
+    };
 
     metrics::set_global_recorder(metrics_forwarder::MetricsForwarder {
         tx: scheduler_tx.clone(),
@@ -83,8 +95,24 @@ pub struct WorkerConfig {
     )]
     pub(crate) broker_api_addr: String,
 
+    /// Set by the scheduler when it spawns the worker, not used by remote workers
     #[clap(long, env = "BL_WORKER_ID")]
-    pub(crate) worker_id: u64,
+    pub(crate) worker_id: Option<u64>,
+
+    /// Address of a scheduler to connect to over tcp instead of being spawned by it
+    #[clap(long, env = "BL_WORKER_REMOTE_SCHEDULER_ADDR")]
+    pub(crate) remote_scheduler_addr: Option<String>,
+
+    /// Shared secret used to authenticate with the remote scheduler
+    #[clap(long, env = "BL_WORKER_REMOTE_SECRET")]
+    pub(crate) remote_secret: Option<String>,
+
+    #[clap(long, env = "BL_WORKER_REMOTE_NAME", default_value = "remote")]
+    pub(crate) remote_name: String,
+
+    /// The premium tier this worker provides capacity for when connected remotely: free, lite or premium
+    #[clap(long, env = "BL_WORKER_REMOTE_PREMIUM_TIER", default_value = "free")]
+    pub(crate) remote_premium_tier: String,
 }
 
 struct WorkerState {
@@ -221,6 +249,32 @@ impl Worker {
             }
             SchedulerMessage::Shutdown => Ok(ContinueState::Stop),
             SchedulerMessage::CreateScriptsVm(data) => self.handle_create_scripts_vm(data).await,
+            SchedulerMessage::UpdateScript(req) => {
+                // acked once the vm is done with it, so the scheduler knows when the new
+                // version is running
+                let sent = self.current_state.as_ref().is_some_and(|current| {
+                    current
+                        .scripts_vm
+                        .send(VmCommand::UpdateScript(req.script, req.seq))
+                        .is_ok()
+                });
+
+                if !sent {
+                    self.write_message(WorkerMessage::Ack(req.seq)).await?;
+                }
+                Ok(ContinueState::Continue)
+            }
+            SchedulerMessage::Ping => {
+                self.write_message(WorkerMessage::Pong).await?;
+                Ok(ContinueState::Continue)
+            }
+            SchedulerMessage::AuthChallenge(_)
+            | SchedulerMessage::Welcome(_)
+            | SchedulerMessage::RemoteWelcome(..) => {
+                // only sent during the handshake
+                error!("received handshake message after connecting");
+                Ok(ContinueState::Continue)
+            }
             SchedulerMessage::Complete => {
                 // complete the vm
                 if let Some(current) = &self.current_state {
@@ -248,6 +302,14 @@ impl Worker {
             RuntimeEvent::NewTaskScheduled => {
                 self.write_message(WorkerMessage::TaskScheduled).await?;
             }
+            RuntimeEvent::EvalOutput(output) => {
+                self.write_message(WorkerMessage::EvalOutput(output))
+                    .await?;
+            }
+            RuntimeEvent::WebhookResponse(resp) => {
+                self.write_message(WorkerMessage::WebhookResponse(resp))
+                    .await?;
+            }
         }
         Ok(ContinueState::Continue)
     }
@@ -261,6 +323,11 @@ impl Worker {
     async fn handle_vm_evt(&mut self, evt: VmEvent) -> anyhow::Result<ContinueState> {
         match evt {
             VmEvent::DispatchedEvent(id) => self.write_message(WorkerMessage::Ack(id)).await?,
+            VmEvent::ScriptUpdated(id) => self.write_message(WorkerMessage::Ack(id)).await?,
+            VmEvent::EvalOutput(output) => {
+                self.write_message(WorkerMessage::EvalOutput(output))
+                    .await?
+            }
             VmEvent::VmFinished => {
                 while let Ok(evt) = self.runtime_evt_rx.try_recv() {
                     self.handle_runtime_evt(evt).await?;
@@ -330,6 +397,7 @@ impl Worker {
                 rx: vm_cmd_rx,
                 tx: vm_evt_tx,
                 load_scripts: req.scripts,
+                vendored_modules: req.vendored_modules,
 
                 extension_factory: Box::new(move || runtime::create_extensions(rt_ctx.clone())),
                 extension_modules: runtime::jsmodules::create_module_map(),
@@ -433,22 +501,29 @@ async fn connect_scheduler(
         .await
         .expect("scheduler should have opened socket");
 
-    simpleproto::write_message(&WorkerMessage::Hello(id), &mut stream)
+    let hello = WorkerHello {
+        worker_id: id,
+        protocol_version: PROTOCOL_VERSION,
+        codecs: Codec::ALL.to_vec(),
+    };
+    simpleproto::write_message(&WorkerMessage::Hello(hello), &mut stream)
         .await
         .expect("should write to scheduler successfully");
 
+    let codec = read_welcome(&mut stream).await;
+
     let (mut reader_half, mut writer_half) = stream.into_split();
 
     let scheduler_rx = {
         let (tx, rx) = mpsc::unbounded_channel::<SchedulerMessage>();
 
-        tokio::spawn(async move { simpleproto::message_reader(&mut reader_half, tx).await });
+        tokio::spawn(async move { simpleproto::message_reader(&mut reader_half, tx, codec).await });
         rx
     };
 
     let scheduler_tx = {
         let (tx, rx) = mpsc::unbounded_channel::<WorkerMessage>();
-        tokio::spawn(async move { simpleproto::message_writer(&mut writer_half, rx).await });
+        tokio::spawn(async move { simpleproto::message_writer(&mut writer_half, rx, codec).await });
 
         tx
     };
@@ -468,25 +543,134 @@ async fn connect_scheduler(
         .await
         .expect("scheduler should have opened socket");
 
-    simpleproto::write_message(&WorkerMessage::Hello(id), &mut stream)
+    let hello = WorkerHello {
+        worker_id: id,
+        protocol_version: PROTOCOL_VERSION,
+        codecs: Codec::ALL.to_vec(),
+    };
+    simpleproto::write_message(&WorkerMessage::Hello(hello), &mut stream)
+        .await
+        .expect("should write to scheduler successfully");
+
+    let codec = read_welcome(&mut stream).await;
+
+    let (mut reader_half, mut writer_half) = stream.into_split();
+
+    let scheduler_rx = {
+        let (tx, rx) = mpsc::unbounded_channel::<SchedulerMessage>();
+
+        tokio::spawn(async move { simpleproto::message_reader(&mut reader_half, tx, codec).await });
+        rx
+    };
+
+    let scheduler_tx = {
+        let (tx, rx) = mpsc::unbounded_channel::<WorkerMessage>();
+        tokio::spawn(async move { simpleproto::message_writer(&mut writer_half, rx, codec).await });
+
+        tx
+    };
+
+    (scheduler_tx, scheduler_rx)
+}
+
+async fn connect_remote_scheduler(
+    addr: &str,
+    config: &WorkerConfig,
+) -> (
+    mpsc::UnboundedSender<WorkerMessage>,
+    mpsc::UnboundedReceiver<SchedulerMessage>,
+) {
+    let secret = config
+        .remote_secret
+        .as_deref()
+        .expect("BL_WORKER_REMOTE_SECRET is required for remote workers");
+
+    let premium_tier = match config.remote_premium_tier.as_str() {
+        "free" => None,
+        "lite" => Some(PremiumSlotTier::Lite),
+        "premium" => Some(PremiumSlotTier::Premium),
+        other => panic!("unknown premium tier: {other}"),
+    };
+
+    let mut stream = tokio::net::TcpStream::connect(addr)
+        .await
+        .expect("should connect to remote scheduler");
+    stream.set_nodelay(true).expect("set nodelay");
+
+    let challenge = match simpleproto::read_message(&mut stream).await {
+        Ok(SchedulerMessage::AuthChallenge(challenge)) => challenge,
+        Ok(_) => panic!("first scheduler message was not an auth challenge"),
+        Err(err) => panic!("failed reading auth challenge: {err}"),
+    };
+
+    let worker_challenge = remote_auth::new_challenge();
+    let handshake = Handshake {
+        secret,
+        scheduler_challenge: &challenge,
+        worker_challenge: &worker_challenge,
+    };
+
+    let hello = RemoteWorkerHello {
+        name: config.remote_name.clone(),
+        premium_tier,
+        challenge: worker_challenge.clone(),
+        signature: handshake.sign(Side::Worker),
+        protocol_version: PROTOCOL_VERSION,
+        codecs: Codec::ALL.to_vec(),
+    };
+    simpleproto::write_message(&WorkerMessage::RemoteHello(hello), &mut stream)
         .await
         .expect("should write to scheduler successfully");
 
+    // anyone on the path could answer our hello, only the scheduler knows the secret
+    let codec = match simpleproto::read_message(&mut stream).await {
+        Ok(SchedulerMessage::RemoteWelcome(codec, signature)) => {
+            if !handshake.verify(Side::Scheduler, &signature) {
+                panic!("invalid signature from scheduler");
+            }
+
+            info!(?codec, "scheduler accepted connection");
+            codec
+        }
+        Ok(other) => panic!("expected welcome from scheduler, got {}", other.span_name()),
+        Err(err) => panic!("scheduler closed the connection during the handshake: {err}"),
+    };
+
+    info!(addr, "connected to remote scheduler");
+
+    // if the connection is lost the worker shuts down, it's up to the process supervisor to restart it
     let (mut reader_half, mut writer_half) = stream.into_split();
+    let (send_mac, recv_mac) = handshake.session(Side::Worker);
 
     let scheduler_rx = {
         let (tx, rx) = mpsc::unbounded_channel::<SchedulerMessage>();
 
-        tokio::spawn(async move { simpleproto::message_reader(&mut reader_half, tx).await });
+        tokio::spawn(async move {
+            remote_auth::message_reader(&mut reader_half, tx, codec, recv_mac).await
+        });
         rx
     };
 
     let scheduler_tx = {
         let (tx, rx) = mpsc::unbounded_channel::<WorkerMessage>();
-        tokio::spawn(async move { simpleproto::message_writer(&mut writer_half, rx).await });
+        tokio::spawn(async move {
+            remote_auth::message_writer(&mut writer_half, rx, codec, send_mac).await
+        });
 
         tx
     };
 
     (scheduler_tx, scheduler_rx)
 }
+
+/// Waits for the scheduler to accept our hello, returning the codec it picked
+async fn read_welcome(stream: &mut (impl tokio::io::AsyncRead + Unpin)) -> Codec {
+    match simpleproto::read_message(stream).await {
+        Ok(SchedulerMessage::Welcome(codec)) => {
+            info!(?codec, "scheduler accepted connection");
+            codec
+        }
+        Ok(other) => panic!("expected welcome from scheduler, got {}", other.span_name()),
+        Err(err) => panic!("scheduler closed the connection during the handshake: {err}"),
+    }
+}
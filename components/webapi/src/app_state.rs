@@ -7,6 +7,7 @@ use twilight_model::id::Id;
 
 use crate::{
     news_poller::{self, NewsHandle},
+    routes::inbound_webhooks::InboundWebhookRateLimiter,
     WebConfig,
 };
 
@@ -20,6 +21,7 @@ pub struct InnerAppState {
     pub discord_config: Arc<DiscordConfig>,
     pub bot_rpc_client: botrpc::Client,
     pub state_client: dbrokerapi::state_client::Client,
+    pub inbound_webhook_ratelimiter: InboundWebhookRateLimiter,
 }
 
 pub type AppState = Arc<InnerAppState>;
@@ -42,6 +44,9 @@ pub async fn init_app_state(common_conf: &RunConfig, web_conf: &WebConfig) -> Ap
     let stripe_client = init_stripe_client(postgres_store.clone(), web_conf);
     let state_client = dbrokerapi::state_client::Client::new(web_conf.broker_api_addr.clone());
 
+    let inbound_webhook_ratelimiter = InboundWebhookRateLimiter::default();
+    tokio::spawn(inbound_webhook_ratelimiter.clone().run_pruning());
+
     Arc::new(InnerAppState {
         web_config: web_conf.clone(),
         common_config: common_conf.clone(),
@@ -52,6 +57,7 @@ pub async fn init_app_state(common_conf: &RunConfig, web_conf: &WebConfig) -> Ap
         discord_config,
         bot_rpc_client,
         state_client,
+        inbound_webhook_ratelimiter,
     })
 }
 
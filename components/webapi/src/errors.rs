@@ -4,6 +4,7 @@ use axum::{
     http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
+use common::plugin::{PluginCapabilities, PluginDependency};
 use serde_json::json;
 use validation::ValidationError;
 
@@ -71,6 +72,63 @@ pub enum ApiErrorResponse {
 
     #[error("Stripe integration not enabled")]
     StripeNotEnabled,
+
+    #[error("Vendored module not found")]
+    VendoredModuleNotFound,
+
+    #[error("Reached max vendored modules")]
+    MaxVendoredModulesReached,
+
+    #[error("Plugin version does not exist")]
+    PluginVersionNotFound,
+
+    #[error("Script revision does not exist")]
+    ScriptRevisionNotFound,
+
+    #[error("Plugin review does not exist")]
+    PluginReviewNotFound,
+
+    #[error("You need to be an admin of a server that has the plugin installed")]
+    NotAdminOfGuildWithPlugin,
+
+    #[error("the plugin needs capabilities you have not granted it")]
+    PluginCapabilitiesNotGranted(PluginCapabilities),
+
+    #[error("the plugin depends on a version of another plugin that can't be installed")]
+    PluginDependencyNotSatisfied(PluginDependency),
+
+    #[error("Inbound webhook does not exist")]
+    InboundWebhookNotFound,
+
+    #[error("Reached max inbound webhooks")]
+    MaxInboundWebhooksReached,
+
+    #[error("There is already a inbound webhook with this name")]
+    InboundWebhookNameTaken,
+
+    #[error("Missing or invalid signature")]
+    InvalidWebhookSignature,
+
+    #[error("Too many requests to this webhook, slow down")]
+    WebhookRateLimited,
+
+    #[error("Body has to be valid json: {0}")]
+    InvalidWebhookBody(serde_json::Error),
+
+    #[error("The request could not be passed on to the scripts: {0}")]
+    WebhookNotDelivered(String),
+
+    #[error("The scripts did not respond in time")]
+    WebhookTimedOut,
+
+    #[error("This api token is not allowed to do this")]
+    ApiTokenMissingScope,
+
+    #[error("Body has to be valid json: {0}")]
+    InvalidJsonBody(serde_json::Error),
+
+    #[error("The plugin {name} depends on this plugin, remove or disable it first")]
+    PluginRequiredByDependent { plugin_id: u64, name: String },
 }
 
 impl ApiErrorResponse {
@@ -101,6 +159,37 @@ impl ApiErrorResponse {
             Self::MaxImagesReached => (StatusCode::BAD_REQUEST, 19, None),
             Self::ScriptNotFound => (StatusCode::BAD_REQUEST, 20, None),
             Self::StripeNotEnabled => (StatusCode::INTERNAL_SERVER_ERROR, 21, None),
+            Self::VendoredModuleNotFound => (StatusCode::BAD_REQUEST, 22, None),
+            Self::MaxVendoredModulesReached => (StatusCode::BAD_REQUEST, 23, None),
+            Self::PluginVersionNotFound => (StatusCode::BAD_REQUEST, 24, None),
+            Self::ScriptRevisionNotFound => (StatusCode::BAD_REQUEST, 25, None),
+            Self::PluginReviewNotFound => (StatusCode::BAD_REQUEST, 26, None),
+            Self::NotAdminOfGuildWithPlugin => (StatusCode::FORBIDDEN, 27, None),
+            Self::PluginCapabilitiesNotGranted(needed) => (
+                StatusCode::BAD_REQUEST,
+                28,
+                Some(serde_json::to_value(needed).unwrap_or_default()),
+            ),
+            Self::PluginDependencyNotSatisfied(dependency) => (
+                StatusCode::BAD_REQUEST,
+                29,
+                Some(serde_json::to_value(dependency).unwrap_or_default()),
+            ),
+            Self::InboundWebhookNotFound => (StatusCode::NOT_FOUND, 30, None),
+            Self::MaxInboundWebhooksReached => (StatusCode::BAD_REQUEST, 31, None),
+            Self::InboundWebhookNameTaken => (StatusCode::BAD_REQUEST, 32, None),
+            Self::InvalidWebhookSignature => (StatusCode::UNAUTHORIZED, 33, None),
+            Self::WebhookRateLimited => (StatusCode::TOO_MANY_REQUESTS, 34, None),
+            Self::InvalidWebhookBody(_) => (StatusCode::BAD_REQUEST, 35, None),
+            Self::WebhookNotDelivered(_) => (StatusCode::SERVICE_UNAVAILABLE, 36, None),
+            Self::WebhookTimedOut => (StatusCode::GATEWAY_TIMEOUT, 37, None),
+            Self::ApiTokenMissingScope => (StatusCode::FORBIDDEN, 38, None),
+            Self::InvalidJsonBody(_) => (StatusCode::BAD_REQUEST, 39, None),
+            Self::PluginRequiredByDependent { plugin_id, name } => (
+                StatusCode::BAD_REQUEST,
+                40,
+                Some(serde_json::json!({ "plugin_id": plugin_id, "name": name })),
+            ),
         }
     }
 }
@@ -2,15 +2,15 @@ use std::sync::Arc;
 
 use axum::{
     error_handling::HandleErrorLayer,
-    extract::Extension,
+    extract::{DefaultBodyLimit, Extension},
     http::StatusCode,
     response::IntoResponse,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
     BoxError, Router,
 };
 
 use routes::auth::AuthHandlers;
-use stores::inmemory::web::InMemoryCsrfStore;
+use stores::{inmemory::web::InMemoryCsrfStore, web::ApiTokenOperation};
 use tower::ServiceBuilder;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing::{error, info, Level};
@@ -24,8 +24,9 @@ mod routes;
 mod util;
 
 use crate::middlewares::{
-    bl_admin_only::bl_admin_only_mw, plugins::plugin_middleware,
-    require_current_guild_admin_middleware, CorsLayer, NoSession, OptionalSession, SessionLayer,
+    bl_admin_only::bl_admin_only_mw, plugins::plugin_middleware, require_api_token_operation_mw,
+    require_current_guild_admin_middleware, require_unrestricted_session_mw, CorsLayer, NoSession,
+    OptionalSession, SessionLayer,
 };
 use crate::{errors::ApiErrorResponse, middlewares::current_guild_injector_middleware};
 
@@ -87,52 +88,186 @@ pub async fn run(
 
     let authorized_admin_routes = Router::new()
         .route("/vm_workers", get(routes::admin::get_worker_statuses))
+        .route(
+            "/vm_workers/queue",
+            get(routes::admin::get_worker_queue_status),
+        )
         .route(
             "/guild/:guild_id/status",
             get(routes::admin::get_guild_status),
         )
+        .route("/suspensions", get(routes::admin::get_active_suspensions))
+        .route(
+            "/guild/:guild_id/suspensions",
+            get(routes::admin::get_guild_suspensions)
+                .post(routes::admin::impose_guild_suspension)
+                .delete(routes::admin::lift_guild_suspension),
+        )
+        .route(
+            "/plugins/:plugin_id/reviews",
+            get(routes::admin::get_plugin_reviews),
+        )
+        .route(
+            "/plugins/:plugin_id/reviews/:user_id",
+            patch(routes::admin::moderate_plugin_review)
+                .delete(routes::admin::delete_plugin_review),
+        )
         .layer(axum::middleware::from_fn_with_state(
             state.clone(),
             bl_admin_only_mw,
         ));
 
-    let authorized_api_guild_routes = Router::new()
-        .route("/reload_vm", post(routes::vm::reload_guild_vm))
+    // routes that only read the configuration of the guild, api tokens restricted to reading
+    // them never see any secrets
+    let read_api_guild_routes = Router::new()
         .route("/settings", get(routes::guilds::get_guild_settings))
         .route(
             "/premium_slots",
             get(routes::guilds::get_guild_premium_slots),
         )
-        .route(
-            "/scripts",
-            get(routes::scripts::get_all_guild_scripts).put(routes::scripts::create_guild_script),
-        )
+        .route("/scripts", get(routes::scripts::get_all_guild_scripts))
         .route(
             "/scripts_with_plugins",
             get(routes::scripts::get_all_guild_scripts_with_plugins),
         )
+        .route(
+            "/scripts/:script_id/validate_settings",
+            post(routes::scripts::validate_script_settings),
+        )
+        .route(
+            "/scripts/:script_id/revisions",
+            get(routes::scripts::get_script_revisions),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:revision_id",
+            get(routes::scripts::get_script_revision),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:from_revision/diff/:to_revision",
+            get(routes::scripts::diff_script_revisions),
+        )
+        .route(
+            "/vendored_modules",
+            get(routes::vendored_modules::get_guild_vendored_modules),
+        )
+        .route(
+            "/inbound_webhooks",
+            get(routes::inbound_webhooks::get_inbound_webhooks),
+        )
+        .route("/full_guild", get(routes::guilds::get_full_guild))
+        .route_layer(axum::middleware::from_fn_with_state(
+            ApiTokenOperation::ReadScripts,
+            require_api_token_operation_mw,
+        ));
+
+    let write_api_guild_routes = Router::new()
+        .route(
+            "/settings/log_webhook",
+            put(routes::guilds::update_guild_log_webhook),
+        )
+        .route("/scripts", put(routes::scripts::create_guild_script))
         .route(
             "/scripts/:script_id",
             patch(routes::scripts::update_guild_script)
                 .delete(routes::scripts::delete_guild_script),
         )
-        .route(
-            "/scripts/:script_id/validate_settings",
-            post(routes::scripts::validate_script_settings),
-        )
         .route(
             "/scripts/:script_id/update_plugin",
             post(routes::scripts::update_script_plugin),
         )
+        .route(
+            "/scripts/:script_id/plugin_version",
+            post(routes::scripts::set_script_plugin_version),
+        )
+        .route(
+            "/scripts/:script_id/plugin_channel",
+            post(routes::scripts::set_script_plugin_channel),
+        )
+        .route(
+            "/scripts/:script_id/revisions/:revision_id/restore",
+            post(routes::scripts::restore_script_revision),
+        )
+        .route(
+            "/vendored_modules",
+            put(routes::vendored_modules::upload_guild_vendored_module),
+        )
+        .route(
+            "/vendored_modules/:name",
+            delete(routes::vendored_modules::delete_guild_vendored_module),
+        )
+        .route(
+            "/inbound_webhooks",
+            put(routes::inbound_webhooks::create_inbound_webhook),
+        )
+        .route(
+            "/inbound_webhooks/:webhook_id",
+            patch(routes::inbound_webhooks::update_inbound_webhook)
+                .delete(routes::inbound_webhooks::delete_inbound_webhook),
+        )
         .route("/add_plugin", post(routes::plugins::guild_add_plugin))
-        .route("/full_guild", get(routes::guilds::get_full_guild))
+        .route_layer(axum::middleware::from_fn_with_state(
+            ApiTokenOperation::WriteScripts,
+            require_api_token_operation_mw,
+        ));
+
+    let authorized_api_guild_routes = Router::new()
+        .route(
+            "/reload_vm",
+            post(routes::vm::reload_guild_vm).layer(axum::middleware::from_fn_with_state(
+                ApiTokenOperation::ReloadVms,
+                require_api_token_operation_mw,
+            )),
+        )
+        .merge(read_api_guild_routes)
+        .merge(write_api_guild_routes)
         .layer(auth_guild_mw_stack);
 
+    // routes api tokens restricted to specific guilds or operations can use, the guild
+    // middlewares and the operation layers below check the scope of the token
+    let scoped_api_routes = Router::new()
+        .nest("/guilds/:guild", authorized_api_guild_routes)
+        .route("/guilds", get(routes::guilds::list_user_guilds_route))
+        .route("/current_user", get(routes::general::get_current_user))
+        .route(
+            "/user/plugins/:plugin_id/dev_version",
+            patch(routes::plugins::update_plugin_dev_source)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    plugin_middleware,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    ApiTokenOperation::PublishPluginVersions,
+                    require_api_token_operation_mw,
+                )),
+        )
+        .route(
+            "/user/plugins/:plugin_id/publish_script_version",
+            post(routes::plugins::publish_plugin_version)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    plugin_middleware,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    ApiTokenOperation::PublishPluginVersions,
+                    require_api_token_operation_mw,
+                )),
+        )
+        .route(
+            "/user/plugins/:plugin_id/versions/:version_number/rollout",
+            patch(routes::plugins::update_plugin_version_rollout)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    plugin_middleware,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    ApiTokenOperation::PublishPluginVersions,
+                    require_api_token_operation_mw,
+                )),
+        );
+
     let authorized_api_routes =
         Router::new()
-            .nest("/guilds/:guild", authorized_api_guild_routes)
             .nest("/admin", authorized_admin_routes)
-            .route("/guilds", get(routes::guilds::list_user_guilds_route))
             .route(
                 "/premium_slots/:slot_id/update_guild",
                 post(routes::premium::update_premium_slot_guild),
@@ -148,7 +283,6 @@ pub async fn run(
                     .put(routes::sessions::create_api_token),
             )
             .route("/sessions/all", delete(routes::sessions::del_all_sessions))
-            .route("/current_user", get(routes::general::get_current_user))
             .route(
                 "/user/plugins",
                 get(routes::plugins::get_user_plugins).put(routes::plugins::create_plugin),
@@ -160,16 +294,19 @@ pub async fn run(
                 ),
             )
             .route(
-                "/user/plugins/:plugin_id/dev_version",
-                patch(routes::plugins::update_plugin_dev_source).layer(
+                "/user/plugins/:plugin_id/reviews/:user_id/reply",
+                put(routes::plugin_reviews::reply_to_plugin_review).layer(
                     axum::middleware::from_fn_with_state(state.clone(), plugin_middleware),
                 ),
             )
             .route(
-                "/user/plugins/:plugin_id/publish_script_version",
-                post(routes::plugins::publish_plugin_version).layer(
-                    axum::middleware::from_fn_with_state(state.clone(), plugin_middleware),
-                ),
+                "/plugins/:plugin_id/review",
+                put(routes::plugin_reviews::put_plugin_review)
+                    .delete(routes::plugin_reviews::delete_plugin_review)
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        plugin_middleware,
+                    )),
             )
             .route(
                 "/user/plugins/:plugin_id/images",
@@ -183,6 +320,28 @@ pub async fn run(
                     axum::middleware::from_fn_with_state(state.clone(), plugin_middleware),
                 ),
             )
+            .route(
+                "/user/plugins/:plugin_id/vendored_modules",
+                get(routes::vendored_modules::get_plugin_vendored_modules)
+                    .put(routes::vendored_modules::upload_plugin_vendored_module)
+                    .layer(axum::middleware::from_fn_with_state(
+                        state.clone(),
+                        plugin_middleware,
+                    )),
+            )
+            .route(
+                "/user/plugins/:plugin_id/vendored_modules/:name",
+                delete(routes::vendored_modules::delete_plugin_vendored_module).layer(
+                    axum::middleware::from_fn_with_state(state.clone(), plugin_middleware),
+                ),
+            )
+            // diffing is expensive, so unlike the versions themselves this needs a session
+            .route(
+                "/plugins/:plugin_id/versions/:from_version/diff/:to_version",
+                get(routes::plugins::diff_plugin_versions).layer(
+                    axum::middleware::from_fn_with_state(state.clone(), plugin_middleware),
+                ),
+            )
             .route("/logout", post(AuthHandlers::handle_logout))
             .route(
                 "/stripe/customer_portal",
@@ -191,7 +350,9 @@ pub async fn run(
             .route(
                 "/stripe/create_checkout_session",
                 post(routes::stripe::handle_create_checkout_session),
-            );
+            )
+            .layer(axum::middleware::from_fn(require_unrestricted_session_mw))
+            .merge(scoped_api_routes);
 
     let auth_routes_mw_stack = ServiceBuilder::new()
         .layer(HandleErrorLayer::new(handle_mw_err_no_auth))
@@ -201,6 +362,25 @@ pub async fn run(
         .nest("/api", authorized_api_routes)
         .layer(auth_routes_mw_stack);
 
+    // public routes for a specific plugin
+    let public_plugin_routes = Router::new()
+        .route(
+            "/api/plugins/:plugin_id/versions",
+            get(routes::plugins::get_plugin_versions),
+        )
+        .route(
+            "/api/plugins/:plugin_id/versions/:version_number",
+            get(routes::plugins::get_plugin_version),
+        )
+        .route(
+            "/api/plugins/:plugin_id/reviews",
+            get(routes::plugin_reviews::get_plugin_reviews),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            plugin_middleware,
+        ));
+
     let public_routes = Router::new()
         .route("/error", get(routes::errortest::handle_errortest))
         .route("/login", get(AuthHandlers::handle_login))
@@ -212,6 +392,7 @@ pub async fn run(
             "/api/plugins",
             get(routes::plugins::get_published_public_plugins),
         )
+        .route("/api/plugins/search", get(routes::plugins::search_plugins))
         .route(
             "/api/plugins/:plugin_id",
             get(routes::plugins::get_plugin).layer(axum::middleware::from_fn_with_state(
@@ -225,7 +406,14 @@ pub async fn run(
             "/api/confirm_login",
             post(AuthHandlers::handle_confirm_login),
         )
-        .route("/api/stripe/webhook", post(routes::stripe::handle_webhook));
+        .route("/api/stripe/webhook", post(routes::stripe::handle_webhook))
+        .route(
+            "/api/hooks/:guild/:webhook_id",
+            post(routes::inbound_webhooks::handle_inbound_webhook).layer(DefaultBodyLimit::max(
+                routes::inbound_webhooks::MAX_WEBHOOK_BODY_SIZE,
+            )),
+        )
+        .merge(public_plugin_routes);
 
     let app = public_routes
         .merge(authorized_routes)
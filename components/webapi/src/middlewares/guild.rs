@@ -37,6 +37,10 @@ pub async fn current_guild_injector_middleware(
 
     if let (Some(s), Ok(gp)) = (session, guild_path) {
         if let Some(guild_id) = Id::<GuildMarker>::new_checked(gp.guild) {
+            if !s.session.scope.allows_guild(guild_id) {
+                return Err(ApiErrorResponse::ApiTokenMissingScope);
+            }
+
             if let Some(g) = fetch_guild(s, guild_id).await? {
                 span = Some(tracing::info_span!("guild", guild_id=%g.id));
                 request.extensions_mut().insert(g);
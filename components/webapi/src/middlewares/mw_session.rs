@@ -1,8 +1,10 @@
 use discordoauthwrapper::{ClientCache, DiscordOauthApiClient, TwilightApiProvider};
 
 use axum::{
+    extract::State,
     http::{Request, Response},
-    BoxError,
+    middleware::Next,
+    BoxError, Extension,
 };
 use core::fmt;
 use futures::future::BoxFuture;
@@ -14,7 +16,12 @@ use std::{
 use tower::{Layer, Service};
 use tracing::{error, Instrument};
 
-use stores::{web::Session, Db};
+use stores::{
+    web::{ApiTokenOperation, Session},
+    Db,
+};
+
+use crate::errors::ApiErrorResponse;
 
 type OAuthApiClientWrapper = DiscordOauthApiClient<TwilightApiProvider, oauth2::basic::BasicClient>;
 
@@ -123,7 +130,16 @@ where
 
             match auth_header.map(|e| e.to_str()) {
                 Some(Ok(t)) => {
-                    if let Some(session) = store.get_session(t).await? {
+                    // expired api tokens are treated the same as unknown ones
+                    if let Some(session) = store.get_session(t).await?.filter(|s| !s.is_expired()) {
+                        if session.should_update_last_used() {
+                            store
+                                .set_session_last_used(&session.token)
+                                .await
+                                .map_err(|err| error!(%err, "failed updating session last used"))
+                                .ok();
+                        }
+
                         let extensions = req.extensions_mut();
 
                         let span = tracing::info_span!("session", user_id=%session.user.id);
@@ -241,3 +257,31 @@ impl fmt::Display for NoSession {
 }
 
 impl std::error::Error for NoSession {}
+
+/// Rejects api tokens that are restricted to specific guilds or operations, used for the routes that
+/// manage the account itself
+pub async fn require_unrestricted_session_mw(
+    session: Extension<LoggedInSession>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Result<axum::response::Response, ApiErrorResponse> {
+    if session.session.scope.is_restricted() {
+        return Err(ApiErrorResponse::ApiTokenMissingScope);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Rejects api tokens that are not allowed to perform the operation provided as the state
+pub async fn require_api_token_operation_mw(
+    State(operation): State<ApiTokenOperation>,
+    session: Extension<LoggedInSession>,
+    request: axum::extract::Request,
+    next: Next,
+) -> Result<axum::response::Response, ApiErrorResponse> {
+    if !session.session.scope.allows_operation(operation) {
+        return Err(ApiErrorResponse::ApiTokenMissingScope);
+    }
+
+    Ok(next.run(request).await)
+}
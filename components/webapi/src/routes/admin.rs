@@ -1,11 +1,20 @@
+use std::time::Duration;
+
 use axum::{
     extract::{Path, State},
+    response::IntoResponse,
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use stores::{
+    plugin_reviews::PluginReview,
+    suspensions::{GuildSuspension, SuspensionReason},
+};
 use twilight_model::id::{marker::GuildMarker, Id};
+use validation::{validate, ValidationContext, Validator};
 
-use crate::{app_state::AppState, errors::ApiErrorResponse, ApiResult};
+use crate::{app_state::AppState, errors::ApiErrorResponse, util::EmptyResponse, ApiResult};
 
 use tracing::error;
 
@@ -32,11 +41,49 @@ pub async fn get_worker_statuses(
                 last_claimed_by_guild_id: v.last_claimed_by_guild_id.map(|v| v.to_string()),
                 claimed_last_ms_ago: v.claimed_last_ms_ago,
                 returned_last_ms_ago: v.returned_last_ms_ago,
+                remote_name: v.remote_name,
             })
             .collect::<Vec<_>>(),
     ))
 }
 
+pub async fn get_worker_queue_status(
+    State(state): State<AppState>,
+) -> ApiResult<Json<ApiWorkerQueueStatus>> {
+    let response = state
+        .bot_rpc_client
+        .get_vm_worker_pool_status()
+        .await
+        .map_err(|err| {
+            error!(%err, "failed retrieving vm worker pool status");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(ApiWorkerQueueStatus {
+        queued_requests: response
+            .queued_requests
+            .into_iter()
+            .map(|v| ApiQueuedWorkerRequest {
+                guild_id: v.guild_id.to_string(),
+                priority_index: v.priority_index,
+                waiting_ms: v.waiting_ms,
+            })
+            .collect(),
+        guild_waits: response
+            .guild_waits
+            .into_iter()
+            .map(|v| ApiGuildWorkerWait {
+                guild_id: v.guild_id.to_string(),
+                last_wait_ms: v.last_wait_ms,
+                max_wait_ms: v.max_wait_ms,
+                total_wait_ms: v.total_wait_ms,
+                num_waits: v.num_waits,
+                num_shed: v.num_shed,
+            })
+            .collect(),
+    }))
+}
+
 #[derive(Deserialize)]
 pub struct GuildIdParam {
     pub guild_id: u64,
@@ -69,6 +116,249 @@ pub async fn get_guild_status(
     }))
 }
 
+// how many past suspensions of a guild are returned
+const SUSPENSION_HISTORY_LIMIT: u32 = 100;
+
+pub async fn get_active_suspensions(
+    State(state): State<AppState>,
+) -> ApiResult<Json<Vec<ApiGuildSuspension>>> {
+    let suspensions = state
+        .db
+        .get_active_guild_suspensions()
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching active guild suspensions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(suspensions.into_iter().map(Into::into).collect()))
+}
+
+pub async fn get_guild_suspensions(
+    Path(params): Path<GuildIdParam>,
+    State(state): State<AppState>,
+) -> ApiResult<Json<Vec<ApiGuildSuspension>>> {
+    let Some(guild_id) = Id::new_checked(params.guild_id) else {
+        return Err(ApiErrorResponse::NoActiveGuild);
+    };
+
+    let suspensions = state
+        .db
+        .get_guild_suspension_history(guild_id, SUSPENSION_HISTORY_LIMIT)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching guild suspensions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(suspensions.into_iter().map(Into::into).collect()))
+}
+
+#[derive(Deserialize)]
+pub struct ImposeSuspensionRequest {
+    /// Suspended until lifted if not set
+    duration_secs: Option<u64>,
+    message: Option<String>,
+}
+
+impl Validator for ImposeSuspensionRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(secs) = self.duration_secs {
+            validation::web::check_suspension_duration(ctx, "duration_secs", secs);
+        }
+
+        if let Some(message) = &self.message {
+            validation::web::check_suspension_message(ctx, "message", message);
+        }
+    }
+}
+
+pub async fn impose_guild_suspension(
+    Path(params): Path<GuildIdParam>,
+    State(state): State<AppState>,
+    Json(body): Json<ImposeSuspensionRequest>,
+) -> ApiResult<Json<Vec<ApiGuildSuspension>>> {
+    let Some(guild_id) = Id::new_checked(params.guild_id) else {
+        return Err(ApiErrorResponse::NoActiveGuild);
+    };
+
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    state
+        .bot_rpc_client
+        .impose_guild_suspension(
+            guild_id,
+            body.duration_secs.map(Duration::from_secs),
+            body.message,
+        )
+        .await
+        .map_err(|err| {
+            error!(%err, "failed imposing guild suspension");
+            ApiErrorResponse::InternalError
+        })?;
+
+    get_guild_suspensions(Path(params), State(state)).await
+}
+
+pub async fn lift_guild_suspension(
+    Path(params): Path<GuildIdParam>,
+    State(state): State<AppState>,
+) -> ApiResult<Json<ApiLiftSuspensionResponse>> {
+    let Some(guild_id) = Id::new_checked(params.guild_id) else {
+        return Err(ApiErrorResponse::NoActiveGuild);
+    };
+
+    let was_suspended = state
+        .bot_rpc_client
+        .lift_guild_suspension(guild_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed lifting guild suspension");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(ApiLiftSuspensionResponse { was_suspended }))
+}
+
+#[derive(Deserialize)]
+pub struct PluginReviewParams {
+    pub plugin_id: u64,
+    pub user_id: u64,
+}
+
+/// Returns all the reviews of the plugin, including hidden ones
+pub async fn get_plugin_reviews(
+    Path(params): Path<PluginIdParam>,
+    State(state): State<AppState>,
+) -> ApiResult<Json<Vec<PluginReview>>> {
+    let reviews = state
+        .db
+        .get_plugin_reviews(params.plugin_id, true)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching plugin reviews");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(reviews))
+}
+
+#[derive(Deserialize)]
+pub struct PluginIdParam {
+    pub plugin_id: u64,
+}
+
+#[derive(Deserialize)]
+pub struct ModeratePluginReviewRequest {
+    hidden: bool,
+    reason: Option<String>,
+}
+
+impl Validator for ModeratePluginReviewRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(reason) = &self.reason {
+            validation::web::check_suspension_message(ctx, "reason", reason);
+        }
+    }
+}
+
+pub async fn moderate_plugin_review(
+    Path(params): Path<PluginReviewParams>,
+    State(state): State<AppState>,
+    Json(body): Json<ModeratePluginReviewRequest>,
+) -> ApiResult<Json<PluginReview>> {
+    let Some(user_id) = Id::new_checked(params.user_id) else {
+        return Err(ApiErrorResponse::PluginReviewNotFound);
+    };
+
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let hidden_reason = body
+        .hidden
+        .then(|| body.reason.as_deref().unwrap_or_default());
+
+    let review = state
+        .db
+        .set_plugin_review_hidden(params.plugin_id, user_id, hidden_reason)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::PluginReviewNotFound
+            } else {
+                error!(%err, "failed moderating plugin review");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(Json(review))
+}
+
+pub async fn delete_plugin_review(
+    Path(params): Path<PluginReviewParams>,
+    State(state): State<AppState>,
+) -> ApiResult<impl IntoResponse> {
+    let Some(user_id) = Id::new_checked(params.user_id) else {
+        return Err(ApiErrorResponse::PluginReviewNotFound);
+    };
+
+    state
+        .db
+        .delete_plugin_review(params.plugin_id, user_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::PluginReviewNotFound
+            } else {
+                error!(%err, "failed deleting plugin review");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(EmptyResponse)
+}
+
+#[derive(Serialize)]
+pub struct ApiGuildSuspension {
+    pub id: String,
+    pub guild_id: String,
+    pub reason: SuspensionReason,
+    pub message: Option<String>,
+    pub offense_level: u32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub lifted_at: Option<DateTime<Utc>>,
+    pub active: bool,
+}
+
+impl From<GuildSuspension> for ApiGuildSuspension {
+    fn from(value: GuildSuspension) -> Self {
+        Self {
+            active: value.is_active(),
+            id: value.id.to_string(),
+            guild_id: value.guild_id.to_string(),
+            reason: value.reason,
+            message: value.message,
+            offense_level: value.offense_level,
+            created_at: value.created_at,
+            expires_at: value.expires_at,
+            lifted_at: value.lifted_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ApiLiftSuspensionResponse {
+    pub was_suspended: bool,
+}
+
 #[derive(Serialize)]
 pub struct ApiVmWorkerStatus {
     pub worker_id: u32,
@@ -76,6 +366,30 @@ pub struct ApiVmWorkerStatus {
     pub last_claimed_by_guild_id: Option<String>,
     pub claimed_last_ms_ago: u64,
     pub returned_last_ms_ago: u64,
+    pub remote_name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ApiWorkerQueueStatus {
+    pub queued_requests: Vec<ApiQueuedWorkerRequest>,
+    pub guild_waits: Vec<ApiGuildWorkerWait>,
+}
+
+#[derive(Serialize)]
+pub struct ApiQueuedWorkerRequest {
+    pub guild_id: String,
+    pub priority_index: u32,
+    pub waiting_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct ApiGuildWorkerWait {
+    pub guild_id: String,
+    pub last_wait_ms: u64,
+    pub max_wait_ms: u64,
+    pub total_wait_ms: u64,
+    pub num_waits: u32,
+    pub num_shed: u32,
 }
 
 #[derive(Serialize)]
@@ -5,10 +5,10 @@ use axum::{
 };
 use chrono::{DateTime, Utc};
 use dbrokerapi::models::BrokerGuild;
-use stores::config::{PremiumSlot, PremiumSlotTier};
+use stores::config::{GuildLogWebhook, GuildMetaConfig, PremiumSlot, PremiumSlotTier};
 use twilight_model::{
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{ChannelMarker, GuildMarker, UserMarker},
         Id,
     },
     user::CurrentUserGuild,
@@ -18,8 +18,9 @@ use crate::{
     app_state::AppState, errors::ApiErrorResponse, middlewares::LoggedInSession, ApiResult,
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing::error;
+use validation::{validate, ValidationContext, Validator};
 
 #[derive(Serialize)]
 pub struct GuildList {
@@ -45,6 +46,12 @@ pub async fn list_user_guilds_route(
             ApiErrorResponse::InternalError
         })?;
 
+    // api tokens restricted to specific guilds only see those
+    let user_guilds = user_guilds
+        .into_iter()
+        .filter(|g| session.session.scope.allows_guild(g.id))
+        .collect::<Vec<_>>();
+
     let guild_ids = user_guilds.iter().map(|g| g.id).collect::<Vec<_>>();
 
     let connected_guilds = state
@@ -70,10 +77,39 @@ pub async fn list_user_guilds_route(
     Ok(Json(GuildList { guilds: result }))
 }
 
+/// The guild settings as returned by the api, the secret of the log webhook is only included
+/// when it was just created or regenerated
+#[derive(Serialize)]
+pub struct ApiGuildSettings {
+    pub guild_id: Id<GuildMarker>,
+    pub error_channel_id: Option<Id<ChannelMarker>>,
+    pub log_webhook: Option<ApiGuildLogWebhook>,
+}
+
+#[derive(Serialize)]
+pub struct ApiGuildLogWebhook {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl ApiGuildSettings {
+    fn new(settings: GuildMetaConfig, include_secret: bool) -> Self {
+        Self {
+            guild_id: settings.guild_id,
+            error_channel_id: settings.error_channel_id,
+            log_webhook: settings.log_webhook.map(|v| ApiGuildLogWebhook {
+                url: v.url,
+                secret: include_secret.then_some(v.secret),
+            }),
+        }
+    }
+}
+
 pub async fn get_guild_settings(
     State(state): State<AppState>,
     Extension(current_guild): Extension<CurrentUserGuild>,
-) -> ApiResult<impl IntoResponse> {
+) -> ApiResult<Json<ApiGuildSettings>> {
     let settings = state
         .db
         .get_guild_meta_config_or_default(current_guild.id)
@@ -83,7 +119,71 @@ pub async fn get_guild_settings(
             ApiErrorResponse::InternalError
         })?;
 
-    Ok(Json(settings))
+    Ok(Json(ApiGuildSettings::new(settings, false)))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateLogWebhookRequest {
+    url: Option<String>,
+    #[serde(default)]
+    regenerate_secret: bool,
+}
+
+impl Validator for UpdateLogWebhookRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(url) = &self.url {
+            validation::web::check_log_webhook_url(ctx, "url", url);
+        }
+    }
+}
+
+pub async fn update_guild_log_webhook(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(body): Json<UpdateLogWebhookRequest>,
+) -> ApiResult<Json<ApiGuildSettings>> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let mut new_secret = false;
+    let webhook = if let Some(url) = body.url {
+        let current = state
+            .db
+            .get_guild_meta_config_or_default(current_guild.id)
+            .await
+            .map_err(|err| {
+                error!(%err, "failed fetching guild config");
+                ApiErrorResponse::InternalError
+            })?;
+
+        // keep the current secret around unless asked not to, so the receiving end
+        // does not have to be updated when only the url changes
+        let secret = match current.log_webhook {
+            Some(existing) if !body.regenerate_secret => existing.secret,
+            _ => {
+                new_secret = true;
+                stores::web::gen_token()
+            }
+        };
+
+        Some(GuildLogWebhook { url, secret })
+    } else {
+        None
+    };
+
+    let settings = state
+        .db
+        .update_guild_log_webhook(current_guild.id, webhook)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed updating guild log webhook");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(ApiGuildSettings::new(settings, new_secret)))
 }
 
 pub async fn get_guild_premium_slots(
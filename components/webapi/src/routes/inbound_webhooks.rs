@@ -0,0 +1,354 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use stores::{config::ConfigStoreError, inbound_webhooks::InboundWebhook};
+use tracing::error;
+use twilight_model::{
+    id::{marker::GuildMarker, Id},
+    user::CurrentUserGuild,
+};
+use validation::{validate, ValidationContext, Validator};
+
+use crate::{app_state::AppState, errors::ApiErrorResponse, util::EmptyResponse, ApiResult};
+
+/// Max size of the json body of requests to inbound webhooks
+pub const MAX_WEBHOOK_BODY_SIZE: usize = 64 * 1024;
+
+// requests signed longer ago than this are rejected, so captured requests can't be replayed later
+const MAX_SIGNATURE_AGE: Duration = Duration::from_secs(5 * 60);
+
+// how long to wait for the scripts to respond before giving up on the request
+const DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RATELIMIT_WINDOW: Duration = Duration::from_secs(60);
+const RATELIMIT_MAX_REQUESTS: u32 = 60;
+
+#[derive(Deserialize)]
+pub struct CreateInboundWebhookRequest {
+    name: String,
+}
+
+impl Validator for CreateInboundWebhookRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        validation::web::check_inbound_webhook_name(ctx, "name", &self.name);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateInboundWebhookRequest {
+    name: Option<String>,
+    #[serde(default)]
+    regenerate_secret: bool,
+}
+
+impl Validator for UpdateInboundWebhookRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(name) = &self.name {
+            validation::web::check_inbound_webhook_name(ctx, "name", name);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InboundWebhookPathParams {
+    pub webhook_id: u64,
+}
+
+/// The secret is only included right after it was generated, it can't be fetched again later
+#[derive(Serialize)]
+pub struct ApiInboundWebhook {
+    pub id: u64,
+    pub guild_id: Id<GuildMarker>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_triggered_at: Option<DateTime<Utc>>,
+}
+
+impl ApiInboundWebhook {
+    fn new(webhook: InboundWebhook, include_secret: bool) -> Self {
+        Self {
+            id: webhook.id,
+            guild_id: webhook.guild_id,
+            name: webhook.name,
+            secret: include_secret.then_some(webhook.secret),
+            created_at: webhook.created_at,
+            last_triggered_at: webhook.last_triggered_at,
+        }
+    }
+}
+
+pub async fn get_inbound_webhooks(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<Json<Vec<ApiInboundWebhook>>> {
+    let webhooks = state
+        .db
+        .get_inbound_webhooks(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching inbound webhooks");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(
+        webhooks
+            .into_iter()
+            .map(|v| ApiInboundWebhook::new(v, false))
+            .collect(),
+    ))
+}
+
+pub async fn create_inbound_webhook(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(body): Json<CreateInboundWebhookRequest>,
+) -> ApiResult<Json<ApiInboundWebhook>> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let webhook = state
+        .db
+        .create_inbound_webhook(current_guild.id, &body.name, &stores::web::gen_token())
+        .await
+        .map_err(store_err_to_api_err)?;
+
+    Ok(Json(ApiInboundWebhook::new(webhook, true)))
+}
+
+pub async fn update_inbound_webhook(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(InboundWebhookPathParams { webhook_id }): Path<InboundWebhookPathParams>,
+    Json(body): Json<UpdateInboundWebhookRequest>,
+) -> ApiResult<Json<ApiInboundWebhook>> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let secret = body.regenerate_secret.then(stores::web::gen_token);
+    let webhook = state
+        .db
+        .update_inbound_webhook(
+            current_guild.id,
+            webhook_id,
+            body.name.as_deref(),
+            secret.as_deref(),
+        )
+        .await
+        .map_err(store_err_to_api_err)?;
+
+    Ok(Json(ApiInboundWebhook::new(
+        webhook,
+        body.regenerate_secret,
+    )))
+}
+
+pub async fn delete_inbound_webhook(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(InboundWebhookPathParams { webhook_id }): Path<InboundWebhookPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    state
+        .db
+        .delete_inbound_webhook(current_guild.id, webhook_id)
+        .await
+        .map_err(store_err_to_api_err)?;
+
+    Ok(EmptyResponse)
+}
+
+fn store_err_to_api_err(err: ConfigStoreError) -> ApiErrorResponse {
+    match err {
+        ConfigStoreError::InboundWebhookNotFound(_) => ApiErrorResponse::InboundWebhookNotFound,
+        ConfigStoreError::InboundWebhookLimitReached(_) => {
+            ApiErrorResponse::MaxInboundWebhooksReached
+        }
+        ConfigStoreError::InboundWebhookNameTaken => ApiErrorResponse::InboundWebhookNameTaken,
+        other => {
+            error!(%other, "failed updating inbound webhook");
+            ApiErrorResponse::InternalError
+        }
+    }
+}
+
+/// Public endpoint external services call, the request is passed on to the guild's scripts as a
+/// webhook event
+///
+/// Requests have to be signed the same way as the log webhooks botloader sends out:
+///  - `X-Botloader-Timestamp`: unix timestamp in seconds of when the request was sent
+///  - `X-Botloader-Signature`: `sha256=<hex hmac>` of `<timestamp>.<body>` using the webhook's secret
+///
+/// Responds with the json body returned by the script, or with no content if it didn't return one
+pub async fn handle_inbound_webhook(
+    State(state): State<AppState>,
+    Path((guild_id, webhook_id)): Path<(u64, u64)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> ApiResult<Response> {
+    let Some(guild_id) = Id::<GuildMarker>::new_checked(guild_id) else {
+        return Err(ApiErrorResponse::InboundWebhookNotFound);
+    };
+
+    let webhook = state
+        .db
+        .get_inbound_webhook(guild_id, webhook_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::InboundWebhookNotFound
+            } else {
+                error!(%err, "failed fetching inbound webhook");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    if !verify_signature(&webhook.secret, &headers, &body) {
+        return Err(ApiErrorResponse::InvalidWebhookSignature);
+    }
+
+    // only signed requests count, otherwise anyone could use up the limit of the webhook
+    if !state.inbound_webhook_ratelimiter.check(webhook_id) {
+        return Err(ApiErrorResponse::WebhookRateLimited);
+    }
+
+    let parsed: serde_json::Value =
+        serde_json::from_slice(&body).map_err(ApiErrorResponse::InvalidWebhookBody)?;
+
+    if let Err(err) = state
+        .db
+        .set_inbound_webhook_triggered(guild_id, webhook_id)
+        .await
+    {
+        error!(%err, "failed updating inbound webhook last triggered");
+    }
+
+    let dispatch = state.bot_rpc_client.dispatch_webhook(
+        guild_id,
+        webhook.id,
+        webhook.name,
+        parsed.to_string(),
+    );
+
+    let response = match tokio::time::timeout(DISPATCH_TIMEOUT, dispatch).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(status)) if status.code() == tonic::Code::Unavailable => {
+            return Err(ApiErrorResponse::WebhookNotDelivered(
+                status.message().to_owned(),
+            ))
+        }
+        Ok(Err(err)) => {
+            error!(%err, "failed dispatching inbound webhook");
+            return Err(ApiErrorResponse::InternalError);
+        }
+        Err(_) => return Err(ApiErrorResponse::WebhookTimedOut),
+    };
+
+    match response {
+        Some(body) => Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/json")],
+            body,
+        )
+            .into_response()),
+        None => Ok(EmptyResponse.into_response()),
+    }
+}
+
+fn verify_signature(secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+    let (Some(timestamp), Some(signature)) = (
+        headers
+            .get("X-Botloader-Timestamp")
+            .and_then(|v| v.to_str().ok()),
+        headers
+            .get("X-Botloader-Signature")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("sha256=")),
+    ) else {
+        return false;
+    };
+
+    let Ok(sent_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+
+    let age = chrono::Utc::now().timestamp().abs_diff(sent_at);
+    if age > MAX_SIGNATURE_AGE.as_secs() {
+        return false;
+    }
+
+    let Ok(decoded) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.verify_slice(&decoded).is_ok()
+}
+
+/// Limits the number of requests each inbound webhook accepts within a fixed window
+///
+/// This is per webapi instance, which is fine since it's only there to keep a misbehaving
+/// sender from flooding the guild's vm
+#[derive(Default, Clone)]
+pub struct InboundWebhookRateLimiter {
+    windows: Arc<Mutex<HashMap<u64, (Instant, u32)>>>,
+}
+
+impl InboundWebhookRateLimiter {
+    /// Returns false if the webhook has used up its requests for the current window
+    pub fn check(&self, webhook_id: u64) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        let (started, count) = windows.entry(webhook_id).or_insert((now, 0));
+        if now.duration_since(*started) >= RATELIMIT_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+
+        if *count >= RATELIMIT_MAX_REQUESTS {
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+
+    /// Periodically drops the expired windows so webhooks that are no longer used don't pile up
+    pub async fn run_pruning(self) {
+        let mut interval = tokio::time::interval(RATELIMIT_WINDOW);
+        loop {
+            interval.tick().await;
+
+            let now = Instant::now();
+            self.windows
+                .lock()
+                .unwrap()
+                .retain(|_, (started, _)| now.duration_since(*started) < RATELIMIT_WINDOW);
+        }
+    }
+}
@@ -3,10 +3,13 @@ pub mod auth;
 pub mod errortest;
 pub mod general;
 pub mod guilds;
+pub mod inbound_webhooks;
+pub mod plugin_reviews;
 pub mod plugins;
 pub mod premium;
 pub mod scripts;
 pub mod sessions;
 pub mod stripe;
+pub mod vendored_modules;
 pub mod vm;
 pub mod ws;
@@ -0,0 +1,219 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use common::plugin::Plugin;
+use serde::{Deserialize, Serialize};
+use stores::plugin_reviews::PluginReview;
+use tracing::error;
+use twilight_model::{
+    guild::Permissions,
+    id::{marker::UserMarker, Id},
+};
+use validation::{validate, ValidationContext, Validator};
+
+use crate::{
+    app_state::AppState, errors::ApiErrorResponse, middlewares::LoggedInSession,
+    util::EmptyResponse, ApiResult,
+};
+
+/// A review as shown to everyone, the guild the reviewer has the plugin installed on is only
+/// shown to the admins
+#[derive(Serialize)]
+pub struct ApiPluginReview {
+    pub plugin_id: u64,
+    pub user_id: Id<UserMarker>,
+    pub rating: u8,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub author_reply: Option<String>,
+    pub author_replied_at: Option<DateTime<Utc>>,
+    pub hidden_at: Option<DateTime<Utc>>,
+    pub hidden_reason: Option<String>,
+}
+
+impl From<PluginReview> for ApiPluginReview {
+    fn from(review: PluginReview) -> Self {
+        Self {
+            plugin_id: review.plugin_id,
+            user_id: review.user_id,
+            rating: review.rating,
+            body: review.body,
+            created_at: review.created_at,
+            updated_at: review.updated_at,
+            author_reply: review.author_reply,
+            author_replied_at: review.author_replied_at,
+            hidden_at: review.hidden_at,
+            hidden_reason: review.hidden_reason,
+        }
+    }
+}
+
+pub async fn get_plugin_reviews(
+    State(state): State<AppState>,
+    Extension(plugin): Extension<Plugin>,
+) -> ApiResult<Json<Vec<ApiPluginReview>>> {
+    let reviews = state
+        .db
+        .get_plugin_reviews(plugin.id, false)
+        .await
+        .map_err(|err| {
+            error!(?err, "failed fetching plugin reviews");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(reviews.into_iter().map(Into::into).collect()))
+}
+
+#[derive(Deserialize)]
+pub struct PutPluginReviewData {
+    rating: u8,
+    #[serde(default)]
+    body: String,
+}
+
+impl Validator for PutPluginReviewData {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        validation::web::check_plugin_review_rating(ctx, "rating", self.rating);
+        validation::web::check_plugin_review_text(ctx, "body", &self.body);
+    }
+}
+
+/// Creates or replaces the current user's review of the plugin
+///
+/// Only users that admin a server with the plugin installed can review it
+pub async fn put_plugin_review(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+    Json(body): Json<PutPluginReviewData>,
+) -> ApiResult<Json<ApiPluginReview>> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let user_guilds = session
+        .api_client
+        .current_user_guilds()
+        .await
+        .map_err(|err| {
+            error!(?err, "failed fetching user guilds");
+            ApiErrorResponse::InternalError
+        })?;
+
+    let admin_guilds = user_guilds
+        .into_iter()
+        .filter(|g| {
+            g.permissions
+                .intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD)
+        })
+        .map(|g| g.id)
+        .collect::<Vec<_>>();
+
+    let guild_id = state
+        .db
+        .find_guild_with_plugin(plugin.id, &admin_guilds)
+        .await
+        .map_err(|err| {
+            error!(?err, "failed finding guild with plugin");
+            ApiErrorResponse::InternalError
+        })?
+        .ok_or(ApiErrorResponse::NotAdminOfGuildWithPlugin)?;
+
+    let review = state
+        .db
+        .upsert_plugin_review(
+            plugin.id,
+            session.session.user.id,
+            guild_id,
+            body.rating,
+            &body.body,
+        )
+        .await
+        .map_err(|err| {
+            error!(?err, "failed saving plugin review");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(review.into()))
+}
+
+pub async fn delete_plugin_review(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+) -> ApiResult<impl IntoResponse> {
+    state
+        .db
+        .delete_plugin_review(plugin.id, session.session.user.id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::PluginReviewNotFound
+            } else {
+                error!(?err, "failed deleting plugin review");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(EmptyResponse)
+}
+
+#[derive(Deserialize)]
+pub struct PluginReviewPathParams {
+    user_id: u64,
+}
+
+#[derive(Deserialize)]
+pub struct ReplyToPluginReviewData {
+    /// Removes the reply if not set
+    reply: Option<String>,
+}
+
+impl Validator for ReplyToPluginReviewData {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(reply) = &self.reply {
+            validation::web::check_plugin_review_text(ctx, "reply", reply);
+        }
+    }
+}
+
+pub async fn reply_to_plugin_review(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+    Path(PluginReviewPathParams { user_id }): Path<PluginReviewPathParams>,
+    Json(body): Json<ReplyToPluginReviewData>,
+) -> ApiResult<Json<ApiPluginReview>> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    if plugin.author_id != session.session.user.id {
+        return Err(ApiErrorResponse::NoAccessToPlugin);
+    }
+
+    let user_id = Id::new_checked(user_id).ok_or(ApiErrorResponse::PluginReviewNotFound)?;
+
+    let review = state
+        .db
+        .set_plugin_review_reply(plugin.id, user_id, body.reply.as_deref())
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::PluginReviewNotFound
+            } else {
+                error!(?err, "failed replying to plugin review");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(Json(review.into()))
+}
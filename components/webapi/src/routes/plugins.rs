@@ -1,19 +1,23 @@
 use std::io::Cursor;
 
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, StatusCode},
     response::IntoResponse,
     Extension, Json,
 };
 use common::{
-    plugin::{Plugin, PluginImageKind},
+    plugin::{
+        LibraryModule, Plugin, PluginCapabilities, PluginCategory, PluginDependency,
+        PluginImageKind, PluginVersion, ReleaseChannel, SettingsMigration,
+    },
     DiscordConfig,
 };
 use image::{codecs::webp::WebPEncoder, GenericImageView, ImageError, Limits};
 use serde::{Deserialize, Serialize};
 use stores::config::{
-    ConfigStoreError, CreateImage, CreatePlugin, CreateUpdatePluginImage, UpdatePluginMeta,
+    ConfigStoreError, CreateImage, CreatePlugin, CreateUpdatePluginImage, PluginSearchCursor,
+    PluginSearchQuery, PluginSort, PublishScriptPluginVersion, UpdatePluginMeta,
 };
 use tracing::error;
 use twilight_http::api_error::{ApiError, GeneralApiError};
@@ -22,13 +26,13 @@ use twilight_model::{
     user::{CurrentUser, CurrentUserGuild},
 };
 use uuid::Uuid;
-use validation::{validate, ValidationContext, Validator};
+use validation::{validate, ValidationContext, ValidationError, Validator};
 
 use crate::{
     app_state::AppState,
     errors::ApiErrorResponse,
     middlewares::{plugins::fetch_plugin, LoggedInSession, OptionalSession},
-    util::EmptyResponse,
+    util::{diff_script_sources, DiffSide, EmptyResponse},
     ApiResult,
 };
 
@@ -79,6 +83,84 @@ pub async fn get_published_public_plugins(
     Ok(Json(plugins))
 }
 
+#[derive(Deserialize)]
+pub struct SearchPluginsQuery {
+    q: Option<String>,
+    category: Option<PluginCategory>,
+    /// Comma separated, only plugins with all of the tags are returned
+    tags: Option<String>,
+    /// Defaults to relevance when searching for text, installed guilds otherwise
+    sort: Option<PluginSort>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct SearchPluginsResponse {
+    plugins: Vec<PluginResponse>,
+    /// Pass this as the cursor to get the next page, none if this was the last one
+    next_cursor: Option<String>,
+}
+
+pub async fn search_plugins(
+    State(state): State<AppState>,
+    Extension(maybe_session): Extension<OptionalSession>,
+    Query(query): Query<SearchPluginsQuery>,
+) -> ApiResult<Json<SearchPluginsResponse>> {
+    let text = query
+        .q
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    let after = match &query.cursor {
+        Some(cursor) => Some(PluginSearchCursor::decode(cursor).ok_or_else(|| {
+            ApiErrorResponse::ValidationFailed(vec![ValidationError {
+                field: "cursor".to_string(),
+                msg: "invalid cursor".to_string(),
+            }])
+        })?),
+        None => None,
+    };
+
+    let search = PluginSearchQuery {
+        sort: query.sort.unwrap_or(if text.is_some() {
+            PluginSort::Relevance
+        } else {
+            PluginSort::InstalledGuilds
+        }),
+        text,
+        category: query.category,
+        tags: query
+            .tags
+            .map(|v| {
+                v.split(',')
+                    .map(|tag| tag.trim().to_lowercase())
+                    .filter(|tag| !tag.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        after,
+        limit: query.limit.unwrap_or(50).clamp(1, 100),
+    };
+
+    let page = state.db.search_plugins(&search).await.map_err(|err| {
+        error!(?err, "failed searching plugins");
+        ApiErrorResponse::InternalError
+    })?;
+
+    let plugins = fetch_plugin_authors(
+        &state.discord_config,
+        maybe_session.as_ref().map(|v| &v.session.user),
+        &page.plugins,
+    )
+    .await?;
+
+    Ok(Json(SearchPluginsResponse {
+        plugins,
+        next_cursor: page.next_cursor.map(|v| v.encode()),
+    }))
+}
+
 // get user plugins
 pub async fn get_user_plugins(
     State(state): State<AppState>,
@@ -175,6 +257,10 @@ pub struct UpdatePluginMetaRequest {
     pub is_public: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_published: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<PluginCategory>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
 }
 
 // update plugin meta
@@ -193,6 +279,10 @@ pub async fn update_plugin_meta(
         author_id: None,
         is_published: body.is_published,
         discord_thread_id: None,
+        category: body.category,
+        tags: body
+            .tags
+            .map(|tags| tags.iter().map(|v| v.trim().to_lowercase()).collect()),
     };
 
     if let Err(err) = validate(&update, &()) {
@@ -218,12 +308,20 @@ pub async fn update_plugin_meta(
 #[derive(Deserialize)]
 pub struct UpdatePluginDevSourceRequest {
     new_source: String,
+    #[serde(default)]
+    library_modules: Vec<LibraryModule>,
 }
 
 impl Validator for UpdatePluginDevSourceRequest {
-    type ContextData = ();
-    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+    type ContextData = Plugin;
+    fn validate(&self, ctx: &mut ValidationContext, plugin: &Plugin) {
         validation::web::check_script_source(ctx, "new_source", &self.new_source);
+        validation::web::check_library_modules(
+            ctx,
+            "library_modules",
+            &plugin.name,
+            &self.library_modules,
+        );
     }
 }
 
@@ -234,7 +332,7 @@ pub async fn update_plugin_dev_source(
     Extension(plugin): Extension<Plugin>,
     Json(body): Json<UpdatePluginDevSourceRequest>,
 ) -> ApiResult<impl IntoResponse> {
-    if let Err(err) = validate(&body, &()) {
+    if let Err(err) = validate(&body, &plugin) {
         return Err(ApiErrorResponse::ValidationFailed(err));
     }
 
@@ -244,7 +342,7 @@ pub async fn update_plugin_dev_source(
 
     let plugin = state
         .db
-        .update_script_plugin_dev_version(plugin.id, body.new_source)
+        .update_script_plugin_dev_version(plugin.id, body.new_source, body.library_modules)
         .await
         .map_err(|err| {
             error!(?err, "failed updating plugin");
@@ -258,13 +356,55 @@ pub async fn update_plugin_dev_source(
 #[derive(Deserialize)]
 pub struct PublishPluginVersionData {
     new_source: String,
+    #[serde(default)]
+    library_modules: Vec<LibraryModule>,
+    #[serde(default)]
+    changelog: String,
+    #[serde(default)]
+    channel: ReleaseChannel,
+    /// Percentage of the auto updating guilds to update right away, the rest are updated over time
+    #[serde(default = "default_rollout_percentage")]
+    rollout_percentage: u8,
+    /// What the version needs access to, guilds are asked to consent to anything they haven't
+    /// granted the plugin already
+    #[serde(default)]
+    capabilities: PluginCapabilities,
+    /// Other plugins the version needs, installed along with it
+    #[serde(default)]
+    dependencies: Vec<PluginDependency>,
+    /// Applied to the settings of the guilds updated to the version
+    #[serde(default)]
+    settings_migrations: Vec<SettingsMigration>,
+}
+
+fn default_rollout_percentage() -> u8 {
+    100
 }
 
 impl Validator for PublishPluginVersionData {
-    type ContextData = ();
+    type ContextData = Plugin;
 
-    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+    fn validate(&self, ctx: &mut ValidationContext, plugin: &Plugin) {
         validation::web::check_script_source(ctx, "new_source", &self.new_source);
+        validation::web::check_library_modules(
+            ctx,
+            "library_modules",
+            &plugin.name,
+            &self.library_modules,
+        );
+        validation::web::check_plugin_changelog(ctx, "changelog", &self.changelog);
+        validation::web::check_plugin_rollout_percentage(
+            ctx,
+            "rollout_percentage",
+            self.rollout_percentage,
+        );
+        validation::web::check_plugin_capabilities(ctx, "capabilities", &self.capabilities);
+        validation::web::check_plugin_dependencies(ctx, "dependencies", &self.dependencies);
+        validation::web::check_plugin_settings_migrations(
+            ctx,
+            "settings_migrations",
+            &self.settings_migrations,
+        );
     }
 }
 
@@ -274,7 +414,7 @@ pub async fn publish_plugin_version(
     Extension(plugin): Extension<Plugin>,
     Json(body): Json<PublishPluginVersionData>,
 ) -> ApiResult<impl IntoResponse> {
-    if let Err(err) = validate(&body, &()) {
+    if let Err(err) = validate(&body, &plugin) {
         return Err(ApiErrorResponse::ValidationFailed(err));
     }
 
@@ -284,11 +424,28 @@ pub async fn publish_plugin_version(
 
     let guilds = state
         .db
-        .publish_script_plugin_version(plugin.id, body.new_source)
+        .publish_script_plugin_version(
+            plugin.id,
+            PublishScriptPluginVersion {
+                source: body.new_source,
+                library_modules: body.library_modules,
+                changelog: body.changelog,
+                channel: body.channel,
+                rollout_percentage: body.rollout_percentage,
+                capabilities: body.capabilities,
+                dependencies: body.dependencies,
+                settings_migrations: body.settings_migrations,
+            },
+        )
         .await
-        .map_err(|err| {
-            error!(?err, "failed updating plugin");
-            ApiErrorResponse::InternalError
+        .map_err(|err| match err {
+            ConfigStoreError::PluginDependencyNotSatisfied(dependency) => {
+                ApiErrorResponse::PluginDependencyNotSatisfied(dependency)
+            }
+            _ => {
+                error!(?err, "failed updating plugin");
+                ApiErrorResponse::InternalError
+            }
         })?;
 
     // restart relevant guild vms
@@ -302,10 +459,175 @@ pub async fn publish_plugin_version(
     Ok(EmptyResponse)
 }
 
+#[derive(Deserialize)]
+pub struct UpdatePluginRolloutData {
+    percentage: Option<u8>,
+    paused: Option<bool>,
+}
+
+impl Validator for UpdatePluginRolloutData {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(percentage) = self.percentage {
+            validation::web::check_plugin_rollout_percentage(ctx, "percentage", percentage);
+        }
+    }
+}
+
+/// Lets the author pause, resume or manually advance the rollout of a version
+pub async fn update_plugin_version_rollout(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+    Path(PluginVersionPathParams { version_number }): Path<PluginVersionPathParams>,
+    Json(body): Json<UpdatePluginRolloutData>,
+) -> ApiResult<impl IntoResponse> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    if plugin.author_id != session.session.user.id {
+        return Err(ApiErrorResponse::NoAccessToPlugin);
+    }
+
+    let version = fetch_plugin_version(&state, plugin.id, version_number).await?;
+
+    if body.paused == Some(true) {
+        state
+            .db
+            .pause_plugin_rollout(plugin.id, version_number, "paused by the author")
+            .await
+            .map_err(|err| {
+                error!(?err, "failed pausing plugin rollout");
+                ApiErrorResponse::InternalError
+            })?;
+    } else if body.paused == Some(false) || body.percentage.is_some() {
+        let percentage = body.percentage.unwrap_or(version.meta.rollout.percentage);
+
+        let guilds = state
+            .db
+            .advance_plugin_rollout(plugin.id, version_number, percentage)
+            .await
+            .map_err(|err| {
+                error!(?err, "failed advancing plugin rollout");
+                ApiErrorResponse::InternalError
+            })?;
+
+        for guild_id in guilds {
+            if let Err(err) = state.bot_rpc_client.restart_guild_vm(guild_id).await {
+                error!(%err, "failed reloading guild vm");
+            }
+        }
+    }
+
+    let version = fetch_plugin_version(&state, plugin.id, version_number).await?;
+    Ok(Json(version.meta))
+}
+
+pub async fn get_plugin_versions(
+    State(state): State<AppState>,
+    Extension(plugin): Extension<Plugin>,
+) -> ApiResult<impl IntoResponse> {
+    let versions = state
+        .db
+        .get_plugin_versions(plugin.id)
+        .await
+        .map_err(|err| {
+            error!(?err, "failed fetching plugin versions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(versions))
+}
+
+#[derive(Deserialize)]
+pub struct PluginVersionPathParams {
+    version_number: u32,
+}
+
+pub async fn get_plugin_version(
+    State(state): State<AppState>,
+    Extension(plugin): Extension<Plugin>,
+    Path(PluginVersionPathParams { version_number }): Path<PluginVersionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let version = fetch_plugin_version(&state, plugin.id, version_number).await?;
+    Ok(Json(version))
+}
+
+#[derive(Deserialize)]
+pub struct PluginVersionDiffPathParams {
+    from_version: u32,
+    to_version: u32,
+}
+
+#[derive(Serialize)]
+pub struct PluginVersionDiff {
+    from_version: u32,
+    to_version: u32,
+    /// Unified diff of the script and its library modules
+    diff: String,
+}
+
+pub async fn diff_plugin_versions(
+    State(state): State<AppState>,
+    Extension(plugin): Extension<Plugin>,
+    Path(PluginVersionDiffPathParams {
+        from_version,
+        to_version,
+    }): Path<PluginVersionDiffPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let from = fetch_plugin_version(&state, plugin.id, from_version).await?;
+    let to = fetch_plugin_version(&state, plugin.id, to_version).await?;
+
+    let diff = diff_script_sources(
+        plugin.name,
+        DiffSide {
+            label: format!("v{}", from.meta.version_number),
+            source: from.source,
+            library_modules: from.library_modules,
+        },
+        DiffSide {
+            label: format!("v{}", to.meta.version_number),
+            source: to.source,
+            library_modules: to.library_modules,
+        },
+    )
+    .await?;
+
+    Ok(Json(PluginVersionDiff {
+        from_version,
+        to_version,
+        diff,
+    }))
+}
+
+pub async fn fetch_plugin_version(
+    state: &AppState,
+    plugin_id: u64,
+    version_number: u32,
+) -> ApiResult<PluginVersion> {
+    state
+        .db
+        .get_plugin_version(plugin_id, version_number)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::PluginVersionNotFound
+            } else {
+                error!(?err, "failed fetching plugin version");
+                ApiErrorResponse::InternalError
+            }
+        })
+}
+
 #[derive(Deserialize)]
 pub struct GuildAddPluginData {
     plugin_id: u64,
     auto_update: bool,
+    /// The capabilities of the current version shown to, and consented to by the admin
+    #[serde(default)]
+    accepted_capabilities: PluginCapabilities,
 }
 
 pub async fn guild_add_plugin(
@@ -322,10 +644,22 @@ pub async fn guild_add_plugin(
 
     let script = state
         .db
-        .try_guild_add_script_plugin(current_guild.id, plugin.id, body.auto_update)
+        .try_guild_add_script_plugin(
+            current_guild.id,
+            plugin.id,
+            body.auto_update,
+            Some(session.session.user.id),
+            &body.accepted_capabilities,
+        )
         .await
         .map_err(|err| match err {
             ConfigStoreError::GuildAlreadyHasPlugin => ApiErrorResponse::GuildAlreadyHasPlugin,
+            ConfigStoreError::PluginCapabilitiesNotGranted(needed) => {
+                ApiErrorResponse::PluginCapabilitiesNotGranted(needed)
+            }
+            ConfigStoreError::PluginDependencyNotSatisfied(dependency) => {
+                ApiErrorResponse::PluginDependencyNotSatisfied(dependency)
+            }
             _ => {
                 error!(?err, "failed adding plugin");
                 ApiErrorResponse::InternalError
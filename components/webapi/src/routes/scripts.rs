@@ -1,14 +1,18 @@
 use std::rc::Rc;
 
 use axum::{
+    body::Bytes,
     extract::{Extension, Path, State},
     response::IntoResponse,
     Json,
 };
-use common::plugin::Plugin;
+use common::plugin::{Plugin, PluginCapabilities, ReleaseChannel};
 use runtime_models::internal::script::SettingsOptionValue;
 use serde::{Deserialize, Serialize};
-use stores::config::{CreateScript, Script, UpdateScript};
+use stores::{
+    config::{ConfigStoreError, CreateScript, Script, UpdateScript},
+    script_revisions::ScriptRevision,
+};
 use tracing::error;
 use twilight_model::user::CurrentUserGuild;
 use validation::{
@@ -18,8 +22,12 @@ use validation::{
 };
 
 use crate::{
-    app_state::AppState, errors::ApiErrorResponse, middlewares::plugins::fetch_plugin,
-    util::EmptyResponse, ApiResult,
+    app_state::AppState,
+    errors::ApiErrorResponse,
+    middlewares::{plugins::fetch_plugin, LoggedInSession},
+    routes::plugins::fetch_plugin_version,
+    util::{diff_script_sources, DiffSide, EmptyResponse},
+    ApiResult,
 };
 
 pub async fn get_all_guild_scripts(
@@ -84,10 +92,13 @@ pub struct CreateRequestData {
     pub name: String,
     pub original_source: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub is_library: bool,
 }
 
 pub async fn create_guild_script(
     State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
     Extension(current_guild): Extension<CurrentUserGuild>,
     Json(payload): Json<CreateRequestData>,
 ) -> ApiResult<impl IntoResponse> {
@@ -98,6 +109,8 @@ pub async fn create_guild_script(
         plugin_auto_update: None,
         plugin_id: None,
         plugin_version_number: None,
+        is_library: payload.is_library,
+        library_modules: Vec::new(),
     };
 
     if let Err(verr) = validate(&cs, &()) {
@@ -118,7 +131,7 @@ pub async fn create_guild_script(
 
     let script = state
         .db
-        .create_script(current_guild.id, cs)
+        .create_script(current_guild.id, cs, Some(session.session.user.id))
         .await
         .map_err(|err| {
             error!(%err, "failed creating guild script");
@@ -138,10 +151,13 @@ pub struct UpdateRequestData {
     pub enabled: Option<bool>,
     #[serde(default)]
     pub settings_values: Option<Vec<SettingsOptionValue>>,
+    #[serde(default)]
+    pub is_library: Option<bool>,
 }
 
 pub async fn update_guild_script(
     Extension(current_guild): Extension<CurrentUserGuild>,
+    Extension(session): Extension<LoggedInSession>,
     State(state): State<AppState>,
     Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
     Json(payload): Json<UpdateRequestData>,
@@ -168,6 +184,8 @@ pub async fn update_guild_script(
         plugin_version_number: None,
         settings_definitions: None,
         settings_values: payload.settings_values,
+        is_library: payload.is_library,
+        library_modules: None,
     };
 
     {
@@ -208,19 +226,25 @@ pub async fn update_guild_script(
 
     let script = state
         .db
-        .update_script(current_guild.id, sc)
+        .update_script(current_guild.id, sc, Some(session.session.user.id))
         .await
-        .map_err(|err| {
-            error!(%err, "failed updating guild script");
-            ApiErrorResponse::InternalError
+        .map_err(|err| match err {
+            ConfigStoreError::PluginRequiredByDependent { plugin_id, name } => {
+                ApiErrorResponse::PluginRequiredByDependent { plugin_id, name }
+            }
+            _ => {
+                error!(%err, "failed updating guild script");
+                ApiErrorResponse::InternalError
+            }
         })?;
 
+    // the scheduler falls back to restarting the whole vm if the change can't be applied in place
     state
         .bot_rpc_client
-        .restart_guild_vm(current_guild.id)
+        .reload_guild_script(current_guild.id, script.id)
         .await
         .map_err(|err| {
-            error!(%err, "failed reloading guild vm");
+            error!(%err, "failed reloading guild script");
             ApiErrorResponse::InternalError
         })?;
 
@@ -255,6 +279,8 @@ pub async fn validate_script_settings(
         plugin_version_number: None,
         settings_definitions: None,
         settings_values: payload.settings_values,
+        is_library: payload.is_library,
+        library_modules: None,
     };
 
     let validation_data = if sc.settings_definitions.is_some() {
@@ -312,38 +338,57 @@ pub async fn delete_guild_script(
         .db
         .del_script(current_guild.id, script.name.clone())
         .await
-        .map_err(|err| {
-            error!(%err, "failed deleting guild script");
-            ApiErrorResponse::InternalError
+        .map_err(|err| match err {
+            ConfigStoreError::PluginRequiredByDependent { plugin_id, name } => {
+                ApiErrorResponse::PluginRequiredByDependent { plugin_id, name }
+            }
+            _ => {
+                error!(%err, "failed deleting guild script");
+                ApiErrorResponse::InternalError
+            }
         })?;
 
     Ok(Json(script))
 }
 
+#[derive(Deserialize, Default)]
+pub struct UpdateScriptPluginData {
+    /// Needed if the latest version asks for capabilities the script hasn't been granted yet
+    accepted_capabilities: Option<PluginCapabilities>,
+}
+
+/// Updates a plugin script to the latest version of the plugin
 pub async fn update_script_plugin(
     State(state): State<AppState>,
-    // Extension(session): Extension<LoggedInSession<CurrentSessionStore>>,
+    Extension(session): Extension<LoggedInSession>,
     Extension(current_guild): Extension<CurrentUserGuild>,
     Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
+    body: Bytes,
 ) -> ApiResult<impl IntoResponse> {
+    let req = if body.is_empty() {
+        UpdateScriptPluginData::default()
+    } else {
+        serde_json::from_slice::<UpdateScriptPluginData>(&body)
+            .map_err(ApiErrorResponse::InvalidJsonBody)?
+    };
+
     let script = state
         .db
         .get_script_by_id(current_guild.id, script_id)
         .await
         .map_err(|err| {
-            error!(?err, "failed fetching guild script");
-            ApiErrorResponse::InternalError
+            if err.is_not_found() {
+                ApiErrorResponse::ScriptNotFound
+            } else {
+                error!(%err, "failed fetching script");
+                ApiErrorResponse::InternalError
+            }
         })?;
 
     let Some(plugin_id) = script.plugin_id else {
         return Err(ApiErrorResponse::ScriptNotAPlugin);
     };
 
-    let plugin = fetch_plugin(&state.db, plugin_id).await?;
-    let new_source = match plugin.data {
-        common::plugin::PluginData::ScriptPlugin(p) => p.published_version.unwrap_or_default(),
-    };
-
     // I think if we have already added a plugin to a guild then we should still be able to update it even if it's set to private afterwards
     //
     // TODO decision on this
@@ -352,34 +397,311 @@ pub async fn update_script_plugin(
     //     return Err(ApiErrorResponse::NoAccessToPlugin);
     // }
 
-    let sc = UpdateScript {
-        id: script_id,
-        enabled: None,
-        original_source: Some(new_source),
-        name: None,
-        contributes: None,
-        plugin_version_number: Some(plugin.current_version),
-        settings_definitions: None,
-        settings_values: None,
+    let plugin = fetch_plugin(&state.db, plugin_id).await?;
+    let version = fetch_plugin_version(&state, plugin_id, plugin.current_version).await?;
+
+    // goes through the same consent, dependency and settings migration checks as any other
+    // version change
+    let script = state
+        .db
+        .set_guild_script_plugin_version(
+            current_guild.id,
+            script_id,
+            version,
+            script.plugin_auto_update.unwrap_or_default(),
+            Some(session.session.user.id),
+            req.accepted_capabilities.as_ref(),
+        )
+        .await
+        .map_err(plugin_version_change_error)?;
+
+    state
+        .bot_rpc_client
+        .reload_guild_script(current_guild.id, script.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed reloading guild script");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(script))
+}
+
+fn plugin_version_change_error(err: ConfigStoreError) -> ApiErrorResponse {
+    match err {
+        ConfigStoreError::PluginCapabilitiesNotGranted(needed) => {
+            ApiErrorResponse::PluginCapabilitiesNotGranted(needed)
+        }
+        ConfigStoreError::PluginDependencyNotSatisfied(dependency) => {
+            ApiErrorResponse::PluginDependencyNotSatisfied(dependency)
+        }
+        _ => {
+            error!(%err, "failed updating guild script plugin version");
+            ApiErrorResponse::InternalError
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetScriptPluginVersionData {
+    version_number: u32,
+    /// Keep updating to newly published versions, the script stays pinned to this version if false
+    #[serde(default)]
+    auto_update: bool,
+    /// Needed if the version asks for capabilities the script hasn't been granted yet
+    accepted_capabilities: Option<PluginCapabilities>,
+}
+
+/// Rolls back or pins a plugin script to a specific published version
+pub async fn set_script_plugin_version(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
+    Json(body): Json<SetScriptPluginVersionData>,
+) -> ApiResult<impl IntoResponse> {
+    let script = state
+        .db
+        .get_script_by_id(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::ScriptNotFound
+            } else {
+                error!(%err, "failed fetching script");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    let Some(plugin_id) = script.plugin_id else {
+        return Err(ApiErrorResponse::ScriptNotAPlugin);
     };
 
+    let version = fetch_plugin_version(&state, plugin_id, body.version_number).await?;
+
+    let script = state
+        .db
+        .set_guild_script_plugin_version(
+            current_guild.id,
+            script_id,
+            version,
+            body.auto_update,
+            Some(session.session.user.id),
+            body.accepted_capabilities.as_ref(),
+        )
+        .await
+        .map_err(plugin_version_change_error)?;
+
+    state
+        .bot_rpc_client
+        .reload_guild_script(current_guild.id, script.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed reloading guild script");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(script))
+}
+
+#[derive(Deserialize)]
+pub struct SetScriptPluginChannelData {
+    channel: ReleaseChannel,
+}
+
+/// Switches the channel a plugin script receives auto updates from
+///
+/// The script itself is left as is until the next version on the channel is rolled out to it
+pub async fn set_script_plugin_channel(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
+    Json(body): Json<SetScriptPluginChannelData>,
+) -> ApiResult<impl IntoResponse> {
+    let script = state
+        .db
+        .get_script_by_id(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::ScriptNotFound
+            } else {
+                error!(%err, "failed fetching script");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    if script.plugin_id.is_none() {
+        return Err(ApiErrorResponse::ScriptNotAPlugin);
+    }
+
     let script = state
         .db
-        .update_script(current_guild.id, sc)
+        .set_guild_script_plugin_channel(current_guild.id, script_id, body.channel)
         .await
         .map_err(|err| {
-            error!(%err, "failed updating guild script");
+            error!(%err, "failed updating guild script plugin channel");
             ApiErrorResponse::InternalError
         })?;
 
+    Ok(Json(script))
+}
+
+pub async fn get_script_revisions(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(GuildScriptPathParams { script_id }): Path<GuildScriptPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let revisions = state
+        .db
+        .get_script_revisions(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed fetching script revisions");
+            ApiErrorResponse::InternalError
+        })?;
+
+    Ok(Json(revisions))
+}
+
+#[derive(Deserialize)]
+pub struct ScriptRevisionPathParams {
+    script_id: u64,
+    revision_id: u64,
+}
+
+pub async fn get_script_revision(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionPathParams {
+        script_id,
+        revision_id,
+    }): Path<ScriptRevisionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let revision = fetch_script_revision(&state, &current_guild, script_id, revision_id).await?;
+    Ok(Json(revision))
+}
+
+#[derive(Deserialize)]
+pub struct ScriptRevisionDiffPathParams {
+    script_id: u64,
+    from_revision: u64,
+    to_revision: u64,
+}
+
+#[derive(Serialize)]
+pub struct ScriptRevisionDiff {
+    from_revision: u64,
+    to_revision: u64,
+    /// Unified diff of the script and its library modules
+    diff: String,
+}
+
+pub async fn diff_script_revisions(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionDiffPathParams {
+        script_id,
+        from_revision,
+        to_revision,
+    }): Path<ScriptRevisionDiffPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let script = state
+        .db
+        .get_script_by_id(current_guild.id, script_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::ScriptNotFound
+            } else {
+                error!(%err, "failed fetching script");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    let from = fetch_script_revision(&state, &current_guild, script_id, from_revision).await?;
+    let to = fetch_script_revision(&state, &current_guild, script_id, to_revision).await?;
+
+    let diff = diff_script_sources(
+        script.name,
+        DiffSide {
+            label: format!("r{from_revision}"),
+            source: from.source,
+            library_modules: from.library_modules,
+        },
+        DiffSide {
+            label: format!("r{to_revision}"),
+            source: to.source,
+            library_modules: to.library_modules,
+        },
+    )
+    .await?;
+
+    Ok(Json(ScriptRevisionDiff {
+        from_revision,
+        to_revision,
+        diff,
+    }))
+}
+
+/// Restores the script to a previous revision, the restore itself is recorded as a new revision
+pub async fn restore_script_revision(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(ScriptRevisionPathParams {
+        script_id,
+        revision_id,
+    }): Path<ScriptRevisionPathParams>,
+) -> ApiResult<impl IntoResponse> {
+    let script = state
+        .db
+        .restore_script_revision(
+            current_guild.id,
+            script_id,
+            revision_id,
+            Some(session.session.user.id),
+        )
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::PluginDependencyNotSatisfied(dependency) => {
+                ApiErrorResponse::PluginDependencyNotSatisfied(dependency)
+            }
+            err if err.is_not_found() => ApiErrorResponse::ScriptRevisionNotFound,
+            _ => {
+                error!(%err, "failed restoring script revision");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
     state
         .bot_rpc_client
-        .restart_guild_vm(current_guild.id)
+        .reload_guild_script(current_guild.id, script.id)
         .await
         .map_err(|err| {
-            error!(%err, "failed reloading guild vm");
+            error!(%err, "failed reloading guild script");
             ApiErrorResponse::InternalError
         })?;
 
     Ok(Json(script))
 }
+
+async fn fetch_script_revision(
+    state: &AppState,
+    current_guild: &CurrentUserGuild,
+    script_id: u64,
+    revision_id: u64,
+) -> ApiResult<ScriptRevision> {
+    state
+        .db
+        .get_script_revision(current_guild.id, script_id, revision_id)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::ScriptRevisionNotFound
+            } else {
+                error!(%err, "failed fetching script revision");
+                ApiErrorResponse::InternalError
+            }
+        })
+}
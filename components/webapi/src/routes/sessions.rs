@@ -1,10 +1,12 @@
 use axum::{
+    body::Bytes,
     extract::{Extension, State},
     response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
-use stores::web::{Session, SessionType};
+use stores::web::{ApiTokenScope, CreateApiToken, Session, SessionType};
+use validation::{validate, ValidationContext, Validator};
 
 use crate::{
     app_state::AppState, errors::ApiErrorResponse, middlewares::LoggedInSession,
@@ -17,6 +19,10 @@ use tracing::error;
 pub struct SessionMeta {
     kind: SessionType,
     created_at: chrono::DateTime<chrono::Utc>,
+    name: Option<String>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    scope: ApiTokenScope,
 }
 
 impl From<Session> for SessionMeta {
@@ -24,24 +30,51 @@ impl From<Session> for SessionMeta {
         Self {
             created_at: s.created_at,
             kind: s.kind,
+            name: s.name,
+            expires_at: s.expires_at,
+            last_used_at: s.last_used_at,
+            scope: s.scope,
         }
     }
 }
 
 #[derive(Serialize)]
 pub struct SessionMetaWithKey {
-    kind: SessionType,
-    created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    meta: SessionMeta,
     token: String,
 }
 
 impl From<Session> for SessionMetaWithKey {
     fn from(s: Session) -> Self {
         Self {
-            created_at: s.created_at,
-            kind: s.kind,
-            token: s.token,
+            token: s.token.clone(),
+            meta: s.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct CreateApiTokenRequest {
+    name: Option<String>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    scope: ApiTokenScope,
+}
+
+impl Validator for CreateApiTokenRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        if let Some(name) = &self.name {
+            validation::web::check_api_token_name(ctx, "name", name);
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            validation::web::check_api_token_expiry(ctx, "expires_at", expires_at);
         }
+
+        validation::web::check_api_token_scope(ctx, "scope", &self.scope);
     }
 }
 
@@ -61,13 +94,34 @@ pub async fn get_all_sessions(
     Ok(Json(sessions.into_iter().map(|e| e.into()).collect()))
 }
 
+/// Creates a new api token, the body is optional and without one the token is unrestricted and
+/// never expires
 pub async fn create_api_token(
     Extension(session): Extension<LoggedInSession>,
     State(state): State<AppState>,
+    body: Bytes,
 ) -> ApiResult<Json<SessionMetaWithKey>> {
+    let req = if body.is_empty() {
+        CreateApiTokenRequest::default()
+    } else {
+        serde_json::from_slice::<CreateApiTokenRequest>(&body)
+            .map_err(ApiErrorResponse::InvalidJsonBody)?
+    };
+
+    if let Err(err) = validate(&req, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
     let session = state
         .db
-        .create_session(session.session.user.clone(), SessionType::ApiKey)
+        .create_api_token(
+            session.session.user.clone(),
+            CreateApiToken {
+                name: req.name,
+                expires_at: req.expires_at,
+                scope: req.scope,
+            },
+        )
         .await
         .map_err(|err| {
             error!(%err, "failed creating all api key");
@@ -0,0 +1,193 @@
+use axum::{
+    extract::{Extension, Path, State},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use common::plugin::Plugin;
+use serde::{Deserialize, Serialize};
+use stores::config::{ConfigStoreError, VendoredModule, VendoredModuleOwner};
+use tracing::error;
+use twilight_model::user::CurrentUserGuild;
+use validation::{validate, ValidationContext, Validator};
+
+use crate::{
+    app_state::AppState, errors::ApiErrorResponse, middlewares::LoggedInSession,
+    util::EmptyResponse, ApiResult,
+};
+
+/// Vendored module without the source, the bundles can be big so they're not included in listings
+#[derive(Serialize)]
+pub struct VendoredModuleMeta {
+    pub name: String,
+    pub content_hash: String,
+    pub size_bytes: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<VendoredModule> for VendoredModuleMeta {
+    fn from(value: VendoredModule) -> Self {
+        Self {
+            name: value.name,
+            content_hash: value.content_hash,
+            size_bytes: value.size_bytes,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UploadVendoredModuleRequest {
+    name: String,
+    source: String,
+}
+
+impl Validator for UploadVendoredModuleRequest {
+    type ContextData = ();
+
+    fn validate(&self, ctx: &mut ValidationContext, _: &()) {
+        validation::web::check_vendored_module_name(ctx, "name", &self.name);
+        validation::web::check_vendored_module_source(ctx, "source", &self.source);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct VendoredModulePathParams {
+    pub name: String,
+}
+
+pub async fn get_guild_vendored_modules(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+) -> ApiResult<Json<Vec<VendoredModuleMeta>>> {
+    list_vendored_modules(&state, VendoredModuleOwner::Guild(current_guild.id)).await
+}
+
+pub async fn upload_guild_vendored_module(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Json(body): Json<UploadVendoredModuleRequest>,
+) -> ApiResult<Json<VendoredModuleMeta>> {
+    let module =
+        upload_vendored_module(&state, VendoredModuleOwner::Guild(current_guild.id), body).await?;
+
+    restart_guild_vm(&state, &current_guild).await?;
+    Ok(Json(module))
+}
+
+pub async fn delete_guild_vendored_module(
+    State(state): State<AppState>,
+    Extension(current_guild): Extension<CurrentUserGuild>,
+    Path(VendoredModulePathParams { name }): Path<VendoredModulePathParams>,
+) -> ApiResult<impl IntoResponse> {
+    delete_vendored_module(&state, VendoredModuleOwner::Guild(current_guild.id), &name).await?;
+
+    restart_guild_vm(&state, &current_guild).await?;
+    Ok(EmptyResponse)
+}
+
+pub async fn get_plugin_vendored_modules(
+    State(state): State<AppState>,
+    Extension(plugin): Extension<Plugin>,
+) -> ApiResult<Json<Vec<VendoredModuleMeta>>> {
+    list_vendored_modules(&state, VendoredModuleOwner::Plugin(plugin.id)).await
+}
+
+// guilds using the plugin pick up the changes the next time their vm is restarted,
+// scripts can pin the hash of a module to avoid being broken by this
+pub async fn upload_plugin_vendored_module(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+    Json(body): Json<UploadVendoredModuleRequest>,
+) -> ApiResult<Json<VendoredModuleMeta>> {
+    if plugin.author_id != session.session.user.id {
+        return Err(ApiErrorResponse::NoAccessToPlugin);
+    }
+
+    let module =
+        upload_vendored_module(&state, VendoredModuleOwner::Plugin(plugin.id), body).await?;
+    Ok(Json(module))
+}
+
+pub async fn delete_plugin_vendored_module(
+    State(state): State<AppState>,
+    Extension(session): Extension<LoggedInSession>,
+    Extension(plugin): Extension<Plugin>,
+    Path(VendoredModulePathParams { name }): Path<VendoredModulePathParams>,
+) -> ApiResult<impl IntoResponse> {
+    if plugin.author_id != session.session.user.id {
+        return Err(ApiErrorResponse::NoAccessToPlugin);
+    }
+
+    delete_vendored_module(&state, VendoredModuleOwner::Plugin(plugin.id), &name).await?;
+    Ok(EmptyResponse)
+}
+
+async fn list_vendored_modules(
+    state: &AppState,
+    owner: VendoredModuleOwner,
+) -> ApiResult<Json<Vec<VendoredModuleMeta>>> {
+    let modules = state.db.list_vendored_modules(owner).await.map_err(|err| {
+        error!(%err, "failed fetching vendored modules");
+        ApiErrorResponse::InternalError
+    })?;
+
+    Ok(Json(modules.into_iter().map(Into::into).collect()))
+}
+
+async fn upload_vendored_module(
+    state: &AppState,
+    owner: VendoredModuleOwner,
+    body: UploadVendoredModuleRequest,
+) -> ApiResult<VendoredModuleMeta> {
+    if let Err(err) = validate(&body, &()) {
+        return Err(ApiErrorResponse::ValidationFailed(err));
+    }
+
+    let module = state
+        .db
+        .upsert_vendored_module(owner, body.name, body.source)
+        .await
+        .map_err(|err| match err {
+            ConfigStoreError::VendoredModuleLimitReached(_) => {
+                ApiErrorResponse::MaxVendoredModulesReached
+            }
+            other => {
+                error!(%other, "failed uploading vendored module");
+                ApiErrorResponse::InternalError
+            }
+        })?;
+
+    Ok(module.into())
+}
+
+async fn delete_vendored_module(
+    state: &AppState,
+    owner: VendoredModuleOwner,
+    name: &str,
+) -> ApiResult<()> {
+    state
+        .db
+        .del_vendored_module(owner, name)
+        .await
+        .map_err(|err| {
+            if err.is_not_found() {
+                ApiErrorResponse::VendoredModuleNotFound
+            } else {
+                error!(%err, "failed deleting vendored module");
+                ApiErrorResponse::InternalError
+            }
+        })
+}
+
+async fn restart_guild_vm(state: &AppState, current_guild: &CurrentUserGuild) -> ApiResult<()> {
+    state
+        .bot_rpc_client
+        .restart_guild_vm(current_guild.id)
+        .await
+        .map_err(|err| {
+            error!(%err, "failed reloading guild vm");
+            ApiErrorResponse::InternalError
+        })
+}
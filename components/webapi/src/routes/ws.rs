@@ -7,10 +7,13 @@ use axum::{
     },
     response::IntoResponse,
 };
+use common::dispatch_event::{EvalOutput, EvalOutputKind};
 use discordoauthwrapper::{ClientCache, DiscordOauthApiClient, TwilightApiProvider};
 use futures::{stream::SelectAll, Stream, StreamExt};
 use guild_logger::LogEntry;
 use serde::{Deserialize, Serialize};
+use stores::web::ApiTokenOperation;
+use tracing::error;
 use twilight_model::{
     guild::Permissions,
     id::{marker::GuildMarker, Id},
@@ -19,6 +22,9 @@ use twilight_model::{
 
 use crate::{app_state::AppState, middlewares::LoggedInSession};
 
+const MAX_ACTIVE_EVALS: usize = 5;
+const MAX_EVAL_SOURCE_LEN: usize = 10_000;
+
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Extension(client_cache): Extension<
@@ -42,6 +48,7 @@ struct WsConn {
     client_cache: ClientCache<TwilightApiProvider, oauth2::basic::BasicClient>,
 
     active_log_streams: SelectAll<GuildLogStream>,
+    active_evals: SelectAll<EvalStream>,
 
     app_state: AppState,
 
@@ -59,6 +66,7 @@ impl WsConn {
         Self {
             socket,
             active_log_streams: SelectAll::new(),
+            active_evals: SelectAll::new(),
             state: WsState::UnAuth,
             app_state: state,
             client_cache,
@@ -70,39 +78,29 @@ impl WsConn {
 
         loop {
             // SelectAll returns Ready(None) when empty
-            // so if we didn't have this check this thread
+            // so if we didn't have the is_empty checks this thread
             // would get pinned at 100%
-            if !self.active_log_streams.is_empty() {
-                tokio::select! {
-                    item = self.active_log_streams.next() => {
-                        if !self.handle_log_stream_item(item).await {
-                            return;
-                        }
-                    },
-                    ws = self.socket.recv() => {
-                        if !self.handle_ws_rcv(ws).await {
-                            return;
-                        }
-                    },
-                    _ = ping_ticker.tick() => {
-                        if !self.send_ping().await{
-                            return;
-                        }
-                    },
-                }
-            } else {
-                tokio::select! {
-                    ws = self.socket.recv() => {
-                        if !self.handle_ws_rcv(ws).await {
-                            return;
-                        }
-                    },
-                    _ = ping_ticker.tick() => {
-                        if !self.send_ping().await{
-                            return;
-                        }
-                    },
-                }
+            tokio::select! {
+                item = self.active_log_streams.next(), if !self.active_log_streams.is_empty() => {
+                    if !self.handle_log_stream_item(item).await {
+                        return;
+                    }
+                },
+                item = self.active_evals.next(), if !self.active_evals.is_empty() => {
+                    if !self.handle_eval_stream_item(item).await {
+                        return;
+                    }
+                },
+                ws = self.socket.recv() => {
+                    if !self.handle_ws_rcv(ws).await {
+                        return;
+                    }
+                },
+                _ = ping_ticker.tick() => {
+                    if !self.send_ping().await{
+                        return;
+                    }
+                },
             }
         }
     }
@@ -130,6 +128,36 @@ impl WsConn {
         }
     }
 
+    async fn handle_eval_stream_item(&mut self, item: Option<EvalStreamItem>) -> bool {
+        let Some((guild_id, nonce, item)) = item else {
+            // There can't be a none since we have the is_empty check in the caller
+            return true;
+        };
+
+        let output = match item {
+            Ok(output) => output,
+            Err(_) => EvalOutput {
+                eval_id: 0,
+                kind: EvalOutputKind::Exception,
+                message: "error on communication with bot".to_owned(),
+            },
+        };
+
+        let evt = WsEvent::EvalOutput(WsEvalOutput {
+            guild_id,
+            nonce,
+            kind: output.kind,
+            message: output.message,
+        });
+
+        if let Err(reason) = self.send_event(evt).await {
+            self.close(reason).await;
+            false
+        } else {
+            true
+        }
+    }
+
     async fn handle_ws_rcv(&mut self, ws_msg: Option<Result<Message, axum::Error>>) -> bool {
         match ws_msg {
             None | Some(Err(_)) => false,
@@ -200,7 +228,17 @@ impl WsConn {
             .get_session(&token)
             .await
             .map_err(|_| WsCloseReason::InternalError)?
+            .filter(|s| !s.is_expired())
         {
+            if session.should_update_last_used() {
+                self.app_state
+                    .db
+                    .set_session_last_used(&session.token)
+                    .await
+                    .map_err(|err| error!(%err, "failed updating session last used"))
+                    .ok();
+            }
+
             let api_client = self
                 .client_cache
                 .fetch(session.user.id, || {
@@ -232,6 +270,7 @@ impl WsConn {
         match cmd {
             WsCommand::SubscribeLogs(g) => self.subscribe_logs(g).await,
             WsCommand::UnSubscribeLogs(g) => self.unsubscribe_logs(g).await,
+            WsCommand::Eval(req) => self.start_eval(req).await,
 
             WsCommand::Authorize(_) => Err(WsCloseReason::AuthWhenAuthorized),
         }
@@ -247,6 +286,7 @@ impl WsConn {
             return Ok(());
         }
 
+        self.check_token_scope(guild_id, ApiTokenOperation::ReadLogs)?;
         self.check_guild_acces(guild_id).await?;
 
         let stream = self
@@ -275,6 +315,49 @@ impl WsConn {
         self.emit_subscriptions().await
     }
 
+    async fn start_eval(&mut self, req: WsEvalRequest) -> WsResult {
+        let reject_reason = if self.active_evals.len() >= MAX_ACTIVE_EVALS {
+            Some(format!(
+                "too many evals running, at most {MAX_ACTIVE_EVALS} can run at the same time"
+            ))
+        } else if req.source.len() > MAX_EVAL_SOURCE_LEN {
+            Some(format!(
+                "source too long, max length is {MAX_EVAL_SOURCE_LEN} characters"
+            ))
+        } else {
+            None
+        };
+
+        if let Some(message) = reject_reason {
+            return self
+                .send_event(WsEvent::EvalOutput(WsEvalOutput {
+                    guild_id: req.guild_id,
+                    nonce: req.nonce,
+                    kind: EvalOutputKind::Exception,
+                    message,
+                }))
+                .await;
+        }
+
+        self.check_token_scope(req.guild_id, ApiTokenOperation::RunEvals)?;
+        self.check_guild_acces(req.guild_id).await?;
+
+        let stream = self
+            .app_state
+            .bot_rpc_client
+            .eval_stream(req.guild_id, req.source)
+            .await
+            .map_err(|_| WsCloseReason::BotRpcError)?;
+
+        let guild_id = req.guild_id;
+        let nonce = req.nonce;
+        self.active_evals.push(Box::pin(
+            stream.map(move |item| (guild_id, nonce.clone(), item)),
+        ));
+
+        Ok(())
+    }
+
     async fn emit_subscriptions(&mut self) -> WsResult {
         let ids = self
             .active_log_streams
@@ -285,6 +368,24 @@ impl WsConn {
         self.send_event(WsEvent::SubscriptionsUpdated(ids)).await
     }
 
+    fn check_token_scope(
+        &self,
+        guild_id: Id<GuildMarker>,
+        operation: ApiTokenOperation,
+    ) -> WsResult {
+        let session = match &self.state {
+            WsState::Authorized(s) => s,
+            _ => panic!("can't check token scope when not authorized"),
+        };
+
+        let scope = &session.session.session.scope;
+        if scope.allows_guild(guild_id) && scope.allows_operation(operation) {
+            Ok(())
+        } else {
+            Err(WsCloseReason::TokenMissingScope)
+        }
+    }
+
     async fn check_guild_acces(&mut self, guild_id: Id<GuildMarker>) -> WsResult {
         let session = match &self.state {
             WsState::Authorized(s) => s,
@@ -358,9 +459,18 @@ enum WsEvent {
     AuthSuccess(CurrentUser),
     SubscriptionsUpdated(Vec<Id<GuildMarker>>),
     ScriptLogMessage(LogEntry),
+    EvalOutput(WsEvalOutput),
     // GeneralLogMEssage(String)
 }
 
+#[derive(Serialize)]
+struct WsEvalOutput {
+    guild_id: Id<GuildMarker>,
+    nonce: String,
+    kind: EvalOutputKind,
+    message: String,
+}
+
 /// Command is something that is from client -> server
 #[derive(Deserialize)]
 #[serde(tag = "t", content = "d")]
@@ -370,6 +480,20 @@ enum WsCommand {
     // below commands requires authorization
     SubscribeLogs(Id<GuildMarker>),
     UnSubscribeLogs(Id<GuildMarker>),
+
+    /// Evaluates the source inside the guild's vm, the source is ran as the body of a async function
+    /// so use `return` to get a value back.
+    ///
+    /// The output is sent back as EvalOutput events with the provided nonce
+    Eval(WsEvalRequest),
+}
+
+#[derive(Deserialize)]
+struct WsEvalRequest {
+    guild_id: Id<GuildMarker>,
+    #[serde(default)]
+    nonce: String,
+    source: String,
 }
 
 #[derive(Serialize)]
@@ -403,6 +527,9 @@ enum WsCloseReason {
 
     // an error occured cummincating with the bot
     BotRpcError,
+
+    // the api token used for auth is not allowed to do this
+    TokenMissingScope,
 }
 
 impl WsCloseReason {
@@ -418,6 +545,7 @@ impl WsCloseReason {
             WsCloseReason::UnknownGuild => 4003,
             WsCloseReason::GuildMissingAccess => 4004,
             WsCloseReason::BotRpcError => 4006,
+            WsCloseReason::TokenMissingScope => 4007,
         }
     }
 
@@ -433,10 +561,15 @@ impl WsCloseReason {
             WsCloseReason::UnknownGuild => "unknown guild",
             WsCloseReason::GuildMissingAccess => "missing access to guild",
             WsCloseReason::BotRpcError => "error on communication with bot",
+            WsCloseReason::TokenMissingScope => "api token is not allowed to do this",
         }
     }
 }
 
+type EvalStreamItem = (Id<GuildMarker>, String, Result<EvalOutput, tonic::Status>);
+
+type EvalStream = Pin<Box<dyn Stream<Item = EvalStreamItem> + Send>>;
+
 struct GuildLogStream {
     guild_id: Id<GuildMarker>,
     inner: Pin<Box<dyn Stream<Item = Result<LogEntry, tonic::Status>> + Send>>,
@@ -3,6 +3,14 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use common::plugin::LibraryModule;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+use crate::{errors::ApiErrorResponse, ApiResult};
+
+// past this the diffs fall back to less minimal ones, crafted sources can take a long time otherwise
+const DIFF_TIMEOUT: Duration = Duration::from_millis(500);
 
 pub struct EmptyResponse;
 
@@ -14,3 +22,72 @@ impl IntoResponse for EmptyResponse {
             .unwrap()
     }
 }
+
+/// One side of a diff between two versions of a script
+pub struct DiffSide {
+    /// Prefixed to the file names in the diff headers, e.g. `v2`
+    pub label: String,
+    pub source: String,
+    pub library_modules: Vec<LibraryModule>,
+}
+
+/// Unified diff of a script and its library modules, computed on the blocking pool
+pub async fn diff_script_sources(name: String, from: DiffSide, to: DiffSide) -> ApiResult<String> {
+    tokio::task::spawn_blocking(move || diff_script_sources_blocking(&name, &from, &to))
+        .await
+        .map_err(|err| {
+            error!(%err, "failed diffing script sources");
+            ApiErrorResponse::InternalError
+        })
+}
+
+fn diff_script_sources_blocking(name: &str, from: &DiffSide, to: &DiffSide) -> String {
+    let mut files = vec![(
+        format!("{name}.ts"),
+        from.source.as_str(),
+        to.source.as_str(),
+    )];
+
+    for module in &from.library_modules {
+        let new_source = to
+            .library_modules
+            .iter()
+            .find(|v| v.name == module.name)
+            .map(|v| v.source.as_str())
+            .unwrap_or_default();
+
+        files.push((
+            format!("{}.ts", module.name),
+            module.source.as_str(),
+            new_source,
+        ));
+    }
+
+    for module in &to.library_modules {
+        if !from.library_modules.iter().any(|v| v.name == module.name) {
+            files.push((format!("{}.ts", module.name), "", module.source.as_str()));
+        }
+    }
+
+    // shared between the files so the modules don't each get the full timeout
+    let deadline = Instant::now() + DIFF_TIMEOUT;
+
+    let mut out = String::new();
+    for (file_name, old, new) in files {
+        let diff = similar::TextDiff::configure()
+            .deadline(deadline)
+            .diff_lines(old, new);
+        out.push_str(
+            &diff
+                .unified_diff()
+                .context_radius(3)
+                .header(
+                    &format!("{}/{file_name}", from.label),
+                    &format!("{}/{file_name}", to.label),
+                )
+                .to_string(),
+        );
+    }
+
+    out
+}
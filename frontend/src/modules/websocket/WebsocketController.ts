@@ -170,6 +170,7 @@ export interface LogItem {
     message: string,
     script_context?: ScriptContext,
     level: LogLevel,
+    stack?: StackFrame[],
 }
 
 export type LogLevel = "Critical" |
@@ -183,6 +184,12 @@ export interface ScriptContext {
     line_col?: [number, number],
 }
 
+export interface StackFrame {
+    filename: string,
+    line_col?: [number, number],
+    function_name?: string,
+}
+
 export function getWsUrl() {
     if (BuildConfig.botloaderApiBase.startsWith("https")) {
         return BuildConfig.botloaderApiBase.replace("https", "wss")